@@ -0,0 +1,246 @@
+use crate::document::Document;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+
+/// A flashcard found in a note
+#[derive(Debug, PartialEq)]
+pub enum Card {
+    /// From a `Q:` line and the `A:` lines after it
+    Basic { front: String, back: String },
+    /// A paragraph with `{{c1::...}}` deletions, kept as Anki writes them
+    Cloze { text: String },
+}
+
+/// The cards in `body`. A question starts at a line beginning `Q:` and its answer at the next
+/// line beginning `A:`, each running on until a blank line or the next question. Any other
+/// paragraph with a `{{c` deletion is a cloze card.
+pub fn cards(body: &str) -> Vec<Card> {
+    let mut cards = Vec::new();
+    for paragraph in paragraphs(body) {
+        let mut question: Option<Vec<&str>> = None;
+        let mut answer: Option<Vec<&str>> = None;
+        let mut rest = Vec::new();
+        for line in paragraph {
+            if let Some(q) = line.strip_prefix("Q:") {
+                push_basic(&mut cards, question.take(), answer.take());
+                question = Some(vec![q.trim()]);
+            } else if let (Some(a), true) = (line.strip_prefix("A:"), question.is_some()) {
+                answer = Some(vec![a.trim()]);
+            } else if let Some(a) = answer.as_mut() {
+                a.push(line);
+            } else if let Some(q) = question.as_mut() {
+                q.push(line);
+            } else {
+                rest.push(line);
+            }
+        }
+        push_basic(&mut cards, question, answer);
+        let text = rest.join("\n");
+        if text.contains("{{c") && text.contains("::") {
+            cards.push(Card::Cloze { text });
+        }
+    }
+    cards
+}
+
+/// Runs of non-blank lines, trimmed at the end
+fn paragraphs(body: &str) -> Vec<Vec<&str>> {
+    let mut paragraphs = vec![Vec::new()];
+    for line in body.lines().map(str::trim_end) {
+        if line.is_empty() {
+            paragraphs.push(Vec::new());
+        } else {
+            paragraphs.last_mut().unwrap().push(line);
+        }
+    }
+    paragraphs.retain(|p| !p.is_empty());
+    paragraphs
+}
+
+/// Add the card for a question, if it got an answer
+fn push_basic(cards: &mut Vec<Card>, question: Option<Vec<&str>>, answer: Option<Vec<&str>>) {
+    if let (Some(q), Some(a)) = (question, answer) {
+        cards.push(Card::Basic {
+            front: q.join("\n"),
+            back: a.join("\n"),
+        });
+    }
+}
+
+/// A field quoted if it holds a tab, newline or quote, as Anki's text import expects
+fn field(s: &str) -> String {
+    if s.contains(&['\t', '\n', '"'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// The guid of the card asking `question` in the note `chain_id`, from a hash of the question
+/// so that it stays put when cards before it are added or removed
+fn card_guid(chain_id: &str, question: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input(question.as_bytes());
+    let hash = format!("{:x}", hasher.result());
+    format!("{}-{}", chain_id, &hash[..12])
+}
+
+/// An Anki text import file of the cards in `docs` for `deck`, and how many cards it holds.
+/// Each card's guid is the note's chain id and a hash of the card's question, so importing
+/// again updates cards rather than duplicating them, and edits to other cards of the note
+/// leave it alone.
+pub fn tsv(docs: &[Document], deck: &str) -> (String, usize) {
+    let mut out = format!(
+        "#separator:tab\n#html:false\n#notetype column:1\n#guid column:2\n#deck:{}\n#tags column:5\n",
+        deck
+    );
+    let mut count = 0;
+    for doc in docs {
+        let tags = doc
+            .tags
+            .iter()
+            .map(|t| t.replace(' ', "_"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Times each guid came up in the note, to tell apart cards asking the same question
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for card in cards(&doc.body) {
+            let question = match &card {
+                Card::Basic { front, .. } => front,
+                Card::Cloze { text } => text,
+            };
+            let mut guid = card_guid(doc.chain_id(), question);
+            let n = seen.entry(guid.clone()).or_insert(0);
+            *n += 1;
+            if *n > 1 {
+                guid = format!("{}-{}", guid, n);
+            }
+            let (notetype, first, second) = match card {
+                Card::Basic { front, back } => ("Basic", front, back),
+                // The note's title goes under Back Extra, to find the note again
+                Card::Cloze { text } => ("Cloze", text, doc.title.clone()),
+            };
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                notetype,
+                guid,
+                field(&first),
+                field(&second),
+                field(&tags)
+            ));
+            count += 1;
+        }
+    }
+    (out, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(front: &str, back: &str) -> Card {
+        Card::Basic {
+            front: front.to_owned(),
+            back: back.to_owned(),
+        }
+    }
+
+    #[test]
+    fn questions_run_until_their_answer_and_answers_until_a_blank_line() {
+        let body = "Q: What does\nthe borrow checker check?\nA: Lifetimes\nof references\n\nQ: Next?\nA: Yes";
+        assert_eq!(
+            cards(body),
+            [
+                basic(
+                    "What does\nthe borrow checker check?",
+                    "Lifetimes\nof references"
+                ),
+                basic("Next?", "Yes")
+            ]
+        );
+    }
+
+    #[test]
+    fn a_question_ends_at_the_next_one() {
+        let body = "Q: One\nA: 1\nQ: Unanswered\nQ: Two\nA: 2";
+        assert_eq!(cards(body), [basic("One", "1"), basic("Two", "2")]);
+    }
+
+    #[test]
+    fn answers_without_a_question_are_not_cards() {
+        assert!(cards("A: Nothing asked\n\nJust prose.").is_empty());
+    }
+
+    #[test]
+    fn paragraphs_with_deletions_are_cloze_cards() {
+        let body =
+            "Intro.\n\nThe {{c1::borrow checker}} runs at\n{{c2::compile}} time.\n\nQ: Q\nA: A";
+        assert_eq!(
+            cards(body),
+            [
+                Card::Cloze {
+                    text: "The {{c1::borrow checker}} runs at\n{{c2::compile}} time.".to_owned()
+                },
+                basic("Q", "A"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_are_quoted_only_when_needed() {
+        assert_eq!(field("plain text"), "plain text");
+        assert_eq!(field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(field("a\tb"), "\"a\tb\"");
+    }
+
+    fn note(body: &str) -> Document {
+        Document {
+            id: "n1".to_owned(),
+            title: "Rust".to_owned(),
+            tags: vec!["flashcard".to_owned(), "two words".to_owned()],
+            body: body.to_owned(),
+            ..Document::new()
+        }
+    }
+
+    /// The guid column of each card line
+    fn guids(tsv: &str) -> Vec<String> {
+        tsv.lines()
+            .filter(|l| !l.starts_with('#'))
+            .map(|l| l.split('\t').nth(1).unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn guids_stay_put_as_other_cards_come_and_go() {
+        let (before, count) = tsv(&[note("Q: One\nA: 1")], "mz");
+        assert_eq!(count, 1);
+        let (after, count) = tsv(&[note("Q: Zero\nA: 0\n\nQ: One\nA: 1 edited")], "mz");
+        assert_eq!(count, 2);
+        assert_eq!(guids(&before)[0], guids(&after)[1]);
+        assert!(guids(&before)[0].starts_with("n1-"));
+    }
+
+    #[test]
+    fn repeated_questions_get_numbered_guids() {
+        let (out, _) = tsv(&[note("Q: Same\nA: 1\n\nQ: Same\nA: 2")], "mz");
+        let guids = guids(&out);
+        assert_eq!(guids[1], format!("{}-2", guids[0]));
+    }
+
+    #[test]
+    fn lines_carry_the_notetype_fields_and_tags() {
+        let (out, _) = tsv(&[note("Q: One\nA: 1\n\nThe {{c1::answer}}.")], "Deck");
+        assert!(out.contains("#deck:Deck\n"));
+        let lines: Vec<_> = out.lines().filter(|l| !l.starts_with('#')).collect();
+        let basic: Vec<_> = lines[0].split('\t').collect();
+        assert_eq!((basic[0], basic[2], basic[3]), ("Basic", "One", "1"));
+        assert_eq!(basic[4], "flashcard two_words");
+        let cloze: Vec<_> = lines[1].split('\t').collect();
+        assert_eq!(
+            (cloze[0], cloze[2], cloze[3]),
+            ("Cloze", "The {{c1::answer}}.", "Rust")
+        );
+    }
+}
@@ -1,13 +1,31 @@
+use crate::client::Client;
 use crate::date::DateRange;
 use crate::document;
 use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
 use color_eyre::Report;
-use eyre::{eyre, Result};
+use eyre::{bail, eyre, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use unicode_width::UnicodeWidthStr; // Provides `width()` method on String
 
+/// Tag `archive` adds, documents carrying it are left out of queries unless asked for
+pub const ARCHIVED: &str = "archived";
+
+/// Hits or documents asked for per request, and the default limit of a query. Anything wanting
+/// more than a page reads through `Pages`.
+pub const PAGE_SIZE: u32 = 1000;
+
+/// `filter`, if any, restricted to documents that aren't archived
+pub fn without_archived(filter: Option<&str>) -> String {
+    let archived = format!("tags != \"{}\"", ARCHIVED);
+    match filter {
+        Some(f) => format!("({}) AND {}", f, archived),
+        None => archived,
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ApiQuery {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -20,10 +38,13 @@ pub struct ApiQuery {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub sort: Option<Vec<String>>,
+    /// Fields to count values of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub facets: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
-    #[serde(rename = "facetsDistribution")]
-    pub facets_distribution: Option<Vec<String>>,
+    pub offset: Option<u32>,
     #[serde(default)]
     pub limit: u32,
 }
@@ -41,11 +62,16 @@ impl ApiQuery {
     pub fn new() -> Self {
         ApiQuery {
             sort: Some(vec!["date:desc".to_owned()]),
-            limit: 10000,
+            limit: PAGE_SIZE,
             ..Default::default()
         }
     }
 
+    /// Leave out archived documents, on top of any filter already set
+    pub fn exclude_archived(&mut self) {
+        self.filter = Some(without_archived(self.filter.as_deref()));
+    }
+
     pub fn process_filter(&mut self, input: String) {
         // If the supplied string doesn't parse with our expected grammer, just return
         let mut expr = match Filter::parse(Rule::expression, input.as_str()) {
@@ -125,15 +151,27 @@ impl ApiQuery {
                     };
                     curr_comparator = None; // Reset comparator
                 }
+                Rule::count => {
+                    let mut inner = token.into_inner();
+                    let field = match inner.next().unwrap().as_str() {
+                        "words" => "word_count",
+                        _ => "reading_minutes",
+                    };
+                    let op = match inner.next().unwrap().into_inner().next().unwrap().as_rule() {
+                        Rule::gt => ">",
+                        _ => "<",
+                    };
+                    let n = inner.next().unwrap().as_str();
+                    filter.push_str(&format!("{} {} {}", field, op, n));
+                }
                 Rule::tag => {
                     filter.push_str("tags = ");
-                    filter.push_str(token.as_str());
+                    filter.push_str(token.into_inner().next().unwrap().as_str());
                 }
                 Rule::not_tag => {
                     filter.push_str("tags != ");
-                    for inner in token.into_inner() {
-                        filter.push_str(inner.as_str());
-                    }
+                    let tag = token.into_inner().next().unwrap();
+                    filter.push_str(tag.into_inner().next().unwrap().as_str());
                 }
                 Rule::operator => match token.into_inner().next().unwrap().as_rule() {
                     Rule::and => {
@@ -154,11 +192,122 @@ impl ApiQuery {
     }
 }
 
+/// Where `Pages` reads from
+#[derive(Debug)]
+enum Source {
+    /// Matches of a search, in its sort order. Servers stop at their pagination.maxTotalHits,
+    /// which `index::init` raises.
+    Search(ApiQuery),
+    /// Every stored document, in the server's order and without limit
+    Documents,
+}
+
+/// Reads every match of a search, or every document of the index, a page at a time, so
+/// callers needn't guess a limit large enough
+#[derive(Debug)]
+pub struct Pages<'a> {
+    client: &'a Client,
+    source: Source,
+    offset: u32,
+    /// Matches the server estimated a search has, once the first page has been read
+    total: Option<u32>,
+    done: bool,
+}
+
+impl<'a> Pages<'a> {
+    /// Every match of `query`, from its offset if set. Its limit is replaced by the page size.
+    pub fn search(client: &'a Client, query: ApiQuery) -> Pages<'a> {
+        let offset = query.offset.unwrap_or(0);
+        Pages {
+            client,
+            source: Source::Search(query),
+            offset,
+            total: None,
+            done: false,
+        }
+    }
+
+    /// Every document of the index, revisions and archived ones included
+    pub fn documents(client: &'a Client) -> Pages<'a> {
+        Pages {
+            client,
+            source: Source::Documents,
+            offset: 0,
+            total: None,
+            done: false,
+        }
+    }
+
+    /// The next page, None once they have all been read. Fails rather than end a search
+    /// short of its matches.
+    pub async fn next(&mut self) -> Result<Option<Vec<document::Document>>, Report> {
+        if self.done {
+            self.check_complete().await?;
+            return Ok(None);
+        }
+        let page = match &mut self.source {
+            Source::Search(q) => {
+                q.offset = Some(self.offset);
+                q.limit = PAGE_SIZE;
+                let resp = self.client.search(q).await?;
+                self.total.get_or_insert(resp.num_hits);
+                resp.hits
+            }
+            Source::Documents => self.client.documents_page(self.offset, PAGE_SIZE).await?,
+        };
+        // A short page is the last, which saves asking for an empty one
+        self.done = (page.len() as u32) < PAGE_SIZE;
+        self.offset += page.len() as u32;
+        if page.is_empty() {
+            self.check_complete().await?;
+            return Ok(None);
+        }
+        Ok(Some(page))
+    }
+
+    /// Fail if a search ended before its last match, as it does on reaching the server's
+    /// pagination.maxTotalHits. Only asked once the last page has been read, so that reading
+    /// just the first costs no extra request.
+    async fn check_complete(&self) -> Result<(), Report> {
+        let total = match (&self.source, self.total) {
+            (Source::Search(_), Some(total)) => total,
+            _ => return Ok(()),
+        };
+        let capped = self.offset < total
+            || (self.offset > 0
+                && self
+                    .client
+                    .max_total_hits()
+                    .await?
+                    .is_some_and(|max| self.offset >= max));
+        if capped {
+            bail!(
+                "❌ Only {} matches could be read, the server's pagination.maxTotalHits stops \
+                 searches there. Run `mz init` to raise it.",
+                self.offset
+            );
+        }
+        Ok(())
+    }
+
+    /// Every page read into one list
+    pub async fn all(mut self) -> Result<Vec<document::Document>, Report> {
+        let mut docs = Vec::new();
+        while let Some(page) = self.next().await? {
+            docs.extend(page);
+        }
+        Ok(docs)
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ApiResponse {
     pub hits: Vec<document::Document>,
-    #[serde(rename = "nbHits")]
+    /// Estimated matches, `totalHits` when paged by page number
+    #[serde(rename = "estimatedTotalHits", alias = "totalHits")]
     pub num_hits: u32,
+    /// Only sent by servers before v0.28
+    #[serde(default)]
     #[serde(rename = "exhaustiveNbHits")]
     pub exhaustive_num_hits: bool,
     pub query: String,
@@ -166,6 +315,10 @@ pub struct ApiResponse {
     pub offset: u32,
     #[serde(rename = "processingTimeMs")]
     pub processing_time_ms: u32,
+    /// Value counts per facet, present when `facets` were requested
+    #[serde(default)]
+    #[serde(rename = "facetDistribution")]
+    pub facet_distribution: Option<HashMap<String, HashMap<String, u32>>>,
 }
 
 type PestPair<'a> = pest_iterators::Pair<'a, Rule>;
@@ -220,8 +373,34 @@ impl TryFrom<PestPair<'_>> for DateRange {
                     ),
                 )
             }
+            Rule::natural => {
+                let words = item.into_inner().next().unwrap().as_str();
+                return crate::date::parse_natural(words);
+            }
             e => return Err(eyre!("Unexpected match item {:?}", e)),
         };
         Ok(DateRange { start, end })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(input: &str) -> Option<String> {
+        let mut q = ApiQuery::new();
+        q.process_filter(input.to_owned());
+        q.filter
+    }
+
+    #[test]
+    fn tags_are_bare_or_after_tag_equals() {
+        assert_eq!(filter("flashcard").as_deref(), Some("tags = flashcard"));
+        assert_eq!(filter("tag=flashcard").as_deref(), Some("tags = flashcard"));
+        assert_eq!(filter("!tag=draft").as_deref(), Some("tags != draft"));
+        assert_eq!(
+            filter("tag=rust + !draft").as_deref(),
+            Some("tags = rust AND tags != draft")
+        );
+    }
+}
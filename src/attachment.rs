@@ -0,0 +1,107 @@
+use crate::config::AttachmentsConfig;
+use crate::state;
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A file attached to a document, kept in the attachments directory
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Where the file is, relative to the attachments directory
+    pub path: String,
+    /// Hex SHA-1 of the contents
+    pub sha1: String,
+}
+
+impl Attachment {
+    /// The file name it was attached under
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// The attachments directory, as configured or else e.g. ~/.local/share/meilizet/attachments
+pub fn dir(config: &AttachmentsConfig) -> Result<PathBuf, Report> {
+    match &config.dir {
+        Some(dir) => Ok(PathBuf::from(
+            shellexpand::tilde(&dir.to_string_lossy()).as_ref(),
+        )),
+        None => Ok(state::state_dir()?.join("attachments")),
+    }
+}
+
+/// Hex SHA-1 of the contents of `path`
+fn sha1(path: &Path) -> Result<String, Report> {
+    let mut hasher = Sha1::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.result()))
+}
+
+/// Copy `file` into `dir` under its hash and name, so the same contents are only stored once
+/// per name
+pub fn store(dir: &Path, file: &Path) -> Result<Attachment, Report> {
+    let name = file
+        .file_name()
+        .ok_or_else(|| eyre!("❌ {} is not a file", file.display()))?
+        .to_string_lossy()
+        .into_owned();
+    store_as(dir, file, &name)
+}
+
+/// Copy `file` into `dir` as `store` does, attached as `name` rather than its own name
+pub fn store_as(dir: &Path, file: &Path, name: &str) -> Result<Attachment, Report> {
+    let sha1 = sha1(file).map_err(|e| eyre!("❌ Failed to read {}: {}", file.display(), e))?;
+    let attachment = Attachment {
+        path: format!("{}/{}", sha1, name),
+        sha1,
+    };
+    let dest = path(dir, &attachment);
+    if !dest.exists() {
+        fs::create_dir_all(dest.parent().unwrap())?;
+        fs::copy(file, &dest)?;
+    }
+    Ok(attachment)
+}
+
+/// Where `attachment` is kept
+pub fn path(dir: &Path, attachment: &Attachment) -> PathBuf {
+    dir.join(&attachment.path)
+}
+
+/// The stored copy of `attachment`, failing if it is missing or its contents changed
+pub fn verified(dir: &Path, attachment: &Attachment) -> Result<PathBuf, Report> {
+    let path = path(dir, attachment);
+    let sha1 =
+        sha1(&path).map_err(|e| eyre!("❌ Attachment {} is missing: {}", path.display(), e))?;
+    if sha1 != attachment.sha1 {
+        bail!(
+            "❌ Attachment {} has changed, its SHA-1 is {} not {}",
+            path.display(),
+            sha1,
+            attachment.sha1
+        );
+    }
+    Ok(path)
+}
+
+/// Open `path` in the desktop's default application, without waiting for it
+pub fn open(path: &Path) -> Result<(), Report> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    Command::new(opener)
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| eyre!("❌ Failed to run {}: {}", opener, e))?;
+    Ok(())
+}
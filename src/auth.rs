@@ -0,0 +1,61 @@
+use color_eyre::Report;
+use eyre::eyre;
+use keyring::{Entry, Error};
+use tracing::info;
+
+/// Keyring service the API keys are stored under, with the profile as the user name
+pub(crate) const SERVICE: &str = "meilizet";
+
+/// The API key stored for `profile`, None if there is none
+pub fn stored_key(profile: &str) -> Result<Option<String>, Report> {
+    match Entry::new(SERVICE, profile).get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(Error::NoEntry) => Ok(None),
+        Err(e) => Err(eyre!(
+            "❌ Failed to read the key for profile {} from the keyring: {}",
+            profile,
+            e
+        )),
+    }
+}
+
+/// `key` unless it is empty, in which case the API key stored for `profile`, or the empty key
+/// if there is none or the keyring can't be read, e.g. on a headless machine without a secret
+/// service
+pub fn resolve_key(profile: &str, key: &str) -> String {
+    if !key.is_empty() {
+        return key.to_owned();
+    }
+    match stored_key(profile) {
+        Ok(Some(key)) => key,
+        Ok(None) => String::new(),
+        Err(e) => {
+            info!("{}", e);
+            String::new()
+        }
+    }
+}
+
+/// Store `key` for `profile`, replacing any stored before
+pub fn store_key(profile: &str, key: &str) -> Result<(), Report> {
+    Entry::new(SERVICE, profile).set_password(key).map_err(|e| {
+        eyre!(
+            "❌ Failed to store the key for profile {} in the keyring: {}",
+            profile,
+            e
+        )
+    })
+}
+
+/// Remove the key stored for `profile`, returning false if there was none
+pub fn forget_key(profile: &str) -> Result<bool, Report> {
+    match Entry::new(SERVICE, profile).delete_password() {
+        Ok(()) => Ok(true),
+        Err(Error::NoEntry) => Ok(false),
+        Err(e) => Err(eyre!(
+            "❌ Failed to remove the key for profile {} from the keyring: {}",
+            profile,
+            e
+        )),
+    }
+}
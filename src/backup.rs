@@ -0,0 +1,126 @@
+use crate::date::Date;
+use crate::document::{Document, SerializationType};
+use crate::error::ParseError;
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Version of the archive layout, bumped when a change would trip up older restores
+pub const FORMAT: u32 = 1;
+
+/// zstd level archives are written at, the library default
+const LEVEL: i32 = 3;
+
+/// What an archive holds, stored in it as `manifest.json`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    /// Index the documents were read from
+    pub index: String,
+    pub documents: usize,
+    pub created: Date,
+}
+
+/// Everything needed to rebuild an index: its documents and every setting the server reported,
+/// synonyms, stop words and ranking rules included
+#[derive(Debug)]
+pub struct Backup {
+    pub manifest: Manifest,
+    pub settings: Value,
+    pub documents: Vec<Document>,
+}
+
+impl Backup {
+    pub fn new(index: &str, settings: Value, documents: Vec<Document>) -> Backup {
+        Backup {
+            manifest: Manifest {
+                format: FORMAT,
+                index: index.to_owned(),
+                documents: documents.len(),
+                created: Date::now(),
+            },
+            settings,
+            documents,
+        }
+    }
+
+    /// Write a zstd compressed tar of `manifest.json`, `settings.json` and `documents.json` to
+    /// `path`
+    pub fn write(&self, path: &Path) -> Result<(), Report> {
+        let documents: Vec<_> = self
+            .documents
+            .iter()
+            .cloned()
+            .map(|mut d| {
+                d.serialization_type = SerializationType::Storage;
+                d
+            })
+            .collect();
+        let encoder = zstd::Encoder::new(File::create(path)?, LEVEL)?;
+        let mut tar = tar::Builder::new(encoder);
+        for (name, contents) in &[
+            ("manifest.json", serde_json::to_vec_pretty(&self.manifest)?),
+            ("settings.json", serde_json::to_vec_pretty(&self.settings)?),
+            ("documents.json", serde_json::to_vec(&documents)?),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o600);
+            header.set_mtime(self.manifest.created.timestamp().max(0) as u64);
+            header.set_cksum();
+            tar.append_data(&mut header, name, contents.as_slice())?;
+        }
+        tar.into_inner()?.finish()?;
+        Ok(())
+    }
+
+    /// Read an archive `write` made
+    pub fn read(path: &Path) -> Result<Backup, Report> {
+        let failed = |e: &dyn std::fmt::Display| {
+            Report::from(ParseError(format!(
+                "❌ Failed to read backup {}: {}",
+                path.display(),
+                e
+            )))
+        };
+        let file =
+            File::open(path).map_err(|e| eyre!("❌ Can't open {}: {}", path.display(), e))?;
+        let mut tar = tar::Archive::new(zstd::Decoder::new(file)?);
+        let (mut manifest, mut settings, mut documents) = (None, None, None);
+        for entry in tar.entries().map_err(|e| failed(&e))? {
+            let mut entry = entry.map_err(|e| failed(&e))?;
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).map_err(|e| failed(&e))?;
+            match entry.path().map_err(|e| failed(&e))?.to_str() {
+                Some("manifest.json") => {
+                    manifest = Some(serde_json::from_slice(&contents).map_err(|e| failed(&e))?)
+                }
+                Some("settings.json") => {
+                    settings = Some(serde_json::from_slice(&contents).map_err(|e| failed(&e))?)
+                }
+                Some("documents.json") => {
+                    documents = Some(serde_json::from_slice(&contents).map_err(|e| failed(&e))?)
+                }
+                _ => {}
+            }
+        }
+        let manifest: Manifest = manifest.ok_or_else(|| failed(&"no manifest.json"))?;
+        if manifest.format > FORMAT {
+            bail!(
+                "❌ {} is a format {} backup, this mz reads up to format {}",
+                path.display(),
+                manifest.format,
+                FORMAT
+            );
+        }
+        Ok(Backup {
+            manifest,
+            settings: settings.ok_or_else(|| failed(&"no settings.json"))?,
+            documents: documents.ok_or_else(|| failed(&"no documents.json"))?,
+        })
+    }
+}
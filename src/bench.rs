@@ -0,0 +1,100 @@
+use crate::client::Client;
+use crate::document::Document;
+use crate::index::Settings;
+use color_eyre::Report;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// One batch size and concurrency tried
+#[derive(Debug)]
+pub struct Trial {
+    pub batch_size: usize,
+    pub concurrency: usize,
+    pub documents: usize,
+    /// From the first upload until the server had indexed every note
+    pub elapsed: Duration,
+}
+
+impl Trial {
+    pub fn per_second(&self) -> f64 {
+        self.documents as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+}
+
+impl fmt::Display for Trial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "batch {:>6}  concurrency {:>3}  {:>9.1} notes/s  {:>8.2}s",
+            self.batch_size,
+            self.concurrency,
+            self.per_second(),
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+/// Import `docs` once for each pairing of `batch_sizes` and `concurrencies`, timing each until
+/// the server has indexed every note. The imports go into a scratch index named after the notes
+/// index and given its expected settings, recreated empty for each trial and deleted at the
+/// end, so the notes index is never touched.
+pub async fn import(
+    client: &Client,
+    docs: &[Document],
+    batch_sizes: &[usize],
+    concurrencies: &[usize],
+) -> Result<Vec<Trial>, Report> {
+    let scratch = client.with_index(&format!("{}_bench", client.index()));
+    let trials = trials(&scratch, docs, batch_sizes, concurrencies).await;
+    // Clean up after a failed trial too
+    if let Err(e) = scratch.delete_index().await {
+        warn!(
+            "❌ Failed to delete the scratch index {}: {}",
+            scratch.index(),
+            e
+        );
+    }
+    trials
+}
+
+async fn trials(
+    scratch: &Client,
+    docs: &[Document],
+    batch_sizes: &[usize],
+    concurrencies: &[usize],
+) -> Result<Vec<Trial>, Report> {
+    let mut trials = Vec::new();
+    for &batch_size in batch_sizes {
+        for &concurrency in concurrencies {
+            // A previous trial, or an interrupted run, leaves documents behind
+            if !scratch.create_index().await? {
+                scratch.delete_index().await?;
+                scratch.create_index().await?;
+            }
+            scratch.set_settings(&Settings::expected()).await?;
+
+            let start = Instant::now();
+            let tasks = scratch
+                .add_documents_batched(docs, batch_size, concurrency)
+                .await?;
+            scratch.wait_for_tasks(&tasks).await?;
+            let trial = Trial {
+                batch_size: batch_size.max(1),
+                concurrency: concurrency.max(1),
+                documents: docs.len(),
+                elapsed: start.elapsed(),
+            };
+            println!("{}", trial);
+            trials.push(trial);
+        }
+    }
+    Ok(trials)
+}
+
+/// The trial that indexed the most notes a second
+pub fn best(trials: &[Trial]) -> Option<&Trial> {
+    trials
+        .iter()
+        .max_by(|a, b| a.per_second().total_cmp(&b.per_second()))
+}
@@ -0,0 +1,60 @@
+use crate::client;
+use crate::{api, document};
+use color_eyre::Report;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+/// `client::Client` driven by its own runtime, for synchronous callers such as the
+/// interactive query. Must not be used from within another runtime.
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: client::Client,
+    rt: Arc<Runtime>,
+}
+
+impl Client {
+    /// Drive `inner` from a new runtime
+    pub fn new(inner: client::Client) -> Result<Client, Report> {
+        Ok(Client {
+            inner,
+            rt: Arc::new(Runtime::new()?),
+        })
+    }
+
+    /// The async client, for calls without a blocking wrapper
+    pub fn inner(&self) -> &client::Client {
+        &self.inner
+    }
+
+    /// Run a future, typically a call on `inner()`, to completion
+    pub fn block_on<F: Future>(&self, f: F) -> F::Output {
+        self.rt.block_on(f)
+    }
+
+    pub fn search(&self, q: &api::ApiQuery) -> Result<api::ApiResponse, Report> {
+        self.block_on(self.inner.search(q))
+    }
+
+    /// Add or replace `docs`, returning the uid of each task
+    pub fn add_documents(&self, docs: &[document::Document]) -> Result<Vec<u64>, Report> {
+        self.block_on(self.inner.add_documents(docs))
+    }
+
+    /// Change only the fields present in each of the `updates`, which must include the id,
+    /// returning the uid of each task
+    pub fn update_documents(&self, updates: &Value) -> Result<Vec<u64>, Report> {
+        self.block_on(self.inner.update_documents(updates))
+    }
+
+    /// Fetch a document by id, None if there is none
+    pub fn get_document(&self, id: &str) -> Result<Option<document::Document>, Report> {
+        self.block_on(self.inner.get_document(id))
+    }
+
+    /// Delete the documents with `ids`, returning the task uid
+    pub fn delete(&self, ids: &[String]) -> Result<u64, Report> {
+        self.block_on(self.inner.delete(ids))
+    }
+}
@@ -0,0 +1,221 @@
+use crate::date::Date;
+use crate::document::Document;
+use crate::slug;
+use color_eyre::Report;
+use eyre::eyre;
+use sha1::{Digest, Sha1};
+use std::str::FromStr;
+use url::Url;
+
+/// An export `import-bookmarks` reads
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Pocket's HTML export, also what Instapaper exports as HTML
+    Pocket,
+    /// The bookmarks file every browser exports, from Netscape's
+    NetscapeHtml,
+}
+
+impl FromStr for Format {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Format, Self::Err> {
+        match s {
+            "pocket" => Ok(Format::Pocket),
+            "netscape-html" => Ok(Format::NetscapeHtml),
+            _ => Err(eyre!(
+                "❌ Unknown bookmark format {}, expected pocket or netscape-html",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// The link attribute holding when it was saved, epoch seconds
+    fn added_attribute(self) -> &'static str {
+        match self {
+            Format::Pocket => "time_added",
+            Format::NetscapeHtml => "add_date",
+        }
+    }
+}
+
+/// A saved article or bookmark
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub title: String,
+    pub url: String,
+    pub added: Option<Date>,
+    pub tags: Vec<String>,
+    /// The description saved with it, empty if there is none
+    pub excerpt: String,
+}
+
+/// `text` with the entities HTML exports use replaced by the characters they stand for
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let decoded = entity.and_then(|(name, end)| {
+            let c = match name {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => std::char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+                    None => std::char::from_u32(name.strip_prefix('#')?.parse().ok()?)?,
+                },
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `html` without its tags, unescaped, with runs of whitespace made single spaces
+fn text(html: &str) -> String {
+    let mut plain = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    unescape(&plain)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The `name="value"` attributes of the inside of a tag, names lowercased
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut rest = tag;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq]
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let after = rest[eq + 1..].trim_start();
+        let (value, next) = match after.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => match after[1..].find(quote) {
+                Some(end) => (&after[1..end + 1], &after[end + 2..]),
+                None => (&after[1..], ""),
+            },
+            _ => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        attrs.push((name, unescape(value)));
+        rest = next;
+    }
+    attrs
+}
+
+/// The bookmarks in an export written as `format`, in the order they appear. Links that
+/// aren't to a web page, such as Firefox's `place:` queries, are left out.
+pub fn parse(html: &str, format: Format) -> Vec<Bookmark> {
+    // Lowercasing ASCII keeps every offset, so tags are found in it and read from `html`
+    let lower = html.to_ascii_lowercase();
+    let mut bookmarks = Vec::new();
+    let mut at = 0;
+    while let Some(start) = lower[at..].find("<a ").map(|i| at + i) {
+        let open_end = match lower[start..].find('>') {
+            Some(i) => start + i,
+            None => break,
+        };
+        let close = lower[open_end..]
+            .find("</a>")
+            .map_or(html.len(), |i| open_end + i);
+        at = (close + "</a>".len()).min(html.len());
+        let attrs = attributes(&html[start + 2..open_end]);
+        let attr = |name: &str| {
+            attrs
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let url = match attr("href").map(Url::parse) {
+            Some(Ok(url)) if url.scheme() == "http" || url.scheme() == "https" => url,
+            _ => continue,
+        };
+        let added = attr(format.added_attribute())
+            .and_then(|t| t.parse().ok())
+            .map(Date::new);
+        let tags = attr("tags")
+            .map(|tags| {
+                tags.split(',')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Browsers write the description after the link, as a <DD> up to the next tag
+        let excerpt = match lower[at..].trim_start().strip_prefix("<dd>") {
+            Some(dd) => {
+                let from = html.len() - dd.len();
+                let to = lower[from..].find('<').map_or(html.len(), |i| from + i);
+                text(&html[from..to])
+            }
+            None => String::new(),
+        };
+        bookmarks.push(Bookmark {
+            title: text(&html[open_end + 1..close]),
+            url: url.to_string(),
+            added,
+            tags,
+            excerpt,
+        });
+    }
+    bookmarks
+}
+
+/// `bookmark` as a note, titled as saved or by its URL, linking to the URL with the excerpt as
+/// the body. Its id is the hex SHA-1 of the URL, so the same page imported twice is one note.
+pub fn to_document(bookmark: &Bookmark) -> Document {
+    let title = if bookmark.title.is_empty() {
+        bookmark.url.clone()
+    } else {
+        bookmark.title.clone()
+    };
+    let mut doc = Document {
+        id: format!("{:x}", Sha1::digest(bookmark.url.as_bytes())),
+        title,
+        date: bookmark.added.clone().unwrap_or_else(Date::now),
+        body: if bookmark.excerpt.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n", bookmark.excerpt)
+        },
+        tags: bookmark.tags.clone(),
+        links: vec![bookmark.url.clone()],
+        ..Document::new()
+    };
+    slug::fill(&mut doc);
+    doc.filename = format!("{}.md", doc.slug.replace('/', "-"));
+    doc
+}
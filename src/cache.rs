@@ -0,0 +1,241 @@
+use crate::client::Client;
+use crate::document::{Document, SerializationType};
+use crate::{api, state};
+use color_eyre::Report;
+use eyre::eyre;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// Where the copy of `index` is kept, e.g. ~/.local/share/meilizet/cache/notes.json
+fn path(index: &str) -> Result<PathBuf, Report> {
+    Ok(state::state_dir()?
+        .join("cache")
+        .join(format!("{}.json", index)))
+}
+
+/// Replace the local copy of `index` with `docs`
+pub fn save(index: &str, docs: &[Document]) -> Result<(), Report> {
+    let docs: Vec<_> = docs
+        .iter()
+        .cloned()
+        .map(|mut d| {
+            d.serialization_type = SerializationType::Storage;
+            d
+        })
+        .collect();
+    let path = path(index)?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, serde_json::to_string(&docs)?)?;
+    Ok(())
+}
+
+/// The ETag the server sent with a page of documents, and the ids on that page to find them in
+/// the local copy again when the server says the page is unchanged
+#[derive(Debug, Deserialize, Serialize)]
+struct PageTag {
+    etag: String,
+    ids: Vec<String>,
+}
+
+/// Where the ETags of the pages the local copy of `index` was read in are kept
+fn tags_path(index: &str) -> Result<PathBuf, Report> {
+    Ok(state::state_dir()?
+        .join("cache")
+        .join(format!("{}.etags.json", index)))
+}
+
+/// Replace the local copy of the client's index with every document on the server. Pages the
+/// server answers are unchanged since the last refresh, going by their ETags, are taken from
+/// the old copy rather than downloaded again. Returns the documents.
+pub async fn refresh(client: &Client) -> Result<Vec<Document>, Report> {
+    let index = client.index();
+    let tags: Vec<Option<PageTag>> = fs::read_to_string(tags_path(index)?)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    let mut known: HashMap<String, Document> = load(index)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|d| (d.id.clone(), d))
+        .collect();
+    let mut docs = Vec::new();
+    let mut new_tags = Vec::new();
+    loop {
+        // Only worth asking whether the page changed if the old copy still has all of it
+        let tag = tags.get(new_tags.len()).and_then(Option::as_ref);
+        let cached = tag.and_then(|t| {
+            t.ids
+                .iter()
+                .map(|id| known.remove(id))
+                .collect::<Option<Vec<_>>>()
+        });
+        let etag = tag.filter(|_| cached.is_some()).map(|t| t.etag.as_str());
+        let offset = docs.len() as u32;
+        let (page, etag) = match client
+            .documents_page_since(offset, api::PAGE_SIZE, etag)
+            .await?
+        {
+            Some(page) => page,
+            None => {
+                debug!(offset, "documents page unchanged");
+                (cached.unwrap_or_default(), etag.map(str::to_owned))
+            }
+        };
+        let len = page.len() as u32;
+        new_tags.push(etag.map(|etag| PageTag {
+            etag,
+            ids: page.iter().map(|d| d.id.clone()).collect(),
+        }));
+        docs.extend(page);
+        if len < api::PAGE_SIZE {
+            break;
+        }
+    }
+    save(index, &docs)?;
+    fs::write(tags_path(index)?, serde_json::to_string(&new_tags)?)?;
+    Ok(docs)
+}
+
+/// The local copy of `index` as of the last `dump` or `sync`
+pub fn load(index: &str) -> Result<Vec<Document>, Report> {
+    let path = path(index)?;
+    let s = fs::read_to_string(&path).map_err(|e| {
+        eyre!(
+            "❌ No offline copy of {} at {}, run `mz sync` while online: {}",
+            index,
+            path.display(),
+            e
+        )
+    })?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+/// Answer `q` from `docs`: every word of the query must appear in the title, subtitle, tags or
+/// body, and the filter is evaluated as far as the filter grammar produces it
+pub fn search(docs: &[Document], q: &api::ApiQuery) -> api::ApiResponse {
+    let words: Vec<_> = q
+        .query
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect();
+    let mut hits: Vec<Document> = docs
+        .iter()
+        .filter(|d| {
+            let text = format!("{} {} {} {}", d.title, d.subtitle, d.tags.join(" "), d.body)
+                .to_lowercase();
+            words.iter().all(|w| text.contains(w.as_str()))
+        })
+        .filter(|d| match &q.filter {
+            Some(filter) => matches(d, filter),
+            None => true,
+        })
+        .cloned()
+        .collect();
+    for sort in q.sort.iter().flatten().rev() {
+        let (field, desc) = match sort.split_once(':') {
+            Some((field, order)) => (field, order == "desc"),
+            None => (sort.as_str(), false),
+        };
+        hits.sort_by(|a, b| {
+            let order = match field {
+                "date" => a.date.timestamp().cmp(&b.date.timestamp()),
+                "weight" => a.weight.cmp(&b.weight),
+                "revision" => a.revision.cmp(&b.revision),
+                "views" => a.views.cmp(&b.views),
+                "word_count" => a.word_count.cmp(&b.word_count),
+                "reading_minutes" => a.reading_minutes.cmp(&b.reading_minutes),
+                _ => std::cmp::Ordering::Equal,
+            };
+            if desc {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+    let num_hits = hits.len() as u32;
+    let offset = q.offset.unwrap_or(0) as usize;
+    let hits: Vec<_> = hits
+        .into_iter()
+        .skip(offset)
+        .take(q.limit as usize)
+        .collect();
+    api::ApiResponse {
+        hits,
+        num_hits,
+        exhaustive_num_hits: true,
+        query: q.query.clone().unwrap_or_default(),
+        limit: q.limit.min(u16::MAX as u32) as u16,
+        offset: offset as u32,
+        ..Default::default()
+    }
+}
+
+/// Whether `doc` passes `filter`, read as a disjunction of conjunctions of `field op value`
+/// clauses with any parentheses ignored. Clauses that can't be evaluated locally pass.
+fn matches(doc: &Document, filter: &str) -> bool {
+    filter
+        .split(" OR ")
+        .any(|all| all.split(" AND ").all(|clause| clause_matches(doc, clause)))
+}
+
+fn clause_matches(doc: &Document, clause: &str) -> bool {
+    let clause = clause.trim().trim_start_matches('(').trim_end_matches(')');
+    let mut parts = clause.splitn(3, ' ');
+    let (field, op, value) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(field), Some(op), Some(value)) => (field, op, value.trim().trim_matches('"')),
+        _ => {
+            debug!("Can't evaluate filter clause {} offline", clause);
+            return true;
+        }
+    };
+    // Documents leave last_reviewed out until first reviewed
+    if field == "last_reviewed" && op == "NOT" && value == "EXISTS" {
+        return doc.last_reviewed == 0;
+    }
+    let number = |n: i64| match (op, value.parse::<i64>()) {
+        (">", Ok(v)) => n > v,
+        ("<", Ok(v)) => n < v,
+        (">=", Ok(v)) => n >= v,
+        ("<=", Ok(v)) => n <= v,
+        ("=", Ok(v)) => n == v,
+        ("!=", Ok(v)) => n != v,
+        _ => true,
+    };
+    let list = |l: &[String]| match op {
+        "=" => l.iter().any(|v| v == value),
+        "!=" => !l.iter().any(|v| v == value),
+        _ => true,
+    };
+    let string = |s: &str| match op {
+        "=" => s == value,
+        "!=" => s != value,
+        _ => true,
+    };
+    match field {
+        "date" => number(doc.date.timestamp()),
+        "weight" => number(doc.weight.into()),
+        "revision" => number(doc.revision.into()),
+        "views" => number(doc.views.into()),
+        "word_count" => number(doc.word_count as i64),
+        "reading_minutes" => number(doc.reading_minutes as i64),
+        "last_reviewed" => number(doc.last_reviewed),
+        "tags" => list(&doc.tags),
+        "authors" => list(&doc.authors),
+        "links" => list(&doc.links),
+        "backlinks" => list(&doc.backlinks),
+        "id" => string(&doc.id),
+        "origid" => string(&doc.origid),
+        "slug" => string(&doc.slug),
+        "latest" => string(if doc.latest { "true" } else { "false" }),
+        _ => {
+            debug!("Can't evaluate filter clause {} offline", clause);
+            true
+        }
+    }
+}
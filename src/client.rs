@@ -0,0 +1,819 @@
+use crate::error::RequestFailed;
+use crate::index::INDEX;
+use crate::recording::{Recording, Sent};
+use crate::server::Task;
+use crate::{api, cache, crypt, document};
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use futures::stream::{self, StreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, ETAG, IF_NONE_MATCH, RETRY_AFTER};
+use reqwest::{
+    Certificate, ClientBuilder, Identity, Method, Proxy, Request, RequestBuilder, Response,
+    StatusCode,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use url::Url;
+
+/// How long to wait for the server to process a task, large imports take a while
+const TASK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Longest a retry waits, however many attempts came before it
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often to retry requests that failed to connect, timed out, or were rejected as rate
+/// limited (429) or unavailable (503). The delay doubles after each attempt.
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Retry {
+        Retry {
+            attempts: 3,
+            delay: Duration::from_millis(250),
+        }
+    }
+}
+
+impl Retry {
+    /// Delay before retry number `attempt`, counting from 0
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.delay
+            .checked_mul(2u32.saturating_pow(attempt))
+            .map_or(MAX_BACKOFF, |d| d.min(MAX_BACKOFF))
+    }
+}
+
+/// What the server answers an asynchronous write with
+#[derive(Debug, Deserialize)]
+pub(crate) struct Enqueued {
+    #[serde(rename = "taskUid")]
+    pub uid: u64,
+}
+
+/// Connection to a Meilisearch server, with document and settings calls scoped to one index.
+/// Calls are async, see `blocking::Client` for use outside a runtime.
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    host: Url,
+    index: String,
+    retry: Retry,
+    /// Local copy of the index searches and reads are answered from instead of the server
+    offline: Option<Arc<Vec<document::Document>>>,
+    /// Key document bodies are encrypted with on upload and decrypted with on reading
+    crypt: Option<Arc<crypt::Crypt>>,
+    /// Where requests and responses are saved to, or responses served from instead of the
+    /// server
+    recording: Option<Arc<Recording>>,
+    /// Servers with copies of the index that reads fall back to when `host` is down
+    replicas: Arc<Replicas>,
+    /// Refuse every request that could change anything on the server
+    read_only: bool,
+}
+
+/// Read replicas, tried in order, and the one reads went to since the primary was found down
+#[derive(Debug, Default)]
+struct Replicas {
+    hosts: Vec<Url>,
+    chosen: Mutex<Option<Url>>,
+}
+
+/// How to reach the server, the defaults suit a local server over plain HTTP
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// PEM or, with a .der extension, DER certificate to trust in addition to the system roots,
+    /// e.g. that of a reverse proxy with a self-signed certificate
+    pub ca_cert: Option<PathBuf>,
+    /// Accept any server certificate, leaving the connection open to interception
+    pub insecure: bool,
+    /// Certificate and key to authenticate to the server with, a PKCS #12 archive when built with
+    /// native-tls or PEM with rustls
+    pub client_cert: Option<PathBuf>,
+    pub client_cert_password: String,
+    /// Limit on a whole request, from connecting to reading the response body
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// Proxy for every request, e.g. `socks5://localhost:1080`. Unless `no_proxy` is set the
+    /// HTTP_PROXY, HTTPS_PROXY and NO_PROXY environment variables are honored otherwise.
+    pub proxy: Option<String>,
+    pub no_proxy: bool,
+}
+
+impl Options {
+    fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder, Report> {
+        if let Some(path) = &self.ca_cert {
+            let bytes = fs::read(path)
+                .map_err(|e| eyre!("❌ Failed to read CA certificate {}: {}", path.display(), e))?;
+            let cert = match path.extension().and_then(|e| e.to_str()) {
+                Some("der") => Certificate::from_der(&bytes),
+                _ => Certificate::from_pem(&bytes),
+            }
+            .map_err(|e| eyre!("❌ Invalid CA certificate {}: {}", path.display(), e))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if let Some(path) = &self.client_cert {
+            let bytes = fs::read(path).map_err(|e| {
+                eyre!(
+                    "❌ Failed to read client certificate {}: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+            let identity = identity(&bytes, &self.client_cert_password)
+                .map_err(|e| eyre!("❌ Invalid client certificate {}: {}", path.display(), e))?;
+            builder = builder.identity(identity);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if self.no_proxy {
+            builder = builder.no_proxy();
+        } else if let Some(proxy) = &self.proxy {
+            let proxy =
+                Proxy::all(proxy).map_err(|e| eyre!("❌ Invalid proxy {}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+        Ok(builder.danger_accept_invalid_certs(self.insecure))
+    }
+}
+
+/// The client certificate in `bytes`, a PKCS #12 archive opened with `password`
+#[cfg(feature = "native-tls")]
+fn identity(bytes: &[u8], password: &str) -> reqwest::Result<Identity> {
+    Identity::from_pkcs12_der(bytes, password)
+}
+
+/// The client certificate in `bytes`, PEM with the certificate and its private key. rustls
+/// reads no PKCS #12, so there's no password.
+#[cfg(not(feature = "native-tls"))]
+fn identity(bytes: &[u8], _password: &str) -> reqwest::Result<Identity> {
+    Identity::from_pem(bytes)
+}
+
+impl Client {
+    /// Connect to the server at `host`, authenticating with `key` unless it is empty
+    pub fn new(host: &str, key: &str) -> Result<Client, Report> {
+        Client::with_options(host, key, &Options::default())
+    }
+
+    /// Connect to the server at `host` as `options` describe
+    pub fn with_options(host: &str, key: &str, options: &Options) -> Result<Client, Report> {
+        let host = base_url(host)?;
+        let mut headers = HeaderMap::new();
+        if !key.is_empty() {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", key))?,
+            );
+        }
+        let http = options
+            .apply(reqwest::Client::builder())?
+            .default_headers(headers)
+            .build()?;
+        Ok(Client {
+            http,
+            host,
+            index: INDEX.to_owned(),
+            retry: Retry::default(),
+            offline: None,
+            crypt: None,
+            recording: None,
+            replicas: Arc::default(),
+            read_only: false,
+        })
+    }
+
+    /// The same connection retrying failed requests according to `retry`
+    pub fn with_retry(self, retry: Retry) -> Client {
+        Client { retry, ..self }
+    }
+
+    /// The same connection scoped to the index `uid`
+    pub fn with_index(&self, uid: &str) -> Client {
+        Client {
+            index: uid.to_owned(),
+            offline: self
+                .offline
+                .as_ref()
+                .map(|_| Arc::new(cache::load(uid).unwrap_or_default())),
+            ..self.clone()
+        }
+    }
+
+    /// Answer searches and document reads from the local copy of the index saved by `dump` or
+    /// `sync`, and refuse every other request, for use without the server
+    pub fn offline(self) -> Result<Client, Report> {
+        let docs = cache::load(&self.index)?;
+        Ok(Client {
+            offline: Some(Arc::new(docs)),
+            ..self
+        })
+    }
+
+    /// The same connection encrypting and decrypting document bodies as `crypt` says
+    pub fn with_crypt(self, crypt: crypt::Crypt) -> Client {
+        Client {
+            crypt: Some(Arc::new(crypt)),
+            ..self
+        }
+    }
+
+    /// The same connection saving every request and response to, or when replaying serving
+    /// responses from, `recording`
+    pub fn with_recording(self, recording: Arc<Recording>) -> Client {
+        Client {
+            recording: Some(recording),
+            ..self
+        }
+    }
+
+    /// The same connection reading from the first of `hosts` to pass a health check whenever
+    /// the primary is down. Writes only ever go to the primary.
+    pub fn with_replicas(self, hosts: &[&str]) -> Result<Client, Report> {
+        let replicas = Replicas {
+            hosts: hosts
+                .iter()
+                .map(|h| base_url(h))
+                .collect::<Result<_, _>>()?,
+            chosen: Mutex::new(None),
+        };
+        Ok(Client {
+            replicas: Arc::new(replicas),
+            ..self
+        })
+    }
+
+    /// The same connection refusing every request but searches and reads, so nothing on the
+    /// server can be changed through it
+    pub fn read_only(self) -> Client {
+        Client {
+            read_only: true,
+            ..self
+        }
+    }
+
+    /// Decrypt the body of each of `docs` if reads are to be decrypted
+    fn open(&self, docs: &mut [document::Document]) -> Result<(), Report> {
+        if let Some(crypt) = &self.crypt {
+            for doc in docs {
+                crypt.open(doc)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn index(&self) -> &str {
+        &self.index
+    }
+
+    /// Server URL for `path`, e.g. `health`
+    pub fn url(&self, path: &str) -> Result<Url, Report> {
+        Ok(self.host.join(path)?)
+    }
+
+    /// Server URL for `path` under the index, e.g. `search`
+    pub fn index_url(&self, path: &str) -> Result<Url, Report> {
+        self.url(&format!("indexes/{}/{}", self.index, path))
+    }
+
+    pub(crate) fn get(&self, url: Url) -> RequestBuilder {
+        self.http.get(url)
+    }
+
+    pub(crate) fn post(&self, url: Url) -> RequestBuilder {
+        self.http.post(url)
+    }
+
+    pub(crate) fn put(&self, url: Url) -> RequestBuilder {
+        self.http.put(url)
+    }
+
+    pub(crate) fn patch(&self, url: Url) -> RequestBuilder {
+        self.http.patch(url)
+    }
+
+    pub(crate) fn delete_request(&self, url: Url) -> RequestBuilder {
+        self.http.delete(url)
+    }
+
+    /// Send the request, retrying connection failures, timeouts, 429 and 503 with exponential
+    /// backoff. A Retry-After header from the server takes precedence over the backoff.
+    pub(crate) async fn execute(&self, req: RequestBuilder) -> Result<Response, Report> {
+        let request = req.build()?;
+        if self.offline.is_some() {
+            bail!(
+                "❌ Offline, can't {} {}: only searches and document reads work",
+                request.method(),
+                request.url()
+            );
+        }
+        if self.read_only && !is_read(&request) {
+            bail!(
+                "❌ Read-only, refusing to {} {}",
+                request.method(),
+                request.url()
+            );
+        }
+        let recording = match &self.recording {
+            Some(recording) => recording,
+            None => return self.dispatch(request).await,
+        };
+        let sent = Sent::new(&self.host, &request);
+        if recording.is_replay() {
+            return recording.replayed(&sent);
+        }
+        let resp = self.dispatch(request).await?;
+        recording.recorded(sent, resp).await
+    }
+
+    /// Send the request to the primary or, if it is a read of the index and the primary is
+    /// down, to the first healthy replica. Once the primary has been found down reads stay on
+    /// that replica for the rest of the run, and go back to the health checks if it fails too.
+    async fn dispatch(&self, request: Request) -> Result<Response, Report> {
+        if self.replicas.hosts.is_empty() || !is_index_read(&request) {
+            return self.retrying(request).await;
+        }
+        let chosen = self.replicas.chosen.lock().unwrap().clone();
+        let copy = match request.try_clone() {
+            Some(copy) => copy,
+            None => return self.retrying(request).await,
+        };
+        let copy = match &chosen {
+            Some(replica) => self.moved(copy, replica)?,
+            None => copy,
+        };
+        // A single attempt, a replica answers sooner than the retries would
+        match self.attempt(copy, 0).await {
+            Ok(resp) if resp.status() != StatusCode::SERVICE_UNAVAILABLE => return Ok(resp),
+            Err(e) if !e.is_connect() && !e.is_timeout() => return Err(e.into()),
+            _ => {}
+        }
+        let replica = match self.healthy_replica(chosen.as_ref()).await {
+            Some(replica) => replica,
+            None => {
+                // Back to the primary, it may be up again
+                *self.replicas.chosen.lock().unwrap() = None;
+                return self.retrying(request).await;
+            }
+        };
+        warn!(
+            "{} is down, reading from {}",
+            chosen.as_ref().unwrap_or(&self.host),
+            replica
+        );
+        *self.replicas.chosen.lock().unwrap() = Some(replica.clone());
+        self.retrying(self.moved(request, &replica)?).await
+    }
+
+    /// The first replica other than `skip` whose health check passes
+    async fn healthy_replica(&self, skip: Option<&Url>) -> Option<Url> {
+        for host in &self.replicas.hosts {
+            if Some(host) == skip {
+                continue;
+            }
+            let url = match host.join("health") {
+                Ok(url) => url,
+                Err(_) => continue,
+            };
+            match self.http.get(url).send().await {
+                Ok(resp) if resp.status().is_success() => return Some(host.clone()),
+                Ok(resp) => debug!(%host, status = resp.status().as_u16(), "replica unhealthy"),
+                Err(e) => debug!(%host, error = %e, "replica unreachable"),
+            }
+        }
+        None
+    }
+
+    /// `request` addressed to `host` rather than the primary
+    fn moved(&self, mut request: Request, host: &Url) -> Result<Request, Report> {
+        let path = match request.url().as_str().strip_prefix(self.host.as_str()) {
+            Some(path) => path.to_owned(),
+            None => return Ok(request),
+        };
+        *request.url_mut() = host.join(&path)?;
+        Ok(request)
+    }
+
+    /// Send the request as `execute` describes
+    async fn retrying(&self, request: Request) -> Result<Response, Report> {
+        let mut attempt = 0;
+        loop {
+            // Streaming bodies can't be cloned, those get a single attempt
+            let copy = match request.try_clone() {
+                Some(r) if attempt < self.retry.attempts => r,
+                _ => break,
+            };
+            let delay = match self.attempt(copy, attempt).await {
+                Ok(resp) => match resp.status() {
+                    StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                        retry_after(&resp).unwrap_or_else(|| self.retry.backoff(attempt))
+                    }
+                    _ => return Ok(resp),
+                },
+                Err(e) if e.is_connect() || e.is_timeout() => self.retry.backoff(attempt),
+                Err(e) => return Err(e.into()),
+            };
+            warn!(
+                "Retrying {} {} in {:?}",
+                request.method(),
+                request.url(),
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+        Ok(self.attempt(request, attempt).await?)
+    }
+
+    /// Send the request once, logging the outcome
+    async fn attempt(&self, request: Request, attempt: u32) -> reqwest::Result<Response> {
+        let (method, url) = (request.method().clone(), request.url().clone());
+        let start = Instant::now();
+        let result = self.http.execute(request).await;
+        let elapsed_ms = start.elapsed().as_millis() as u64;
+        match &result {
+            Ok(resp) => debug!(
+                %method,
+                %url,
+                status = resp.status().as_u16(),
+                elapsed_ms,
+                attempt,
+                "response"
+            ),
+            Err(e) => debug!(%method, %url, error = %e, elapsed_ms, attempt, "request failed"),
+        }
+        result
+    }
+
+    /// Send the request and deserialize the response, turning error statuses into errors
+    pub(crate) async fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T, Report> {
+        parse(self.execute(req).await?).await
+    }
+
+    /// Send an asynchronous write, returning the uid of the task the server queued for it
+    pub(crate) async fn enqueue(&self, req: RequestBuilder) -> Result<u64, Report> {
+        Ok(self.send::<Enqueued>(req).await?.uid)
+    }
+
+    /// Send `items` as one asynchronous write to the index path, halving the batch each time the
+    /// server rejects it as too large. Returns the uid of every task queued, in batch order.
+    async fn enqueue_batch<T: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        items: &[T],
+    ) -> Result<Vec<u64>, Report> {
+        let url = self.index_url(path)?;
+        let mut tasks = Vec::new();
+        let mut pending = vec![items];
+        while let Some(batch) = pending.pop() {
+            let req = self.http.request(method.clone(), url.clone()).json(batch);
+            let resp = self.execute(req).await?;
+            if resp.status() == StatusCode::PAYLOAD_TOO_LARGE && batch.len() > 1 {
+                let (first, second) = batch.split_at(batch.len() / 2);
+                pending.push(second);
+                pending.push(first);
+                continue;
+            }
+            tasks.push(parse::<Enqueued>(resp).await?.uid);
+        }
+        Ok(tasks)
+    }
+
+    /// Poll an asynchronous task until the server has applied it
+    pub async fn wait_for_task(&self, uid: u64) -> Result<(), Report> {
+        let task = self.task_result(uid).await?;
+        match task.status.as_str() {
+            "failed" => bail!("❌ Task {} failed: {}", uid, task.error_message()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait for each of the `uids`, in order
+    pub async fn wait_for_tasks(&self, uids: &[u64]) -> Result<(), Report> {
+        for uid in uids {
+            self.wait_for_task(*uid).await?;
+        }
+        Ok(())
+    }
+
+    /// Poll an asynchronous task until it succeeds or fails, returning its final state
+    pub(crate) async fn task_result(&self, uid: u64) -> Result<Task, Report> {
+        let start = Instant::now();
+        loop {
+            let task = self.task(uid).await?;
+            match task.status.as_str() {
+                "succeeded" | "failed" => return Ok(task),
+                _ if start.elapsed() > TASK_TIMEOUT => {
+                    bail!("❌ Timed out waiting for task {}", uid)
+                }
+                _ => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    pub async fn search(&self, q: &api::ApiQuery) -> Result<api::ApiResponse, Report> {
+        let mut resp: api::ApiResponse = match &self.offline {
+            Some(docs) => cache::search(docs, q),
+            None => {
+                self.send(self.post(self.index_url("search")?).json(q))
+                    .await?
+            }
+        };
+        self.open(&mut resp.hits)?;
+        Ok(resp)
+    }
+
+    /// The most matches a search can page through, the index's pagination.maxTotalHits. None
+    /// offline, where searches have no such limit, and when the server has no pagination
+    /// setting or won't show it to this key.
+    pub async fn max_total_hits(&self) -> Result<Option<u32>, Report> {
+        if self.offline.is_some() {
+            return Ok(None);
+        }
+        let resp = self
+            .execute(self.get(self.index_url("settings/pagination")?))
+            .await?;
+        if !resp.status().is_success() {
+            debug!(status = resp.status().as_u16(), "no pagination setting");
+            return Ok(None);
+        }
+        let pagination: Value = parse(resp).await?;
+        Ok(pagination["maxTotalHits"].as_u64().map(|max| max as u32))
+    }
+
+    /// Add or replace `docs` in batches of `batch_size`, up to `concurrency` requests at a
+    /// time, returning the uid of each task in order. Batches the server finds too large are
+    /// still split.
+    pub async fn add_documents_batched(
+        &self,
+        docs: &[document::Document],
+        batch_size: usize,
+        concurrency: usize,
+    ) -> Result<Vec<u64>, Report> {
+        let mut uploads = stream::iter(docs.chunks(batch_size.max(1)))
+            .map(|batch| self.add_documents(batch))
+            .buffer_unordered(concurrency.max(1));
+        let mut tasks = Vec::new();
+        while let Some(uids) = uploads.next().await {
+            tasks.extend(uids?);
+        }
+        tasks.sort_unstable();
+        Ok(tasks)
+    }
+
+    /// Add or replace `docs`, returning the uid of each task. The documents go in one batch
+    /// unless the server finds it too large, in which case it is split.
+    /// Bodies are encrypted first if the connection encrypts, or if they were encrypted before.
+    pub async fn add_documents(&self, docs: &[document::Document]) -> Result<Vec<u64>, Report> {
+        match &self.crypt {
+            Some(crypt) => {
+                let mut sealed = docs.to_vec();
+                for doc in &mut sealed {
+                    crypt.seal(doc)?;
+                }
+                self.enqueue_batch(Method::POST, "documents", &sealed).await
+            }
+            None => {
+                for doc in docs {
+                    crypt::check_sealed(doc)?;
+                }
+                self.enqueue_batch(Method::POST, "documents", docs).await
+            }
+        }
+    }
+
+    /// Change only the fields present in each of the `updates`, which must include the id,
+    /// returning the uid of each task
+    pub async fn update_documents(&self, updates: &Value) -> Result<Vec<u64>, Report> {
+        let updates = match updates.as_array() {
+            Some(a) => a.as_slice(),
+            None => std::slice::from_ref(updates),
+        };
+        self.enqueue_batch(Method::PUT, "documents", updates).await
+    }
+
+    /// Fetch a document by id, None if there is none
+    pub async fn get_document(&self, id: &str) -> Result<Option<document::Document>, Report> {
+        if let Some(docs) = &self.offline {
+            let mut found: Vec<_> = docs.iter().filter(|d| d.id == id).cloned().collect();
+            self.open(&mut found)?;
+            return Ok(found.pop());
+        }
+        let mut url = self.index_url("documents")?;
+        url.path_segments_mut()
+            .map_err(|_| eyre!("❌ Cannot derive document URL from {}", self.host))?
+            .push(id);
+        let resp = self.execute(self.get(url)).await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let mut doc = parse(resp).await?;
+        self.open(std::slice::from_mut(&mut doc))?;
+        Ok(Some(doc))
+    }
+
+    /// Up to `limit` documents as stored from `offset` on, in the server's order, without
+    /// reading them as Documents, which older ones may not be
+    pub async fn raw_documents_page(&self, offset: u32, limit: u32) -> Result<Vec<Value>, Report> {
+        let page = self.raw_documents_page_since(offset, limit, None).await?;
+        Ok(page.map(|(docs, _)| docs).unwrap_or_default())
+    }
+
+    /// The page `raw_documents_page` reads and its ETag, if the server sends one. Given the
+    /// `etag` of an earlier read, None if the server answers that the page hasn't changed.
+    async fn raw_documents_page_since(
+        &self,
+        offset: u32,
+        limit: u32,
+        etag: Option<&str>,
+    ) -> Result<Option<(Vec<Value>, Option<String>)>, Report> {
+        let mut url = self.index_url("documents")?;
+        url.query_pairs_mut()
+            .append_pair("offset", &offset.to_string())
+            .append_pair("limit", &limit.to_string());
+        let mut req = self.get(url);
+        if let Some(etag) = etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        let resp = self.execute(req).await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let etag = resp
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+        let page: DocumentsPage = parse(resp).await?;
+        Ok(Some((page.results, etag)))
+    }
+
+    /// Every document as stored, without reading it as a Document, which older ones may not be
+    pub async fn raw_documents(&self) -> Result<Vec<Value>, Report> {
+        let mut docs = Vec::new();
+        loop {
+            let page = self
+                .raw_documents_page(docs.len() as u32, api::PAGE_SIZE)
+                .await?;
+            let len = page.len() as u32;
+            docs.extend(page);
+            if len < api::PAGE_SIZE {
+                return Ok(docs);
+            }
+        }
+    }
+
+    /// Up to `limit` documents from `offset` on, in the server's order
+    pub async fn documents_page(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<document::Document>, Report> {
+        let mut docs = match &self.offline {
+            Some(docs) => docs
+                .iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .cloned()
+                .collect(),
+            None => self
+                .raw_documents_page(offset, limit)
+                .await?
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect::<Result<Vec<document::Document>, _>>()?,
+        };
+        self.open(&mut docs)?;
+        Ok(docs)
+    }
+
+    /// The page `documents_page` reads and its ETag, if the server sends one. Given the `etag`
+    /// of an earlier read, None if the server answers that the page hasn't changed.
+    pub async fn documents_page_since(
+        &self,
+        offset: u32,
+        limit: u32,
+        etag: Option<&str>,
+    ) -> Result<Option<(Vec<document::Document>, Option<String>)>, Report> {
+        if self.offline.is_some() {
+            let docs = self.documents_page(offset, limit).await?;
+            return Ok(Some((docs, None)));
+        }
+        let (page, etag) = match self.raw_documents_page_since(offset, limit, etag).await? {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+        let mut docs = page
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<document::Document>, _>>()?;
+        self.open(&mut docs)?;
+        Ok(Some((docs, etag)))
+    }
+
+    /// The latest revision of the document `id` is a revision of, None if there is no such
+    /// document
+    pub async fn latest_revision(&self, id: &str) -> Result<Option<document::Document>, Report> {
+        let doc = match self.get_document(id).await? {
+            Some(doc) if !doc.latest => doc,
+            found => return Ok(found),
+        };
+        let mut q = api::ApiQuery::new();
+        q.filter = Some(format!(
+            "(origid = \"{0}\" OR id = \"{0}\") AND latest = true",
+            doc.chain_id()
+        ));
+        q.sort = Some(vec!["revision:desc".to_owned()]);
+        q.limit = 1;
+        Ok(self.search(&q).await?.hits.pop().or(Some(doc)))
+    }
+
+    /// Add `next`, a revision of `prev`, then demote `prev` from latest, returning the task uids
+    pub async fn add_revision(
+        &self,
+        prev: &document::Document,
+        next: &document::Document,
+    ) -> Result<Vec<u64>, Report> {
+        let mut tasks = self.add_documents(std::slice::from_ref(next)).await?;
+        let demote = serde_json::json!([{ "id": prev.id, "latest": false }]);
+        tasks.extend(self.update_documents(&demote).await?);
+        Ok(tasks)
+    }
+
+    /// Delete the documents with `ids`, returning the task uid
+    pub async fn delete(&self, ids: &[String]) -> Result<u64, Report> {
+        self.enqueue(
+            self.post(self.index_url("documents/delete-batch")?)
+                .json(ids),
+        )
+        .await
+    }
+}
+
+/// Deserialize a response, turning error statuses into errors
+/// One page of the documents route
+#[derive(Debug, Deserialize)]
+struct DocumentsPage {
+    results: Vec<Value>,
+}
+
+async fn parse<T: DeserializeOwned>(resp: Response) -> Result<T, Report> {
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await?;
+        return Err(RequestFailed(format!("❌ Request failed with {}: {}", status, body)).into());
+    }
+    resp.json::<T>()
+        .await
+        .map_err(|e| eyre!("❌ Could not deserialize response: {:?}", e))
+}
+
+/// The delay a 429 or 503 response asks for, if given in seconds
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let secs = resp
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(secs).min(MAX_BACKOFF))
+}
+
+/// `host` as a base URL other paths are joined onto
+fn base_url(host: &str) -> Result<Url, Report> {
+    let mut url = Url::parse(host)?;
+    // Paths are joined onto the host, which drops its last segment unless it ends in /
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(url)
+}
+
+/// Whether `request` leaves the server as it was, searches included
+fn is_read(request: &Request) -> bool {
+    let (method, path) = (request.method(), request.url().path());
+    method == Method::GET
+        || method == Method::HEAD
+        || (method == Method::POST
+            && (path.ends_with("/search") || path.ends_with("/multi-search")))
+}
+
+/// Whether `request` only reads the index, and so can go to a replica
+fn is_index_read(request: &Request) -> bool {
+    request.url().path().contains("/indexes/") && is_read(request)
+}
@@ -0,0 +1,296 @@
+use crate::document::{string_or_list_string, Document};
+use crate::error::ParseError;
+use color_eyre::Report;
+use eyre::eyre;
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Settings read from the TOML config file, every section is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Server used unless --host or MEILI_HOST names another. A comma-separated list names the
+    /// primary first and then read replicas.
+    pub host: Option<String>,
+    /// The primary and read replicas, for when `host` is a single server or unset
+    pub hosts: HostsConfig,
+    /// Index notes are kept in, `notes` by default
+    pub index: Option<String>,
+    /// Editor and pager used unless the flags, $EDITOR or $PAGER name others
+    pub editor: Option<String>,
+    pub pager: Option<String>,
+    /// Where `dump` writes notes and `query --emit paths` finds them, by default the current
+    /// directory
+    pub notes_dir: Option<PathBuf>,
+    /// Zone dates are shown in: local (the default), utc or an offset like +02:00
+    pub timezone: Option<String>,
+    /// Scheme new document ids are generated in: uuid-b64 (the default), uuid4, uuid7 or ulid
+    pub id_scheme: Option<String>,
+    /// Interactive query keybindings, mapping action names to one or more keys
+    pub keys: BTreeMap<String, KeyList>,
+    /// Interactive query colors
+    pub theme: ThemeConfig,
+    /// Defaults for notes created with `new`
+    pub new: NewConfig,
+    /// How `daily` names and tags the day's journal note
+    pub daily: DailyConfig,
+    /// Frontmatter keys to read as Document fields on import, e.g. `created = "date"`
+    pub field_map: FieldMap,
+    /// Where `attach` keeps files
+    pub attachments: AttachmentsConfig,
+    /// Shell commands run when notes are imported, created or deleted
+    pub hooks: HooksConfig,
+    /// How imports upload notes, as `bench import` recommends
+    pub import: ImportConfig,
+    /// Settings for each --profile, by name
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// Refuse every request that could change the server, as --read-only does
+    pub read_only: bool,
+}
+
+/// Servers holding the index: writes go to the primary, and reads fall back to the first
+/// healthy replica when it is down
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HostsConfig {
+    /// Taken over by `host` if that is set
+    pub primary: Option<String>,
+    pub replicas: Vec<String>,
+}
+
+/// Upload requests an import has in flight at once unless configured
+pub const IMPORT_CONCURRENCY: usize = 8;
+
+/// Notes per upload request unless configured
+pub const IMPORT_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ImportConfig {
+    /// Notes per request where none needs checking against the index first: `import
+    /// --on-conflict overwrite`, `import-legacy-md` and `reimport`
+    pub batch_size: Option<usize>,
+    /// Requests in flight at once
+    pub concurrency: Option<usize>,
+}
+
+impl ImportConfig {
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(IMPORT_BATCH_SIZE).max(1)
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or(IMPORT_CONCURRENCY).max(1)
+    }
+}
+
+/// The hook run on each event, if any
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_import: Option<Hook>,
+    pub on_new: Option<Hook>,
+    pub on_delete: Option<Hook>,
+}
+
+/// A shell command, run through `sh -c`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Hook {
+    /// Run for every note, e.g. `on_new = "notify-send 'New note'"`
+    Command(String),
+    /// E.g. `on_import = { command = "./rebuild-site", tags = ["blog"] }`
+    Filtered {
+        command: String,
+        /// Only run for notes with at least one of these tags
+        #[serde(default, deserialize_with = "string_or_list_string")]
+        tags: Vec<String>,
+    },
+}
+
+impl Hook {
+    pub fn command(&self) -> &str {
+        match self {
+            Hook::Command(command) | Hook::Filtered { command, .. } => command,
+        }
+    }
+
+    /// Whether the hook runs for `doc`
+    pub fn applies(&self, doc: &Document) -> bool {
+        match self {
+            Hook::Command(_) => true,
+            Hook::Filtered { tags, .. } => {
+                tags.is_empty() || doc.tags.iter().any(|t| tags.contains(t))
+            }
+        }
+    }
+}
+
+/// Where `attach` copies files to
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AttachmentsConfig {
+    /// Defaults to ~/.local/share/meilizet/attachments
+    pub dir: Option<PathBuf>,
+}
+
+/// Frontmatter keys mapped onto the Document fields they stand for
+pub type FieldMap = BTreeMap<String, FieldMapping>;
+
+/// The Document field a frontmatter key stands for, and how to transform its value
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum FieldMapping {
+    /// Just the field, e.g. `created = "date"`
+    Field(String),
+    /// E.g. `category = { field = "tags", split = ",", lowercase = true }`
+    Transform {
+        field: String,
+        /// Split a string value on this separator into a list, trimming each item
+        #[serde(default)]
+        split: Option<String>,
+        /// Lowercase a string value, or each string in a list
+        #[serde(default)]
+        lowercase: bool,
+    },
+}
+
+impl FieldMapping {
+    /// Name of the Document field
+    pub fn field(&self) -> &str {
+        match self {
+            FieldMapping::Field(field) | FieldMapping::Transform { field, .. } => field,
+        }
+    }
+
+    /// `value` transformed for the field
+    pub fn transform(&self, value: Value) -> Value {
+        let (split, lowercase) = match self {
+            FieldMapping::Field(_) => return value,
+            FieldMapping::Transform {
+                split, lowercase, ..
+            } => (split, *lowercase),
+        };
+        let value = match (split, value) {
+            (Some(sep), Value::String(s)) => Value::Sequence(
+                s.split(sep.as_str())
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(Value::from)
+                    .collect(),
+            ),
+            (_, value) => value,
+        };
+        if !lowercase {
+            return value;
+        }
+        match value {
+            Value::String(s) => Value::String(s.to_lowercase()),
+            Value::Sequence(items) => Value::Sequence(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        Value::String(s) => Value::String(s.to_lowercase()),
+                        item => item,
+                    })
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+}
+
+/// Where `new` finds templates and who it names as the author
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NewConfig {
+    /// Directory of `<name>.md` templates, defaults to ~/.config/meilizet/templates
+    pub templates: Option<PathBuf>,
+    /// Substituted for `{{author}}`, defaults to $USER
+    pub author: Option<String>,
+}
+
+/// Built-in theme to start from plus per-widget overrides
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// One of dark (the default), light or monochrome
+    pub base: Option<String>,
+    /// Syntect theme for the preview pane, or "none" to leave it uncolored
+    pub syntax: Option<String>,
+    pub input: Option<StyleConfig>,
+    pub highlight: Option<StyleConfig>,
+    pub status: Option<StyleConfig>,
+    pub debug: Option<StyleConfig>,
+    pub error: Option<StyleConfig>,
+}
+
+/// Colors are names, `#rrggbb` or palette indexes, modifiers are e.g. bold or reversed
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StyleConfig {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub modifiers: Vec<String>,
+}
+
+/// Title and slug are strftime patterns, e.g. `journal/%Y-%m-%d`, formatted with today's date
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DailyConfig {
+    pub title: String,
+    pub slug: String,
+    #[serde(deserialize_with = "string_or_list_string")]
+    pub tags: Vec<String>,
+}
+
+impl Default for DailyConfig {
+    fn default() -> DailyConfig {
+        DailyConfig {
+            title: String::from("%A %-d %B %Y"),
+            slug: String::from("journal/%Y-%m-%d"),
+            tags: vec![String::from("journal")],
+        }
+    }
+}
+
+/// One or more key names, e.g. `"ctrl-n"` or `["down", "ctrl-n"]`
+#[derive(Debug, Default, Deserialize)]
+pub struct KeyList(#[serde(deserialize_with = "string_or_list_string")] pub Vec<String>);
+
+impl Config {
+    /// Default location of the config file, e.g. ~/.config/meilizet/config.toml
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("meilizet").join("config.toml"))
+    }
+
+    /// Load the config from `path`, or the default location if unset. A missing file yields
+    /// the default config, a malformed one is an error.
+    pub fn load(path: Option<&Path>) -> Result<Config, Report> {
+        let path = match path.map(PathBuf::from).or_else(Config::default_path) {
+            Some(p) => p,
+            None => return Ok(Config::default()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).map_err(|e| {
+                ParseError(format!(
+                    "❌ Failed to parse config {}: {}",
+                    path.display(),
+                    e
+                ))
+                .into()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(eyre!("❌ Failed to read config {}: {}", path.display(), e)),
+        }
+    }
+}
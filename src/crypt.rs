@@ -0,0 +1,247 @@
+use crate::auth;
+use crate::document::{self, Document};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use keyring::{Entry, Error};
+use std::convert::TryFrom;
+
+/// Start of an encrypted body, followed by the base64 of the nonce and ciphertext
+const PREFIX: &str = "mz-encrypted:v1:";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// Key bodies are encrypted with, and which way to apply it
+#[derive(Clone)]
+pub struct Crypt {
+    key: [u8; KEY_LEN],
+    /// Encrypt the body of every document uploaded, not just those already encrypted
+    pub encrypt: bool,
+    /// Decrypt the bodies of documents read
+    pub decrypt: bool,
+}
+
+// Keep the key out of debug output and logs
+impl std::fmt::Debug for Crypt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Crypt")
+            .field("encrypt", &self.encrypt)
+            .field("decrypt", &self.decrypt)
+            .finish()
+    }
+}
+
+/// Keyring entry of the body key for `profile`
+fn entry(profile: &str) -> Entry {
+    Entry::new(auth::SERVICE, &format!("{}/body-key", profile))
+}
+
+/// A fresh random key, base64 encoded
+pub fn generate_key() -> Result<String, Report> {
+    let mut key = [0u8; KEY_LEN];
+    getrandom::getrandom(&mut key).map_err(|e| eyre!("❌ Failed to generate a key: {}", e))?;
+    Ok(base64::encode(key))
+}
+
+/// The base64 body key stored for `profile`, None if there is none
+pub fn stored_key(profile: &str) -> Result<Option<String>, Report> {
+    match entry(profile).get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(Error::NoEntry) => Ok(None),
+        Err(e) => Err(eyre!(
+            "❌ Failed to read the body key for profile {} from the keyring: {}",
+            profile,
+            e
+        )),
+    }
+}
+
+/// Store the base64 body `key` for `profile`, replacing any stored before
+pub fn store_key(profile: &str, key: &str) -> Result<(), Report> {
+    decode_key(key)?;
+    entry(profile).set_password(key).map_err(|e| {
+        eyre!(
+            "❌ Failed to store the body key for profile {} in the keyring: {}",
+            profile,
+            e
+        )
+    })
+}
+
+fn decode_key(key: &str) -> Result<[u8; KEY_LEN], Report> {
+    let bytes = base64::decode(key.trim()).map_err(|_| eyre!("❌ The body key isn't base64"))?;
+    <[u8; KEY_LEN]>::try_from(bytes.as_slice()).map_err(|_| {
+        eyre!(
+            "❌ The body key must be {} bytes, not {}",
+            KEY_LEN,
+            bytes.len()
+        )
+    })
+}
+
+/// Whether `body` is encrypted
+pub fn is_encrypted(body: &str) -> bool {
+    body.starts_with(PREFIX)
+}
+
+impl Crypt {
+    /// Apply the body key stored for `profile`
+    pub fn for_profile(profile: &str, encrypt: bool, decrypt: bool) -> Result<Crypt, Report> {
+        let key = match stored_key(profile)? {
+            Some(key) => decode_key(&key)?,
+            None => bail!(
+                "❌ No body key for profile {}, create one with `mz auth body-key`",
+                profile
+            ),
+        };
+        Ok(Crypt {
+            key,
+            encrypt,
+            decrypt,
+        })
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&Key::from(self.key))
+    }
+
+    /// `body` encrypted under a fresh nonce
+    pub fn encrypt_body(&self, body: &str) -> Result<String, Report> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| eyre!("❌ Failed to make a nonce: {}", e))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.cipher()
+                .encrypt(&XNonce::from(nonce), body.as_bytes())
+                .map_err(|_| eyre!("❌ Failed to encrypt a body"))?,
+        );
+        Ok(format!("{}{}", PREFIX, base64::encode(sealed)))
+    }
+
+    /// The plain text of the encrypted `body`
+    pub fn decrypt_body(&self, body: &str) -> Result<String, Report> {
+        let sealed = base64::decode(&body[PREFIX.len()..])
+            .map_err(|_| eyre!("❌ An encrypted body is corrupt"))?;
+        if sealed.len() < NONCE_LEN {
+            bail!("❌ An encrypted body is corrupt");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = <[u8; NONCE_LEN]>::try_from(nonce)
+            .map_err(|_| eyre!("❌ An encrypted body is corrupt"))?;
+        let plain = self
+            .cipher()
+            .decrypt(&XNonce::from(nonce), ciphertext)
+            .map_err(|_| {
+                eyre!("❌ Failed to decrypt a body, is this the key it was encrypted with?")
+            })?;
+        String::from_utf8(plain).map_err(|_| eyre!("❌ A decrypted body isn't UTF-8"))
+    }
+
+    /// Encrypt `doc`'s body if it should be and isn't yet, keeping its length for filters
+    pub fn seal(&self, doc: &mut Document) -> Result<(), Report> {
+        if (self.encrypt || doc.encrypted) && !is_encrypted(&doc.body) {
+            let (words, minutes) = document::reading_time(&doc.body);
+            doc.word_count = words;
+            doc.reading_minutes = minutes;
+            doc.body = self.encrypt_body(&doc.body)?;
+            doc.encrypted = true;
+        }
+        Ok(())
+    }
+
+    /// Decrypt `doc`'s body if it is encrypted and reads are to be decrypted
+    pub fn open(&self, doc: &mut Document) -> Result<(), Report> {
+        if self.decrypt && is_encrypted(&doc.body) {
+            doc.body = self.decrypt_body(&doc.body)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fail rather than upload the decrypted body of a document that was encrypted without the key
+/// to encrypt it again
+pub fn check_sealed(doc: &Document) -> Result<(), Report> {
+    if doc.encrypted && !is_encrypted(&doc.body) {
+        bail!(
+            "❌ Document {} was encrypted, pass --encrypt to upload it encrypted again",
+            doc.id
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crypt(byte: u8) -> Crypt {
+        Crypt {
+            key: [byte; KEY_LEN],
+            encrypt: true,
+            decrypt: true,
+        }
+    }
+
+    #[test]
+    fn bodies_round_trip() {
+        let crypt = crypt(1);
+        let sealed = crypt.encrypt_body("A secret, with ünïcode").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("secret"));
+        assert_eq!(
+            crypt.decrypt_body(&sealed).unwrap(),
+            "A secret, with ünïcode"
+        );
+        // A fresh nonce each time
+        assert_ne!(
+            crypt.encrypt_body("A secret").unwrap(),
+            crypt.encrypt_body("A secret").unwrap()
+        );
+    }
+
+    #[test]
+    fn documents_round_trip_keeping_their_length() {
+        let crypt = crypt(1);
+        let mut doc = Document {
+            body: "one two three".to_owned(),
+            ..Document::new()
+        };
+        crypt.seal(&mut doc).unwrap();
+        assert!(doc.encrypted && is_encrypted(&doc.body));
+        assert_eq!(doc.word_count, 3);
+        let sealed = doc.body.clone();
+        crypt.seal(&mut doc).unwrap();
+        assert_eq!(doc.body, sealed, "sealing twice encrypts once");
+        crypt.open(&mut doc).unwrap();
+        assert_eq!(doc.body, "one two three");
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let sealed = crypt(1).encrypt_body("A secret").unwrap();
+        assert!(crypt(2).decrypt_body(&sealed).is_err());
+    }
+
+    #[test]
+    fn truncated_body_fails() {
+        let sealed = crypt(1).encrypt_body("A secret").unwrap();
+        assert!(crypt(1).decrypt_body(&sealed[..sealed.len() - 4]).is_err());
+        assert!(crypt(1).decrypt_body(&format!("{}AAAA", PREFIX)).is_err());
+        assert!(crypt(1).decrypt_body(PREFIX).is_err());
+    }
+
+    #[test]
+    fn check_sealed_refuses_decrypted_bodies_only() {
+        let mut doc = Document {
+            body: "plain".to_owned(),
+            ..Document::new()
+        };
+        assert!(check_sealed(&doc).is_ok());
+        doc.encrypted = true;
+        assert!(check_sealed(&doc).is_err());
+        crypt(1).seal(&mut doc).unwrap();
+        assert!(check_sealed(&doc).is_ok());
+    }
+}
@@ -1,13 +1,96 @@
-use chrono::{offset, DateTime, NaiveDateTime, Utc};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, TimeZone,
+    Utc, Weekday,
+};
 use color_eyre::Report;
 use eyre::{eyre, Result};
 use serde::{de, Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::{fmt, marker::PhantomData};
 
+/// Zone dates are shown in and times without an offset are read in, the system's unless set
+static ZONE: Mutex<Option<Zone>> = Mutex::new(None);
+
+/// Times of day without an offset that a Date parses, read in the current zone
+const NAIVE_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+];
+
+/// A point in time, kept as epoch seconds so Meilisearch can filter and sort on it
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Date(i64);
 
+/// A timezone to show dates in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    /// The system's, following daylight saving
+    Local,
+    Utc,
+    Fixed(FixedOffset),
+}
+
+impl FromStr for Zone {
+    type Err = Report;
+
+    /// `local`, `utc` or an offset like `+02:00` or `-0530`
+    fn from_str(s: &str) -> Result<Zone, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(Zone::Local),
+            "utc" | "z" => Ok(Zone::Utc),
+            _ => DateTime::parse_from_str(&format!("2000-01-01 00:00 {}", s), "%Y-%m-%d %H:%M %z")
+                .map(|d| Zone::Fixed(*d.offset()))
+                .map_err(|_| {
+                    eyre!(
+                        "❌ Unknown timezone {}, expected local, utc or an offset like +02:00",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl Zone {
+    /// The zone set with `set`, the system's by default
+    pub fn current() -> Zone {
+        ZONE.lock().unwrap().unwrap_or(Zone::Local)
+    }
+
+    /// Show dates in `zone` from now on
+    pub fn set(zone: Zone) {
+        *ZONE.lock().unwrap() = Some(zone);
+    }
+
+    /// Offset from UTC at the UTC time `utc`
+    fn offset_at(self, utc: &NaiveDateTime) -> FixedOffset {
+        match self {
+            Zone::Local => Local.offset_from_utc_datetime(utc).fix(),
+            Zone::Utc => Utc.offset_from_utc_datetime(utc).fix(),
+            Zone::Fixed(offset) => offset,
+        }
+    }
+
+    /// Epoch seconds of the wall clock time `naive` here, the earlier if it happens twice
+    fn resolve(self, naive: &NaiveDateTime) -> Option<i64> {
+        match self {
+            Zone::Local => Local
+                .from_local_datetime(naive)
+                .earliest()
+                .map(|d| d.timestamp()),
+            Zone::Utc => Some(naive.timestamp()),
+            Zone::Fixed(offset) => offset
+                .from_local_datetime(naive)
+                .earliest()
+                .map(|d| d.timestamp()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DateRange {
     pub start: DateTime<Utc>,
@@ -18,37 +101,234 @@ impl Date {
     pub fn new(d: i64) -> Date {
         Date(d)
     }
+
+    pub fn now() -> Date {
+        Date(Utc::now().timestamp())
+    }
+
+    /// The wall clock time `naive` in the current zone
+    pub fn from_naive(naive: &NaiveDateTime) -> Option<Date> {
+        Zone::current().resolve(naive).map(Date)
+    }
+
+    /// Epoch seconds
+    pub fn timestamp(&self) -> i64 {
+        self.0
+    }
+
+    /// This time in the current zone
+    pub fn in_zone(&self) -> DateTime<FixedOffset> {
+        let utc = NaiveDateTime::from_timestamp(self.0, 0);
+        DateTime::from_utc(utc, Zone::current().offset_at(&utc))
+    }
 }
 
 impl fmt::Display for Date {
+    /// RFC 3339 in the current zone
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Create a NaiveDateTime from the timestamp
-        let naive = NaiveDateTime::from_timestamp(self.0, 0);
-
-        // Create a normal DateTime from the NaiveDateTime
-        let datetime: DateTime<Utc> = DateTime::from_utc(naive, Utc);
-
-        // Format the datetime how you want
-        write!(f, "{}", datetime.with_timezone(&offset::Local).to_rfc3339())
+        write!(f, "{}", self.in_zone().to_rfc3339())
     }
 }
 
 impl FromStr for Date {
     type Err = Report;
 
+    /// RFC 3339, a date and time without an offset like `2021-04-02 14:30` read in the current
+    /// zone, a bare date taken as midnight, or epoch seconds
     fn from_str(s: &str) -> Result<Date, Self::Err> {
+        let s = s.trim();
         if let Ok(rfc3339) = DateTime::parse_from_rfc3339(s) {
-            Ok(Date::new(rfc3339.timestamp()))
-        } else if let Ok(s) = DateTime::parse_from_str(s, &String::from("%Y-%m-%dT%T%z")) {
-            Ok(Date::new(s.timestamp()))
-        } else if let Ok(s) = s.parse::<i64>() {
-            Ok(Date::new(s))
-        } else {
-            Err(eyre!("❌ Failed to convert {} to str", s))
+            return Ok(Date::new(rfc3339.timestamp()));
+        }
+        if let Ok(d) = DateTime::parse_from_str(s, "%Y-%m-%dT%T%z") {
+            return Ok(Date::new(d.timestamp()));
+        }
+        if let Ok(epoch) = s.parse::<i64>() {
+            return Ok(Date::new(epoch));
+        }
+        let naive = NAIVE_FORMATS
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()
+                    .map(|d| d.and_hms(0, 0, 0))
+            });
+        match naive.and_then(|n| Date::from_naive(&n)) {
+            Some(date) => Ok(date),
+            None => Err(eyre!(
+                "❌ Failed to read {} as a date, expected RFC 3339, YYYY-MM-DD HH:MM or epoch seconds",
+                s
+            )),
         }
     }
 }
 
+/// Where to take the date of a note whose frontmatter has none
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateFrom {
+    /// The file's modification time
+    Mtime,
+    /// A date in the file name, e.g. `2021-04-02-foo.md`, taken as midnight
+    Filename,
+    /// The time of the import
+    Now,
+}
+
+impl FromStr for DateFrom {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<DateFrom, Self::Err> {
+        match s {
+            "mtime" => Ok(DateFrom::Mtime),
+            "filename" => Ok(DateFrom::Filename),
+            "now" => Ok(DateFrom::Now),
+            _ => Err(eyre!(
+                "❌ Unknown date source {}, expected mtime, filename or now",
+                s
+            )),
+        }
+    }
+}
+
+impl DateFrom {
+    /// The date to give the note at `path`
+    pub fn date_for(self, path: &Path) -> Result<Date, Report> {
+        match self {
+            DateFrom::Mtime => {
+                let mtime: DateTime<Utc> = fs::metadata(path)?.modified()?.into();
+                Ok(Date::new(mtime.timestamp()))
+            }
+            DateFrom::Filename => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let day = name
+                    .char_indices()
+                    .filter_map(|(i, _)| name.get(i..i + 10))
+                    .find_map(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+                    .ok_or_else(|| eyre!("❌ No YYYY-MM-DD date in {}", path.display()))?;
+                Date::from_naive(&day.and_hms(0, 0, 0))
+                    .ok_or_else(|| eyre!("❌ No midnight on {}", day))
+            }
+            DateFrom::Now => Ok(Date::now()),
+        }
+    }
+}
+
+/// Parse a date in words relative to now, in the current zone: `today`, `yesterday`, a weekday
+/// such as `last tuesday` (the latest before today) or `this tuesday` (the one of this week),
+/// `this week`, `last month`, `last year` and the like, or `3 months ago`, `a week ago`. Calendar periods cover their whole span, `ago` the
+/// day it lands on, or the instant for hours.
+pub fn parse_natural(s: &str) -> Result<DateRange, Report> {
+    let now = Date::now().in_zone().naive_local();
+    let (start, end) = natural_at(s, now).ok_or_else(|| {
+        eyre!(
+            "❌ Can't read {} as a date, try e.g. yesterday, last tuesday, last month or 3 weeks ago",
+            s
+        )
+    })?;
+    let utc = |naive: NaiveDateTime| {
+        Date::from_naive(&naive)
+            .map(|d| DateTime::from_utc(NaiveDateTime::from_timestamp(d.0, 0), Utc))
+    };
+    match (utc(start), utc(end)) {
+        (Some(start), Some(end)) => Ok(DateRange { start, end }),
+        _ => Err(eyre!("❌ {} falls in a gap in the timezone", s)),
+    }
+}
+
+/// First and last second of `s` relative to `now`, both wall clock times
+fn natural_at(s: &str, now: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let words: Vec<_> = s.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let words: Vec<_> = words.iter().map(String::as_str).collect();
+    let today = now.date();
+    let day = |d: NaiveDate| (d.and_hms(0, 0, 0), d.and_hms(23, 59, 59));
+    let days =
+        |first: NaiveDate, last: NaiveDate| (first.and_hms(0, 0, 0), last.and_hms(23, 59, 59));
+    let month_start = |d: NaiveDate| NaiveDate::from_ymd(d.year(), d.month(), 1);
+    let month_end = |d: NaiveDate| match d.month() {
+        12 => NaiveDate::from_ymd(d.year(), 12, 31),
+        m => NaiveDate::from_ymd(d.year(), m + 1, 1).pred(),
+    };
+    let week_start = |d: NaiveDate| d - Duration::days(d.weekday().num_days_from_monday().into());
+
+    match words.as_slice() {
+        ["today"] | ["now"] => Some(day(today)),
+        ["yesterday"] => Some(day(today.pred())),
+        ["tomorrow"] => Some(day(today.succ())),
+        ["this", "week"] => Some(days(
+            week_start(today),
+            week_start(today) + Duration::days(6),
+        )),
+        ["last", "week"] => {
+            let start = week_start(today) - Duration::weeks(1);
+            Some(days(start, start + Duration::days(6)))
+        }
+        ["this", "month"] => Some(days(month_start(today), month_end(today))),
+        ["last", "month"] => {
+            let last = month_start(today).pred();
+            Some(days(month_start(last), last))
+        }
+        ["this", "year"] => Some(days(
+            NaiveDate::from_ymd(today.year(), 1, 1),
+            NaiveDate::from_ymd(today.year(), 12, 31),
+        )),
+        ["last", "year"] => Some(days(
+            NaiveDate::from_ymd(today.year() - 1, 1, 1),
+            NaiveDate::from_ymd(today.year() - 1, 12, 31),
+        )),
+        ["this", weekday] => {
+            let weekday = weekday.parse::<Weekday>().ok()?;
+            Some(day(
+                week_start(today) + Duration::days(weekday.num_days_from_monday().into())
+            ))
+        }
+        ["last", weekday] | [weekday] => {
+            let weekday = weekday.parse::<Weekday>().ok()?;
+            let mut d = today.pred();
+            while d.weekday() != weekday {
+                d = d.pred();
+            }
+            Some(day(d))
+        }
+        [n, unit, "ago"] => {
+            let n: i64 = match *n {
+                "a" | "an" | "one" => 1,
+                n => n.parse().ok()?,
+            };
+            let ago = match unit.trim_end_matches('s') {
+                "hour" => return Some((now - Duration::hours(n), now - Duration::hours(n))),
+                "day" => Duration::days(n),
+                "week" => Duration::weeks(n),
+                "month" => Duration::days(n * 30),
+                "year" => Duration::days(n * 365),
+                _ => return None,
+            };
+            Some(day((now - ago).date()))
+        }
+        _ => None,
+    }
+}
+
+/// Parse an age like those of the filter grammar, e.g. `12h`, `30d`, `2w`, `6m` or `1y`, a
+/// month being 30 days and a year 365
+pub fn parse_age(s: &str) -> Result<chrono::Duration, Report> {
+    let unit = s.chars().last().unwrap_or_default();
+    let n = s[..s.len() - unit.len_utf8().min(s.len())]
+        .parse::<i64>()
+        .map_err(|_| eyre!("❌ Invalid age {}, expected e.g. 30d, 2w, 6m or 1y", s))?;
+    match unit {
+        'h' => Ok(chrono::Duration::hours(n)),
+        'd' => Ok(chrono::Duration::days(n)),
+        'w' => Ok(chrono::Duration::weeks(n)),
+        'm' => Ok(chrono::Duration::days(n * 30)),
+        'y' => Ok(chrono::Duration::days(n * 365)),
+        _ => Err(eyre!(
+            "❌ Invalid age {}, expected e.g. 30d, 2w, 6m or 1y",
+            s
+        )),
+    }
+}
+
 /// Support Deserializing a date from either a string or i64
 pub fn date_deserializer<'de, D>(deserializer: D) -> Result<Date, D::Error>
 where
@@ -60,14 +340,14 @@ where
         type Value = Date;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("Epoch seconds as i64 or RFC 3339 time string")
+            formatter.write_str("epoch seconds, an RFC 3339 time or YYYY-MM-DD HH:MM")
         }
 
         fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
         where
             E: de::Error,
         {
-            Ok(Date::from_str(value).unwrap())
+            Date::from_str(value).map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
         }
 
         fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
@@ -101,3 +381,34 @@ where
 
     deserializer.deserialize_any(StringOrVec(PhantomData))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noon on Friday 2026-10-16
+    fn friday() -> NaiveDateTime {
+        NaiveDate::from_ymd(2026, 10, 16).and_hms(12, 0, 0)
+    }
+
+    fn first_day(s: &str) -> NaiveDate {
+        natural_at(s, friday()).unwrap().0.date()
+    }
+
+    #[test]
+    fn this_weekday_is_the_one_of_the_current_week() {
+        assert_eq!(first_day("this friday"), NaiveDate::from_ymd(2026, 10, 16));
+        assert_eq!(first_day("this monday"), NaiveDate::from_ymd(2026, 10, 12));
+        assert_eq!(first_day("this sunday"), NaiveDate::from_ymd(2026, 10, 18));
+    }
+
+    #[test]
+    fn last_weekday_is_the_latest_before_today() {
+        assert_eq!(first_day("last friday"), NaiveDate::from_ymd(2026, 10, 9));
+        assert_eq!(first_day("friday"), NaiveDate::from_ymd(2026, 10, 9));
+        assert_eq!(
+            first_day("last thursday"),
+            NaiveDate::from_ymd(2026, 10, 15)
+        );
+    }
+}
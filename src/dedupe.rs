@@ -0,0 +1,201 @@
+use crate::document::Document;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Words per shingle when comparing bodies
+const SHINGLE: usize = 3;
+
+/// Shingles found in more documents than this, e.g. from a shared template, say nothing about
+/// whether two documents are duplicates and are ignored
+const COMMON: usize = 50;
+
+/// Two documents that are likely duplicates
+#[derive(Debug)]
+pub struct Duplicate {
+    /// Indexes into the documents compared, `a` the newer of the two
+    pub a: usize,
+    pub b: usize,
+    /// Whether their titles are the same once normalized
+    pub same_title: bool,
+    /// Jaccard similarity of their bodies' shingles, between 0 and 1
+    pub similarity: f64,
+}
+
+/// Lowercase alphanumeric words, so titles differing only in case and punctuation compare equal
+pub fn normalize(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Hashes of every run of `SHINGLE` consecutive words in `body`, case-insensitive
+fn shingles(body: &str) -> HashSet<u64> {
+    let words: Vec<_> = body.split_whitespace().map(|w| w.to_lowercase()).collect();
+    words
+        .windows(SHINGLE.min(words.len()).max(1))
+        .map(|w| {
+            let mut hasher = DefaultHasher::new();
+            w.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Pairs of `docs` with the same normalized title or bodies at least `threshold` similar, most
+/// similar first
+pub fn find(docs: &[Document], threshold: f64) -> Vec<Duplicate> {
+    let sets: Vec<_> = docs.iter().map(|d| shingles(&d.body)).collect();
+
+    // Only pairs sharing a shingle can be similar, so count those rather than comparing every pair
+    let mut holders: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, set) in sets.iter().enumerate() {
+        for shingle in set {
+            holders.entry(*shingle).or_default().push(i);
+        }
+    }
+    let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+    for docs in holders.values().filter(|d| d.len() <= COMMON) {
+        for (n, &i) in docs.iter().enumerate() {
+            for &j in &docs[n + 1..] {
+                *shared.entry((i, j)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut titles: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, doc) in docs.iter().enumerate() {
+        let title = normalize(&doc.title);
+        if !title.is_empty() {
+            titles.entry(title).or_default().push(i);
+        }
+    }
+    for same in titles.values() {
+        for (n, &i) in same.iter().enumerate() {
+            for &j in &same[n + 1..] {
+                shared.entry((i, j)).or_insert(0);
+            }
+        }
+    }
+
+    let mut found: Vec<_> = shared
+        .into_iter()
+        .filter_map(|((i, j), shared)| {
+            let union = sets[i].len() + sets[j].len() - shared;
+            let similarity = if union == 0 {
+                1.0
+            } else {
+                shared as f64 / union as f64
+            };
+            let same_title = normalize(&docs[i].title) == normalize(&docs[j].title)
+                && !docs[i].title.trim().is_empty();
+            if !same_title && similarity < threshold {
+                return None;
+            }
+            let (a, b) = if docs[i].date.timestamp() >= docs[j].date.timestamp() {
+                (i, j)
+            } else {
+                (j, i)
+            };
+            Some(Duplicate {
+                a,
+                b,
+                same_title,
+                similarity,
+            })
+        })
+        .collect();
+    found.sort_by(|x, y| y.similarity.partial_cmp(&x.similarity).unwrap());
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::Date;
+
+    fn doc(title: &str, body: &str, date: i64) -> Document {
+        Document {
+            title: title.to_owned(),
+            body: body.to_owned(),
+            date: Date::new(date),
+            ..Document::new()
+        }
+    }
+
+    /// Twenty distinct words, giving eighteen shingles
+    fn words(last: &str) -> String {
+        let mut words: Vec<_> = (0..19).map(|n| format!("word{}", n)).collect();
+        words.push(last.to_owned());
+        words.join(" ")
+    }
+
+    #[test]
+    fn exact_duplicates_are_fully_similar() {
+        let docs = [doc("One", &words("end"), 0), doc("Two", &words("end"), 0)];
+        let found = find(&docs, 0.8);
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].same_title);
+        assert_eq!(found[0].similarity, 1.0);
+    }
+
+    #[test]
+    fn near_duplicates_are_found_above_the_threshold_only() {
+        // One changed word out of eighteen shingles leaves 17 shared of 19
+        let docs = [doc("One", &words("end"), 0), doc("Two", &words("fin"), 0)];
+        let found = find(&docs, 0.85);
+        assert_eq!(found.len(), 1);
+        assert!((found[0].similarity - 17.0 / 19.0).abs() < 1e-9);
+        assert!(find(&docs, 0.9).is_empty());
+    }
+
+    #[test]
+    fn unrelated_bodies_are_not_duplicates() {
+        let docs = [
+            doc("One", &words("end"), 0),
+            doc("Two", "nothing in common with the other one at all", 0),
+        ];
+        assert!(find(&docs, 0.1).is_empty());
+    }
+
+    #[test]
+    fn empty_bodies_are_duplicates_only_with_equal_titles() {
+        let docs = [doc("Shopping list", "", 0), doc("shopping  List!", "", 0)];
+        let found = find(&docs, 0.8);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].same_title);
+        assert_eq!(found[0].similarity, 1.0);
+
+        let docs = [doc("Shopping list", "", 0), doc("Packing list", "", 0)];
+        assert!(find(&docs, 0.8).is_empty());
+    }
+
+    #[test]
+    fn newer_document_is_a() {
+        let docs = [
+            doc("One", &words("end"), 100),
+            doc("Two", &words("end"), 200),
+        ];
+        let found = find(&docs, 0.8);
+        assert_eq!((found[0].a, found[0].b), (1, 0));
+
+        let docs = [
+            doc("One", &words("end"), 200),
+            doc("Two", &words("end"), 100),
+        ];
+        let found = find(&docs, 0.8);
+        assert_eq!((found[0].a, found[0].b), (0, 1));
+    }
+
+    #[test]
+    fn titles_normalize_case_and_punctuation() {
+        assert_eq!(
+            normalize("  Rust: Ownership & Borrowing! "),
+            "rust ownership borrowing"
+        );
+        assert_eq!(normalize("--"), "");
+    }
+}
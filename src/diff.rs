@@ -0,0 +1,101 @@
+use crate::document::{Document, SerializationType};
+use similar::TextDiff;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+
+/// Every attribute off
+const RESET: &str = "\x1b[m";
+
+/// `doc` as `dump` writes it, frontmatter and body
+fn disk(doc: &Document) -> String {
+    let mut doc = doc.clone();
+    doc.serialization_type = SerializationType::Disk;
+    doc.to_string()
+}
+
+/// `line` of a unified diff coloured by what it is: headers bold, hunk ranges cyan, removed
+/// lines red and added ones green
+fn paint(line: &str) -> String {
+    let color = if line.starts_with("---") || line.starts_with("+++") {
+        BOLD
+    } else if line.starts_with("@@") {
+        CYAN
+    } else if line.starts_with('-') {
+        RED
+    } else if line.starts_with('+') {
+        GREEN
+    } else {
+        return line.to_owned();
+    };
+    format!("{}{}{}", color, line, RESET)
+}
+
+/// Unified diff of `old` against `new` as `dump` writes them, headed with `old_name` and
+/// `new_name`, coloured if `color`. Empty when the two write the same file.
+pub fn render(
+    old: &Document,
+    new: &Document,
+    old_name: &str,
+    new_name: &str,
+    color: bool,
+) -> String {
+    let (old, new) = (disk(old), disk(new));
+    let diff = TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .header(old_name, new_name)
+        .to_string();
+    if !color {
+        return diff;
+    }
+    diff.lines().map(|line| paint(line) + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(body: &str) -> Document {
+        Document {
+            id: "n1".to_owned(),
+            title: "Note".to_owned(),
+            body: body.to_owned(),
+            ..Document::new()
+        }
+    }
+
+    #[test]
+    fn same_file_renders_nothing() {
+        let doc = note("one\ntwo\n");
+        assert_eq!(render(&doc, &doc.clone(), "a", "b", true), "");
+    }
+
+    #[test]
+    fn changed_lines_are_removed_and_added() {
+        let diff = render(&note("one\ntwo\n"), &note("one\nthree\n"), "a", "b", false);
+        assert!(diff.starts_with("--- a\n+++ b\n@@ "), "{}", diff);
+        assert!(diff.contains("\n-two\n+three\n"), "{}", diff);
+        assert!(diff.contains("\n one\n"), "{}", diff);
+    }
+
+    #[test]
+    fn colored_diff_paints_each_kind_of_line() {
+        let diff = render(&note("one\ntwo\n"), &note("one\nthree\n"), "a", "b", true);
+        assert!(diff.starts_with(&format!("{}--- a{}\n", BOLD, RESET)));
+        assert!(diff.contains(&format!("{}-two{}\n", RED, RESET)));
+        assert!(diff.contains(&format!("{}+three{}\n", GREEN, RESET)));
+        assert!(diff.contains("\n one\n"));
+    }
+
+    #[test]
+    fn paint_leaves_context_lines_alone() {
+        assert_eq!(paint(" context"), " context");
+        assert_eq!(
+            paint("@@ -1 +1 @@"),
+            format!("{}@@ -1 +1 @@{}", CYAN, RESET)
+        );
+        assert_eq!(paint("+++ b"), format!("{}+++ b{}", BOLD, RESET));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::client::Client;
+use crate::index::Settings;
+use crate::server::Version;
+use crate::style;
+use color_eyre::Report;
+use eyre::bail;
+use reqwest::StatusCode;
+use std::fmt;
+
+/// Oldest server version with multi-search and the task, swap and filter features `mz` uses
+pub const MIN_VERSION: (u32, u32) = (1, 1);
+
+/// What each check looks at, in the order they run
+const CHECKS: &[&str] = &[
+    "connection",
+    "API key",
+    "server version",
+    "index",
+    "index settings",
+];
+
+/// Outcome of one check
+#[derive(Debug)]
+pub enum Check {
+    Passed(String),
+    Failed {
+        problem: String,
+        fix: String,
+    },
+    /// Not run because an earlier check it depends on failed
+    Skipped(String),
+}
+
+impl Check {
+    pub fn failed(&self) -> bool {
+        matches!(self, Check::Failed { .. })
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Passed(what) => write!(f, "✅ {}", what),
+            Check::Failed { problem, fix } => write!(f, "❌ {}\n   {}", problem, fix),
+            Check::Skipped(what) => write!(f, "-  Skipped: {}", what),
+        }
+    }
+}
+
+/// `major.minor` of a version like `1.3.0` or `v0.30.0-rc.1`
+fn major_minor(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim_start_matches('v').split(&['.', '-'][..]);
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// Check `client` can reach the server, is allowed in, talks to a server new enough and finds
+/// the index with the settings the filter grammar and sorting need. Checks that can't run once
+/// an earlier one fails are skipped.
+pub async fn run(client: &Client, host: &str) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let skip_rest = |mut checks: Vec<Check>| {
+        for what in &CHECKS[checks.len()..] {
+            checks.push(Check::Skipped((*what).into()));
+        }
+        checks
+    };
+
+    let health = match client.url("health") {
+        Ok(url) => client.execute(client.get(url)).await,
+        Err(e) => Err(e),
+    };
+    match health {
+        Ok(resp) if resp.status().is_success() => {
+            checks.push(Check::Passed(format!("Reached Meilisearch at {}", host)))
+        }
+        Ok(resp) => {
+            checks.push(Check::Failed {
+                problem: format!("Meilisearch at {} answers {}", host, resp.status()),
+                fix: "Check its logs, it may still be starting or out of disk".into(),
+            });
+            return skip_rest(checks);
+        }
+        Err(e) => {
+            checks.push(Check::Failed {
+                problem: format!("Can't reach {}: {}", host, e),
+                fix: "Start Meilisearch, or point --host or MEILI_HOST at it".into(),
+            });
+            return skip_rest(checks);
+        }
+    }
+
+    // /health is public, /version needs a key when the server has a master key
+    let version = match auth(client).await {
+        Ok(version) => {
+            checks.push(Check::Passed("API key accepted".into()));
+            version
+        }
+        Err(check) => {
+            checks.push(check);
+            return skip_rest(checks);
+        }
+    };
+
+    checks.push(match major_minor(&version.pkg_version) {
+        Some(v) if v < MIN_VERSION => Check::Failed {
+            problem: format!(
+                "Meilisearch {} is older than the {}.{} mz needs",
+                version.pkg_version, MIN_VERSION.0, MIN_VERSION.1
+            ),
+            fix: "Upgrade the server, moving the data over with `mz backup` and `mz restore`"
+                .into(),
+        },
+        Some(_) => Check::Passed(format!("Meilisearch {} is supported", version.pkg_version)),
+        None => Check::Passed(format!(
+            "Meilisearch {} is of unknown age, assuming it is supported",
+            version.pkg_version
+        )),
+    });
+
+    let settings = match client.settings().await {
+        Ok(settings) => {
+            checks.push(Check::Passed(format!("Index {} exists", client.index())));
+            settings
+        }
+        Err(e) => {
+            checks.push(Check::Failed {
+                problem: format!("Can't read index {}: {}", client.index(), e),
+                fix: "Create it with `mz init`".into(),
+            });
+            return skip_rest(checks);
+        }
+    };
+
+    let diff = settings.diff(&Settings::expected());
+    checks.push(if diff.is_missing() {
+        Check::Failed {
+            problem: format!(
+                "Index {} lacks attributes filters and sorting use:\n{}",
+                client.index(),
+                diff.to_string()
+                    .lines()
+                    .map(|l| format!("   {}", l))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            fix: "Apply them with `mz init`".into(),
+        }
+    } else {
+        Check::Passed("Filterable, sortable and searchable attributes are set".into())
+    });
+    checks
+}
+
+/// Fetch the server version, which needs the key to be accepted
+async fn auth(client: &Client) -> Result<Version, Check> {
+    let failed = |problem: String| Check::Failed {
+        problem,
+        fix: "Store a key with `mz auth login`, or pass it with --key or MEILI_KEY".into(),
+    };
+    let unreadable = |e: &dyn fmt::Display| Check::Failed {
+        problem: format!("Can't read the server version: {}", e),
+        fix: "Check --host points at Meilisearch and not another service".into(),
+    };
+    let url = client.url("version").map_err(|e| unreadable(&e))?;
+    let resp = client
+        .execute(client.get(url))
+        .await
+        .map_err(|e| unreadable(&e))?;
+    match resp.status() {
+        StatusCode::UNAUTHORIZED => Err(failed("The server wants an API key".into())),
+        StatusCode::FORBIDDEN => Err(failed("The server rejects the API key".into())),
+        s if !s.is_success() => Err(failed(format!(
+            "Reading the server version failed with {}",
+            s
+        ))),
+        _ => resp.json().await.map_err(|e| unreadable(&e)),
+    }
+}
+
+/// Run every check, printing each, and fail if any did
+pub async fn print(client: &Client, host: &str) -> Result<(), Report> {
+    let checks = run(client, host).await;
+    for check in &checks {
+        println!("{}", style::mark(&check.to_string()));
+    }
+    let failed = checks.iter().filter(|c| c.failed()).count();
+    if failed > 0 {
+        bail!("❌ {} of {} checks failed", failed, checks.len());
+    }
+    Ok(())
+}
@@ -1,12 +1,17 @@
-use crate::date::{date_deserializer, Date};
-use eyre::Result;
-use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
-use std::io::{Error, ErrorKind};
+use crate::attachment::Attachment;
+use crate::config::FieldMap;
+use crate::date::{date_deserializer, Date, DateFrom};
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::io::Error;
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::{fmt, fs, io, marker::PhantomData};
+use tracing::warn;
 use unicode_width::UnicodeWidthStr;
 use uuid_b64::UuidB64;
-use yaml_rust::YamlEmitter;
 
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 pub enum SerializationType {
@@ -22,6 +27,154 @@ impl Default for SerializationType {
     }
 }
 
+/// Scheme new ids are generated in, url-safe base64 UUIDs unless set
+static ID_SCHEME: Mutex<Option<IdScheme>> = Mutex::new(None);
+
+/// Crockford's base32 alphabet ULIDs are written in
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// How new document ids are generated. Ids of any scheme can live side by side in one index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IdScheme {
+    /// A random UUID in url-safe base64, such as `hP5GeaxiRmamNWzU1u4sSA`
+    UuidB64,
+    /// A random UUID, hyphenated
+    Uuid4,
+    /// A UUID starting with the time it was made in, so ids sort by creation
+    Uuid7,
+    /// A ULID, time-ordered like a UUIDv7 but 26 characters of base32
+    Ulid,
+}
+
+impl FromStr for IdScheme {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<IdScheme, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uuid-b64" | "uuidb64" => Ok(IdScheme::UuidB64),
+            "uuid" | "uuid4" | "uuidv4" => Ok(IdScheme::Uuid4),
+            "uuid7" | "uuidv7" => Ok(IdScheme::Uuid7),
+            "ulid" => Ok(IdScheme::Ulid),
+            _ => Err(eyre!(
+                "❌ Unknown id scheme {}, expected uuid-b64, uuid4, uuid7 or ulid",
+                s
+            )),
+        }
+    }
+}
+
+impl IdScheme {
+    /// The scheme set with `set`, url-safe base64 UUIDs by default
+    pub fn current() -> IdScheme {
+        ID_SCHEME.lock().unwrap().unwrap_or(IdScheme::UuidB64)
+    }
+
+    /// Generate ids in `scheme` from now on
+    pub fn set(scheme: IdScheme) {
+        *ID_SCHEME.lock().unwrap() = Some(scheme);
+    }
+}
+
+/// 16 random bytes, the first 6 replaced by the milliseconds since the epoch when `timed`
+fn id_bytes(timed: bool) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("❌ No random source to generate an id from");
+    if timed {
+        let millis = chrono::Utc::now().timestamp_millis() as u64;
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    }
+    bytes
+}
+
+/// `bytes` as a hyphenated UUID of `version`, with the RFC 4122 variant
+fn uuid_string(mut bytes: [u8; 16], version: u8) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | (version << 4);
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// `bytes` in Crockford's base32, 5 bits per character from the top
+fn ulid_string(bytes: [u8; 16]) -> String {
+    let value = u128::from_be_bytes(bytes);
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// Id of a document revision, in the configured `IdScheme`.
+/// Document stores ids as plain strings, as Meilisearch does; this is where new ones come from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DocumentId(String);
+
+impl DocumentId {
+    /// A fresh id in the current scheme
+    pub fn generate() -> DocumentId {
+        DocumentId::generate_in(IdScheme::current())
+    }
+
+    /// A fresh id in `scheme`
+    pub fn generate_in(scheme: IdScheme) -> DocumentId {
+        DocumentId(match scheme {
+            IdScheme::UuidB64 => UuidB64::new().to_string(),
+            IdScheme::Uuid4 => uuid_string(id_bytes(false), 4),
+            IdScheme::Uuid7 => uuid_string(id_bytes(true), 7),
+            IdScheme::Ulid => ulid_string(id_bytes(true)),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for DocumentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<DocumentId> for String {
+    fn from(id: DocumentId) -> String {
+        id.0
+    }
+}
+
+/// What a file's frontmatter is written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontmatterFormat {
+    /// Between `---` lines
+    #[default]
+    Yaml,
+    /// Between `+++` lines, as Hugo writes it
+    Toml,
+}
+
+impl FromStr for FrontmatterFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<FrontmatterFormat, Self::Err> {
+        match s {
+            "yaml" => Ok(FrontmatterFormat::Yaml),
+            "toml" => Ok(FrontmatterFormat::Toml),
+            _ => Err(eyre!(
+                "❌ Unknown frontmatter format {}, expected yaml or toml",
+                s
+            )),
+        }
+    }
+}
+
 // TODO add `backlink` field for hierarchical linking
 #[derive(Clone, Debug, Default, PartialEq, Deserialize)]
 pub struct Document {
@@ -30,6 +183,14 @@ pub struct Document {
     // For hierarchical linking, link to a parent document
     #[serde(default)]
     pub parentid: String,
+    // Id of the first revision, shared by every later revision of the same document
+    #[serde(default)]
+    pub origid: String,
+    #[serde(default = "first_revision")]
+    pub revision: u32,
+    // Only the newest revision is latest, older ones are kept for history
+    #[serde(default = "is_latest")]
+    pub latest: bool,
     #[serde(default, alias = "author")]
     pub authors: Vec<String>,
     // Note the custom Serialize implementation below to skip the `body` depending on how
@@ -39,14 +200,23 @@ pub struct Document {
     #[serde(default)]
     #[serde(skip)]
     pub serialization_type: SerializationType,
-    /// Epoch seconds
-    #[serde(deserialize_with = "date_deserializer")]
+    /// What the frontmatter is written in on Disk, stored so TOML notes stay TOML
+    #[serde(default)]
+    pub frontmatter: FrontmatterFormat,
+    /// Epoch seconds, 0 if unset
+    #[serde(default, deserialize_with = "date_deserializer")]
     pub date: Date,
     pub title: String,
     #[serde(default)]
     pub background_img: String,
     #[serde(default)]
     pub links: Vec<String>,
+    /// Files attached with `attach`
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    // Chain ids of the documents linking here, written by `backlinks rebuild`
+    #[serde(default)]
+    pub backlinks: Vec<String>,
     #[serde(default)]
     pub slug: String,
     #[serde(default)]
@@ -60,8 +230,30 @@ pub struct Document {
     pub writes: u16,
     #[serde(default)]
     pub views: i32,
+    /// Epoch seconds of the last `review` of this document, 0 if never
+    #[serde(default)]
+    pub last_reviewed: i64,
+    /// Whether the body is stored encrypted, see `crypt`
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Words in the body and minutes to read them, as of the last import or store
+    #[serde(default)]
+    pub word_count: usize,
+    #[serde(default)]
+    pub reading_minutes: usize,
     #[serde(default)]
     pub filename: String,
+    /// Frontmatter keys without a field of their own, kept so they survive a round trip
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+}
+
+fn first_revision() -> u32 {
+    1
+}
+
+fn is_latest() -> bool {
+    true
 }
 
 #[allow(dead_code)]
@@ -72,53 +264,383 @@ fn is_false(v: &bool) -> bool {
 impl Document {
     pub fn new() -> Self {
         Document {
+            revision: first_revision(),
+            latest: is_latest(),
             ..Default::default()
         }
     }
 
+    /// Id shared by every revision of this document
+    pub fn chain_id(&self) -> &str {
+        if self.origid.is_empty() {
+            &self.id
+        } else {
+            &self.origid
+        }
+    }
+
+    /// A copy with a fresh id that succeeds this document as the latest revision
+    pub fn next_revision(&self) -> Document {
+        Document {
+            id: DocumentId::generate().into(),
+            origid: self.chain_id().to_owned(),
+            revision: self.revision + 1,
+            latest: true,
+            writes: self.writes + 1,
+            ..self.clone()
+        }
+    }
+
+    /// Append `text` to the body on lines of its own, under a `## heading` if one is given
+    pub fn append(&mut self, text: &str, heading: Option<&str>) {
+        if !self.body.is_empty() && !self.body.ends_with('\n') {
+            self.body.push('\n');
+        }
+        if let Some(heading) = heading {
+            if !self.body.is_empty() {
+                self.body.push('\n');
+            }
+            self.body.push_str(&format!("## {}\n\n", heading));
+        }
+        self.body.push_str(text);
+        if !text.ends_with('\n') {
+            self.body.push('\n');
+        }
+    }
+
     pub fn parse_file(path: &std::path::Path) -> Result<Document, io::Error> {
-        let full_path = path.to_str().unwrap();
-        let s = fs::read_to_string(full_path)?;
-
-        let (yaml, content) = frontmatter::parse_and_find_content(&s).unwrap();
-        match yaml {
-            Some(yaml) => {
-                let mut out_str = String::new();
-                {
-                    let mut emitter = YamlEmitter::new(&mut out_str);
-                    emitter.dump(&yaml).unwrap(); // dump the YAML object to a String
+        Document::parse_file_with(path, &ParseOptions::default())
+    }
+
+    /// Parse the file at `path`, reading frontmatter written for other tools as `options` says
+    pub fn parse_file_with(
+        path: &std::path::Path,
+        options: &ParseOptions,
+    ) -> Result<Document, io::Error> {
+        Document::parse_str_with(&fs::read_to_string(path)?, path, options)
+    }
+
+    /// Parse `s`, the contents of the file at `path`, reading frontmatter written for other tools
+    /// as `options` says
+    pub fn parse_str_with(
+        s: &str,
+        path: &std::path::Path,
+        options: &ParseOptions,
+    ) -> Result<Document, io::Error> {
+        let failed = |e: &dyn fmt::Display| {
+            warn!("Error reading frontmatter {}: {}", path.display(), e);
+            let problems: Vec<_> = problems(s, &options.field_map)
+                .iter()
+                .map(|p| p.to_string())
+                .collect();
+            Error::other(format!(
+                "Error reading frontmatter {}: {}",
+                path.display(),
+                problems.join("; ")
+            ))
+        };
+        let (format, value, content) = read_frontmatter(s).map_err(|p| failed(&p))?;
+        let value = match value {
+            serde_yaml::Value::Mapping(m) => {
+                serde_yaml::Value::Mapping(remap(m, &options.field_map))
+            }
+            v => v,
+        };
+        let mut doc: Document = serde_yaml::from_value(value).map_err(|e| failed(&e))?;
+        doc.frontmatter = format;
+        doc.filename = String::from(path.file_name().unwrap().to_str().unwrap());
+        doc.body = content.to_string();
+        let (words, minutes) = reading_time(&doc.body);
+        doc.word_count = words;
+        doc.reading_minutes = minutes;
+        if doc.id.width() == 0 {
+            doc.id = DocumentId::generate().into();
+            doc.parentid = doc.id.clone();
+        }
+        if doc.date == Date::default() {
+            doc.date = match options.date_from {
+                Some(from) => from
+                    .date_for(path)
+                    .map_err(|e| Error::other(e.to_string()))?,
+                None => {
+                    return Err(Error::other(format!(
+                        "No date in {}, see --date-from",
+                        path.display()
+                    )))
                 }
+            };
+        }
+
+        Ok(doc)
+    }
+}
+
+/// How to read frontmatter that doesn't follow the Document layout
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// Where to take the date of a file without one, which is otherwise an error
+    pub date_from: Option<DateFrom>,
+    /// Frontmatter keys to read as Document fields
+    pub field_map: FieldMap,
+}
+
+/// Frontmatter key `key` with `value` as the Document field it is mapped onto, if any
+fn remap_entry(
+    key: serde_yaml::Value,
+    value: serde_yaml::Value,
+    field_map: &FieldMap,
+) -> (serde_yaml::Value, serde_yaml::Value) {
+    match key.as_str().and_then(|k| field_map.get(k)) {
+        Some(mapping) => (
+            serde_yaml::Value::from(mapping.field()),
+            mapping.transform(value),
+        ),
+        None => (key, value),
+    }
+}
+
+/// `mapping` with its keys renamed as `field_map` says. A key already named after a field wins
+/// over one mapped onto it.
+fn remap(mapping: serde_yaml::Mapping, field_map: &FieldMap) -> serde_yaml::Mapping {
+    let (mapped, unmapped): (Vec<_>, Vec<_>) = mapping.into_iter().partition(|(k, _)| {
+        k.as_str()
+            .map(|k| field_map.contains_key(k))
+            .unwrap_or(false)
+    });
+    let mut out: serde_yaml::Mapping = unmapped.into_iter().collect();
+    for (key, value) in mapped {
+        let (key, value) = remap_entry(key, value, field_map);
+        if !out.contains_key(&key) {
+            out.insert(key, value);
+        }
+    }
+    out
+}
+
+/// Reading speed `reading_time` assumes, in words a minute
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Words in `body` and the whole minutes it takes to read them, at least one if there are any
+pub fn reading_time(body: &str) -> (usize, usize) {
+    let words = body.split_whitespace().count();
+    (words, words.div_ceil(WORDS_PER_MINUTE))
+}
+
+/// Line the frontmatter starts on, after the opening `---`
+const FRONTMATTER_LINE: usize = 2;
+
+/// The frontmatter between the opening `---` or `+++` line and the next one like it, the format
+/// that says it's in, and the body after it
+fn split_frontmatter(s: &str) -> Option<(FrontmatterFormat, &str, &str)> {
+    let (format, fence) = if s.starts_with("+++\n") {
+        (FrontmatterFormat::Toml, "+++\n")
+    } else {
+        (FrontmatterFormat::Yaml, "---\n")
+    };
+    let rest = s.strip_prefix(fence)?;
+    let end = rest.find(fence)?;
+    Some((format, &rest[..end], &rest[end + fence.len()..]))
+}
 
-                let mut doc: Document = match serde_yaml::from_str(&out_str) {
-                    Ok(d) => d,
-                    Err(e) => {
-                        eprintln!("Error reading yaml {}: {:?} {}", full_path, e, out_str);
-                        return Err(Error::new(
-                            ErrorKind::Other,
-                            format!("Error reading yaml {}: {}", path.display(), e.to_string()),
-                        ));
-                    }
+/// The frontmatter of file contents `s` as YAML whatever it was written in, the format it was
+/// written in, and the body after it
+fn read_frontmatter(s: &str) -> Result<(FrontmatterFormat, serde_yaml::Value, &str), Problem> {
+    let (format, text, body) = split_frontmatter(s).ok_or_else(|| Problem {
+        line: 1,
+        column: 1,
+        key: None,
+        message: "expected YAML frontmatter between two --- lines or TOML between two +++ lines"
+            .to_owned(),
+    })?;
+    let value = match format {
+        FrontmatterFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+            let (line, column) = match e.location() {
+                Some(l) => (l.line() + FRONTMATTER_LINE - 1, l.column() + 1),
+                None => (FRONTMATTER_LINE, 1),
+            };
+            let message = e.to_string();
+            let message = match message.rsplit_once(" at line ") {
+                Some((message, _)) => message.to_owned(),
+                None => message,
+            };
+            Problem {
+                line,
+                column,
+                key: None,
+                message,
+            }
+        })?,
+        FrontmatterFormat::Toml => {
+            let table = text.parse::<toml::Value>().map_err(|e| {
+                let (line, column) = match e.line_col() {
+                    Some((line, column)) => (line + FRONTMATTER_LINE, column + 1),
+                    None => (FRONTMATTER_LINE, 1),
+                };
+                let message = e.to_string();
+                let message = match message.rsplit_once(" at line ") {
+                    Some((message, _)) => message.to_owned(),
+                    None => message,
                 };
-                doc.filename = String::from(path.file_name().unwrap().to_str().unwrap());
-                doc.body = content.to_string();
-                if doc.id.width() == 0 {
-                    let uuid = UuidB64::new();
-                    doc.id = uuid.to_string();
-                    doc.parentid = uuid.to_string();
+                Problem {
+                    line,
+                    column,
+                    key: None,
+                    message,
                 }
+            })?;
+            toml_to_yaml(table)
+        }
+    };
+    Ok((format, value, body))
+}
 
-                Ok(doc)
-            }
-            None => Err(Error::new(
-                ErrorKind::Other,
-                format!("Failed to process file {}", path.display()),
-            )),
+/// `value` as YAML, with datetimes as RFC 3339 strings
+fn toml_to_yaml(value: toml::Value) -> serde_yaml::Value {
+    match value {
+        toml::Value::String(s) => serde_yaml::Value::String(s),
+        toml::Value::Integer(i) => serde_yaml::Value::from(i),
+        toml::Value::Float(f) => serde_yaml::Value::from(f),
+        toml::Value::Boolean(b) => serde_yaml::Value::Bool(b),
+        toml::Value::Datetime(d) => serde_yaml::Value::String(d.to_string()),
+        toml::Value::Array(a) => {
+            serde_yaml::Value::Sequence(a.into_iter().map(toml_to_yaml).collect())
+        }
+        toml::Value::Table(t) => serde_yaml::Value::Mapping(
+            t.into_iter()
+                .map(|(k, v)| (serde_yaml::Value::String(k), toml_to_yaml(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// `value` as TOML, None for a null TOML has no way to write. Strings that are RFC 3339 times
+/// become datetimes.
+fn yaml_to_toml(value: serde_yaml::Value) -> Option<toml::Value> {
+    Some(match value {
+        serde_yaml::Value::Null => return None,
+        serde_yaml::Value::Bool(b) => toml::Value::Boolean(b),
+        serde_yaml::Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64()?),
+        },
+        serde_yaml::Value::String(s) => match chrono::DateTime::parse_from_rfc3339(&s) {
+            Ok(_) => s
+                .parse()
+                .map(toml::Value::Datetime)
+                .unwrap_or(toml::Value::String(s)),
+            Err(_) => toml::Value::String(s),
+        },
+        serde_yaml::Value::Sequence(s) => {
+            toml::Value::Array(s.into_iter().filter_map(yaml_to_toml).collect())
+        }
+        serde_yaml::Value::Mapping(m) => toml::Value::Table(
+            m.into_iter()
+                .filter_map(|(k, v)| {
+                    let k = match k {
+                        serde_yaml::Value::String(k) => k,
+                        k => serde_yaml::to_string(&k)
+                            .ok()?
+                            .trim_start_matches("---")
+                            .trim()
+                            .to_owned(),
+                    };
+                    Some((k, yaml_to_toml(v)?))
+                })
+                .collect(),
+        ),
+    })
+}
+
+/// Something wrong with a file's frontmatter
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    /// Line and column in the file, from 1
+    pub line: usize,
+    pub column: usize,
+    /// The frontmatter key at fault, if it's down to one
+    pub key: Option<String>,
+    /// What's wrong, including the type expected for a value of the wrong type
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        if let Some(key) = &self.key {
+            write!(f, "{}: ", key)?;
         }
+        write!(f, "{}", self.message)
     }
 }
 
+/// Every problem stopping file contents `s` from parsing as a Document with its keys mapped by
+/// `field_map`, empty if there are none. Each key's value is checked on its own so one bad value
+/// doesn't hide the next.
+pub fn problems(s: &str, field_map: &FieldMap) -> Vec<Problem> {
+    let mapping = match read_frontmatter(s) {
+        Ok((_, serde_yaml::Value::Mapping(m), _)) => m,
+        Ok(_) => {
+            return vec![Problem {
+                line: FRONTMATTER_LINE,
+                column: 1,
+                key: None,
+                message: "expected the frontmatter to be a mapping of keys to values".to_owned(),
+            }]
+        }
+        Err(problem) => return vec![problem],
+    };
+    let text = match split_frontmatter(s) {
+        Some((_, text, _)) => text,
+        None => "",
+    };
+
+    let title = serde_yaml::Value::from("title");
+    let mut found = Vec::new();
+    for (key, value) in &mapping {
+        // Pair the key with a title so the only thing that can fail is its own value
+        let mut alone = serde_yaml::Mapping::new();
+        alone.insert(title.clone(), serde_yaml::Value::from(""));
+        let (field, value) = remap_entry(key.clone(), value.clone(), field_map);
+        alone.insert(field, value);
+        if let Err(e) = serde_yaml::from_value::<Document>(serde_yaml::Value::Mapping(alone)) {
+            let key = match key {
+                serde_yaml::Value::String(k) => k.clone(),
+                k => serde_yaml::to_string(k)
+                    .unwrap_or_default()
+                    .trim_start_matches("---")
+                    .trim()
+                    .to_owned(),
+            };
+            let line = text
+                .lines()
+                .position(|l| {
+                    l.strip_prefix(key.as_str())
+                        .map(|rest| rest.trim_start().starts_with(&[':', '='][..]))
+                        .unwrap_or(false)
+                })
+                .map(|n| n + FRONTMATTER_LINE)
+                .unwrap_or(FRONTMATTER_LINE);
+            found.push(Problem {
+                line,
+                column: 1,
+                key: Some(key),
+                message: e.to_string(),
+            });
+        }
+    }
+    if !remap(mapping, field_map).contains_key(&title) {
+        found.push(Problem {
+            line: FRONTMATTER_LINE,
+            column: 1,
+            key: Some("title".to_owned()),
+            message: "missing, expected a string".to_owned(),
+        });
+    }
+    found
+}
+
 /// Support Deserializing a string into a list of string of length 1
-fn string_or_list_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+pub(crate) fn string_or_list_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -154,6 +676,12 @@ impl fmt::Display for Document {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.serialization_type == SerializationType::Human {
             write!(f, "{}", self.body)
+        } else if self.frontmatter == FrontmatterFormat::Toml {
+            let value = serde_yaml::to_value(self).map_err(|_| fmt::Error)?;
+            let table =
+                yaml_to_toml(value).unwrap_or_else(|| toml::Value::Table(Default::default()));
+            let toml = toml::to_string(&table).map_err(|_| fmt::Error)?;
+            write!(f, "+++\n{}+++\n{}", toml, self.body)
         } else {
             let yaml = serde_yaml::to_string(&self).unwrap();
             write!(f, "{}---\n{}", yaml, self.body)
@@ -163,10 +691,10 @@ impl fmt::Display for Document {
 
 impl From<markdown_fm_doc::Document> for Document {
     fn from(item: markdown_fm_doc::Document) -> Self {
-        let uuid = UuidB64::new();
+        let id: String = DocumentId::generate().into();
         Document {
-            id: uuid.to_string(),
-            parentid: uuid.to_string(),
+            id: id.clone(),
+            parentid: id,
             authors: vec![item.author],
             body: item.body,
             date: Date::from_str(&item.date).unwrap(),
@@ -175,6 +703,8 @@ impl From<markdown_fm_doc::Document> for Document {
             title: item.title,
             subtitle: item.subtitle,
             filename: item.filename,
+            revision: first_revision(),
+            latest: is_latest(),
             ..Default::default()
         }
     }
@@ -187,44 +717,79 @@ impl Serialize for Document {
         S: Serializer,
     {
         let mut s = match self.serialization_type {
-            SerializationType::Storage => serializer.serialize_struct("Document", 14)?,
-            SerializationType::Disk => serializer.serialize_struct("Document", 12)?,
+            SerializationType::Storage | SerializationType::Disk => {
+                serializer.serialize_map(None)?
+            }
             SerializationType::Human => {
                 // The Display trait implementation above handles displaying just the
                 // document body, don't need to serialize any of the doc metadata
-                return serializer.serialize_struct("Document", 0)?.end();
+                return serializer.serialize_map(Some(0))?.end();
             }
         };
 
-        s.serialize_field("title", &self.title)?;
+        s.serialize_entry("title", &self.title)?;
         if self.subtitle.width() > 0 {
-            s.serialize_field("subtitle", &self.subtitle)?;
+            s.serialize_entry("subtitle", &self.subtitle)?;
         };
         if self.serialization_type == SerializationType::Storage {
-            s.serialize_field("date", &self.date)?;
+            s.serialize_entry("date", &self.date)?;
         } else {
-            s.serialize_field("date", &format!("{}", &self.date))?;
+            s.serialize_entry("date", &format!("{}", &self.date))?;
         }
-        s.serialize_field("tags", &self.tags)?;
+        s.serialize_entry("tags", &self.tags)?;
         if self.serialization_type == SerializationType::Storage {
-            s.serialize_field("filename", &self.filename)?;
+            s.serialize_entry("filename", &self.filename)?;
+        };
+        s.serialize_entry("authors", &self.authors)?;
+        s.serialize_entry("id", &self.id)?;
+        s.serialize_entry("parentid", &self.parentid)?;
+        if !self.origid.is_empty() {
+            s.serialize_entry("origid", &self.origid)?;
+        };
+        s.serialize_entry("revision", &self.revision)?;
+        s.serialize_entry("latest", &self.latest)?;
+        s.serialize_entry("weight", &self.weight)?;
+        s.serialize_entry("writes", &self.writes)?;
+        s.serialize_entry("views", &self.views)?;
+        if self.last_reviewed != 0 {
+            s.serialize_entry("last_reviewed", &self.last_reviewed)?;
         };
-        s.serialize_field("authors", &self.authors)?;
-        s.serialize_field("id", &self.id)?;
-        s.serialize_field("parentid", &self.parentid)?;
-        s.serialize_field("weight", &self.weight)?;
-        s.serialize_field("writes", &self.writes)?;
         if self.background_img.width() > 0 {
-            s.serialize_field("background_img", &self.background_img)?;
+            s.serialize_entry("background_img", &self.background_img)?;
         };
         if !self.links.is_empty() {
-            s.serialize_field("links", &self.links)?;
+            s.serialize_entry("links", &self.links)?;
+        };
+        if !self.attachments.is_empty() {
+            s.serialize_entry("attachments", &self.attachments)?;
+        };
+        if self.serialization_type == SerializationType::Storage && !self.backlinks.is_empty() {
+            s.serialize_entry("backlinks", &self.backlinks)?;
         };
         if self.slug.width() > 0 {
-            s.serialize_field("slug", &self.slug)?;
+            s.serialize_entry("slug", &self.slug)?;
         };
+        for (key, value) in &self.extra {
+            s.serialize_entry(key, value)?;
+        }
+        if self.serialization_type == SerializationType::Storage
+            && self.frontmatter == FrontmatterFormat::Toml
+        {
+            s.serialize_entry("frontmatter", "toml")?;
+        }
+        if self.encrypted {
+            s.serialize_entry("encrypted", &self.encrypted)?;
+        }
         if self.serialization_type == SerializationType::Storage {
-            s.serialize_field("body", &self.body)?;
+            // An encrypted body's length can only be known from before it was encrypted
+            let (words, minutes) = if crate::crypt::is_encrypted(&self.body) {
+                (self.word_count, self.reading_minutes)
+            } else {
+                reading_time(&self.body)
+            };
+            s.serialize_entry("word_count", &words)?;
+            s.serialize_entry("reading_minutes", &minutes)?;
+            s.serialize_entry("body", &self.body)?;
         }
         s.end()
     }
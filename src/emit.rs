@@ -0,0 +1,60 @@
+use crate::document::{Document, SerializationType};
+use color_eyre::Report;
+use eyre::eyre;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How documents picked in the interactive query are written to stdout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emit {
+    /// One document id per line
+    Ids,
+    /// A JSON array of the full documents
+    Json,
+    /// One path per line to where `dump` writes each document
+    Paths,
+    /// The document bodies, separated by blank lines
+    Body,
+}
+
+impl FromStr for Emit {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Emit, Self::Err> {
+        match s {
+            "ids" => Ok(Emit::Ids),
+            "json" => Ok(Emit::Json),
+            "paths" => Ok(Emit::Paths),
+            "body" => Ok(Emit::Body),
+            _ => Err(eyre!(
+                "❌ Unknown emit format {}, expected ids, json, paths or body",
+                s
+            )),
+        }
+    }
+}
+
+impl Emit {
+    /// Render `docs`, resolving `Paths` against the `dir` they were dumped to
+    pub fn render(self, docs: &mut [Document], dir: &Path) -> Result<String, Report> {
+        let out = match self {
+            Emit::Ids => docs.iter().map(|d| format!("{}\n", d.id)).collect(),
+            Emit::Json => {
+                for doc in docs.iter_mut() {
+                    doc.serialization_type = SerializationType::Storage;
+                }
+                format!("{}\n", serde_json::to_string_pretty(docs)?)
+            }
+            Emit::Paths => docs
+                .iter()
+                .map(|d| format!("{}\n", dir.join(&d.filename).display()))
+                .collect(),
+            Emit::Body => docs
+                .iter()
+                .map(|d| format!("{}\n", d.body.trim_end()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        Ok(out)
+    }
+}
@@ -0,0 +1,126 @@
+use color_eyre::Report;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Process exit status, distinguishing failures scripts may want to handle differently
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitCode {
+    Success = 0,
+    Failure = 1,
+    /// A config, settings or note file could not be parsed
+    Parse = 2,
+    /// The server could not be reached or answered with an error
+    Network = 3,
+    /// Some files were imported, others failed to load
+    PartialImport = 4,
+}
+
+impl ExitCode {
+    /// Name used for the `kind` of a `--json` error
+    pub fn kind(self) -> &'static str {
+        match self {
+            ExitCode::Success => "success",
+            ExitCode::Failure => "failure",
+            ExitCode::Parse => "parse",
+            ExitCode::Network => "network",
+            ExitCode::PartialImport => "partial-import",
+        }
+    }
+
+    /// Classify `report` by the errors in its chain
+    pub fn of(report: &Report) -> ExitCode {
+        for cause in report.chain() {
+            if cause.is::<ImportSummary>() {
+                return ExitCode::PartialImport;
+            }
+            if cause.is::<reqwest::Error>() || cause.is::<RequestFailed>() {
+                return ExitCode::Network;
+            }
+            if cause.is::<ParseError>()
+                || cause.is::<serde_json::Error>()
+                || cause.is::<serde_yaml::Error>()
+                || cause.is::<toml::de::Error>()
+            {
+                return ExitCode::Parse;
+            }
+        }
+        ExitCode::Failure
+    }
+}
+
+/// A file that could not be parsed, the message naming the file and why
+#[derive(Debug)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The server answered with an error status
+#[derive(Debug)]
+pub struct RequestFailed(pub String);
+
+impl fmt::Display for RequestFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for RequestFailed {}
+
+/// What an import did, an error if any file failed to load and was skipped
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Files left out as already in the index
+    pub skipped: usize,
+    /// Each file, or glob entry, that failed to load, and each note the server failed to index
+    pub failed: Vec<String>,
+}
+
+impl ImportSummary {
+    /// The `--json` form of a complete import
+    pub fn to_json(&self) -> Value {
+        json!({
+            "kind": ExitCode::Success.kind(),
+            "code": ExitCode::Success as i32,
+            "imported": self.imported,
+            "skipped": self.skipped,
+            "failed": self.failed,
+        })
+    }
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "❌ {} files failed to load, imported {}",
+            self.failed.len(),
+            self.imported
+        )
+    }
+}
+
+impl std::error::Error for ImportSummary {}
+
+/// The `--json` form of a failure: its kind, exit code and message, plus what was imported and
+/// what failed for a partial import
+pub fn to_json(report: &Report) -> Value {
+    let code = ExitCode::of(report);
+    let mut out = json!({
+        "kind": code.kind(),
+        "code": code as i32,
+        "message": report.to_string(),
+    });
+    if let Some(partial) = report.downcast_ref::<ImportSummary>() {
+        out["imported"] = Value::from(partial.imported);
+        out["skipped"] = Value::from(partial.skipped);
+        out["failed"] = Value::from(partial.failed.clone());
+    }
+    out
+}
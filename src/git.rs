@@ -0,0 +1,144 @@
+use crate::date::{Date, DateFrom};
+use crate::document::{Document, ParseOptions};
+use crate::import;
+use crate::slug;
+use color_eyre::Report;
+use eyre::bail;
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
+use std::process::Command;
+use tracing::warn;
+
+/// A file as one commit left it
+#[derive(Clone, Debug)]
+pub struct Version {
+    /// Where the file was in the repository then, which changes when it's renamed
+    pub path: String,
+    /// When it was committed, epoch seconds
+    pub time: i64,
+    pub contents: String,
+}
+
+/// Standard output of `git args` run in `repo`
+fn git(repo: &Path, args: &[&str]) -> Result<String, Report> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()?;
+    if !out.status.success() {
+        bail!(
+            "❌ git {} failed in {}: {}",
+            args.join(" "),
+            repo.display(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// The files tracked in `repo` matching the glob `pattern`, relative to its top
+pub fn files(repo: &Path, pattern: &str) -> Result<Vec<String>, Report> {
+    let pattern = Pattern::new(pattern)?;
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    Ok(git(repo, &["ls-files", "-z"])?
+        .split('\0')
+        .filter(|f| !f.is_empty() && pattern.matches_with(f, options))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Every committed version of `file` in `repo`, oldest first, following it through renames
+pub fn history(repo: &Path, file: &str) -> Result<Vec<Version>, Report> {
+    // Each commit comes out as a NUL, its hash and time, a blank line and the path it touched
+    let log = git(
+        repo,
+        &[
+            "log",
+            "--follow",
+            "--diff-filter=AMR",
+            "--name-only",
+            "--format=%x00%H %ct",
+            "--",
+            file,
+        ],
+    )?;
+    let mut versions = Vec::new();
+    for entry in log.split('\0').filter(|e| !e.trim().is_empty()) {
+        let mut lines = entry.lines().filter(|l| !l.is_empty());
+        let (commit, time, path) = match (lines.next(), lines.next()) {
+            (Some(head), Some(path)) => match head.split_once(' ') {
+                Some((commit, time)) => (commit, time, path),
+                None => bail!("❌ Unexpected git log line {}", head),
+            },
+            _ => bail!("❌ Unexpected git log entry {:?}", entry),
+        };
+        versions.push(Version {
+            path: path.to_owned(),
+            time: time.parse()?,
+            contents: git(repo, &["show", &format!("{}:{}", commit, path)])?,
+        });
+    }
+    versions.reverse();
+    Ok(versions)
+}
+
+/// One revision per version, oldest first and dated by its commit, sharing the id the newest
+/// version's frontmatter gives as the chain id. A version saying nothing the one before it
+/// didn't is left out, as is one whose frontmatter doesn't parse.
+pub fn chain(versions: &[Version], options: &ParseOptions) -> Vec<Document> {
+    // The commit dates every revision, so a missing date is no reason to fail
+    let options = ParseOptions {
+        date_from: Some(DateFrom::Now),
+        ..options.clone()
+    };
+    let mut parsed = Vec::new();
+    for version in versions {
+        match Document::parse_str_with(&version.contents, Path::new(&version.path), &options) {
+            Ok(mut doc) => {
+                doc.date = Date::new(version.time);
+                slug::fill(&mut doc);
+                parsed.push(doc);
+            }
+            Err(e) => warn!("Skipping a version of {}: {}", version.path, e),
+        }
+    }
+    let (id, parentid) = match parsed.last() {
+        Some(doc) => (doc.id.clone(), doc.parentid.clone()),
+        None => return Vec::new(),
+    };
+    let mut revisions: Vec<Document> = Vec::new();
+    for doc in parsed {
+        let next = match revisions.last_mut() {
+            None => Document {
+                id: id.clone(),
+                parentid: parentid.clone(),
+                ..doc
+            },
+            Some(prev) => {
+                let unchanged = Document {
+                    date: prev.date.clone(),
+                    ..doc.clone()
+                };
+                if import::same_content(&unchanged, prev) {
+                    continue;
+                }
+                prev.latest = false;
+                let chained = prev.next_revision();
+                Document {
+                    id: chained.id,
+                    parentid: chained.parentid,
+                    origid: chained.origid,
+                    revision: chained.revision,
+                    writes: chained.writes,
+                    ..doc
+                }
+            }
+        };
+        revisions.push(next);
+    }
+    revisions
+}
@@ -0,0 +1,302 @@
+use crate::client::Client;
+use crate::{api, document};
+use color_eyre::Report;
+use eyre::eyre;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// How `graph export` writes the link graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Graphviz
+    Dot,
+    /// `{"nodes": [...], "edges": [...]}`, for d3 and the like
+    Json,
+    /// Gephi's XML format
+    Gexf,
+}
+
+impl FromStr for Format {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Format, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            "gexf" => Ok(Format::Gexf),
+            _ => Err(eyre!(
+                "❌ Unknown graph format {}, expected dot, json or gexf",
+                s
+            )),
+        }
+    }
+}
+
+/// A document in the graph
+#[derive(Debug)]
+pub struct Node {
+    pub id: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub weight: i32,
+}
+
+/// Directed graph of the latest revision of every document, with an edge for each link that
+/// resolves to another document
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    /// Indexes into `nodes` of the linking and the linked document
+    pub edges: Vec<(usize, usize)>,
+}
+
+/// The latest revision of every document
+pub async fn latest(client: &Client) -> Result<Vec<document::Document>, Report> {
+    let mut q = api::ApiQuery::new();
+    q.filter = Some("latest = true".to_owned());
+    q.sort = None;
+    api::Pages::search(client, q).all().await
+}
+
+/// Where each id, chain id and slug of `docs` points, for resolving links
+pub fn targets(docs: &[document::Document]) -> HashMap<&str, usize> {
+    let mut targets = HashMap::new();
+    for (i, doc) in docs.iter().enumerate() {
+        targets.insert(doc.id.as_str(), i);
+        targets.insert(doc.chain_id(), i);
+        if !doc.slug.is_empty() {
+            targets.insert(doc.slug.as_str(), i);
+        }
+    }
+    targets
+}
+
+impl Graph {
+    /// Link `docs`, expected to be latest revisions. Links to missing documents are left out.
+    pub fn build(docs: &[document::Document]) -> Graph {
+        let targets = targets(docs);
+        let mut edges = Vec::new();
+        for (i, doc) in docs.iter().enumerate() {
+            for link in &doc.links {
+                if let Some(&j) = targets.get(link.as_str()) {
+                    if !edges.contains(&(i, j)) {
+                        edges.push((i, j));
+                    }
+                }
+            }
+        }
+        let nodes = docs
+            .iter()
+            .map(|d| Node {
+                id: d.id.clone(),
+                title: d.title.clone(),
+                tags: d.tags.clone(),
+                weight: d.weight,
+            })
+            .collect();
+        Graph { nodes, edges }
+    }
+
+    /// Nodes that link to no other node and that no other node links to
+    pub fn orphans(&self) -> Vec<&Node> {
+        let mut linked = vec![false; self.nodes.len()];
+        for &(from, to) in self.edges.iter().filter(|(from, to)| from != to) {
+            linked[from] = true;
+            linked[to] = true;
+        }
+        self.nodes
+            .iter()
+            .zip(linked)
+            .filter(|(_, linked)| !linked)
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Dot => self.to_dot(),
+            Format::Json => format!("{}\n", self.to_json()),
+            Format::Gexf => self.to_gexf(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph notes {\n");
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "  \"{}\" [label=\"{}\", tags=\"{}\", weight={}];\n",
+                dot_escape(&node.id),
+                dot_escape(&node.title),
+                dot_escape(&node.tags.join(",")),
+                node.weight
+            ));
+        }
+        for &(from, to) in &self.edges {
+            out.push_str(&format!(
+                "  \"{}\" -> \"{}\";\n",
+                dot_escape(&self.nodes[from].id),
+                dot_escape(&self.nodes[to].id)
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_json(&self) -> Value {
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|n| json!({ "id": n.id, "title": n.title, "tags": n.tags, "weight": n.weight }))
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(
+                |&(from, to)| json!({ "source": self.nodes[from].id, "target": self.nodes[to].id }),
+            )
+            .collect();
+        json!({ "nodes": nodes, "edges": edges })
+    }
+
+    pub fn to_gexf(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n",
+            "  <graph defaultedgetype=\"directed\">\n",
+            "    <attributes class=\"node\">\n",
+            "      <attribute id=\"tags\" title=\"tags\" type=\"string\"/>\n",
+            "      <attribute id=\"weight\" title=\"weight\" type=\"integer\"/>\n",
+            "    </attributes>\n",
+            "    <nodes>\n",
+        ));
+        for node in &self.nodes {
+            out.push_str(&format!(
+                concat!(
+                    "      <node id=\"{}\" label=\"{}\">\n",
+                    "        <attvalues>\n",
+                    "          <attvalue for=\"tags\" value=\"{}\"/>\n",
+                    "          <attvalue for=\"weight\" value=\"{}\"/>\n",
+                    "        </attvalues>\n",
+                    "      </node>\n",
+                ),
+                xml_escape(&node.id),
+                xml_escape(&node.title),
+                xml_escape(&node.tags.join("|")),
+                node.weight
+            ));
+        }
+        out.push_str("    </nodes>\n    <edges>\n");
+        for (i, &(from, to)) in self.edges.iter().enumerate() {
+            out.push_str(&format!(
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\"/>\n",
+                i,
+                xml_escape(&self.nodes[from].id),
+                xml_escape(&self.nodes[to].id)
+            ));
+        }
+        out.push_str("    </edges>\n  </graph>\n</gexf>\n");
+        out
+    }
+}
+
+/// Chain ids of the documents linking to each of `docs`, sorted, in the order of `docs`
+pub fn backlinks(docs: &[document::Document]) -> Vec<Vec<String>> {
+    let mut backlinks = vec![Vec::new(); docs.len()];
+    for (from, to) in Graph::build(docs).edges {
+        if from != to {
+            backlinks[to].push(docs[from].chain_id().to_owned());
+        }
+    }
+    for links in backlinks.iter_mut() {
+        links.sort();
+        links.dedup();
+    }
+    backlinks
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    fn doc(id: &str, origid: &str, slug: &str, links: &[&str]) -> Document {
+        Document {
+            id: id.to_owned(),
+            origid: origid.to_owned(),
+            title: format!("Note {}", id),
+            slug: slug.to_owned(),
+            links: links.iter().map(|l| (*l).to_owned()).collect(),
+            ..Document::new()
+        }
+    }
+
+    /// a links to b by id, c by slug and d by the id of its first revision
+    fn docs() -> Vec<Document> {
+        vec![
+            doc("a", "", "", &["b", "c-slug", "d1", "missing", "b"]),
+            doc("b", "", "", &["b"]),
+            doc("c", "", "c-slug", &["a"]),
+            doc("d2", "d1", "", &[]),
+            doc("e", "", "", &[]),
+        ]
+    }
+
+    #[test]
+    fn links_resolve_by_id_chain_id_and_slug() {
+        let graph = Graph::build(&docs());
+        assert_eq!(graph.edges, [(0, 1), (0, 2), (0, 3), (1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn orphans_ignore_links_to_themselves() {
+        let graph = Graph::build(&docs());
+        let ids: Vec<_> = graph.orphans().iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["e"]);
+    }
+
+    #[test]
+    fn backlinks_are_chain_ids_without_self_links() {
+        let backlinks = backlinks(&docs());
+        assert_eq!(backlinks[0], ["c"]);
+        assert_eq!(backlinks[1], ["a"]);
+        assert_eq!(backlinks[3], ["a"]);
+        assert!(backlinks[4].is_empty());
+    }
+
+    #[test]
+    fn formats_escape_ids_and_titles() {
+        let mut docs = vec![doc("x", "", "", &["y"]), doc("y", "", "", &[])];
+        docs[0].title = "Say \"hi\" & <wave>".to_owned();
+        let graph = Graph::build(&docs);
+        assert!(graph
+            .to_dot()
+            .contains("\"x\" [label=\"Say \\\"hi\\\" & <wave>\""));
+        assert!(graph.to_dot().contains("\"x\" -> \"y\";"));
+        assert!(graph
+            .to_gexf()
+            .contains("label=\"Say &quot;hi&quot; &amp; &lt;wave&gt;\""));
+        assert_eq!(
+            graph.to_json()["edges"],
+            json!([{ "source": "x", "target": "y" }])
+        );
+    }
+
+    #[test]
+    fn formats_parse() {
+        assert_eq!("dot".parse::<Format>().unwrap(), Format::Dot);
+        assert_eq!("gexf".parse::<Format>().unwrap(), Format::Gexf);
+        assert!("svg".parse::<Format>().is_err());
+    }
+}
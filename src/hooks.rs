@@ -0,0 +1,70 @@
+use crate::config::{Hook, HooksConfig};
+use crate::document::{Document, SerializationType};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
+
+/// A change to the index hooks can run on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// A note was added or revised by `import`
+    Import,
+    /// A note was created with `new`
+    New,
+    /// A note was deleted by `purge` or moved to the trash
+    Delete,
+}
+
+impl Event {
+    /// Passed to hooks as MZ_EVENT
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Import => "import",
+            Event::New => "new",
+            Event::Delete => "delete",
+        }
+    }
+
+    fn hook(self, config: &HooksConfig) -> Option<&Hook> {
+        match self {
+            Event::Import => config.on_import.as_ref(),
+            Event::New => config.on_new.as_ref(),
+            Event::Delete => config.on_delete.as_ref(),
+        }
+    }
+}
+
+/// Run the hook configured for `event`, if any and if it applies to `doc`, with the document
+/// as JSON on stdin and MZ_EVENT and MZ_ID set. The change has already been made, so a failing
+/// hook is only warned about.
+pub fn run(config: &HooksConfig, event: Event, doc: &Document) {
+    let hook = match event.hook(config) {
+        Some(hook) if hook.applies(doc) => hook,
+        _ => return,
+    };
+    debug!("Running the {} hook for {}", event.name(), doc.id);
+    if let Err(e) = spawn(hook.command(), event, doc) {
+        warn!("❌ The {} hook failed for {}: {}", event.name(), doc.id, e);
+    }
+}
+
+fn spawn(command: &str, event: Event, doc: &Document) -> Result<(), String> {
+    let mut doc = doc.clone();
+    doc.serialization_type = SerializationType::Storage;
+    let json = serde_json::to_vec(&doc).map_err(|e| e.to_string())?;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("MZ_EVENT", event.name())
+        .env("MZ_ID", &doc.id)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    // A hook that doesn't read stdin closes it early, which is fine
+    let _ = child.stdin.take().unwrap().write_all(&json);
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("`{}` exited with {}", command, status));
+    }
+    Ok(())
+}
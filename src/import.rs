@@ -0,0 +1,167 @@
+use crate::client::Client;
+use crate::document::Document;
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// What `import` does with a file whose id is already in the index
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnConflict {
+    /// Leave the indexed document as it is
+    Skip,
+    /// Replace the indexed document with the file
+    Overwrite,
+    /// Add the file as the next revision of the indexed document, unless nothing changed
+    NewRevision,
+    /// Abort the import before anything is written
+    Fail,
+}
+
+impl FromStr for OnConflict {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<OnConflict, Self::Err> {
+        match s {
+            "skip" => Ok(OnConflict::Skip),
+            "overwrite" => Ok(OnConflict::Overwrite),
+            "new-revision" => Ok(OnConflict::NewRevision),
+            "fail" => Ok(OnConflict::Fail),
+            _ => Err(eyre!(
+                "❌ Unknown conflict strategy {}, expected skip, overwrite, new-revision or fail",
+                s
+            )),
+        }
+    }
+}
+
+/// Notes read from another app's export, and what of it couldn't be made part of them
+#[derive(Debug, Default)]
+pub struct Converted {
+    pub docs: Vec<Document>,
+    /// Each item, link or file left behind, saying why
+    pub unconverted: Vec<String>,
+}
+
+/// `body` with the target of each markdown link or image replaced by what `f` gives for it, or
+/// left as it is when that's None
+pub fn rewrite_links(body: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("](") {
+        let target_start = start + 2;
+        let end = match rest[target_start..].find(')') {
+            Some(end) => target_start + end,
+            None => break,
+        };
+        out.push_str(&rest[..target_start]);
+        let target = &rest[target_start..end];
+        match f(target) {
+            Some(replaced) => out.push_str(&replaced),
+            None => out.push_str(target),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// What importing a document did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Added,
+    Skipped,
+    Revised,
+}
+
+/// The directory a glob `pattern` starts matching in, the part before any wildcard
+pub fn glob_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|c| {
+            !c.as_os_str()
+                .to_string_lossy()
+                .contains(&['*', '?', '['][..])
+        })
+        .collect()
+}
+
+/// The directories between `base` and `path`, e.g. `projects` and `rust` for
+/// `projects/rust/notes.md`
+pub fn path_tags(base: &Path, path: &Path) -> Vec<String> {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    relative
+        .parent()
+        .map(|dir| {
+            dir.components()
+                .filter_map(|c| match c {
+                    Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Ids of `docs` already in the index
+pub async fn conflicts(client: &Client, docs: &[Document]) -> Result<Vec<String>, Report> {
+    let mut found = Vec::new();
+    for doc in docs {
+        if client.get_document(&doc.id).await?.is_some() {
+            found.push(doc.id.clone());
+        }
+    }
+    Ok(found)
+}
+
+/// Add `doc`, resolving a clash with an indexed document of the same id as `on_conflict` says.
+/// Returns what was done and the uids of the tasks queued for it.
+pub async fn add(
+    client: &Client,
+    doc: &Document,
+    on_conflict: OnConflict,
+) -> Result<(Outcome, Vec<u64>), Report> {
+    if on_conflict == OnConflict::Overwrite || client.get_document(&doc.id).await?.is_none() {
+        let tasks = client.add_documents(std::slice::from_ref(doc)).await?;
+        return Ok((Outcome::Added, tasks));
+    }
+    let latest = match on_conflict {
+        OnConflict::Skip => return Ok((Outcome::Skipped, Vec::new())),
+        OnConflict::Fail => bail!("❌ Document {} is already in the index", doc.id),
+        _ => match client.latest_revision(&doc.id).await? {
+            Some(latest) => latest,
+            None => bail!("❌ Document {} disappeared during the import", doc.id),
+        },
+    };
+    if same_content(doc, &latest) {
+        return Ok((Outcome::Skipped, Vec::new()));
+    }
+    let next = Document {
+        revision: latest.revision,
+        writes: latest.writes,
+        ..doc.clone()
+    };
+    let next = Document {
+        origid: latest.chain_id().to_owned(),
+        ..next.next_revision()
+    };
+    let tasks = client.add_revision(&latest, &next).await?;
+    Ok((Outcome::Revised, tasks))
+}
+
+/// Whether `doc` says nothing `indexed` doesn't, ignoring the fields the index maintains
+pub(crate) fn same_content(doc: &Document, indexed: &Document) -> bool {
+    let probe = Document {
+        id: indexed.id.clone(),
+        parentid: indexed.parentid.clone(),
+        origid: indexed.origid.clone(),
+        revision: indexed.revision,
+        latest: indexed.latest,
+        writes: indexed.writes,
+        views: indexed.views,
+        backlinks: indexed.backlinks.clone(),
+        last_reviewed: indexed.last_reviewed,
+        ..doc.clone()
+    };
+    probe == *indexed
+}
@@ -0,0 +1,344 @@
+use crate::client::{Client, Enqueued};
+use crate::config::ImportConfig;
+use crate::document;
+use crate::error::{ParseError, RequestFailed};
+use crate::status;
+use color_eyre::Report;
+use eyre::bail;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::Path;
+
+/// Index the notes are stored in
+pub const INDEX: &str = "notes";
+
+/// Attributes the filter grammar, links, backlinks, revisions and review filter on
+pub const FILTERABLE: &[&str] = &[
+    "id",
+    "tags",
+    "date",
+    "authors",
+    "weight",
+    "latest",
+    "slug",
+    "links",
+    "origid",
+    "backlinks",
+    "word_count",
+    "reading_minutes",
+    "last_reviewed",
+];
+
+/// Attributes results can be sorted by
+pub const SORTABLE: &[&str] = &[
+    "date",
+    "weight",
+    "revision",
+    "views",
+    "word_count",
+    "reading_minutes",
+];
+
+/// Attributes searched by the query, in order of importance
+pub const SEARCHABLE: &[&str] = &["title", "subtitle", "tags", "body"];
+
+/// Custom ranking rule that floats manually weighted notes to the top
+pub const WEIGHT_RULE: &str = "weight:desc";
+
+/// Matches a search can page through, raised from the server default of 1000 so that bulk
+/// commands reading every match of a filter see them all
+pub const MAX_TOTAL_HITS: u32 = 100_000;
+
+/// The index settings this CLI manages, unset fields are left alone when applied
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "filterable_attributes")]
+    pub filterable_attributes: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "sortable_attributes")]
+    pub sortable_attributes: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "searchable_attributes")]
+    pub searchable_attributes: Option<Vec<String>>,
+}
+
+/// Per setting, the expected attributes it lacks and the unexpected ones it has
+#[derive(Debug, Default)]
+pub struct SettingsDiff(Vec<(&'static str, Vec<String>, Vec<String>)>);
+
+impl Settings {
+    /// The settings the `Document` schema needs
+    pub fn expected() -> Settings {
+        let owned = |attrs: &[&str]| Some(attrs.iter().map(|a| a.to_string()).collect());
+        Settings {
+            filterable_attributes: owned(FILTERABLE),
+            sortable_attributes: owned(SORTABLE),
+            searchable_attributes: owned(SEARCHABLE),
+        }
+    }
+
+    /// Read settings from a TOML file if its extension says so, JSON otherwise
+    pub fn from_file(path: &Path) -> Result<Settings, Report> {
+        let s = std::fs::read_to_string(path)?;
+        let parsed = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&s).map_err(|e| e.to_string()),
+            _ => serde_json::from_str(&s).map_err(|e| e.to_string()),
+        };
+        parsed
+            .map_err(|e| ParseError(format!("❌ Failed to parse {}: {}", path.display(), e)).into())
+    }
+
+    /// Compare against `expected`, skipping settings it leaves unset
+    pub fn diff(&self, expected: &Settings) -> SettingsDiff {
+        let fields = [
+            (
+                "filterableAttributes",
+                &self.filterable_attributes,
+                &expected.filterable_attributes,
+            ),
+            (
+                "sortableAttributes",
+                &self.sortable_attributes,
+                &expected.sortable_attributes,
+            ),
+            (
+                "searchableAttributes",
+                &self.searchable_attributes,
+                &expected.searchable_attributes,
+            ),
+        ];
+        let mut diff = Vec::new();
+        for (name, actual, expected) in fields {
+            let expected = match expected {
+                Some(e) => e,
+                None => continue,
+            };
+            let actual = actual.as_deref().unwrap_or_default();
+            // `*` is the server default of searching every attribute
+            let wildcard = actual.iter().any(|a| a == "*");
+            let missing: Vec<String> = expected
+                .iter()
+                .filter(|e| !wildcard && !actual.contains(e))
+                .cloned()
+                .collect();
+            let extra: Vec<String> = actual
+                .iter()
+                .filter(|a| *a != "*" && !expected.contains(a))
+                .cloned()
+                .collect();
+            if !missing.is_empty() || !extra.is_empty() {
+                diff.push((name, missing, extra));
+            }
+        }
+        SettingsDiff(diff)
+    }
+}
+
+impl SettingsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether any expected attribute is missing, extra ones don't break anything
+    pub fn is_missing(&self) -> bool {
+        self.0.iter().any(|(_, missing, _)| !missing.is_empty())
+    }
+}
+
+impl fmt::Display for SettingsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, missing, extra) in &self.0 {
+            writeln!(f, "{}", name)?;
+            for attr in missing {
+                writeln!(f, "  + {}", attr)?;
+            }
+            for attr in extra {
+                writeln!(f, "  - {}", attr)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create the notes index if needed, configure the filterable, sortable and searchable
+/// attributes and how many matches searches page through, and check the server reports the
+/// attributes back
+pub async fn init(client: &Client, verbosity: u8) -> Result<(), Report> {
+    if client.create_index().await? {
+        status!("✅ Created index {}", client.index());
+    } else if verbosity > 0 {
+        status!("✅ Index {} already exists", client.index());
+    }
+
+    let expected = Settings::expected();
+    client.set_settings(&expected).await?;
+    let diff = client.settings().await?.diff(&expected);
+    if diff.is_missing() {
+        bail!("❌ Server settings differ from those expected:\n{}", diff);
+    }
+    client.set_max_total_hits(MAX_TOTAL_HITS).await?;
+    status!("✅ Configured index {}", client.index());
+    Ok(())
+}
+
+/// Rebuild the index from `docs` without serving a half-populated index. The documents go
+/// into a temporary index with the live settings, which is then swapped with the live one.
+pub async fn reimport(
+    client: &Client,
+    docs: &[document::Document],
+    import: &ImportConfig,
+    verbosity: u8,
+) -> Result<(), Report> {
+    let tmp = client.with_index(&format!("{}_reimport", client.index()));
+    // A previous run may have been interrupted
+    if !tmp.create_index().await? {
+        tmp.delete_index().await?;
+        tmp.create_index().await?;
+    }
+    client.create_index().await?;
+    tmp.copy_settings_from(client).await?;
+    let tasks = tmp
+        .add_documents_batched(docs, import.batch_size(), import.concurrency())
+        .await?;
+    client.wait_for_tasks(&tasks).await?;
+    if verbosity > 0 {
+        status!("✅ Imported {} documents into {}", docs.len(), tmp.index());
+    }
+
+    client.swap_with(&tmp).await?;
+    if verbosity > 0 {
+        status!("✅ Swapped {} with {}", tmp.index(), client.index());
+    }
+    tmp.delete_index().await?;
+    status!("✅ Reimported {} documents", docs.len());
+    Ok(())
+}
+
+impl Client {
+    /// Create the index, returning false if it already exists
+    pub async fn create_index(&self) -> Result<bool, Report> {
+        let resp = self
+            .execute(
+                self.post(self.url("indexes")?)
+                    .json(&json!({ "uid": self.index(), "primaryKey": "id" })),
+            )
+            .await?;
+        match resp.status() {
+            StatusCode::ACCEPTED => {
+                let task = self.task_result(resp.json::<Enqueued>().await?.uid).await?;
+                match task.error_code() {
+                    None => Ok(true),
+                    Some("index_already_exists") => Ok(false),
+                    Some(_) => bail!(
+                        "❌ Failed to create index {}: {}",
+                        self.index(),
+                        task.error_message()
+                    ),
+                }
+            }
+            _ => Err(RequestFailed(format!(
+                "❌ Failed to create index {}: {}",
+                self.index(),
+                resp.text().await?
+            ))
+            .into()),
+        }
+    }
+
+    /// Delete the index along with its documents
+    pub async fn delete_index(&self) -> Result<(), Report> {
+        let url = self.url(&format!("indexes/{}", self.index()))?;
+        self.wait_for_task(self.enqueue(self.delete_request(url)).await?)
+            .await
+    }
+
+    /// Give the index every setting of the index `other` is scoped to
+    pub async fn copy_settings_from(&self, other: &Client) -> Result<(), Report> {
+        self.set_all_settings(&other.all_settings().await?).await
+    }
+
+    /// Fetch every setting of the index, including those this CLI doesn't manage
+    pub async fn all_settings(&self) -> Result<Value, Report> {
+        self.send(self.get(self.index_url("settings")?)).await
+    }
+
+    /// Apply settings as `all_settings` returns them and wait for the server to process them
+    pub async fn set_all_settings(&self, settings: &Value) -> Result<(), Report> {
+        let url = self.index_url("settings")?;
+        self.wait_for_task(self.enqueue(self.patch(url).json(settings)).await?)
+            .await
+    }
+
+    /// Atomically exchange the contents of this index with the one `other` is scoped to
+    pub async fn swap_with(&self, other: &Client) -> Result<(), Report> {
+        let resp = self
+            .execute(
+                self.post(self.url("swap-indexes")?)
+                    .json(&json!([{ "indexes": [self.index(), other.index()] }])),
+            )
+            .await?;
+        match resp.status() {
+            s if s.is_success() => self.wait_for_task(resp.json::<Enqueued>().await?.uid).await,
+            s => Err(RequestFailed(format!(
+                "❌ Failed to swap indexes with {}: {}",
+                s,
+                resp.text().await?
+            ))
+            .into()),
+        }
+    }
+
+    /// Let searches page through up to `max` matches and wait for the server to apply it
+    pub async fn set_max_total_hits(&self, max: u32) -> Result<(), Report> {
+        let url = self.index_url("settings/pagination")?;
+        let resp = self
+            .execute(self.patch(url).json(&json!({ "maxTotalHits": max })))
+            .await?;
+        match resp.status() {
+            s if s.is_success() => self.wait_for_task(resp.json::<Enqueued>().await?.uid).await,
+            s => Err(RequestFailed(format!(
+                "❌ Failed to set pagination of {}: {}: {}",
+                self.index(),
+                s,
+                resp.text().await?
+            ))
+            .into()),
+        }
+    }
+
+    /// Fetch the settings this CLI manages
+    pub async fn settings(&self) -> Result<Settings, Report> {
+        self.send(self.get(self.index_url("settings")?)).await
+    }
+
+    /// Apply the fields set in `settings` and wait for the server to process them
+    pub async fn set_settings(&self, settings: &Settings) -> Result<(), Report> {
+        let url = self.index_url("settings")?;
+        self.wait_for_task(self.enqueue(self.patch(url).json(settings)).await?)
+            .await
+    }
+
+    /// Fetch a list valued setting such as `stop-words` or `ranking-rules`
+    pub async fn setting_list(&self, name: &str) -> Result<Vec<String>, Report> {
+        self.send(self.get(self.index_url(&format!("settings/{}", name))?))
+            .await
+    }
+
+    /// Replace a list valued setting and wait for the server to process it
+    pub async fn set_setting_list(&self, name: &str, values: &[String]) -> Result<(), Report> {
+        let url = self.index_url(&format!("settings/{}", name))?;
+        self.wait_for_task(self.enqueue(self.put(url).json(values)).await?)
+            .await
+    }
+
+    /// Restore a setting to the server default and wait for the server to process it
+    pub async fn reset_setting(&self, name: &str) -> Result<(), Report> {
+        let url = self.index_url(&format!("settings/{}", name))?;
+        self.wait_for_task(self.enqueue(self.delete_request(url)).await?)
+            .await
+    }
+}
@@ -0,0 +1,242 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr; // Provides `width()` method on String
+
+/// Single line text buffer with a cursor, backing the query and filter input boxes. The cursor
+/// moves over grapheme clusters so combined characters and emoji are edited as a unit.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LineInput {
+    value: String,
+    /// Cursor position in grapheme clusters, 0 is before the first one
+    cursor: usize,
+}
+
+impl LineInput {
+    pub fn new() -> Self {
+        LineInput {
+            ..Default::default()
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Replace the contents, leaving the cursor at the end
+    pub fn set(&mut self, value: &str) {
+        self.value = value.to_owned();
+        self.cursor = self.len();
+    }
+
+    /// Number of terminal columns between the start of the input and the cursor, wide
+    /// characters such as CJK and emoji take up two
+    pub fn cursor_width(&self) -> usize {
+        self.value[..self.byte_idx(self.cursor)].width()
+    }
+
+    fn len(&self) -> usize {
+        self.value.graphemes(true).count()
+    }
+
+    /// Byte offset of the grapheme cluster at position `idx`
+    fn byte_idx(&self, idx: usize) -> usize {
+        self.value
+            .grapheme_indices(true)
+            .nth(idx)
+            .map(|(b, _)| b)
+            .unwrap_or_else(|| self.value.len())
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let b = self.byte_idx(self.cursor);
+        self.value.insert(b, c);
+        // Combining characters and joiners merge into the preceding cluster rather than
+        // adding one, so recount instead of incrementing
+        self.cursor = self.value[..b + c.len_utf8()].graphemes(true).count();
+    }
+
+    /// Delete the grapheme cluster before the cursor
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.remove_at(self.cursor);
+        }
+    }
+
+    /// Delete the grapheme cluster under the cursor
+    pub fn delete(&mut self) {
+        if self.cursor < self.len() {
+            self.remove_at(self.cursor);
+        }
+    }
+
+    fn remove_at(&mut self, idx: usize) {
+        let (from, to) = (self.byte_idx(idx), self.byte_idx(idx + 1));
+        self.value.replace_range(from..to, "");
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        if self.cursor < self.len() {
+            self.cursor += 1;
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.len();
+    }
+
+    /// Delete the word before the cursor, along with any whitespace following it
+    pub fn delete_word(&mut self) {
+        let graphemes: Vec<&str> = self.value.graphemes(true).collect();
+        let is_space = |g: &str| g.chars().all(char::is_whitespace);
+        let mut start = self.cursor;
+        while start > 0 && is_space(graphemes[start - 1]) {
+            start -= 1;
+        }
+        while start > 0 && !is_space(graphemes[start - 1]) {
+            start -= 1;
+        }
+        let (from, to) = (self.byte_idx(start), self.byte_idx(self.cursor));
+        self.value.replace_range(from..to, "");
+        self.cursor = start;
+    }
+
+    /// Byte offset where the run of `is_word` chars ending at the cursor starts
+    fn word_start(&self, is_word: impl Fn(char) -> bool) -> usize {
+        let end = self.byte_idx(self.cursor);
+        self.value[..end]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_word(*c))
+            .last()
+            .map(|(b, _)| b)
+            .unwrap_or(end)
+    }
+
+    /// The run of `is_word` chars immediately before the cursor
+    pub fn current_word(&self, is_word: impl Fn(char) -> bool) -> &str {
+        &self.value[self.word_start(is_word)..self.byte_idx(self.cursor)]
+    }
+
+    /// Replace the run of `is_word` chars before the cursor with `word`
+    pub fn replace_current_word(&mut self, word: &str, is_word: impl Fn(char) -> bool) {
+        let (from, to) = (self.word_start(is_word), self.byte_idx(self.cursor));
+        self.value.replace_range(from..to, word);
+        self.cursor = self.value[..from + word.len()].graphemes(true).count();
+    }
+
+    pub fn clear(&mut self) {
+        self.value.clear();
+        self.cursor = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(s: &str) -> LineInput {
+        let mut input = LineInput::new();
+        for c in s.chars() {
+            input.insert(c);
+        }
+        input
+    }
+
+    #[test]
+    fn combining_characters_are_one_grapheme() {
+        let mut input = typed("cafe\u{301}");
+        assert_eq!(input.cursor, 4);
+        input.backspace();
+        assert_eq!(input.value(), "caf");
+    }
+
+    #[test]
+    fn emoji_sequences_are_edited_as_a_unit() {
+        // Family joined with zero width joiners, then a flag of two regional indicators
+        let mut input = typed("a👩\u{200d}👩\u{200d}👧🇳🇿");
+        assert_eq!(input.len(), 3);
+        input.left();
+        input.backspace();
+        assert_eq!(input.value(), "a🇳🇿");
+        input.delete();
+        assert_eq!(input.value(), "a");
+    }
+
+    #[test]
+    fn cursor_width_counts_wide_characters_twice() {
+        let mut input = typed("日本go");
+        assert_eq!(input.cursor_width(), 6);
+        input.left();
+        input.left();
+        assert_eq!(input.cursor_width(), 4);
+    }
+
+    #[test]
+    fn cursor_at_the_start() {
+        let mut input = typed("ab");
+        input.home();
+        input.left();
+        input.backspace();
+        input.delete_word();
+        assert_eq!((input.value(), input.cursor), ("ab", 0));
+        input.insert('x');
+        assert_eq!((input.value(), input.cursor), ("xab", 1));
+        input.home();
+        input.delete();
+        assert_eq!((input.value(), input.cursor), ("ab", 0));
+    }
+
+    #[test]
+    fn cursor_at_the_end() {
+        let mut input = typed("ab");
+        input.right();
+        input.delete();
+        assert_eq!((input.value(), input.cursor), ("ab", 2));
+        input.home();
+        input.end();
+        input.backspace();
+        assert_eq!((input.value(), input.cursor), ("a", 1));
+    }
+
+    #[test]
+    fn set_leaves_the_cursor_at_the_end() {
+        let mut input = typed("old");
+        input.set("né👍");
+        assert_eq!(input.cursor, 3);
+        input.clear();
+        assert_eq!((input.value(), input.cursor), ("", 0));
+    }
+
+    #[test]
+    fn delete_word_takes_the_trailing_whitespace() {
+        let mut input = typed("foo bar  ");
+        input.delete_word();
+        assert_eq!((input.value(), input.cursor), ("foo ", 4));
+        input.delete_word();
+        assert_eq!((input.value(), input.cursor), ("", 0));
+    }
+
+    #[test]
+    fn current_word_is_replaced_in_place() {
+        let is_word = |c: char| c.is_alphanumeric();
+        let mut input = typed("tag=ru + x");
+        input.end();
+        for _ in 0..4 {
+            input.left();
+        }
+        assert_eq!(input.current_word(is_word), "ru");
+        input.replace_current_word("rust", is_word);
+        assert_eq!(input.value(), "tag=rust + x");
+        assert_eq!(input.cursor, 8);
+        input.home();
+        assert_eq!(input.current_word(is_word), "");
+    }
+}
@@ -1,55 +1,177 @@
-use crate::{api, document};
+use crate::blocking::Client;
+use crate::input::LineInput;
+use crate::keys::{Action, KeyMap};
+use crate::theme::Theme;
+use crate::{api, attachment, config, document, logging, state, style, tags};
 use ansi_to_tui::ansi_to_text;
 use color_eyre::Report;
 use eyre::bail;
-use reqwest::header::CONTENT_TYPE;
+use similar::TextDiff;
+use std::collections::HashSet;
 use std::io::{stdout, Write};
 use std::process::Command;
+use std::time::{Duration, Instant};
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style as hStyle, ThemeSet};
 use syntect::parsing::SyntaxSet;
 use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 use tempfile::Builder;
-use termion::{event::Key, raw::IntoRawMode, screen::AlternateScreen};
+use termion::{
+    event::{Key, MouseButton, MouseEvent},
+    input::MouseTerminal,
+    raw::{IntoRawMode, RawTerminal},
+    screen::AlternateScreen,
+};
+use tracing::{error, Level};
 use tui::{
-    backend::TermionBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    backend::{Backend, TermionBackend, TestBackend},
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
     text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
-use url::Url;
+use unicode_width::UnicodeWidthStr;
+
+/// Number of hits requested from the server per page of results
+const PAGE_SIZE: u32 = 50;
+
+/// How long a document has to stay in the preview to count as viewed
+const VIEW_DWELL: Duration = Duration::from_secs(3);
+
+/// Most tag completions shown at once
+const MAX_COMPLETIONS: usize = 8;
+
+/// Whether the chars of `pattern` appear in order, not necessarily adjacent, in `text`
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars();
+    pattern.chars().all(|p| text.any(|t| t == p))
+}
+
+/// Characters the filter grammar accepts in a tag
+fn is_tag_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || ".-_/".contains(c)
+}
+
+type Terminal =
+    tui::Terminal<TermionBackend<AlternateScreen<MouseTerminal<RawTerminal<std::io::Stdout>>>>>;
+
+/// Take over the terminal, with mouse reporting enabled
+fn setup_terminal() -> Terminal {
+    tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
+        MouseTerminal::from(stdout().into_raw_mode().unwrap()),
+    )))
+    .unwrap()
+}
+
+/// Screen areas from the last draw, used to work out what a mouse event landed on
+#[derive(Default)]
+struct Areas {
+    matches: Rect,
+    preview: Rect,
+    inputs: [Rect; 2],
+}
+
+impl Areas {
+    fn contains(area: Rect, x: u16, y: u16) -> bool {
+        x >= area.x && x < area.x + area.width && y >= area.y && y < area.y + area.height
+    }
+}
+
+/// Snapshot of the results list, saved when following a link so ctrl-o can restore it
+struct Frame {
+    matches: Vec<document::Document>,
+    num_hits: u32,
+    selected: Option<usize>,
+    revisions: bool,
+}
 
 /// TerminalApp holds the state of the application
 pub(crate) struct TerminalApp {
     /// Current value of the query_input box
-    pub(crate) query_input: String,
+    pub(crate) query_input: LineInput,
     /// Current value of the filter_input box
-    pub(crate) filter_input: String,
+    pub(crate) filter_input: LineInput,
     /// Preview window
     pub(crate) preview: String,
     /// Query Matches
     pub(crate) matches: Vec<document::Document>,
+    /// Total number of hits the server reported for the current query
+    pub(crate) num_hits: u32,
+    /// Serialized payload of the query that produced the current matches
+    pub(crate) current_query: String,
     /// Keep track of which matches are selected
     pub(crate) selected_state: ListState,
     /// Display error messages
     pub(crate) error: String,
     /// Display the serialized payload to send to the server
     pub(crate) debug: String,
-    // TODO Add fields for sort expression
+    /// Sort expression sent with each query
+    pub(crate) sort: Vec<String>,
+    /// Index into the selected document's links, cycled with ctrl-h/ctrl-l
+    pub(crate) link_idx: Option<usize>,
+    /// Results lists to return to after following a link
+    back_stack: Vec<Frame>,
+    /// Whether the list holds a revision chain, previewed as diffs against the prior revision
+    pub(crate) revisions: bool,
+    /// Toggle the pane listing documents that link to the selected one
+    pub(crate) show_backlinks: bool,
+    /// Documents linking to the selected document
+    pub(crate) backlinks: Vec<document::Document>,
+    /// Id of the document the backlinks were fetched for
+    backlinks_for: Option<String>,
+    /// Transient feedback shown below the input boxes
+    pub(crate) status: String,
+    /// Set by ctrl-y, the next key picks what to yank to the clipboard
+    pending_yank: bool,
+    /// Created on first use, kept alive so the yanked contents stay available
+    clipboard: Option<arboard::Clipboard>,
+    /// Which input box has focus, 0 for query and 1 for filter
     inp_idx: usize,
-    // Length here should stay in sync with the number of editable areas
-    inp_widths: [i32; 2],
+    /// Previously submitted inputs
+    pub(crate) history: state::History,
+    /// Entry of the focused box's history being shown while browsing with Up/Down
+    history_pos: Option<usize>,
+    /// Pattern typed since ctrl-r started a fuzzy search over the focused box's history
+    history_search: Option<LineInput>,
+    /// Which of the history search matches, most recent first, is shown
+    history_match: usize,
+    /// Every tag with its document count, fetched the first time the filter box has focus
+    tags: Option<Vec<(String, u32)>>,
+    /// Tags completing the word being typed in the filter box
+    pub(crate) completions: Vec<String>,
+    /// Highlighted completion
+    completion_idx: usize,
+    /// Word the completions were computed for, so they are only recomputed when it changes
+    completion_word: String,
+    /// Lines scrolled past at the top of the preview
+    preview_scroll: u16,
+    /// Id of the document in the preview and when it was put there
+    preview_since: Option<(String, Instant)>,
+    /// Documents marked for picking or bulk tag editing, kept across query changes
+    pub(crate) marked: Vec<document::Document>,
+    /// Tags being typed for the marked documents, e.g. `+todo -draft`
+    tag_prompt: Option<LineInput>,
+    /// Ids of the documents whose views were bumped this session, each counts once
+    viewed: HashSet<String>,
+    /// Show archived documents among the matches
+    include_archived: bool,
+    /// Save the session and history, off when driven by tests
+    persist: bool,
+    /// First match visible in the list, mirrors the offset tui keeps private in `ListState`
+    list_offset: usize,
+    areas: Areas,
 }
 
 impl TerminalApp {
     // TODO make this work for multiple selections
-    pub fn get_selected(&mut self) -> Vec<String> {
-        let ret: Vec<String> = Vec::new();
-        if let Some(i) = self.selected_state.selected() {
-            vec![self.matches[i].id.to_owned()]
-        } else {
-            ret
+    /// The marked documents, or the selected one if none are marked
+    pub fn get_selected(&mut self) -> Vec<document::Document> {
+        if !self.marked.is_empty() {
+            return self.marked.clone();
+        }
+        match self.selected_state.selected() {
+            Some(i) => vec![self.matches[i].to_owned()],
+            None => Vec::new(),
         }
     }
 
@@ -60,11 +182,32 @@ impl TerminalApp {
         }
     }
 
+    /// Whether the server has more hits than have been fetched so far
+    pub fn has_more(&self) -> bool {
+        (self.matches.len() as u32) < self.num_hits
+    }
+
+    /// Whether the cursor sits on the last fetched match and another page is available
+    pub fn wants_next_page(&self) -> bool {
+        match self.selected_state.selected() {
+            Some(i) => i + 1 >= self.matches.len() && self.has_more(),
+            None => false,
+        }
+    }
+
     pub fn next(&mut self) {
+        if self.matches.is_empty() {
+            return;
+        }
         let i = match self.selected_state.selected() {
             Some(i) => {
                 if i >= self.matches.len() - 1 {
-                    0
+                    // Stay put while more pages remain, they get appended below the cursor
+                    if self.has_more() {
+                        i
+                    } else {
+                        0
+                    }
                 } else {
                     i + 1
                 }
@@ -72,9 +215,13 @@ impl TerminalApp {
             None => 0,
         };
         self.selected_state.select(Some(i));
+        self.link_idx = None;
     }
 
     pub fn previous(&mut self) {
+        if self.matches.is_empty() {
+            return;
+        }
         let i = match self.selected_state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -86,19 +233,464 @@ impl TerminalApp {
             None => 0,
         };
         self.selected_state.select(Some(i));
+        self.link_idx = None;
+    }
+
+    /// Links of the currently selected document
+    pub fn selected_links(&self) -> &[String] {
+        match self.selected_state.selected() {
+            Some(i) => &self.matches[i].links,
+            None => &[],
+        }
+    }
+
+    /// Highlight the next (or previous, for a negative step) link of the selected document
+    pub fn cycle_link(&mut self, step: isize) {
+        let len = self.selected_links().len() as isize;
+        if len == 0 {
+            self.link_idx = None;
+            return;
+        }
+        let i = match self.link_idx {
+            Some(i) => (i as isize + step).rem_euclid(len),
+            None if step < 0 => len - 1,
+            None => 0,
+        };
+        self.link_idx = Some(i as usize);
+    }
+
+    /// The currently highlighted link, if any
+    pub fn current_link(&self) -> Option<&String> {
+        self.link_idx.and_then(|i| self.selected_links().get(i))
+    }
+
+    /// Show `doc` as the only match, remembering the current results for `go_back`
+    pub fn follow(&mut self, doc: document::Document) {
+        self.push_frame(vec![doc], false);
+    }
+
+    /// Replace the selected document's entry with its revision chain, newest first
+    pub fn show_revisions(&mut self, chain: Vec<document::Document>) {
+        self.push_frame(chain, true);
+    }
+
+    /// Show `matches`, saving the current list for ctrl-o
+    fn push_frame(&mut self, matches: Vec<document::Document>, revisions: bool) {
+        let matches = std::mem::replace(&mut self.matches, matches);
+        self.back_stack.push(Frame {
+            matches,
+            num_hits: self.num_hits,
+            selected: self.selected_state.selected(),
+            revisions: self.revisions,
+        });
+        self.num_hits = self.matches.len() as u32;
+        self.revisions = revisions;
+        self.selected_state.select(Some(0));
+        self.link_idx = None;
+        self.update_preview();
+    }
+
+    /// Restore the results list from before the last followed link
+    pub fn go_back(&mut self) {
+        if let Some(frame) = self.back_stack.pop() {
+            self.matches = frame.matches;
+            self.num_hits = frame.num_hits;
+            self.revisions = frame.revisions;
+            self.selected_state.select(frame.selected);
+            self.link_idx = None;
+            self.update_preview();
+        }
+    }
+
+    /// Id of the currently selected document
+    pub fn selected_id(&self) -> Option<&String> {
+        self.selected_state.selected().map(|i| &self.matches[i].id)
+    }
+
+    pub fn is_marked(&self, id: &str) -> bool {
+        self.marked.iter().any(|d| d.id == id)
+    }
+
+    /// Mark the selected document, or unmark it if already marked, and move to the next
+    pub fn toggle_mark(&mut self) {
+        let i = match self.selected_state.selected() {
+            Some(i) => i,
+            None => return,
+        };
+        let id = &self.matches[i].id;
+        if self.is_marked(id) {
+            self.marked.retain(|d| &d.id != id);
+        } else {
+            self.marked.push(self.matches[i].to_owned());
+        }
+        self.status = format!("{} marked", self.marked.len());
+        self.next();
+    }
+
+    /// Handle a key while prompting for tags, returning the tags once entered
+    fn tag_prompt_key(&mut self, action: Option<Action>, key: Key) -> Option<String> {
+        let prompt = self.tag_prompt.as_mut().unwrap();
+        match (action, key) {
+            (Some(Action::Select), _) => {
+                let tags = prompt.value().to_owned();
+                self.tag_prompt = None;
+                self.status = String::from("");
+                return Some(tags);
+            }
+            (Some(Action::Quit), _) | (_, Key::Esc) => {
+                self.tag_prompt = None;
+                self.status = String::from("");
+                return None;
+            }
+            (Some(Action::Backspace), _) => prompt.backspace(),
+            (Some(Action::DeleteWord), _) => prompt.delete_word(),
+            (Some(Action::ClearInput), _) => prompt.clear(),
+            (None, Key::Char(c)) => prompt.insert(c),
+            _ => {}
+        }
+        self.status = format!("Tags (+add -remove): {}", prompt.value());
+        None
+    }
+
+    /// Whether the backlinks pane is showing results for a different document
+    pub fn backlinks_stale(&self) -> bool {
+        self.show_backlinks && self.selected_id() != self.backlinks_for.as_ref()
+    }
+
+    /// Copy part of the selected document to the system clipboard, returning a status message
+    pub fn yank(&mut self, what: Key) -> String {
+        let doc = match self.selected_state.selected() {
+            Some(i) => &self.matches[i],
+            None => return String::from("Nothing selected to yank"),
+        };
+        let (name, contents) = match what {
+            Key::Char('i') => ("id", doc.id.to_owned()),
+            Key::Char('b') => ("body", doc.body.to_owned()),
+            Key::Char('p') => ("path", doc.filename.to_owned()),
+            _ => return String::from("Yank cancelled"),
+        };
+        if self.clipboard.is_none() {
+            match arboard::Clipboard::new() {
+                Ok(c) => self.clipboard = Some(c),
+                Err(e) => return format!("Clipboard unavailable: {}", e),
+            }
+        }
+        match self.clipboard.as_mut().unwrap().set_text(contents) {
+            Ok(_) => format!("Yanked {} of \"{}\"", name, doc.title),
+            Err(e) => format!("Failed to yank {}: {}", name, e),
+        }
+    }
+
+    /// Restore the inputs and sort from a saved session
+    fn restore(&mut self, session: &state::Session) {
+        self.query_input.set(&session.query);
+        self.filter_input.set(&session.filter);
+        if !session.sort.is_empty() {
+            self.sort = session.sort.to_owned();
+        }
+    }
+
+    /// The input box that currently has focus
+    fn active_input(&mut self) -> &mut LineInput {
+        match self.inp_idx {
+            0 => &mut self.query_input,
+            _ => &mut self.filter_input,
+        }
+    }
+
+    /// Select the match with the given id, if it is among the fetched matches
+    fn select_id(&mut self, id: &str) {
+        if let Some(i) = self.matches.iter().position(|m| m.id == id) {
+            self.selected_state.select(Some(i));
+            self.update_preview();
+        }
+    }
+
+    /// Snapshot the inputs, sort and selection so the next session can pick up from here
+    fn session(&self) -> state::Session {
+        state::Session {
+            query: self.query_input.value().to_owned(),
+            filter: self.filter_input.value().to_owned(),
+            sort: self.sort.to_owned(),
+            selected: self.selected_id().cloned(),
+        }
+    }
+
+    /// Show the selected document in the preview, scrolled back to the top
+    pub fn update_preview(&mut self) {
+        self.preview = match self.revision_diff() {
+            Some(diff) => diff,
+            None => self.get_selected_contents(),
+        };
+        self.preview_scroll = 0;
+        self.preview_since = self.selected_id().map(|id| (id.to_owned(), Instant::now()));
+    }
+
+    /// Unified diff of the selected revision against the one before it, when showing a chain
+    fn revision_diff(&self) -> Option<String> {
+        if !self.revisions {
+            return None;
+        }
+        let doc = &self.matches[self.selected_state.selected()?];
+        let prev = self
+            .matches
+            .iter()
+            .filter(|d| d.revision < doc.revision)
+            .max_by_key(|d| d.revision)?;
+        let diff = TextDiff::from_lines(&prev.body, &doc.body)
+            .unified_diff()
+            .header(
+                &format!("revision {}", prev.revision),
+                &format!("revision {}", doc.revision),
+            )
+            .to_string();
+        Some(diff)
+    }
+
+    /// Whether the previewed document has been showing long enough to count as viewed
+    fn view_due(&self) -> bool {
+        match (&self.preview_since, self.selected_id()) {
+            (Some((id, since)), Some(selected)) => {
+                id == selected && !self.viewed.contains(id) && since.elapsed() >= VIEW_DWELL
+            }
+            _ => false,
+        }
+    }
+
+    /// Keep `list_offset` in step with how tui scrolls the list to keep the selection visible
+    fn update_list_offset(&mut self, height: usize) {
+        match self.selected_state.selected() {
+            Some(i) if i >= self.list_offset + height => self.list_offset = i + 1 - height,
+            Some(i) if i < self.list_offset => self.list_offset = i,
+            Some(_) => {}
+            None => self.list_offset = 0,
+        }
+    }
+
+    /// Click to select a match or focus an input, scroll the list or preview with the wheel
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        let (x, y, button) = match event {
+            // Mouse coordinates are 1-based
+            MouseEvent::Press(button, x, y) => (x - 1, y - 1, button),
+            _ => return,
+        };
+        let areas = &self.areas;
+        if Areas::contains(areas.matches, x, y) {
+            match button {
+                // Skip the top border of the list block
+                MouseButton::Left if y > areas.matches.y => {
+                    let i = self.list_offset + (y - areas.matches.y - 1) as usize;
+                    if i < self.matches.len() {
+                        self.selected_state.select(Some(i));
+                        self.link_idx = None;
+                        self.update_preview();
+                    }
+                }
+                MouseButton::WheelDown => {
+                    self.next();
+                    self.update_preview();
+                }
+                MouseButton::WheelUp => {
+                    self.previous();
+                    self.update_preview();
+                }
+                _ => {}
+            }
+        } else if Areas::contains(areas.preview, x, y) {
+            match button {
+                MouseButton::WheelDown => self.preview_scroll += 1,
+                MouseButton::WheelUp => self.preview_scroll = self.preview_scroll.saturating_sub(1),
+                _ => {}
+            }
+        } else if let Some(i) = areas.inputs.iter().position(|a| Areas::contains(*a, x, y)) {
+            if button == MouseButton::Left {
+                self.inp_idx = i;
+            }
+        }
+    }
+
+    /// Value of the input box that currently has focus
+    fn active_value(&self) -> &str {
+        match self.inp_idx {
+            0 => self.query_input.value(),
+            _ => self.filter_input.value(),
+        }
+    }
+
+    /// History of the input box that currently has focus, oldest first
+    fn history_entries(&self) -> &[String] {
+        match self.inp_idx {
+            0 => &self.history.query,
+            _ => &self.history.filter,
+        }
+    }
+
+    /// Show the entry before `pos` (or the latest if not yet browsing) in the focused box
+    fn history_back(&mut self, pos: Option<usize>) {
+        let len = self.history_entries().len();
+        let pos = match pos {
+            Some(p) => p.saturating_sub(1),
+            None if len > 0 => len - 1,
+            None => return,
+        };
+        let entry = self.history_entries()[pos].to_owned();
+        self.active_input().set(&entry);
+        self.history_pos = Some(pos);
+    }
+
+    /// Show the entry after `pos` in the focused box, or clear it when past the latest
+    fn history_forward(&mut self, pos: usize) {
+        if pos + 1 < self.history_entries().len() {
+            let entry = self.history_entries()[pos + 1].to_owned();
+            self.active_input().set(&entry);
+            self.history_pos = Some(pos + 1);
+        } else {
+            self.active_input().clear();
+        }
+    }
+
+    /// History entries matching the ctrl-r pattern, most recent first without repeats
+    fn history_search_matches(&self) -> Vec<&String> {
+        let pattern = match &self.history_search {
+            Some(p) => p.value().to_lowercase(),
+            None => return Vec::new(),
+        };
+        let mut seen = std::collections::HashSet::new();
+        self.history_entries()
+            .iter()
+            .rev()
+            .filter(|e| fuzzy_match(&pattern, &e.to_lowercase()) && seen.insert(*e))
+            .collect()
+    }
+
+    /// Handle a key while the ctrl-r history search is active
+    fn history_search_key(&mut self, action: Option<Action>, key: Key) {
+        let pattern = self.history_search.as_mut().unwrap();
+        match (action, key) {
+            (Some(Action::HistorySearch), _) => self.history_match += 1,
+            (Some(Action::Backspace), _) => {
+                pattern.backspace();
+                self.history_match = 0;
+            }
+            (None, Key::Char(c)) => {
+                pattern.insert(c);
+                self.history_match = 0;
+            }
+            (Some(Action::Select), _) | (Some(Action::SwitchInput), _) => {
+                if let Some(entry) = self.history_search_matches().get(self.history_match) {
+                    let entry = entry.to_string();
+                    self.active_input().set(&entry);
+                }
+                self.history_search = None;
+            }
+            (Some(Action::Quit), _) | (_, Key::Esc) => self.history_search = None,
+            _ => {}
+        }
+        if self.history_search.is_none() {
+            self.status = String::from("");
+            return;
+        }
+        // Stay on the oldest match once ctrl-r runs past it
+        let len = self.history_search_matches().len();
+        self.history_match = self.history_match.min(len.saturating_sub(1));
+        let matches = self.history_search_matches();
+        let pattern = self.history_search.as_ref().unwrap().value();
+        self.status = match matches.get(self.history_match) {
+            Some(entry) => format!("(reverse-i-search)`{}': {}", pattern, entry),
+            None => format!("(failing reverse-i-search)`{}'", pattern),
+        };
+    }
+
+    /// Add the current inputs to the history and save it, if saving
+    fn record_history(&mut self) -> Result<(), Report> {
+        let (query, filter) = (self.query_input.value(), self.filter_input.value());
+        self.history.push(query, filter);
+        if !self.persist {
+            return Ok(());
+        }
+        self.history.save()
+    }
+
+    /// Whether the tag completion popup is showing
+    pub fn completing(&self) -> bool {
+        self.inp_idx == 1 && !self.completions.is_empty()
+    }
+
+    /// Recompute tag completions if the word before the filter cursor changed
+    fn update_completions(&mut self) {
+        let word = match self.inp_idx {
+            1 => self.filter_input.current_word(is_tag_char).to_owned(),
+            _ => String::new(),
+        };
+        if word == self.completion_word {
+            return;
+        }
+        self.completions = match (&self.tags, word.is_empty()) {
+            (Some(tags), false) => tags
+                .iter()
+                .map(|(tag, _)| tag)
+                .filter(|tag| tag.starts_with(&word) && **tag != word)
+                .take(MAX_COMPLETIONS)
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        };
+        self.completion_idx = 0;
+        self.completion_word = word;
+    }
+
+    /// Move the completion highlight by `step`, wrapping around
+    fn cycle_completion(&mut self, step: isize) {
+        let len = self.completions.len() as isize;
+        self.completion_idx = (self.completion_idx as isize + step).rem_euclid(len) as usize;
+    }
+
+    /// Replace the word before the filter cursor with the highlighted completion
+    fn accept_completion(&mut self) {
+        let tag = self.completions[self.completion_idx].to_owned();
+        self.filter_input.replace_current_word(&tag, is_tag_char);
+        self.completions.clear();
     }
 
     fn new() -> TerminalApp {
         TerminalApp {
-            query_input: String::new(),
-            filter_input: String::new(),
+            query_input: LineInput::new(),
+            filter_input: LineInput::new(),
             preview: String::new(),
             matches: Vec::new(),
+            num_hits: 0,
+            current_query: String::new(),
             selected_state: ListState::default(),
             error: String::new(),
             debug: String::new(),
+            sort: vec!["date:desc".to_owned()],
+            link_idx: None,
+            back_stack: Vec::new(),
+            revisions: false,
+            show_backlinks: false,
+            backlinks: Vec::new(),
+            backlinks_for: None,
+            status: String::new(),
+            pending_yank: false,
+            clipboard: None,
             inp_idx: 0,
-            inp_widths: [0, 0],
+            history: state::History::default(),
+            history_pos: None,
+            history_search: None,
+            history_match: 0,
+            tags: None,
+            completions: Vec::new(),
+            completion_idx: 0,
+            completion_word: String::new(),
+            preview_scroll: 0,
+            preview_since: None,
+            viewed: HashSet::new(),
+            marked: Vec::new(),
+            tag_prompt: None,
+            include_archived: false,
+            persist: true,
+            list_offset: 0,
+            areas: Areas::default(),
         }
     }
 }
@@ -120,36 +712,118 @@ pub fn setup_panic() {
     }));
 }
 
+/// How the interactive query runs
+pub struct Options {
+    pub verbosity: u8,
+    pub pager: String,
+    pub editor: String,
+    /// Start with empty inputs instead of restoring the last session
+    pub fresh: bool,
+    pub include_archived: bool,
+    /// Where key presses come from
+    pub keys: event::Source,
+}
+
 /// Interactive query interface
 pub fn query(
-    client: reqwest::blocking::Client,
-    uri: Url,
-    verbosity: u8,
-    pager: String,
-    editor: String,
-) -> Result<Vec<String>, Report> {
-    let mut tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
-        stdout().into_raw_mode().unwrap(),
-    )))
-    .unwrap();
+    client: Client,
+    options: &Options,
+    config: &config::Config,
+) -> Result<Vec<document::Document>, Report> {
+    let (picked, mut tui) = run(client, options, config, setup_terminal, true)?;
+    tui.clear().unwrap();
+    Ok(picked)
+}
+
+/// The interactive query drawn on an off-screen terminal `width` columns by `height` rows, for
+/// tests driving it with keys from `options`. Returns the documents picked along with the
+/// screen as last drawn. The last session and history are neither restored nor saved.
+pub fn query_headless(
+    client: Client,
+    options: &Options,
+    config: &config::Config,
+    width: u16,
+    height: u16,
+) -> Result<(Vec<document::Document>, Buffer), Report> {
+    let open = || tui::Terminal::new(TestBackend::new(width, height)).unwrap();
+    let (picked, tui) = run(client, options, config, open, false)?;
+    Ok((picked, tui.backend().buffer().clone()))
+}
+
+/// Run the interactive query on the terminal `open` takes over, again after each time it is
+/// handed back to an editor or pager, until documents are picked or it is quit. Returns them
+/// and the terminal, still showing the last screen. Unless `persist`, the last session and
+/// history are neither restored nor saved.
+fn run<B: Backend>(
+    client: Client,
+    options: &Options,
+    config: &config::Config,
+    open: impl Fn() -> tui::Terminal<B>,
+    persist: bool,
+) -> Result<(Vec<document::Document>, tui::Terminal<B>), Report> {
+    // Check the configured keys and theme before taking over the terminal
+    let keymap = KeyMap::new(&config.keys)?;
+    let theme = if style::color() {
+        Theme::from_config(&config.theme)?
+    } else {
+        Theme::monochrome()
+    };
 
     let ps = SyntaxSet::load_defaults_newlines();
     let ts = ThemeSet::load_defaults();
 
     let syntax = ps.find_syntax_by_extension("md").unwrap();
-    let mut highlighter = HighlightLines::new(syntax, &ts.themes["Solarized (dark)"]);
-    //let mut highlighter = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
+    let mut highlighter = match &theme.syntax {
+        Some(name) => match ts.themes.get(name) {
+            Some(t) => Some(HighlightLines::new(syntax, t)),
+            None => bail!(
+                "❌ Unknown syntax theme {}, available: {:?}",
+                name,
+                ts.themes.keys().collect::<Vec<_>>()
+            ),
+        },
+        None => None,
+    };
+
+    // Log events would corrupt the alternate screen, show them in the debug and error panes.
+    // Declared first so anything left over is printed once the terminal is restored
+    let _capture = logging::capture();
+    let mut tui = open();
 
     // Setup event handlers
-    let mut events = event::Events::new();
+    let mut events = event::Events::from_source(&options.keys);
 
-    // Create default app state
+    // Create default app state, picking up where the last session left off unless asked not to
     let mut app = TerminalApp::new();
+    app.include_archived = options.include_archived;
+    app.persist = persist;
+    let mut picked = false;
+    if persist {
+        app.history = state::History::load();
+    }
+    let session = if options.fresh || !persist {
+        state::Session::default()
+    } else {
+        state::Session::load()
+    };
+    app.restore(&session);
+    refresh(&mut app, &client);
+    if let Some(id) = &session.selected {
+        app.select_id(id);
+    }
 
     loop {
+        for (level, line) in logging::drain_captured() {
+            if level <= Level::WARN {
+                app.error = line;
+            } else {
+                app.debug = line;
+            }
+        }
+
         // Draw UI
         if let Err(e) = tui.draw(|f| {
-            let main = if verbosity > 0 {
+            let main = if options.verbosity > 0 {
                 // Enable debug and error areas
                 Layout::default()
                     .direction(Direction::Vertical)
@@ -190,16 +864,65 @@ pub fn query(
 
             // Preview area where content is displayed
             let mut preview_text = String::from("");
-            for line in LinesWithEndings::from(app.preview.as_ref()) {
-                let ranges: Vec<(hStyle, &str)> = highlighter.highlight(line, &ps);
-                let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
-                preview_text.push_str(&escaped);
+            match highlighter.as_mut() {
+                Some(highlighter) => {
+                    for line in LinesWithEndings::from(app.preview.as_ref()) {
+                        let ranges: Vec<(hStyle, &str)> = highlighter.highlight(line, &ps);
+                        let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
+                        preview_text.push_str(&escaped);
+                    }
+                }
+                None => preview_text.push_str(&app.preview),
             }
             //let preview_text = Paragraph::new(app.preview.as_ref())
+            let links_title = match app.current_link() {
+                Some(link) => format!(
+                    "Link {}/{}: {}",
+                    app.link_idx.unwrap() + 1,
+                    app.selected_links().len(),
+                    link
+                ),
+                None => match app.selected_links().len() {
+                    0 => String::from(""),
+                    n => format!("{} links (ctrl-h/ctrl-l)", n),
+                },
+            };
             let preview_text = Paragraph::new(ansi_to_text(preview_text.bytes()).unwrap())
-                .block(Block::default().borders(Borders::NONE))
-                .wrap(Wrap { trim: true });
-            f.render_widget(preview_text, screen[1]);
+                .block(Block::default().title(links_title).borders(Borders::NONE))
+                .wrap(Wrap { trim: true })
+                .scroll((app.preview_scroll, 0));
+            if app.show_backlinks {
+                let preview = Layout::default()
+                    .direction(Direction::Vertical)
+                    .margin(0)
+                    .constraints(
+                        [
+                            // Document content
+                            Constraint::Percentage(70),
+                            // Documents linking to the selected one
+                            Constraint::Percentage(30),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(screen[1]);
+                app.areas.preview = preview[0];
+                f.render_widget(preview_text, preview[0]);
+
+                let backlinks: Vec<ListItem> = app
+                    .backlinks
+                    .iter()
+                    .map(|m| ListItem::new(vec![Spans::from(Span::raw(m.title.to_string()))]))
+                    .collect();
+                let backlinks = List::new(backlinks).block(
+                    Block::default()
+                        .title(format!("Backlinks ({})", app.backlinks.len()))
+                        .borders(Borders::TOP),
+                );
+                f.render_widget(backlinks, preview[1]);
+            } else {
+                app.areas.preview = screen[1];
+                f.render_widget(preview_text, screen[1]);
+            }
 
             // Output area where match titles are displayed
             // TODO panes specifically for tag, weight, date, author, id, parentid
@@ -214,26 +937,38 @@ pub fn query(
                         Constraint::Length(3),
                         // Filter input box
                         Constraint::Length(3),
+                        // Status line
+                        Constraint::Length(1),
                     ]
                     .as_ref(),
                 )
                 .split(screen[0]);
 
-            let selected_style = Style::default().add_modifier(Modifier::REVERSED);
             let matches: Vec<ListItem> = app
                 .matches
                 .iter()
-                .map(|m| ListItem::new(vec![Spans::from(Span::raw(m.title.to_string()))]))
+                .map(|m| {
+                    let mark = if app.is_marked(&m.id) { "* " } else { "" };
+                    ListItem::new(vec![Spans::from(Span::raw(format!("{}{}", mark, m.title)))])
+                })
                 .collect();
+            let mut title = format!("{}/{} matches", app.matches.len(), app.num_hits);
+            if !app.marked.is_empty() {
+                title.push_str(&format!(", {} marked", app.marked.len()));
+            }
             let matches = List::new(matches)
-                .block(Block::default().borders(Borders::ALL))
-                .highlight_style(selected_style)
+                .block(Block::default().title(title).borders(Borders::ALL))
+                .highlight_style(theme.highlight)
                 .highlight_symbol("> ");
             f.render_stateful_widget(matches, interactive[0], &mut app.selected_state);
+            app.areas.matches = interactive[0];
+            app.areas.inputs = [interactive[1], interactive[2]];
+            // List height less the top and bottom borders
+            app.update_list_offset(interactive[0].height.saturating_sub(2) as usize);
 
             // Input area where queries are entered
-            let query_input = Paragraph::new(app.query_input.as_ref())
-                .style(Style::default().fg(Color::Yellow))
+            let query_input = Paragraph::new(app.query_input.value())
+                .style(theme.input)
                 .block(
                     Block::default()
                         .title("Query input")
@@ -242,8 +977,8 @@ pub fn query(
             f.render_widget(query_input, interactive[1]);
 
             // Input area where filters are entered
-            let filter_input = Paragraph::new(app.filter_input.as_ref())
-                .style(Style::default().fg(Color::Yellow))
+            let filter_input = Paragraph::new(app.filter_input.value())
+                .style(theme.input)
                 .block(
                     Block::default()
                         .title("Filter input (e.g. 'vim | !bash')")
@@ -251,19 +986,61 @@ pub fn query(
                 );
             f.render_widget(filter_input, interactive[2]);
 
+            // Status line for transient feedback, or the selected note's length
+            let length = match app.selected_state.selected() {
+                Some(i) if app.status.is_empty() => {
+                    let (words, minutes) = document::reading_time(&app.matches[i].body);
+                    format!("{} words, {} min read", words, minutes)
+                }
+                _ => String::new(),
+            };
+            let status = Paragraph::new(if app.status.is_empty() {
+                length.as_str()
+            } else {
+                app.status.as_str()
+            })
+            .style(theme.status);
+            f.render_widget(status, interactive[3]);
+
+            // Tag completion popup, floating just above the filter input
+            if app.completing() {
+                let filter_area = interactive[2];
+                let height = (app.completions.len() as u16 + 2).min(filter_area.y);
+                let width = app
+                    .completions
+                    .iter()
+                    .map(|c| c.width() as u16 + 4)
+                    .max()
+                    .unwrap_or(0)
+                    .max(20)
+                    .min(filter_area.width);
+                let popup = Rect::new(filter_area.x, filter_area.y - height, width, height);
+                let completions: Vec<ListItem> = app
+                    .completions
+                    .iter()
+                    .map(|c| ListItem::new(Span::raw(c.to_owned())))
+                    .collect();
+                let completions = List::new(completions)
+                    .block(Block::default().title("Tags").borders(Borders::ALL))
+                    .highlight_style(theme.highlight);
+                let mut state = ListState::default();
+                state.select(Some(app.completion_idx));
+                f.render_widget(Clear, popup);
+                f.render_stateful_widget(completions, popup, &mut state);
+            }
+
             // Make the cursor visible and ask tui-rs to put it at the specified
             // coordinates after rendering
             f.set_cursor(
-                // Put cursor past the end of the input text
-                // TODO refactor input area switching
-                interactive[app.inp_idx + 1].x + 1 + app.inp_widths[app.inp_idx] as u16,
+                // Put cursor at its position within the focused input box
+                interactive[app.inp_idx + 1].x + 1 + app.active_input().cursor_width() as u16,
                 interactive[app.inp_idx + 1].y + 1,
             );
 
-            if verbosity > 0 {
+            if options.verbosity > 0 {
                 // Area to display debug messages
                 let debug = Paragraph::new(app.debug.as_ref())
-                    .style(Style::default().fg(Color::Green).bg(Color::Black))
+                    .style(theme.debug)
                     .block(
                         Block::default()
                             .title("Debug messages")
@@ -274,7 +1051,7 @@ pub fn query(
 
                 // Area to display Error messages
                 let error = Paragraph::new(app.error.as_ref())
-                    .style(Style::default().fg(Color::Red).bg(Color::Black))
+                    .style(theme.error)
                     .block(
                         Block::default()
                             .title("Error messages")
@@ -296,49 +1073,151 @@ pub fn query(
                 drop(tui);
                 bail!("Failed to handle input {}", e.to_string());
             }
+            Ok(event::Event::Tick) => {
+                if app.view_due() {
+                    record_view(&mut app, &client);
+                }
+            }
+            Ok(event::Event::Mouse(mouse)) => {
+                app.handle_mouse(mouse);
+                refresh(&mut app, &client);
+            }
             Ok(ev) => {
                 if let event::Event::Input(input) = ev {
                     // TODO add support for:
-                    //  - ctrl-e to open selected in $EDITOR, then submit on file close
+                    //  - submit the document edited via ctrl-x on file close
                     //  - pageup/pagedn/home/end for navigating displayed selection
                     //  - ctrl-jkdu for navigating displayed selection
-                    //  - ctrl-hl for navigating between links
                     //  - Limit query and filter input box length
                     //  - +/- (and return) to modify weight
                     //  - ctrl-m to toggle displaying frontmatter metadata (off by default)
-                    match input {
-                        Key::Char('\n') => {
+                    // Any key other than Up/Down stops history browsing
+                    let history_pos = app.history_pos.take();
+                    match (keymap.action(input), input) {
+                        (action, key) if app.history_search.is_some() => {
+                            app.history_search_key(action, key)
+                        }
+                        (action, key) if app.tag_prompt.is_some() => {
+                            if let Some(tags) = app.tag_prompt_key(action, key) {
+                                edit_tags(&mut app, &client, &tags);
+                            }
+                        }
+                        (_, key) if app.pending_yank => {
+                            app.pending_yank = false;
+                            app.status = app.yank(key);
+                        }
+                        (Some(Action::Next), _) if app.completing() => app.cycle_completion(1),
+                        (Some(Action::Previous), _) if app.completing() => app.cycle_completion(-1),
+                        (Some(Action::Select), _) if app.completing() => app.accept_completion(),
+                        (_, Key::Esc) if app.completing() => app.completions.clear(),
+                        (Some(Action::Previous), Key::Up)
+                            if history_pos.is_some() || app.active_value().is_empty() =>
+                        {
+                            app.history_back(history_pos)
+                        }
+                        (Some(Action::Next), Key::Down) if history_pos.is_some() => {
+                            app.history_forward(history_pos.unwrap())
+                        }
+                        (Some(Action::HistorySearch), _) => {
+                            app.history_search = Some(LineInput::new());
+                            app.history_match = 0;
+                            app.history_search_key(None, Key::Null);
+                        }
+                        (Some(Action::ToggleMark), _) => app.toggle_mark(),
+                        (Some(Action::EditTags), _) => {
+                            app.tag_prompt = Some(LineInput::new());
+                            app.tag_prompt_key(None, Key::Null);
+                        }
+                        (Some(Action::Yank), _) => {
+                            app.pending_yank = true;
+                            app.status = String::from("Yank: (i)d, (b)ody or (p)ath");
+                        }
+                        (Some(Action::Select), _) => {
                             // Select choice
                             // TODO increment weight for selected doc
+                            record_view(&mut app, &client);
+                            picked = true;
                             break;
                         }
-                        Key::Ctrl('c') => {
+                        (Some(Action::Quit), _) => {
                             break;
                         }
-                        Key::Left | Key::Right | Key::Char('\t') => {
+                        (Some(Action::NextLink), _) => {
+                            app.cycle_link(1);
+                        }
+                        (Some(Action::PreviousLink), _) => {
+                            app.cycle_link(-1);
+                        }
+                        (Some(Action::FollowLink), _) => {
+                            // Follow the highlighted link
+                            if let Some(link) = app.current_link().cloned() {
+                                match fetch_linked(&client, &link) {
+                                    Ok(doc) => {
+                                        app.follow(humanize(vec![doc]).remove(0));
+                                        app.error = String::from("");
+                                    }
+                                    Err(e) => app.error = e,
+                                }
+                            }
+                        }
+                        (Some(Action::Back), _) => {
+                            app.go_back();
+                        }
+                        (Some(Action::OpenAttachments), _) => {
+                            let selected = app.selected_state.selected().map(|i| &app.matches[i]);
+                            app.status = match selected {
+                                Some(doc) if !doc.attachments.is_empty() => {
+                                    match open_attachments(doc, config) {
+                                        Ok(()) => {
+                                            format!("Opened {} attachments", doc.attachments.len())
+                                        }
+                                        Err(e) => {
+                                            app.error = e.to_string();
+                                            String::new()
+                                        }
+                                    }
+                                }
+                                Some(_) => String::from("No attachments"),
+                                None => String::new(),
+                            };
+                        }
+                        (Some(Action::Revisions), _) => {
+                            let selected = app.selected_state.selected().map(|i| &app.matches[i]);
+                            match selected {
+                                Some(doc) if doc.revision > 1 => {
+                                    match fetch_revisions(&client, doc.chain_id()) {
+                                        Ok(chain) => {
+                                            app.show_revisions(humanize(chain));
+                                            app.error = String::from("");
+                                        }
+                                        Err(e) => app.error = e,
+                                    }
+                                }
+                                Some(_) => app.status = String::from("No earlier revisions"),
+                                None => {}
+                            }
+                        }
+                        (Some(Action::ToggleBacklinks), _) => {
+                            app.show_backlinks = !app.show_backlinks;
+                        }
+                        (Some(Action::SwitchInput), _) => {
                             app.inp_idx = match app.inp_idx {
                                 1 => 0,
                                 _ => 1,
                             };
                         }
-                        Key::Char(c) => {
-                            if app.inp_idx == 0 {
-                                app.query_input.push(c);
-                            } else {
-                                app.filter_input.push(c);
+                        (Some(Action::CursorLeft), _) => app.active_input().left(),
+                        (Some(Action::CursorRight), _) => app.active_input().right(),
+                        (Some(Action::CursorHome), _) => app.active_input().home(),
+                        (Some(Action::CursorEnd), _) => app.active_input().end(),
+                        (Some(Action::DeleteWord), _) => app.active_input().delete_word(),
+                        (Some(Action::ClearInput), _) => app.active_input().clear(),
+                        (Some(Action::Backspace), _) => app.active_input().backspace(),
+                        (Some(Action::DeleteChar), _) => app.active_input().delete(),
+                        (Some(Action::Edit), _) => {
+                            if let Err(e) = app.record_history() {
+                                error!("❌ Failed to save history: {:?}", e);
                             }
-                            app.inp_widths[app.inp_idx] += 1;
-                        }
-                        Key::Backspace => {
-                            // TODO prevent this from going to far back
-                            if app.inp_idx == 0 {
-                                app.query_input.pop();
-                            } else {
-                                app.filter_input.pop();
-                            }
-                            app.inp_widths[app.inp_idx] -= 1;
-                        }
-                        Key::Ctrl('e') => {
                             // Temporarily drop the TUI app and event handling while
                             // we shell out to EDITOR, restore these on return
                             //events.tx.send("q");
@@ -353,7 +1232,7 @@ pub fn query(
                                 .rand_bytes(5)
                                 .tempfile()?;
                             tf.write_all(app.get_selected_contents().as_bytes())?;
-                            let editor = editor.clone();
+                            let editor = options.editor.clone();
                             let mut editor = editor.split_whitespace();
                             let mut cmd = Command::new(editor.next().unwrap());
                             for arg in editor {
@@ -362,13 +1241,13 @@ pub fn query(
                             cmd.arg(tf.path())
                                 .status()
                                 .expect("failed to execute process");
-                            events = event::Events::new();
-                            tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
-                                stdout().into_raw_mode().unwrap(),
-                            )))
-                            .unwrap();
+                            events = event::Events::from_source(&options.keys);
+                            tui = open();
                         }
-                        Key::Ctrl('v') => {
+                        (Some(Action::Pager), _) => {
+                            if let Err(e) = app.record_history() {
+                                error!("❌ Failed to save history: {:?}", e);
+                            }
                             // Temporarily drop the TUI app and event handling while
                             // we shell out to less, restore these on return
                             //events.tx.send("q");
@@ -383,7 +1262,7 @@ pub fn query(
                                 .rand_bytes(5)
                                 .tempfile()?;
                             tf.write_all(app.get_selected_contents().as_bytes())?;
-                            let viewer = pager.clone();
+                            let viewer = options.pager.clone();
                             // Support setting PAGER="bat --paging always"
                             let mut viewer = viewer.split_whitespace();
                             let mut cmd = Command::new(viewer.next().unwrap());
@@ -393,110 +1272,376 @@ pub fn query(
                             cmd.arg(tf.path())
                                 .status()
                                 .expect("failed to execute process");
-                            events = event::Events::new();
-                            tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
-                                stdout().into_raw_mode().unwrap(),
-                            )))
-                            .unwrap();
+                            events = event::Events::from_source(&options.keys);
+                            tui = open();
                         }
-                        Key::Down | Key::Ctrl('n') => {
+                        (Some(Action::Next), _) => {
                             app.next();
-                            app.preview = app.get_selected_contents();
+                            app.update_preview();
                         }
-                        Key::Up | Key::Ctrl('p') => {
+                        (Some(Action::Previous), _) => {
                             app.previous();
-                            app.preview = app.get_selected_contents();
+                            app.update_preview();
                         }
-                        _ => {}
+                        (None, Key::Char(c)) => app.active_input().insert(c),
+                        (None, _) => {}
                     }
 
-                    let mut q = api::ApiQuery::new();
-                    q.query = Some(app.query_input.to_owned());
-
-                    q.process_filter(app.filter_input.to_owned());
-
-                    app.debug = serde_json::to_string(&q).unwrap();
-
-                    // Split up the JSON decoding into two steps.
-                    // 1.) Get the text of the body.
-                    let response_body = match client
-                        .post(uri.as_ref())
-                        .body::<String>(serde_json::to_string(&q).unwrap())
-                        .header(CONTENT_TYPE, "application/json")
-                        .send()
-                    {
-                        Ok(resp) => {
-                            if !resp.status().is_success() {
-                                app.error = format!("Request failed: {:?}", resp);
-                                continue;
-                            }
-                            match resp.text() {
-                                Ok(text) => text,
-                                Err(e) => {
-                                    app.error = format!("resp.text() failed: {:?}", e);
-                                    continue;
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            app.error = format!("Send failed: {:?}", e);
-                            continue;
-                        }
-                    };
-
-                    // 2.) Parse the results as JSON.
-                    match serde_json::from_str::<api::ApiResponse>(&response_body) {
-                        Ok(mut resp) => {
-                            app.matches = resp
-                                .hits
-                                .iter_mut()
-                                .map(|mut m| {
-                                    m.serialization_type = document::SerializationType::Human;
-                                    m.to_owned()
-                                })
-                                .collect::<Vec<_>>();
-                            app.error = String::from("");
-                        }
-                        Err(e) => {
-                            app.error = format!(
-                                "Could not deserialize body from: {}; error: {:?}",
-                                response_body, e
-                            )
-                        }
-                    };
+                    refresh(&mut app, &client);
                 }
             }
         }
     }
 
-    tui.clear().unwrap();
+    if persist {
+        if let Err(e) = app.session().save() {
+            error!("❌ Failed to save session: {:?}", e);
+        }
+    }
+    if let Err(e) = app.record_history() {
+        error!("❌ Failed to save history: {:?}", e);
+    }
+
+    // Quitting picks nothing, so scripts reading the selection can tell it was cancelled
+    if picked {
+        Ok((app.get_selected(), tui))
+    } else {
+        Ok((Vec::new(), tui))
+    }
+}
+
+/// Bring the matches, and the backlinks pane if shown, up to date with the inputs
+fn refresh(app: &mut TerminalApp, client: &Client) {
+    let mut q = api::ApiQuery::new();
+    q.query = Some(app.query_input.value().to_owned());
+    q.sort = Some(app.sort.to_owned());
+    q.limit = PAGE_SIZE;
+
+    q.process_filter(app.filter_input.value().to_owned());
+    if !app.include_archived {
+        q.exclude_archived();
+    }
+
+    let payload = serde_json::to_string(&q).unwrap();
+    if payload != app.current_query {
+        // Query or filter changed, start over from the first page
+        app.back_stack.clear();
+        app.revisions = false;
+        app.debug = payload.clone();
+        app.current_query = payload;
+        match search(client, &q) {
+            Ok(resp) => {
+                app.matches = humanize(resp.hits);
+                app.num_hits = resp.num_hits;
+                app.selected_state.select(None);
+                app.preview = String::from("");
+                app.error = String::from("");
+            }
+            Err(e) => app.error = e,
+        };
+    } else if app.wants_next_page() {
+        // Cursor reached the end of the list, append the next page
+        q.offset = Some(app.matches.len() as u32);
+        app.debug = serde_json::to_string(&q).unwrap();
+        match search(client, &q) {
+            Ok(resp) => {
+                if resp.hits.is_empty() {
+                    // Server ran out of hits before estimatedTotalHits said it would
+                    app.num_hits = app.matches.len() as u32;
+                }
+                app.matches.extend(humanize(resp.hits));
+                app.error = String::from("");
+            }
+            Err(e) => app.error = e,
+        };
+    }
+
+    if app.inp_idx == 1 && app.tags.is_none() {
+        match fetch_tags(client) {
+            Ok(tags) => app.tags = Some(tags),
+            Err(e) => {
+                // Don't retry on every keypress
+                app.tags = Some(Vec::new());
+                app.error = e;
+            }
+        }
+    }
+    app.update_completions();
+
+    if app.backlinks_stale() {
+        let selected = app.selected_state.selected().map(|i| &app.matches[i]);
+        app.backlinks = match selected {
+            Some(doc) => {
+                let mut q = api::ApiQuery::new();
+                q.filter = Some(backlinks_filter(doc));
+                match search(client, &q) {
+                    Ok(resp) => resp.hits,
+                    Err(e) => {
+                        app.error = e;
+                        Vec::new()
+                    }
+                }
+            }
+            None => Vec::new(),
+        };
+        app.backlinks_for = app.selected_id().cloned();
+    }
+}
+
+/// Search the server, errors are returned as messages suitable for the error pane
+fn search(client: &Client, q: &api::ApiQuery) -> Result<api::ApiResponse, String> {
+    client.search(q).map_err(|e| e.to_string())
+}
+
+/// Apply `tags`, e.g. `+todo -draft` with bare tags added, to the marked documents, or the
+/// selected one if none are marked, in a single partial update
+fn edit_tags(app: &mut TerminalApp, client: &Client, tags: &str) {
+    let (mut add, mut remove) = (Vec::new(), Vec::new());
+    for tag in tags.split_whitespace() {
+        match tag.strip_prefix('-') {
+            Some(t) => remove.push(t),
+            None => add.push(tag.trim_start_matches('+')),
+        }
+    }
+
+    let mut updates = Vec::new();
+    for mut doc in app.get_selected() {
+        let edited = tags::edit(&doc.tags, &add, &remove);
+        if doc.tags != edited {
+            doc.tags = edited;
+            updates.push(doc);
+        }
+    }
+    if updates.is_empty() {
+        app.status = String::from("No documents modified");
+        return;
+    }
+
+    let body: Vec<_> = updates
+        .iter()
+        .map(|d| serde_json::json!({ "id": d.id, "tags": d.tags }))
+        .collect();
+    if let Err(e) = update_documents(client, serde_json::Value::from(body)) {
+        app.error = e;
+        return;
+    }
+
+    // Mirror the update locally, the index applies it asynchronously
+    for doc in app.matches.iter_mut().chain(app.marked.iter_mut()) {
+        if let Some(updated) = updates.iter().find(|u| u.id == doc.id) {
+            doc.tags = updated.tags.clone();
+        }
+    }
+    app.update_preview();
+    app.tags = None;
+    app.status = format!("Modified {} documents", updates.len());
+}
+
+/// Count a view of the selected document, once per session, with a partial update of `views`
+fn record_view(app: &mut TerminalApp, client: &Client) {
+    let i = match app.selected_state.selected() {
+        Some(i) if !app.viewed.contains(&app.matches[i].id) => i,
+        _ => return,
+    };
+    let id = app.matches[i].id.to_owned();
+    app.viewed.insert(id.clone());
+    // Count on top of the views stored now rather than those read with the matches, which
+    // other sessions may have raised since. A view another session records between this read
+    // and the update is still lost, as the server has no atomic increment.
+    let views = match client.get_document(&id) {
+        Ok(Some(stored)) => stored.views + 1,
+        Ok(None) => return,
+        Err(e) => {
+            app.error = e.to_string();
+            return;
+        }
+    };
+    app.matches[i].views = views;
+    let update = serde_json::json!([{ "id": id, "views": views }]);
+    if let Err(e) = update_documents(client, update) {
+        app.error = e;
+    }
+}
 
-    Ok(app.get_selected())
+/// Send a partial update, changing only the fields present in each of the `updates`
+fn update_documents(client: &Client, updates: serde_json::Value) -> Result<(), String> {
+    client
+        .update_documents(&updates)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Fetch the document a link refers to, either by id or, failing that, by slug
+fn fetch_linked(client: &Client, link: &str) -> Result<document::Document, String> {
+    if let Some(doc) = client.get_document(link).map_err(|e| e.to_string())? {
+        return Ok(doc);
+    }
+
+    let mut q = api::ApiQuery::new();
+    q.filter = Some(format!("slug = \"{}\"", link));
+    q.limit = 1;
+    match search(client, &q)?.hits.pop() {
+        Some(doc) => Ok(doc),
+        None => Err(format!("No document with id or slug {}", link)),
+    }
+}
+
+/// Open each of `doc`'s attachments in the desktop's default application
+fn open_attachments(doc: &document::Document, config: &config::Config) -> Result<(), Report> {
+    let dir = attachment::dir(&config.attachments)?;
+    for attached in &doc.attachments {
+        attachment::open(&attachment::verified(&dir, attached)?)?;
+    }
+    Ok(())
+}
+
+/// Every revision of the document with `chain_id`, newest first
+fn fetch_revisions(client: &Client, chain_id: &str) -> Result<Vec<document::Document>, String> {
+    let mut q = api::ApiQuery::new();
+    // The first revision has no origid, its id is what later ones point back to
+    q.filter = Some(format!(
+        "origid = \"{}\" OR id = \"{}\"",
+        chain_id, chain_id
+    ));
+    q.sort = Some(vec!["revision:desc".to_owned()]);
+    Ok(search(client, &q)?.hits)
+}
+
+/// Every tag in the index with its document count, most used first
+fn fetch_tags(client: &Client) -> Result<Vec<(String, u32)>, String> {
+    client
+        .block_on(tags::counts(client.inner()))
+        .map_err(|e| e.to_string())
+}
+
+/// Filter matching documents whose links reference `doc` by id or slug
+fn backlinks_filter(doc: &document::Document) -> String {
+    let mut filter = format!("links = \"{}\"", doc.id);
+    if !doc.slug.is_empty() {
+        filter.push_str(&format!(" OR links = \"{}\"", doc.slug));
+    }
+    filter
+}
+
+/// Prepare matches for display in the preview pane
+fn humanize(hits: Vec<document::Document>) -> Vec<document::Document> {
+    hits.into_iter()
+        .map(|mut m| {
+            m.serialization_type = document::SerializationType::Human;
+            m
+        })
+        .collect()
 }
 
 pub mod event {
 
+    use crate::keys;
+    use color_eyre::Report;
+    use eyre::eyre;
+    use std::fs;
     use std::io;
-    use std::sync::mpsc;
+    use std::path::Path;
+    use std::sync::{mpsc, Arc, Mutex};
     use std::thread;
     use std::time::Duration;
 
-    use termion::event::Key;
+    use termion::event::{Event as TermEvent, Key, MouseEvent};
     use termion::input::TermRead;
 
     pub enum Event<I> {
         Input(I),
+        Mouse(MouseEvent),
         Tick,
     }
 
-    /// A small event handler that wrap termion input and tick events. Each event
-    /// type is handled in its own thread and returned to a common `Receiver`
+    /// Where key presses come from
+    #[derive(Clone)]
+    pub enum Source {
+        /// The terminal, which also reports mouse events
+        Stdin,
+        /// Keys sent down a channel, by a test or read from a script. Input ends, failing the
+        /// query, once every sender is dropped and the keys sent are used up.
+        Keys(Arc<Mutex<mpsc::Receiver<Key>>>),
+    }
+
+    /// One line of a key script
+    enum Step {
+        Key(Key),
+        Wait(Duration),
+    }
+
+    impl Source {
+        /// A source of the keys sent to the returned sender
+        pub fn channel() -> (mpsc::Sender<Key>, Source) {
+            let (tx, rx) = mpsc::channel();
+            (tx, Source::Keys(Arc::new(Mutex::new(rx))))
+        }
+
+        /// The keys of the script at `path`, e.g. for recording a demo. Each line holds key
+        /// names as in the `[keys]` config separated by spaces, `type <text>` for the
+        /// characters of the text, or `wait <milliseconds>` to pause. Blank lines and lines
+        /// starting with # are skipped.
+        pub fn script(path: &Path) -> Result<Source, Report> {
+            let script = fs::read_to_string(path)
+                .map_err(|e| eyre!("❌ Failed to read {}: {}", path.display(), e))?;
+            let mut steps = Vec::new();
+            for (n, line) in script.lines().enumerate() {
+                let line = line.trim_start();
+                let failed = |e: Report| eyre!("❌ {} line {}: {}", path.display(), n + 1, e);
+                if line.trim().is_empty() || line.starts_with('#') {
+                    continue;
+                } else if let Some(text) = line.strip_prefix("type ") {
+                    steps.extend(text.chars().map(|c| Step::Key(Key::Char(c))));
+                } else if let Some(ms) = line.strip_prefix("wait ") {
+                    match ms.trim().parse() {
+                        Ok(ms) => steps.push(Step::Wait(Duration::from_millis(ms))),
+                        Err(_) => return Err(failed(eyre!("expected milliseconds, got {}", ms))),
+                    }
+                } else {
+                    for name in line.split_whitespace() {
+                        steps.push(Step::Key(keys::parse_key(name).map_err(failed)?));
+                    }
+                }
+            }
+            let (tx, source) = Source::channel();
+            thread::spawn(move || {
+                for step in steps {
+                    match step {
+                        Step::Key(key) => {
+                            if tx.send(key).is_err() {
+                                return;
+                            }
+                        }
+                        Step::Wait(pause) => thread::sleep(pause),
+                    }
+                }
+            });
+            Ok(source)
+        }
+    }
+
+    /// Where `Events` gets input from
+    enum Input {
+        /// Terminal input and ticks, each from a thread of its own into a common `Receiver`
+        Terminal {
+            rx: mpsc::Receiver<Event<Key>>,
+            #[allow(dead_code)]
+            input_handle: thread::JoinHandle<()>,
+            #[allow(dead_code)]
+            tick_handle: thread::JoinHandle<()>,
+        },
+        /// Keys read as they are asked for, so none are lost when the handler is dropped
+        /// while handing the terminal to an editor, with a tick whenever none comes in time
+        Keys {
+            keys: Arc<Mutex<mpsc::Receiver<Key>>>,
+            tick_rate: Duration,
+        },
+    }
+
+    /// A small event handler that wrap termion input and tick events, or keys from another
+    /// `Source`
     pub struct Events {
-        rx: mpsc::Receiver<Event<Key>>,
-        #[allow(dead_code)]
-        input_handle: thread::JoinHandle<()>,
-        #[allow(dead_code)]
-        tick_handle: thread::JoinHandle<()>,
+        input: Input,
     }
 
     #[derive(Debug, Clone, Copy)]
@@ -523,14 +1668,32 @@ pub mod event {
             Events::with_config(Config::default())
         }
 
+        /// Events with keys from `source`
+        pub fn from_source(source: &Source) -> Events {
+            match source {
+                Source::Stdin => Events::new(),
+                Source::Keys(keys) => Events {
+                    input: Input::Keys {
+                        keys: keys.clone(),
+                        tick_rate: Config::default().tick_rate,
+                    },
+                },
+            }
+        }
+
         pub fn with_config(config: Config) -> Events {
             let (tx, rx) = mpsc::channel();
             let input_handle = {
                 let tx = tx.clone();
                 thread::spawn(move || {
                     let stdin = io::stdin();
-                    for evt in stdin.keys().flatten() {
-                        if tx.send(Event::Input(evt)).is_err() {
+                    for evt in stdin.events().flatten() {
+                        let evt = match evt {
+                            TermEvent::Key(key) => Event::Input(key),
+                            TermEvent::Mouse(mouse) => Event::Mouse(mouse),
+                            TermEvent::Unsupported(_) => continue,
+                        };
+                        if tx.send(evt).is_err() {
                             return;
                         }
                     }
@@ -545,14 +1708,25 @@ pub mod event {
                 })
             };
             Events {
-                rx,
-                input_handle,
-                tick_handle,
+                input: Input::Terminal {
+                    rx,
+                    input_handle,
+                    tick_handle,
+                },
             }
         }
 
         pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
-            self.rx.recv()
+            match &self.input {
+                Input::Terminal { rx, .. } => rx.recv(),
+                Input::Keys { keys, tick_rate } => {
+                    match keys.lock().unwrap().recv_timeout(*tick_rate) {
+                        Ok(key) => Ok(Event::Input(key)),
+                        Err(mpsc::RecvTimeoutError::Timeout) => Ok(Event::Tick),
+                        Err(mpsc::RecvTimeoutError::Disconnected) => Err(mpsc::RecvError),
+                    }
+                }
+            }
         }
     }
 }
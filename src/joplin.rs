@@ -0,0 +1,339 @@
+use crate::attachment::{self, Attachment};
+use crate::date::Date;
+use crate::document::Document;
+use crate::import::{self, Converted};
+use crate::slug;
+use color_eyre::Report;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// The `type_` of each kind of item in an export
+const NOTE: &str = "1";
+const FOLDER: &str = "2";
+const RESOURCE: &str = "4";
+const TAG: &str = "5";
+const NOTE_TAG: &str = "6";
+
+/// A note, notebook, tag, resource or other item of a Joplin export
+#[derive(Debug, Default)]
+struct Item {
+    title: String,
+    body: String,
+    /// The `key: value` lines at the end of the file
+    meta: HashMap<String, String>,
+}
+
+impl Item {
+    /// The metadata `key`, empty if the item has none
+    fn get(&self, key: &str) -> &str {
+        self.meta.get(key).map_or("", String::as_str)
+    }
+}
+
+/// Whether `line` is a `key: value` metadata line
+fn is_meta(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, _)) => {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// Read an item as Joplin writes it: the title, a blank line and the body, then a blank line
+/// and the metadata. Items without a title, such as a note's tagging, are metadata alone.
+fn parse_item(s: &str) -> Item {
+    let lines: Vec<&str> = s.trim_end().lines().collect();
+    let start = lines
+        .iter()
+        .rposition(|line| !is_meta(line))
+        .map_or(0, |i| i + 1);
+    let meta = lines[start..]
+        .iter()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.to_owned(), v.trim().to_owned()))
+        .collect();
+    let content = &lines[..start];
+    let title = content.first().copied().unwrap_or_default().to_owned();
+    let body = content
+        .iter()
+        .skip(2)
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n");
+    Item {
+        title,
+        body: body.trim_end().to_owned(),
+        meta,
+    }
+}
+
+/// The id a `:/0123...` link points at
+fn link_id(target: &str) -> Option<&str> {
+    let id = target.strip_prefix(":/")?;
+    if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// The file of the resource `id` in the export's `resources` directory
+fn resource_file(dir: &Path, id: &str) -> Option<PathBuf> {
+    fs::read_dir(dir.join("resources"))
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.file_stem().is_some_and(|stem| stem == id))
+}
+
+/// The notes of the Joplin export `path`, a JEX archive or a RAW export directory, with any
+/// resources they link to stored in the attachments directory `attachments`. Each note keeps
+/// its Joplin id, is tagged with its tags and the notebooks it's in, and links to the notes and
+/// attachments it links to.
+pub fn read(path: &Path, attachments: &Path) -> Result<Converted, Report> {
+    if path.is_dir() {
+        return read_dir(path, attachments);
+    }
+    let unpacked = TempDir::new()?;
+    tar::Archive::new(File::open(path)?).unpack(unpacked.path())?;
+    read_dir(unpacked.path(), attachments)
+}
+
+/// The notes of the export unpacked in `dir`
+fn read_dir(dir: &Path, attachments: &Path) -> Result<Converted, Report> {
+    let mut converted = Converted::default();
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|p| p.extension().is_some_and(|e| e == "md"));
+    paths.sort();
+
+    let mut notes = Vec::new();
+    let mut folders = HashMap::new();
+    let mut tags = HashMap::new();
+    let mut resources = HashMap::new();
+    let mut note_tags: Vec<(String, String)> = Vec::new();
+    for path in paths {
+        let item = parse_item(&fs::read_to_string(&path)?);
+        let id = item.get("id").to_owned();
+        if item.get("encryption_applied") == "1" {
+            converted
+                .unconverted
+                .push(format!("{}: encrypted", path.display()));
+            continue;
+        }
+        match item.get("type_") {
+            NOTE => notes.push(item),
+            FOLDER => {
+                folders.insert(id, item);
+            }
+            TAG => {
+                tags.insert(id, item.title);
+            }
+            RESOURCE => {
+                resources.insert(id, item);
+            }
+            NOTE_TAG => note_tags.push((
+                item.get("note_id").to_owned(),
+                item.get("tag_id").to_owned(),
+            )),
+            kind => converted.unconverted.push(format!(
+                "{}: item of type {}, not a note, notebook, tag or resource",
+                path.display(),
+                kind
+            )),
+        }
+    }
+
+    let note_ids: Vec<String> = notes.iter().map(|n| n.get("id").to_owned()).collect();
+    let mut stored: HashMap<String, Attachment> = HashMap::new();
+    for note in &notes {
+        let id = note.get("id");
+        let mut doc_tags = Vec::new();
+        // Notebooks nest, the outermost first
+        let mut folder = folders.get(note.get("parent_id"));
+        while let Some(f) = folder {
+            doc_tags.insert(0, f.title.clone());
+            folder = folders.get(f.get("parent_id"));
+        }
+        for (_, tag) in note_tags.iter().filter(|(n, _)| n == id) {
+            match tags.get(tag) {
+                Some(title) if doc_tags.contains(title) => {}
+                Some(title) => doc_tags.push(title.clone()),
+                None => converted
+                    .unconverted
+                    .push(format!("{}: tag {} isn't in the export", note.title, tag)),
+            }
+        }
+
+        let mut links = Vec::new();
+        let mut attached = Vec::new();
+        let body = import::rewrite_links(&note.body, |target| {
+            let target_id = link_id(target)?;
+            if note_ids.iter().any(|n| n == target_id) {
+                links.push(target_id.to_owned());
+                return Some(target_id.to_owned());
+            }
+            // A resource is stored the first time a note links to it
+            let found = match stored.get(target_id) {
+                Some(attachment) => Some(attachment.clone()),
+                None => resources.get(target_id).and_then(|resource| {
+                    let file = resource_file(dir, target_id)?;
+                    let name = match resource.title.as_str() {
+                        "" => file.file_name()?.to_string_lossy().into_owned(),
+                        title => title.replace('/', "-"),
+                    };
+                    let attachment = attachment::store_as(attachments, &file, &name).ok()?;
+                    stored.insert(target_id.to_owned(), attachment.clone());
+                    Some(attachment)
+                }),
+            };
+            match found {
+                Some(attachment) => {
+                    let name = attachment.name().to_owned();
+                    if !attached.contains(&attachment) {
+                        attached.push(attachment);
+                    }
+                    Some(name)
+                }
+                None => {
+                    converted.unconverted.push(format!(
+                        "{}: link to {} isn't in the export",
+                        note.title, target
+                    ));
+                    None
+                }
+            }
+        });
+
+        let mut doc = Document {
+            id: id.to_owned(),
+            title: note.title.clone(),
+            date: note
+                .get("created_time")
+                .parse()
+                .unwrap_or_else(|_| Date::now()),
+            body: format!("{}\n", body),
+            tags: doc_tags,
+            links,
+            attachments: attached,
+            ..Document::new()
+        };
+        slug::fill(&mut doc);
+        doc.filename = format!("{}.md", doc.slug.replace('/', "-"));
+        converted.docs.push(doc);
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    const MISSING: &str = "cccccccccccccccccccccccccccccccc";
+
+    #[test]
+    fn items_split_into_title_body_and_metadata() {
+        let item = parse_item(
+            "Groceries\n\n- milk\nnote: not metadata\n\nid: 1\nparent_id: 2\ntype_: 1\n",
+        );
+        assert_eq!(item.title, "Groceries");
+        assert_eq!(item.body, "- milk\nnote: not metadata");
+        assert_eq!((item.get("id"), item.get("type_")), ("1", "1"));
+        assert_eq!(item.get("missing"), "");
+    }
+
+    #[test]
+    fn items_without_a_title_are_metadata_alone() {
+        let item = parse_item("id: 1\nnote_id: 2\ntag_id: 3\ntype_: 6");
+        assert_eq!((item.title.as_str(), item.body.as_str()), ("", ""));
+        assert_eq!(item.get("tag_id"), "3");
+    }
+
+    #[test]
+    fn metadata_keys_are_lowercase_words() {
+        assert!(is_meta("parent_id: 1"));
+        assert!(is_meta("body:"));
+        assert!(!is_meta("Note: capitalized"));
+        assert!(!is_meta(": no key"));
+        assert!(!is_meta("no colon"));
+    }
+
+    #[test]
+    fn links_to_items_are_32_hex_digits() {
+        assert_eq!(link_id(&format!(":/{}", A)), Some(A));
+        assert_eq!(link_id(A), None);
+        assert_eq!(link_id(":/abc"), None);
+        assert_eq!(link_id(&format!(":/{}", A.replace('a', "g"))), None);
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        fs::write(dir.join(name), content).unwrap();
+    }
+
+    #[test]
+    fn notes_are_tagged_with_notebooks_and_tags_and_linked() {
+        let dir = TempDir::new().unwrap();
+        let d = dir.path();
+        write(d, "f1.md", "Work\n\nid: f1\ntype_: 2");
+        write(d, "f2.md", "Projects\n\nid: f2\nparent_id: f1\ntype_: 2");
+        write(
+            d,
+            "note-a.md",
+            &format!(
+                concat!(
+                    "Plan\n\nSee [B](:/{}) and ![](:/{})\n\n",
+                    "id: {}\nparent_id: f2\ncreated_time: 2023-03-14T10:00:00.000Z\ntype_: 1",
+                ),
+                B, MISSING, A
+            ),
+        );
+        write(
+            d,
+            "note-b.md",
+            &format!("B\n\nBody\n\nid: {}\nparent_id: f1\ntype_: 1", B),
+        );
+        write(d, "t1.md", "urgent\n\nid: t1\ntype_: 5");
+        write(
+            d,
+            "nt1.md",
+            &format!("id: nt1\nnote_id: {}\ntag_id: t1\ntype_: 6", A),
+        );
+        write(
+            d,
+            "nt2.md",
+            &format!("id: nt2\nnote_id: {}\ntag_id: t2\ntype_: 6", A),
+        );
+        write(d, "rev.md", "id: r1\ntype_: 13");
+        write(d, "secret.md", "id: s1\nencryption_applied: 1\ntype_: 1");
+        write(d, "ignored.txt", "not an item");
+
+        let converted = read(d, &d.join("attachments")).unwrap();
+        assert_eq!(converted.docs.len(), 2);
+        let (a, b) = (&converted.docs[0], &converted.docs[1]);
+        assert_eq!((a.id.as_str(), a.title.as_str()), (A, "Plan"));
+        assert_eq!(a.tags, ["Work", "Projects", "urgent"]);
+        assert_eq!(a.links, [B]);
+        assert_eq!(a.body, format!("See [B]({}) and ![](:/{})\n", B, MISSING));
+        assert_eq!(a.date.timestamp(), 1678788000);
+        assert_eq!(a.slug, "plan");
+        assert_eq!(b.tags, ["Work"]);
+
+        let unconverted = converted.unconverted.join("\n");
+        for reason in [
+            "encrypted",
+            "item of type 13",
+            "Plan: tag t2 isn't in the export",
+        ]
+        .iter()
+        {
+            assert!(unconverted.contains(reason), "{}", unconverted);
+        }
+        assert!(unconverted.contains(&format!("Plan: link to :/{} isn't", MISSING)));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::config::KeyList;
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use termion::event::Key;
+
+/// Everything a key can be bound to in the interactive query
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Next,
+    Previous,
+    Select,
+    Quit,
+    Edit,
+    Pager,
+    NextLink,
+    PreviousLink,
+    FollowLink,
+    Back,
+    ToggleBacklinks,
+    Yank,
+    SwitchInput,
+    CursorLeft,
+    CursorRight,
+    CursorHome,
+    CursorEnd,
+    DeleteWord,
+    ClearInput,
+    Backspace,
+    DeleteChar,
+    HistorySearch,
+    ToggleMark,
+    EditTags,
+    Revisions,
+    OpenAttachments,
+}
+
+impl Action {
+    /// Config name of the action and its default keys
+    const DEFAULTS: &'static [(&'static str, Action, &'static [&'static str])] = &[
+        ("next", Action::Next, &["down", "ctrl-n"]),
+        ("previous", Action::Previous, &["up", "ctrl-p"]),
+        ("select", Action::Select, &["enter"]),
+        ("quit", Action::Quit, &["ctrl-c"]),
+        ("edit", Action::Edit, &["ctrl-x"]),
+        ("pager", Action::Pager, &["ctrl-v"]),
+        ("next-link", Action::NextLink, &["ctrl-l"]),
+        ("previous-link", Action::PreviousLink, &["ctrl-h"]),
+        ("follow-link", Action::FollowLink, &["ctrl-f"]),
+        ("back", Action::Back, &["ctrl-o"]),
+        ("toggle-backlinks", Action::ToggleBacklinks, &["ctrl-b"]),
+        ("yank", Action::Yank, &["ctrl-y"]),
+        ("switch-input", Action::SwitchInput, &["tab"]),
+        ("cursor-left", Action::CursorLeft, &["left"]),
+        ("cursor-right", Action::CursorRight, &["right"]),
+        ("cursor-home", Action::CursorHome, &["home", "ctrl-a"]),
+        ("cursor-end", Action::CursorEnd, &["end", "ctrl-e"]),
+        ("delete-word", Action::DeleteWord, &["ctrl-w"]),
+        ("clear-input", Action::ClearInput, &["ctrl-u"]),
+        ("backspace", Action::Backspace, &["backspace"]),
+        ("delete-char", Action::DeleteChar, &["delete"]),
+        ("history-search", Action::HistorySearch, &["ctrl-r"]),
+        ("toggle-mark", Action::ToggleMark, &["ctrl-s"]),
+        ("edit-tags", Action::EditTags, &["ctrl-t"]),
+        ("revisions", Action::Revisions, &["ctrl-d"]),
+        ("open-attachments", Action::OpenAttachments, &["ctrl-g"]),
+    ];
+}
+
+impl FromStr for Action {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Action, Self::Err> {
+        Action::DEFAULTS
+            .iter()
+            .find(|(name, _, _)| *name == s)
+            .map(|(_, action, _)| *action)
+            .ok_or_else(|| eyre!("❌ Unknown key binding action {}", s))
+    }
+}
+
+/// Parse a key name such as `ctrl-n`, `alt-x`, `pagedown`, `f5` or a single character
+pub fn parse_key(s: &str) -> Result<Key, Report> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let lower = s.to_lowercase();
+    let key = match lower.as_str() {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" => Key::Esc,
+        "tab" => Key::Char('\t'),
+        "enter" => Key::Char('\n'),
+        "space" => Key::Char(' '),
+        _ => {
+            if let Some(c) = lower.strip_prefix("ctrl-").and_then(single) {
+                Key::Ctrl(c)
+            } else if let Some(c) = lower.strip_prefix("alt-").and_then(single) {
+                Key::Alt(c)
+            } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Key::F(n)
+            } else if let Some(c) = single(s) {
+                Key::Char(c)
+            } else {
+                bail!("❌ Unknown key {}", s)
+            }
+        }
+    };
+    Ok(key)
+}
+
+/// Dispatch table from keys to actions
+pub struct KeyMap(HashMap<Key, Action>);
+
+impl KeyMap {
+    /// Build the default bindings, replacing those of any action named in `overrides`. Keys
+    /// bound in `overrides` take precedence over the defaults of other actions.
+    pub fn new(overrides: &BTreeMap<String, KeyList>) -> Result<KeyMap, Report> {
+        let mut custom: Vec<(Action, Vec<Key>)> = Vec::new();
+        for (name, keys) in overrides {
+            let keys = keys
+                .0
+                .iter()
+                .map(|k| parse_key(k))
+                .collect::<Result<_, _>>()?;
+            custom.push((name.parse()?, keys));
+        }
+
+        let mut map = HashMap::new();
+        for (_, action, keys) in Action::DEFAULTS {
+            if custom.iter().any(|(a, _)| a == action) {
+                continue;
+            }
+            for key in keys.iter() {
+                map.insert(parse_key(key)?, *action);
+            }
+        }
+        for (action, keys) in custom {
+            for key in keys {
+                map.insert(key, action);
+            }
+        }
+        Ok(KeyMap(map))
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.0.get(&key).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepted_keys() {
+        let table = [
+            ("up", Key::Up),
+            ("PageDown", Key::PageDown),
+            ("esc", Key::Esc),
+            ("tab", Key::Char('\t')),
+            ("enter", Key::Char('\n')),
+            ("space", Key::Char(' ')),
+            ("ctrl-n", Key::Ctrl('n')),
+            ("Ctrl-N", Key::Ctrl('n')),
+            ("alt-x", Key::Alt('x')),
+            ("f5", Key::F(5)),
+            ("F12", Key::F(12)),
+            ("f", Key::Char('f')),
+            ("F", Key::Char('F')),
+            ("-", Key::Char('-')),
+            ("é", Key::Char('é')),
+        ];
+        for (s, key) in table.iter() {
+            assert_eq!(parse_key(s).unwrap(), *key, "parsing {}", s);
+        }
+    }
+
+    #[test]
+    fn rejected_keys() {
+        for s in [
+            "", "ctrl-", "ctrl-ab", "alt-", "f256", "fn", "shift-a", "pgdn",
+        ]
+        .iter()
+        {
+            assert!(parse_key(s).is_err(), "parsing {}", s);
+        }
+    }
+
+    fn overrides(table: &[(&str, &[&str])]) -> BTreeMap<String, KeyList> {
+        table
+            .iter()
+            .map(|(action, keys)| {
+                let keys = keys.iter().map(|k| (*k).to_owned()).collect();
+                ((*action).to_owned(), KeyList(keys))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn defaults_bind_every_action() {
+        let keys = KeyMap::new(&BTreeMap::new()).unwrap();
+        for (name, action, defaults) in Action::DEFAULTS {
+            for key in defaults.iter() {
+                assert_eq!(
+                    keys.action(parse_key(key).unwrap()),
+                    Some(*action),
+                    "{}",
+                    name
+                );
+            }
+        }
+        assert_eq!(keys.action(Key::Char('x')), None);
+    }
+
+    #[test]
+    fn overrides_replace_the_defaults_of_their_action() {
+        let keys = KeyMap::new(&overrides(&[("edit", &["ctrl-e", "f2"])])).unwrap();
+        assert_eq!(keys.action(Key::Ctrl('x')), None);
+        assert_eq!(keys.action(Key::F(2)), Some(Action::Edit));
+        // Taken from cursor-end, which keeps its other default
+        assert_eq!(keys.action(Key::Ctrl('e')), Some(Action::Edit));
+        assert_eq!(keys.action(Key::End), Some(Action::CursorEnd));
+    }
+
+    #[test]
+    fn rejected_overrides() {
+        assert!(KeyMap::new(&overrides(&[("edit", &["ctrl-"])])).is_err());
+        assert!(KeyMap::new(&overrides(&[("explode", &["ctrl-e"])])).is_err());
+    }
+}
@@ -1,5 +1,59 @@
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("a TLS backend is needed, enable the rustls or native-tls feature");
+
+pub mod anki;
 pub mod api;
+pub mod attachment;
+pub mod auth;
+pub mod backup;
+pub mod bench;
+pub mod blocking;
+pub mod bookmarks;
+pub mod cache;
+pub mod client;
+pub mod config;
+pub mod crypt;
 pub mod date;
+pub mod dedupe;
+pub mod diff;
+pub mod doctor;
 pub mod document;
+pub mod emit;
+pub mod error;
+pub mod git;
+pub mod graph;
+pub mod hooks;
+pub mod import;
+pub mod index;
+pub mod input;
+#[cfg(feature = "interactive")]
 pub mod interactive;
+pub mod joplin;
+#[cfg(feature = "interactive")]
+pub mod keys;
+pub mod links;
+pub mod logging;
+pub mod mail;
+pub mod manpage;
+pub mod menu;
+pub mod merge;
+pub mod migrate;
+pub mod notion;
+pub mod pager;
 pub mod query;
+pub mod recording;
+pub mod search_index;
+pub mod server;
+pub mod setup;
+pub mod slug;
+pub mod state;
+pub mod style;
+pub mod table;
+pub mod tags;
+pub mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "interactive")]
+pub mod theme;
+pub mod trash;
+pub mod verify;
@@ -0,0 +1,81 @@
+use crate::client::Client;
+use crate::document::Document;
+use crate::{api, graph};
+use color_eyre::Report;
+use futures::stream::{self, StreamExt};
+
+/// How many external links are fetched at once
+const CONCURRENCY: usize = 8;
+
+/// A note with links that lead nowhere
+#[derive(Debug)]
+pub struct Dangling {
+    pub id: String,
+    pub title: String,
+    /// Each broken link with why it is broken
+    pub links: Vec<(String, String)>,
+}
+
+/// Whether `link` points at a web page rather than another note
+pub fn is_external(link: &str) -> bool {
+    link.starts_with("http://") || link.starts_with("https://")
+}
+
+/// Point the links of `doc` to `old` at `new` instead, returning whether any were
+pub fn relink(doc: &mut Document, old: &str, new: &str) -> bool {
+    let mut changed = false;
+    for link in doc.links.iter_mut().filter(|l| *l == old) {
+        *link = new.to_owned();
+        changed = true;
+    }
+    changed
+}
+
+/// Check the links of the latest revision of every note resolve to a note, of any revision, by
+/// id or slug. External links are fetched with `http` if given, and must answer 2xx, or are
+/// skipped otherwise.
+pub async fn check(
+    client: &Client,
+    http: Option<&reqwest::Client>,
+) -> Result<Vec<Dangling>, Report> {
+    let all = api::Pages::documents(client).all().await?;
+    let targets = graph::targets(&all);
+
+    let mut report = Vec::new();
+    for doc in all.iter().filter(|d| d.latest) {
+        let mut links = Vec::new();
+        let mut external = Vec::new();
+        for link in &doc.links {
+            if is_external(link) {
+                external.push(link.as_str());
+            } else if !targets.contains_key(link.as_str()) {
+                links.push((link.to_owned(), "no such note".to_owned()));
+            }
+        }
+        if let Some(http) = http {
+            let failed: Vec<_> = stream::iter(external)
+                .map(|url| async move { fetch(http, url).await.err().map(|e| (url.to_owned(), e)) })
+                .buffer_unordered(CONCURRENCY)
+                .collect()
+                .await;
+            links.extend(failed.into_iter().flatten());
+        }
+        if !links.is_empty() {
+            report.push(Dangling {
+                id: doc.id.clone(),
+                title: doc.title.clone(),
+                links,
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Fetch `url`, returning why if it doesn't answer 2xx
+async fn fetch(http: &reqwest::Client, url: &str) -> Result<(), String> {
+    match http.get(url).send().await {
+        Ok(resp) if resp.status().is_success() => Ok(()),
+        Ok(resp) => Err(resp.status().to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,188 @@
+use chrono::Utc;
+use color_eyre::Report;
+use eyre::eyre;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::Registry;
+
+/// Console events held for the interactive query while it owns the terminal
+static CAPTURED: Mutex<Option<Vec<(Level, String)>>> = Mutex::new(None);
+
+/// Most verbose level shown for `-v` given `verbosity` times, warnings and errors by default
+pub fn level(verbosity: u8) -> Level {
+    match verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// Send this crate's events to stderr at the level `verbosity` selects, or only errors if
+/// `quiet`, and, if `log_file` is given, append them as JSON lines to it at debug level or more
+/// verbose
+pub fn init(verbosity: u8, quiet: bool, log_file: Option<&Path>) -> Result<(), Report> {
+    let file = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| eyre!("❌ Failed to open log file {}: {}", path.display(), e))?;
+            Some(JsonFile {
+                file: Mutex::new(file),
+                level: level(verbosity).max(Level::DEBUG),
+            })
+        }
+        None => None,
+    };
+    let subscriber = Registry::default()
+        .with(Console {
+            level: if quiet {
+                Level::ERROR
+            } else {
+                level(verbosity)
+            },
+        })
+        .with(file);
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(())
+}
+
+/// Hold console events instead of writing them to stderr, for `drain_captured` to hand to the
+/// interactive query, until the returned guard drops
+pub fn capture() -> Capture {
+    if let Ok(mut captured) = CAPTURED.lock() {
+        *captured = Some(Vec::new());
+    }
+    Capture {}
+}
+
+/// Console events held since the last call, oldest first
+pub fn drain_captured() -> Vec<(Level, String)> {
+    match CAPTURED.lock() {
+        Ok(mut captured) => captured.as_mut().map(std::mem::take).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Writes events captured but never drained to stderr when dropped
+pub struct Capture {}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let held = match CAPTURED.lock() {
+            Ok(mut captured) => captured.take().unwrap_or_default(),
+            Err(_) => return,
+        };
+        for (_, line) in held {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Dependencies such as hyper log every connection, only our own events are of interest
+fn ours(target: &str) -> bool {
+    target.starts_with("meilizet") || target == "mz" || target.starts_with("mz::")
+}
+
+/// An event's fields, `message` being the formatted text of the event macro
+#[derive(Default)]
+struct Fields(Map<String, Value>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), Value::from(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+}
+
+/// Writes the message followed by any other fields as key=value, e.g.
+/// `response method=GET status=200`
+struct Console {
+    level: Level,
+}
+
+impl<S: Subscriber> Layer<S> for Console {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        if *meta.level() > self.level || !ours(meta.target()) {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut line = match fields.0.remove("message") {
+            Some(Value::String(s)) => s,
+            Some(v) => v.to_string(),
+            None => String::new(),
+        };
+        for (k, v) in fields.0 {
+            match v {
+                Value::String(s) => line.push_str(&format!(" {}={}", k, s)),
+                v => line.push_str(&format!(" {}={}", k, v)),
+            }
+        }
+
+        let mut captured = match CAPTURED.lock() {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        match captured.as_mut() {
+            Some(held) => held.push((*meta.level(), line)),
+            None => eprintln!("{}", line),
+        }
+    }
+}
+
+/// Appends each event as a JSON object on its own line
+struct JsonFile {
+    file: Mutex<File>,
+    level: Level,
+}
+
+impl<S: Subscriber> Layer<S> for JsonFile {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        if *meta.level() > self.level || !ours(meta.target()) {
+            return;
+        }
+        let mut fields = Fields::default();
+        fields
+            .0
+            .insert("timestamp".to_owned(), Value::from(Utc::now().to_rfc3339()));
+        fields
+            .0
+            .insert("level".to_owned(), Value::from(meta.level().to_string()));
+        fields
+            .0
+            .insert("target".to_owned(), Value::from(meta.target()));
+        event.record(&mut fields);
+        if let Ok(mut file) = self.file.lock() {
+            // Nowhere left to report a failure to log
+            let _ = writeln!(file, "{}", Value::Object(fields.0));
+        }
+    }
+}
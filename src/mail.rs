@@ -0,0 +1,534 @@
+use crate::date::{Date, DateFrom};
+use crate::document::Document;
+use crate::slug;
+use chrono::DateTime;
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A mailbox in a From, To or Cc header
+#[derive(Clone, Debug, PartialEq)]
+pub struct Address {
+    /// The display name, empty if there is none
+    pub name: String,
+    pub address: String,
+}
+
+/// What `import-mail` reads of an email
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mail {
+    pub from: Option<Address>,
+    /// Everyone in To and Cc
+    pub recipients: Vec<Address>,
+    pub subject: String,
+    pub date: Option<Date>,
+    pub message_id: Option<String>,
+    /// The first plain text part, None if there is none
+    pub text: Option<String>,
+}
+
+/// The messages in the maildir `dir`, those read in `cur` and those not yet in `new`, sorted by
+/// file name
+pub fn messages(dir: &Path) -> Result<Vec<PathBuf>, Report> {
+    let mut paths = Vec::new();
+    for sub in &["cur", "new"] {
+        let sub = dir.join(sub);
+        if !sub.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&sub)? {
+            let path = entry?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+    }
+    if paths.is_empty() && !dir.join("cur").is_dir() {
+        bail!(
+            "❌ {} is not a maildir, it has no cur directory",
+            dir.display()
+        );
+    }
+    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    Ok(paths)
+}
+
+/// Offset of the first `needle` in `hay`
+fn find(hay: &[u8], needle: &[u8]) -> Option<usize> {
+    hay.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The unfolded headers and the body of the message or MIME part `raw`
+fn split(raw: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let (head, body) = match (find(raw, b"\r\n\r\n"), find(raw, b"\n\n")) {
+        (Some(crlf), Some(lf)) if crlf < lf => (&raw[..crlf], &raw[crlf + 4..]),
+        (_, Some(lf)) => (&raw[..lf], &raw[lf + 2..]),
+        (Some(crlf), None) => (&raw[..crlf], &raw[crlf + 4..]),
+        (None, None) => (raw, &raw[raw.len()..]),
+    };
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with(char::is_whitespace) {
+            // A folded line continues the header before it
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+        }
+    }
+    (headers, body)
+}
+
+/// The value of the first header called `name`, lowercase
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// `bytes` in `charset`, with Latin-1 and Windows-1252 read as Latin-1 and anything else as
+/// UTF-8
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "latin1" | "windows-1252" => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// The bytes quoted-printable `text` stands for. With `header`, underscores are spaces, as in
+/// a Q encoded word.
+fn quoted_printable(text: &[u8], header: bool) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        match text[i] {
+            b'=' => {
+                let hex = text
+                    .get(i + 1..i + 3)
+                    .and_then(|h| std::str::from_utf8(h).ok())
+                    .and_then(|h| u8::from_str_radix(h, 16).ok());
+                if let Some(byte) = hex {
+                    out.push(byte);
+                    i += 3;
+                } else if text[i + 1..].starts_with(b"\r\n") {
+                    i += 3;
+                } else if text[i + 1..].starts_with(b"\n") {
+                    i += 2;
+                } else {
+                    out.push(b'=');
+                    i += 1;
+                }
+            }
+            b'_' if header => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// `text` with its RFC 2047 encoded words, such as `=?UTF-8?B?...?=`, decoded. Whitespace
+/// between two encoded words is dropped, as the RFC says.
+fn decode_words(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let word = rest[start + 2..].splitn(3, '?').collect::<Vec<_>>();
+        let decoded = match word.as_slice() {
+            [charset, encoding, tail] => tail.find("?=").and_then(|end| {
+                let encoded = &tail[..end];
+                let bytes = match encoding.to_ascii_lowercase().as_str() {
+                    "b" => base64::decode(encoded).ok()?,
+                    "q" => quoted_printable(encoded.as_bytes(), true),
+                    _ => return None,
+                };
+                let len = charset.len() + encoding.len() + end + 6;
+                Some((decode_charset(&bytes, charset), len))
+            }),
+            _ => None,
+        };
+        let between = &rest[..start];
+        match decoded {
+            Some((word, len)) => {
+                if !(after_word && between.trim().is_empty()) {
+                    out.push_str(between);
+                }
+                out.push_str(&word);
+                rest = &rest[start + len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The lowercase type of a Content-Type `value` and the value of its parameter `param`
+fn content_type(value: &str, param: &str) -> (String, Option<String>) {
+    let mut fields = value.split(';');
+    let mime = fields
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let found = fields.find_map(|field| {
+        let (name, value) = field.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case(param) {
+            Some(value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    });
+    (mime, found)
+}
+
+/// The parts of a multipart `body` split at `boundary`
+fn parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    for line in body.split(|&b| b == b'\n') {
+        let end = offset + line.len() + 1;
+        let trimmed = line.strip_suffix(b"\r").unwrap_or(line);
+        if trimmed.starts_with(delimiter.as_bytes()) {
+            if let Some(start) = start {
+                parts.push(&body[start..offset.max(start)]);
+            }
+            if trimmed[delimiter.len()..].starts_with(b"--") {
+                return parts;
+            }
+            start = Some(end.min(body.len()));
+        }
+        offset = end;
+    }
+    parts
+}
+
+/// The first plain text part of the MIME entity with `headers` and `body`, skipping
+/// attachments
+fn text_part(headers: &[(String, String)], body: &[u8]) -> Option<String> {
+    if header(headers, "content-disposition")
+        .is_some_and(|d| d.trim().to_ascii_lowercase().starts_with("attachment"))
+    {
+        return None;
+    }
+    let (mime, boundary) = content_type(
+        header(headers, "content-type").unwrap_or("text/plain"),
+        "boundary",
+    );
+    if mime.starts_with("multipart/") {
+        return parts(body, &boundary?).into_iter().find_map(|part| {
+            let (headers, body) = split(part);
+            text_part(&headers, body)
+        });
+    }
+    if mime != "text/plain" {
+        return None;
+    }
+    let (_, charset) = content_type(header(headers, "content-type").unwrap_or(""), "charset");
+    let encoding = header(headers, "content-transfer-encoding")
+        .unwrap_or("7bit")
+        .trim()
+        .to_ascii_lowercase();
+    let bytes = match encoding.as_str() {
+        "base64" => {
+            let packed: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            base64::decode(&packed).ok()?
+        }
+        "quoted-printable" => quoted_printable(body, false),
+        _ => body.to_vec(),
+    };
+    let text = decode_charset(&bytes, charset.as_deref().unwrap_or("utf-8"));
+    Some(text.replace("\r\n", "\n"))
+}
+
+/// The mailboxes in an address list header `value`, e.g. `Ann <ann@example.com>, bob@example.com`
+pub fn addresses(value: &str) -> Vec<Address> {
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let (mut quoted, mut angled) = (false, false);
+    for c in value.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '<' if !quoted => angled = true,
+            '>' if !quoted => angled = false,
+            ',' if !quoted && !angled => {
+                entries.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    entries.push(current);
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let entry = decode_words(entry.trim());
+            let (name, address) = match (entry.rfind('<'), entry.rfind('>')) {
+                (Some(open), Some(close)) if open < close => (
+                    entry[..open].trim().trim_matches('"').trim(),
+                    entry[open + 1..close].trim(),
+                ),
+                _ => ("", entry.as_str()),
+            };
+            if address.contains('@') {
+                Some(Address {
+                    name: name.to_owned(),
+                    address: address.to_owned(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Read the email `raw`
+pub fn parse(raw: &[u8]) -> Mail {
+    let (headers, body) = split(raw);
+    let list = |name: &str| header(&headers, name).map(addresses).unwrap_or_default();
+    let mut recipients = list("to");
+    recipients.extend(list("cc"));
+    Mail {
+        from: list("from").into_iter().next(),
+        recipients,
+        subject: header(&headers, "subject")
+            .map(decode_words)
+            .unwrap_or_default(),
+        date: header(&headers, "date")
+            // Leaving out a trailing comment such as (UTC), which chrono doesn't accept
+            .and_then(|d| DateTime::parse_from_rfc2822(d.split('(').next()?.trim()).ok())
+            .map(|d| Date::new(d.timestamp())),
+        message_id: header(&headers, "message-id")
+            .map(|id| id.trim_matches(|c: char| c == '<' || c == '>' || c.is_whitespace()))
+            .filter(|id| !id.is_empty())
+            .map(str::to_owned),
+        text: text_part(&headers, body),
+    }
+}
+
+/// `mail`, read from the file at `path`, as a note titled by its subject and dated when it was
+/// sent, or by the file's mtime without a readable Date. The sender is its author and each
+/// recipient's address a tag. Its id is the hex SHA-1 of the Message-ID, so the same email
+/// imported twice is one note. Fails if the email has no plain text.
+pub fn to_document(mail: &Mail, path: &Path) -> Result<Document, Report> {
+    let text = mail
+        .text
+        .as_deref()
+        .ok_or_else(|| eyre!("❌ {} has no plain text part", path.display()))?;
+    let key = match &mail.message_id {
+        Some(id) => id.clone(),
+        None => path.display().to_string(),
+    };
+    let date = match &mail.date {
+        Some(date) => date.clone(),
+        None => DateFrom::Mtime.date_for(path)?,
+    };
+    let title = if mail.subject.trim().is_empty() {
+        "(no subject)".to_owned()
+    } else {
+        mail.subject.trim().to_owned()
+    };
+    let mut doc = Document {
+        id: format!("{:x}", Sha1::digest(key.as_bytes())),
+        title,
+        date,
+        body: format!("{}\n", text.trim_end()),
+        authors: mail
+            .from
+            .iter()
+            .map(|from| {
+                if from.name.is_empty() {
+                    from.address.clone()
+                } else {
+                    from.name.clone()
+                }
+            })
+            .collect(),
+        ..Document::new()
+    };
+    for to in &mail.recipients {
+        let tag = to.address.to_lowercase();
+        if !doc.tags.contains(&tag) {
+            doc.tags.push(tag);
+        }
+    }
+    slug::fill(&mut doc);
+    doc.filename = format!("{}.md", doc.slug.replace('/', "-"));
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTIPART: &str = r#"From: =?UTF-8?B?Q3LDqG1l?= Fan <fan@example.com>
+To: "Doe, Jane" <Jane@Example.com>, bob@example.com
+Cc: jane@example.com
+Subject: Re: =?UTF-8?Q?Cr=C3=A8me_br=C3=BBl=C3=A9e?=
+ recipe
+Date: Tue, 14 Mar 2023 10:00:00 +0000 (UTC)
+Message-ID: <abc@example.com>
+Content-Type: multipart/mixed; boundary="outer"
+
+preamble
+--outer
+Content-Type: multipart/alternative; boundary=inner
+
+--inner
+Content-Type: text/plain; charset=utf-8
+Content-Transfer-Encoding: quoted-printable
+
+Caramelize the sugar=2C then ser=
+ve.
+--inner
+Content-Type: text/html
+
+<p>html</p>
+--inner--
+--outer
+Content-Type: text/plain
+Content-Disposition: attachment; filename=notes.txt
+
+not this
+--outer--
+"#;
+
+    fn address(name: &str, address: &str) -> Address {
+        Address {
+            name: name.to_owned(),
+            address: address.to_owned(),
+        }
+    }
+
+    #[test]
+    fn encoded_words_decode() {
+        let table = [
+            ("plain", "plain"),
+            (
+                "Re: =?UTF-8?Q?Cr=C3=A8me_br=C3=BBl=C3=A9e?=",
+                "Re: Crème brûlée",
+            ),
+            (
+                "=?utf-8?b?Q3LDqG1l?= =?UTF-8?Q?_br=C3=BBl=C3=A9e?=",
+                "Crème brûlée",
+            ),
+            ("=?ISO-8859-1?Q?caf=E9?= time", "café time"),
+            ("=?broken", "=?broken"),
+            ("=?UTF-8?X?abc?=", "=?UTF-8?X?abc?="),
+        ];
+        for (encoded, decoded) in table.iter() {
+            assert_eq!(decode_words(encoded), *decoded, "decoding {}", encoded);
+        }
+    }
+
+    #[test]
+    fn quoted_printable_joins_soft_line_breaks() {
+        assert_eq!(quoted_printable(b"a=3Db=\r\nc=\nd", false), b"a=bcd");
+        assert_eq!(quoted_printable(b"a_b=", false), b"a_b=");
+        assert_eq!(quoted_printable(b"a_b", true), b"a b");
+    }
+
+    #[test]
+    fn address_lists_split_outside_quotes_and_angles() {
+        assert_eq!(
+            addresses(
+                r#""Doe, Jane" <jane@example.com>, bob@example.com, undisclosed-recipients:;"#
+            ),
+            [
+                address("Doe, Jane", "jane@example.com"),
+                address("", "bob@example.com")
+            ]
+        );
+    }
+
+    #[test]
+    fn multipart_mail_parses() {
+        let mail = parse(MULTIPART.as_bytes());
+        assert_eq!(mail.from, Some(address("Crème Fan", "fan@example.com")));
+        assert_eq!(
+            mail.recipients,
+            [
+                address("Doe, Jane", "Jane@Example.com"),
+                address("", "bob@example.com"),
+                address("", "jane@example.com")
+            ]
+        );
+        assert_eq!(mail.subject, "Re: Crème brûlée recipe");
+        assert_eq!(mail.date.map(|d| d.timestamp()), Some(1678788000));
+        assert_eq!(mail.message_id.as_deref(), Some("abc@example.com"));
+        assert_eq!(
+            mail.text.as_deref(),
+            Some("Caramelize the sugar, then serve.\n")
+        );
+    }
+
+    #[test]
+    fn single_part_mail_decodes_its_transfer_encoding_and_charset() {
+        let raw = concat!(
+            "Subject: =?ISO-8859-1?Q?caf=E9?=\r\n",
+            "Content-Type: text/plain; charset=iso-8859-1\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "SGVsbG8gZnJvbSBiYXNlNjQNCg==\r\n",
+        );
+        let mail = parse(raw.as_bytes());
+        assert_eq!(mail.subject, "café");
+        assert_eq!(mail.text.as_deref(), Some("Hello from base64\n"));
+        assert_eq!((mail.date, mail.message_id), (None, None));
+    }
+
+    #[test]
+    fn mail_without_plain_text_has_none() {
+        let raw = "Content-Type: text/html\n\n<p>only html</p>\n";
+        assert_eq!(parse(raw.as_bytes()).text, None);
+    }
+
+    #[test]
+    fn documents_are_keyed_by_message_id_and_tagged_with_recipients() {
+        let mail = parse(MULTIPART.as_bytes());
+        let doc = to_document(&mail, Path::new("cur/1")).unwrap();
+        assert_eq!(doc.id, "760eab446d785ea63ad85b5ea6088e27b37f7546");
+        assert_eq!(doc.title, "Re: Crème brûlée recipe");
+        assert_eq!(doc.slug, "re-creme-brulee-recipe");
+        assert_eq!(doc.filename, "re-creme-brulee-recipe.md");
+        assert_eq!(doc.date.timestamp(), 1678788000);
+        assert_eq!(doc.authors, ["Crème Fan"]);
+        assert_eq!(doc.tags, ["jane@example.com", "bob@example.com"]);
+        assert_eq!(doc.body, "Caramelize the sugar, then serve.\n");
+
+        let untitled = Mail {
+            subject: " ".to_owned(),
+            ..mail.clone()
+        };
+        assert_eq!(
+            to_document(&untitled, Path::new("cur/1")).unwrap().title,
+            "(no subject)"
+        );
+        let textless = Mail { text: None, ..mail };
+        assert!(to_document(&textless, Path::new("cur/1")).is_err());
+    }
+}
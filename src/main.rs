@@ -1,13 +1,38 @@
-mod interactive;
-mod query;
+use chrono::Local;
 use color_eyre::Report;
+use eyre::bail;
+use futures::stream::{self, StreamExt};
 use glob::{glob, Paths};
-use meilizet::{api, document};
-use reqwest::header::CONTENT_TYPE;
+use meilizet::client::{Client, Options, Retry};
+use meilizet::date::{self, Date};
+use meilizet::error::{self, ExitCode, ImportSummary, ParseError};
+#[cfg(feature = "interactive")]
+use meilizet::interactive;
+use meilizet::recording::Recording;
+use meilizet::status;
+use meilizet::{
+    anki, api, attachment, auth, backup, bench, blocking, bookmarks, cache, config, crypt, dedupe,
+    diff, doctor, document, emit, git, graph, hooks, import, index, joplin, links, logging, mail,
+    manpage, menu, merge, migrate, notion, pager, query, search_index, server, setup, slug, state,
+    style, tags, template, trash, verify,
+};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+use structopt::clap::Shell;
 use structopt::StructOpt;
-use url::Url;
+use tokio::runtime::Runtime;
+use tracing::{error, warn};
+
+/// Server used when neither --host, MEILI_HOST nor the config name one
+const DEFAULT_HOST: &str = "http://127.0.0.1:7700";
+
+/// Documents per partial update of `bulk-edit`, progress is reported after each
+const BULK_BATCH: usize = 500;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -20,22 +45,139 @@ struct Opt {
     #[structopt(short, long, parse(from_occurrences))]
     verbosity: u8,
 
+    /// Print only errors and the data asked for, leaving out progress and status messages
+    #[structopt(short, long, conflicts_with = "verbosity")]
+    quiet: bool,
+
+    /// Colour output, and mark outcomes with emoji rather than words: auto, always or never.
+    /// auto colours when stdout is a terminal and NO_COLOR isn't set
+    #[structopt(long, default_value = "auto")]
+    color: style::ColorChoice,
+
+    /// Meilisearch server, by default the config's `host` or http://127.0.0.1:7700. Further
+    /// comma-separated servers are read replicas that searches fall back to
+    #[structopt(short, long, env = "MEILI_HOST")]
+    host: Option<String>,
+
+    /// API key sent as a bearer token, needed when the server runs with a master key. Without
+    /// one, the key stored with `auth login` is sent
     #[structopt(
         short,
         long,
-        default_value = "http://127.0.0.1:7700",
-        env = "MEILI_HOST"
+        default_value = "",
+        env = "MEILI_KEY",
+        hide_env_values = true
     )]
-    host: String,
-
-    #[structopt(short, long, default_value = "", env = "MEILI_KEY")]
     key: String,
 
-    #[structopt(short, long, default_value = "less", env = "PAGER")]
-    pager: String,
+    /// Name the API key is stored under in the system keyring
+    #[structopt(long, default_value = "default", env = "MEILIZET_PROFILE")]
+    profile: String,
+
+    /// Certificate to trust in addition to the system roots, PEM or, named *.der, DER
+    #[structopt(long, env = "MEILI_CA_CERT", parse(from_os_str))]
+    ca_cert: Option<PathBuf>,
+
+    /// Accept any server certificate, e.g. a self-signed one. Anyone on the network path can
+    /// then read the key and notes
+    #[structopt(long)]
+    insecure: bool,
+
+    /// Certificate and key to authenticate to the server with, PKCS #12 or, built with rustls,
+    /// PEM
+    #[structopt(long, env = "MEILI_CLIENT_CERT", parse(from_os_str))]
+    client_cert: Option<PathBuf>,
+
+    /// Password of the --client-cert PKCS #12 archive
+    #[structopt(
+        long,
+        default_value = "",
+        env = "MEILI_CLIENT_CERT_PASSWORD",
+        hide_env_values = true
+    )]
+    client_cert_password: String,
+
+    /// Seconds before giving up on a request, unlimited by default
+    #[structopt(long, env = "MEILI_TIMEOUT")]
+    timeout: Option<u64>,
+
+    /// Seconds before giving up on connecting to the server
+    #[structopt(long, env = "MEILI_CONNECT_TIMEOUT")]
+    connect_timeout: Option<u64>,
+
+    /// Proxy URL for every request, HTTP_PROXY and HTTPS_PROXY are used otherwise
+    #[structopt(long, env = "MEILI_PROXY")]
+    proxy: Option<String>,
 
-    #[structopt(short, long, default_value = "vim", env = "EDITOR")]
-    editor: String,
+    /// Connect directly, ignoring --proxy and the proxy environment variables
+    #[structopt(long)]
+    no_proxy: bool,
+
+    /// How often to retry requests that fail to connect, time out, or get a 429 or 503
+    #[structopt(long, default_value = "3", env = "MEILI_RETRIES")]
+    retries: u32,
+
+    /// Milliseconds before the first retry, doubling with each one after
+    #[structopt(long, default_value = "250", env = "MEILI_RETRY_DELAY")]
+    retry_delay: u64,
+
+    /// By default the config's `pager` or less
+    #[structopt(short, long, env = "PAGER")]
+    pager: Option<String>,
+
+    /// By default the config's `editor` or vim
+    #[structopt(short, long, env = "EDITOR")]
+    editor: Option<String>,
+
+    /// Report failures, and the outcome of imports, as a JSON object on stderr
+    #[structopt(long)]
+    json: bool,
+
+    /// Answer searches and reads from the copy of the index saved by the last `dump` or `sync`,
+    /// for use without the server
+    #[structopt(long)]
+    offline: bool,
+
+    /// Refuse every request that could change the server, documents, settings, keys or
+    /// anything else, for exploring an index that must not be touched. Also set per profile
+    /// in the config.
+    #[structopt(long)]
+    read_only: bool,
+
+    /// Save every request and response to numbered files in this directory, with keys left
+    /// out, e.g. to attach to a bug report
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["replay", "offline"])]
+    record: Option<PathBuf>,
+
+    /// Answer requests with the responses saved by --record in this directory instead of
+    /// contacting the server
+    #[structopt(long, parse(from_os_str), conflicts_with = "offline")]
+    replay: Option<PathBuf>,
+
+    /// Encrypt the body of every document uploaded with the key from `auth body-key`. Bodies
+    /// encrypted before are always encrypted again.
+    #[structopt(long)]
+    encrypt: bool,
+
+    /// Decrypt encrypted bodies read from the server, for display and editing
+    #[structopt(long)]
+    decrypt: bool,
+
+    /// Append JSON logs, including each request and response, to this file
+    #[structopt(long, env = "MEILIZET_LOG_FILE", parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Config file, defaults to ~/.config/meilizet/config.toml
+    #[structopt(short, long = "config", env = "MEILIZET_CONFIG", parse(from_os_str))]
+    config_path: Option<PathBuf>,
+
+    #[structopt(skip)]
+    config: config::Config,
+
+    /// Opened from --record or --replay, shared by every client so the files are numbered
+    /// across them
+    #[structopt(skip)]
+    recording: Option<Arc<Recording>>,
 
     #[structopt(subcommand)]
     subcmd: Subcommands,
@@ -43,219 +185,3101 @@ struct Opt {
 
 #[derive(Debug, StructOpt)]
 enum Subcommands {
+    /// Create the notes index and configure its filterable, sortable and searchable attributes
+    Init {},
+    /// Ask for the server, API key, index, editor, pager and notes directory, check the server
+    /// answers, optionally create the index and write the config file
+    Setup {},
+    /// Check the server is available and report its version
+    Health {},
+    /// Check the connection, API key, server version, index and its settings, suggesting a fix
+    /// for each problem found
+    Doctor {},
+    /// Report the document count, size, indexing state and field distribution of the index
+    Stats {},
+    /// Monitor the server's asynchronous tasks, such as document additions
+    Tasks(TasksCommand),
+    /// Print a completion script for bash, zsh, fish, powershell or elvish
+    Completions {
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
+    /// Print a man page in roff, e.g. `mz manpage > /usr/local/share/man/man1/mz.1`
+    Manpage {},
+    /// Store or remove the API key for the profile in the system keyring
+    Auth(AuthCommand),
+    /// Manage the server's API keys, e.g. to mint a search-only key for a web frontend
+    Keys(KeysCommand),
+    /// Have the server dump every index for backup, unlike `dump` which writes local files
+    ServerDump(ServerDumpCommand),
+    /// Read or write the index's filterable, sortable and searchable attributes
+    Settings(SettingsCommand),
+    /// Read, replace or reset the words ignored in queries
+    Stopwords(StopwordsCommand),
+    /// Read, replace or reset the order relevancy criteria are applied in
+    RankingRules(RankingRulesCommand),
     /// Import markdown-fm-doc formatted files matching the unexpanded glob pattern
     ImportLegacyMd { globpath: String },
     /// Import meilizet/Document formatted files matching the unexpanded glob pattern
-    Import { globpath: String },
-    /// Interactively query the server
-    Query {},
+    Import {
+        globpath: String,
+        /// What to do with files whose id is already in the index: skip, overwrite,
+        /// new-revision (unless unchanged) or fail before importing anything
+        #[structopt(long, default_value = "new-revision")]
+        on_conflict: import::OnConflict,
+        /// Tag each note with the directories between the start of the pattern and the file,
+        /// e.g. projects and rust for projects/rust/notes.md
+        #[structopt(long)]
+        path_tags: bool,
+        /// Date notes without one in their frontmatter by the file's mtime, a YYYY-MM-DD date in
+        /// the file name, or now, instead of failing to load them
+        #[structopt(long)]
+        date_from: Option<date::DateFrom>,
+    },
+    /// Check the frontmatter of the files matching the unexpanded glob pattern, reporting every
+    /// problem an import would trip over
+    Validate {
+        globpath: String,
+        /// Accept notes without a date, as import does when given it
+        #[structopt(long)]
+        date_from: Option<date::DateFrom>,
+    },
+    /// Rebuild the index from the files matching the unexpanded glob pattern, swapping the
+    /// result in once complete
+    Reimport { globpath: String },
+    /// Import the markdown files in the git repository `repo` matching the glob pattern, relative
+    /// to its top, each as a chain of revisions, one per commit that changed it
+    ImportGit { repo: String, globpath: String },
+    /// Import saved articles or bookmarks from an export, one note each titled as saved with
+    /// the URL in its links and any description as the body
+    ImportBookmarks {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// pocket, for Pocket's or Instapaper's HTML export, or netscape-html, for a browser's
+        #[structopt(long)]
+        format: bookmarks::Format,
+        /// Index to import into, e.g. bookmarks to keep them apart from notes. Defaults to the
+        /// notes index
+        #[structopt(long)]
+        index: Option<String>,
+        /// What to do with pages already in the index: skip, overwrite, new-revision (unless
+        /// unchanged) or fail before importing anything
+        #[structopt(long, default_value = "skip")]
+        on_conflict: import::OnConflict,
+    },
+    /// Import the notes of a Joplin JEX archive or RAW export directory with their tags,
+    /// notebooks, links and resources, reporting anything left behind
+    ImportJoplin {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        /// What to do with notes already in the index: skip, overwrite, new-revision (unless
+        /// unchanged) or fail before importing anything
+        #[structopt(long, default_value = "skip")]
+        on_conflict: import::OnConflict,
+    },
+    /// Import the pages of a Notion Markdown & CSV export zip with their properties, links and
+    /// files, reporting anything left behind
+    ImportNotion {
+        #[structopt(parse(from_os_str))]
+        zip: PathBuf,
+        /// What to do with pages already in the index: skip, overwrite, new-revision (unless
+        /// unchanged) or fail before importing anything
+        #[structopt(long, default_value = "skip")]
+        on_conflict: import::OnConflict,
+    },
+    /// Import the emails in a maildir as notes titled by their subject, with the sender as
+    /// author and each recipient's address as a tag
+    ImportMail {
+        #[structopt(parse(from_os_str))]
+        maildir: PathBuf,
+        /// Only the emails whose sender's address contains this, e.g. me@
+        #[structopt(long)]
+        filter_from: Option<String>,
+        /// What to do with emails already in the index: skip, overwrite, new-revision (unless
+        /// unchanged) or fail before importing anything
+        #[structopt(long, default_value = "skip")]
+        on_conflict: import::OnConflict,
+    },
+    /// Measure how fast the server indexes notes, in a scratch index
+    Bench(BenchCommand),
+    /// Interactively query the server. Without a terminal on both ends, asks on stderr and
+    /// picks from a numbered list, or reads the query and filter from piped stdin
+    Query {
+        /// Start with empty inputs instead of restoring the last session
+        #[structopt(long)]
+        fresh: bool,
+        /// Print the picked documents as ids, json, paths or body
+        #[structopt(long, default_value = "ids")]
+        emit: emit::Emit,
+        /// Directory the documents were dumped to, used to build `--emit paths`. Defaults to the
+        /// config's notes_dir or the current directory
+        #[structopt(long, parse(from_os_str))]
+        dir: Option<PathBuf>,
+        /// Also show archived notes
+        #[structopt(long)]
+        include_archived: bool,
+        /// Press the keys listed in this file instead of reading the keyboard, e.g. to record a
+        /// demo. See the README for the format
+        #[structopt(long, parse(from_os_str))]
+        script: Option<PathBuf>,
+    },
     /// Non-interactive query, specify all parameters from the command line
     StaticQuery {
         #[structopt(default_value = "")]
         query: String,
         #[structopt(default_value = "")]
         filter: String,
+        /// Index to search, repeat to search several at once with labelled results. Defaults
+        /// to the notes index
+        #[structopt(long = "index", number_of_values = 1)]
+        indexes: Vec<String>,
+        /// Also show archived notes
+        #[structopt(long)]
+        include_archived: bool,
+        /// Print straight to the terminal even when the matches don't fit on it
+        #[structopt(long)]
+        no_pager: bool,
+        /// Print the matches as a table, debug, or ndjson, one JSON document per line written
+        /// as each page arrives. Hits from other indexes are always listed labelled with their
+        /// index
+        #[structopt(long, default_value = "table")]
+        output: query::Output,
+        /// Page through every match instead of stopping at the first response's
+        #[structopt(long)]
+        all: bool,
+    },
+    /// Archive notes instead of deleting them: tag them archived and mark them no longer latest
+    Archive {
+        /// Id of a note, or of any of its revisions
+        ids: Vec<String>,
+        /// Archive every latest note matching this filter instead
+        #[structopt(long, conflicts_with = "ids")]
+        filter: Option<String>,
+    },
+    /// Dump records to a local path, refreshing the copy used by --offline
+    Dump {
+        /// Defaults to the config's notes_dir
+        path: Option<PathBuf>,
+        /// Write every note's frontmatter as yaml or toml, instead of as it was imported
+        #[structopt(long)]
+        frontmatter: Option<document::FrontmatterFormat>,
+    },
+    /// Compare a directory written by dump against the index, reporting documents with no file,
+    /// files whose contents differ and files no document is dumped to
+    Verify {
+        /// Defaults to the config's notes_dir
+        path: Option<PathBuf>,
+        /// Expect every note's frontmatter as yaml or toml, as passed to dump
+        #[structopt(long)]
+        frontmatter: Option<document::FrontmatterFormat>,
+    },
+    /// Move documents written by older versions to the current schema: hyphenated UUID ids get
+    /// new ids, string dates become timestamps and renamed or missing fields are filled in
+    Migrate {
+        /// Print what would change as a diff, without writing anything
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Write a client-side search bundle of the latest notes' titles, tags, excerpts and slugs,
+    /// for a published site to search without Meilisearch
+    ExportSearchIndex {
+        /// pagefind (a directory of pages to run pagefind on), lunr or fuse (a JSON file)
+        #[structopt(long, default_value = "fuse")]
+        format: search_index::Format,
+        out: PathBuf,
+    },
+    /// Write the flashcards in matching notes, `Q:`/`A:` pairs or cloze deletions, as a file
+    /// Anki imports
+    ExportAnki {
+        /// Notes to take the cards from, in the filter grammar, e.g. `flashcard`,
+        /// `tag=flashcard` or `flashcard + !draft`
+        #[structopt(long, default_value = "flashcard")]
+        filter: String,
+        /// Deck the cards go into
+        #[structopt(long, default_value = "mz")]
+        deck: String,
+        /// Where to write the file, stdout if unset
+        #[structopt(long)]
+        out: Option<PathBuf>,
+    },
+    /// Save every document and index setting to a zstd compressed tar, e.g. notes.tar.zst
+    Backup { path: PathBuf },
+    /// Recreate the index from an archive written by backup
+    Restore {
+        path: PathBuf,
+        /// Delete the index first if it already has documents
+        #[structopt(long)]
+        replace: bool,
+    },
+    /// Refresh the local copy of the index used by --offline
+    Sync {},
+    /// Print a document by id
+    Get {
+        id: String,
+        /// Print only the body, e.g. for a picker's preview window
+        #[structopt(long)]
+        body: bool,
     },
-    /// Dump records to a local path
-    Dump { path: String },
+    /// Show how two documents, e.g. revisions of a note, or a local file and the document in the
+    /// index differ, as a unified diff of the files dump writes for them
+    Diff {
+        id: String,
+        /// Document to compare against
+        #[structopt(required_unless = "file", conflicts_with = "file")]
+        other: Option<String>,
+        /// File to compare the document against, e.g. one edited since it was dumped
+        #[structopt(long, parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+    /// Print `id<TAB>title<TAB>date` for each latest note matching, for fzf or skim to pick from
+    Pick {
+        #[structopt(default_value = "")]
+        query: String,
+        #[structopt(long)]
+        filter: Option<String>,
+        /// Also list archived notes
+        #[structopt(long)]
+        include_archived: bool,
+    },
+    /// List, rename or merge tags across every document
+    Tags(TagsCommand),
+    /// Work with the graph of documents and the links between them
+    Graph(GraphCommand),
+    /// Maintain the `backlinks` field, so documents can be filtered by what links to them
+    Backlinks(BacklinksCommand),
+    /// Find links that lead nowhere
+    Links(LinksCommand),
+    /// Find notes sharing a slug
+    Slug(SlugCommand),
     /// Opens $EDITOR on a template and then adds it when the editor is closed
-    New {},
+    New {
+        /// Template to start from, `<name>.md` in the templates directory
+        #[structopt(long)]
+        template: Option<String>,
+        /// Substituted for {{title}} in the template
+        #[structopt(long, default_value = "")]
+        title: String,
+        /// Tag to add on top of the template's, repeat for several
+        #[structopt(long = "tag", number_of_values = 1)]
+        tags: Vec<String>,
+        /// Body to append to the template's, or - to read it from stdin. Adds the note straight
+        /// away instead of opening the editor
+        #[structopt(long)]
+        body: Option<String>,
+    },
+    /// Append text to a document's body as a new revision, e.g. to keep a running log
+    Append {
+        /// Id of the document, or of any of its revisions
+        id: String,
+        #[structopt(long)]
+        text: Option<String>,
+        /// Read the text from this file
+        #[structopt(long, parse(from_os_str))]
+        file: Option<PathBuf>,
+        /// - to read the text from stdin
+        stdin: Option<String>,
+        /// Put the text under a heading with the current date and time
+        #[structopt(long)]
+        heading: bool,
+    },
+    /// Delete superseded revisions beyond those kept, reporting the space reclaimed
+    Prune {
+        /// Revisions to keep of each note, the newest included
+        #[structopt(long)]
+        keep: usize,
+        /// Only delete revisions older than this, e.g. 30d, 6m or 1y
+        #[structopt(long, parse(try_from_str = date::parse_age))]
+        older_than: Option<chrono::Duration>,
+        /// Only report what would be deleted
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Give a note a new title, and a slug made from it, as a new revision
+    Retitle {
+        /// Id of the note, or of any of its revisions
+        id: String,
+        title: String,
+        /// Also point links to the old slug in every other note at the new one
+        #[structopt(long)]
+        rewrite_links: bool,
+    },
+    /// Merge two notes into a new one holding both bodies and the tags, links and authors of
+    /// each. The two are marked no longer latest, superseded by the new note.
+    Merge {
+        a: String,
+        b: String,
+        /// Title of the new note, by default the first note's
+        #[structopt(long)]
+        into: Option<String>,
+    },
+    /// Move notes, every revision of each, to the trash, out of every search until restored
+    /// with untrash
+    Trash {
+        ids: Vec<String>,
+        #[structopt(subcommand)]
+        cmd: Option<TrashCommand>,
+    },
+    /// Restore notes from the trash, every revision of each
+    Untrash {
+        #[structopt(required = true)]
+        ids: Vec<String>,
+    },
+    /// Delete every document matching a filter, revisions included, after backing them up
+    Purge {
+        /// Documents to delete, in the filter grammar, e.g. `tmp` or `tag=tmp`
+        #[structopt(long)]
+        filter: String,
+        /// Where to back up to, by default a timestamped directory under
+        /// ~/.local/share/meilizet/backups
+        #[structopt(long, parse(from_os_str))]
+        backup_dir: Option<PathBuf>,
+        /// Don't ask for confirmation
+        #[structopt(long)]
+        yes: bool,
+    },
+    /// Change the tags and fields of every latest note matching a filter
+    BulkEdit {
+        #[structopt(long)]
+        filter: String,
+        /// Tag to add, repeat for several
+        #[structopt(long = "add-tag", number_of_values = 1)]
+        add_tags: Vec<String>,
+        /// Tag to remove, repeat for several
+        #[structopt(long = "remove-tag", number_of_values = 1)]
+        remove_tags: Vec<String>,
+        /// Field to set, e.g. weight=5, the value read as JSON if it parses as such and as a
+        /// string otherwise. Repeat for several
+        #[structopt(long = "set", number_of_values = 1)]
+        sets: Vec<String>,
+        /// Only report how many notes would change
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Set or adjust the weight notes are ranked by, or list the heaviest
+    Weight(WeightCommand),
+    /// Copy a file into the attachments directory and attach it to a document
+    Attach {
+        /// Id of the document, or of any of its revisions
+        id: String,
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Retrieve the files attached to a document
+    Attachments(AttachmentsCommand),
+    /// Flag likely duplicate notes, by title or by how similar their bodies are
+    Dedupe {
+        /// Least similarity, between 0 and 1, for bodies to count as duplicates
+        #[structopt(long, default_value = "0.8")]
+        threshold: f64,
+        /// Ask which of each pair to keep, marking the other no longer latest
+        #[structopt(long)]
+        interactive: bool,
+    },
+    /// Show the least viewed and oldest notes one at a time in the pager, recording when each was
+    /// reviewed
+    Review {
+        /// Most notes to review in one pass
+        #[structopt(long, default_value = "10")]
+        limit: usize,
+        /// Skip notes reviewed in the last this many days
+        #[structopt(long, default_value = "30")]
+        interval: i64,
+    },
+    /// Open today's journal note, creating it if needed, and save the edit as a new revision
+    Daily {
+        /// Append this under the current time instead of opening the editor
+        #[structopt(long)]
+        text: Option<String>,
+        /// - to read the text to append from stdin
+        stdin: Option<String>,
+    },
     /// Adds TOML-based document
     Add {},
 }
 
+#[derive(Debug, StructOpt)]
+enum SettingsCommand {
+    /// Print the current settings
+    Get {
+        /// Output format, json or toml
+        #[structopt(long, default_value = "json")]
+        format: String,
+        /// Show how the settings deviate from those the Document schema expects, `+` for
+        /// missing attributes and `-` for unexpected ones
+        #[structopt(long)]
+        diff: bool,
+    },
+    /// Apply settings from a TOML or JSON file, fields it leaves out are unchanged
+    Set {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TasksCommand {
+    /// List tasks, newest first
+    List {
+        /// Only show tasks that are enqueued, processing, succeeded or failed
+        #[structopt(long, possible_values = &["enqueued", "processing", "succeeded", "failed"])]
+        status: Option<String>,
+        /// Redraw the list every couple of seconds until interrupted
+        #[structopt(long)]
+        watch: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TagsCommand {
+    /// Print every tag with the number of documents carrying it
+    List {
+        /// Order by count, most used first, or by name
+        #[structopt(long, default_value = "count", possible_values = &["count", "name"])]
+        sort: String,
+        /// Leave out tags on fewer documents
+        #[structopt(long, default_value = "1")]
+        min_count: u32,
+    },
+    /// Replace the tag `old` with `new` wherever it is used
+    Rename {
+        old: String,
+        new: String,
+        /// Only report how many documents would change
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Replace each of the tags with the one given to --into
+    Merge {
+        #[structopt(required = true)]
+        tags: Vec<String>,
+        #[structopt(long)]
+        into: String,
+        /// Only report how many documents would change
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum GraphCommand {
+    /// Print the graph for Graphviz, d3 or Gephi, with each note's title, tags and weight
+    Export {
+        /// dot, json or gexf
+        #[structopt(long, default_value = "dot")]
+        format: graph::Format,
+    },
+    /// List notes that neither link to nor are linked from any other note
+    Orphans {
+        /// Leave out notes with this tag, e.g. journal, repeat for several
+        #[structopt(long = "exclude-tag", number_of_values = 1)]
+        exclude_tags: Vec<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum BacklinksCommand {
+    /// Recompute every document's backlinks from the links of the others and store those that
+    /// changed
+    Rebuild {},
+}
+
+#[derive(Debug, StructOpt)]
+enum LinksCommand {
+    /// Report links to notes that don't exist, grouped by the note they are in
+    Check {
+        /// Also fetch http(s) links and report those that don't answer 2xx
+        #[structopt(long)]
+        external: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TrashCommand {
+    /// List the notes in the trash and when each was trashed
+    List {},
+    /// Delete the notes in the trash for good
+    Empty {
+        /// Only delete notes trashed longer ago than this, e.g. 30d, 6m or 1y
+        #[structopt(long, parse(try_from_str = date::parse_age))]
+        older_than: Option<chrono::Duration>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum AttachmentsCommand {
+    /// Copy a document's attachments, checked against their hashes, into a directory
+    Get {
+        /// Id of the document, or of any of its revisions
+        id: String,
+        /// Directory to copy them to
+        #[structopt(long, parse(from_os_str), default_value = ".")]
+        out: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum BenchCommand {
+    /// Import the files matching the unexpanded glob pattern into a scratch index once per
+    /// batch size and concurrency, and recommend the fastest for the config
+    Import {
+        globpath: String,
+        /// Notes per upload request, comma separated
+        #[structopt(long, use_delimiter = true, default_value = "100,500,1000")]
+        batch_sizes: Vec<usize>,
+        /// Upload requests in flight at once, comma separated
+        #[structopt(long, use_delimiter = true, default_value = "1,4,8")]
+        concurrency: Vec<usize>,
+        /// Write the fastest settings to the config file's [import] section
+        #[structopt(long)]
+        save: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum SlugCommand {
+    /// Report slugs shared by more than one latest note, which Hugo and links by slug need to
+    /// be unique
+    Check {},
+}
+
+#[derive(Debug, StructOpt)]
+enum WeightCommand {
+    /// Set the weight of a note
+    Set {
+        /// Id of the note, or of any of its revisions
+        id: String,
+        #[structopt(allow_hyphen_values = true)]
+        weight: i32,
+    },
+    /// Add to the weight of a note, e.g. +2 or -1
+    Bump {
+        /// Id of the note, or of any of its revisions
+        id: String,
+        #[structopt(default_value = "1", allow_hyphen_values = true)]
+        by: i32,
+    },
+    /// List the notes with the highest weight
+    Top {
+        #[structopt(long, default_value = "10")]
+        limit: u32,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum AuthCommand {
+    /// Prompt for the API key and store it, used in preference to --key from then on
+    Login {},
+    /// Remove the stored API key
+    Logout {},
+    /// Create the key --encrypt and --decrypt use, unless there is one already
+    BodyKey {
+        /// Print the stored key, to copy it to another machine
+        #[structopt(long)]
+        show: bool,
+        /// Read a key from stdin instead of creating one, replacing any stored
+        #[structopt(long, conflicts_with = "show")]
+        set: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum KeysCommand {
+    /// List the keys with their permissions
+    List {},
+    /// Create a key, by default one that can only search the notes index
+    Create {
+        #[structopt(long)]
+        description: Option<String>,
+        /// Permitted actions, e.g. search, documents.add or `*` for all
+        #[structopt(long, default_value = "search")]
+        actions: Vec<String>,
+        /// Indexes the key works on, `*` for all. Defaults to the notes index
+        #[structopt(long)]
+        indexes: Vec<String>,
+        /// Expiry as an RFC 3339 date, e.g. 2030-01-01T00:00:00Z, never if unset
+        #[structopt(long)]
+        expires_at: Option<String>,
+    },
+    /// Delete a key
+    Delete { key: String },
+}
+
+#[derive(Debug, StructOpt)]
+enum ServerDumpCommand {
+    /// Start a dump and print the uid of the task writing it
+    Create {
+        /// Wait for the server to finish writing the dump
+        #[structopt(long)]
+        wait: bool,
+    },
+    /// Print whether the task writing a dump is enqueued, processing, succeeded or failed, and
+    /// the dump's name once written
+    Status { task: u64 },
+}
+
+#[derive(Debug, StructOpt)]
+enum StopwordsCommand {
+    /// Print the stop words
+    Get {},
+    /// Replace the stop words
+    Set {
+        #[structopt(required = true)]
+        words: Vec<String>,
+    },
+    /// Remove all stop words
+    Reset {},
+}
+
+#[derive(Debug, StructOpt)]
+enum RankingRulesCommand {
+    /// Print the ranking rules, in order
+    Get {},
+    /// Replace the ranking rules, e.g. words typo proximity attribute sort exactness
+    Set {
+        rules: Vec<String>,
+        /// Append `weight:desc` so manually weighted notes rank higher, applied to the
+        /// current rules if none are given
+        #[structopt(long)]
+        weight: bool,
+    },
+    /// Restore the server's default ranking rules
+    Reset {},
+}
+
 impl Opt {
-    fn url(&self, path: &str) -> Url {
-        let mut url = Url::parse(self.host.as_str()).unwrap();
-        url.set_path(path);
-        url
+    /// First configured host, falling back to the config and then the default
+    fn host(&self) -> &str {
+        self.hosts()[0]
     }
 
-    // TODO can I use a trait to define this function once for both Document and markdown_fm_doc?
-    fn import(&self, path: &str) -> Result<(), Report> {
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/documents");
-        // Read the markdown files and post them to local Meilisearch
-        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
-            match entry {
-                Ok(path) => {
-                    if let Ok(doc) = document::Document::parse_file(&path) {
-                        let doc: Vec<document::Document> = vec![doc];
-                        let res = client
-                            .post(url.as_ref())
-                            .body(serde_json::to_string(&doc).unwrap())
-                            .send()?;
-                        if self.verbosity > 0 {
-                            println!("✅ {} {:?}", doc[0], res);
-                        }
-                    } else {
-                        eprintln!("❌ Failed to load file {}", path.display());
-                    }
+    /// The primary server and then any read replicas, from --host, MEILI_HOST or the config.
+    /// A host list on the command line replaces the config's `[hosts]` too.
+    fn hosts(&self) -> Vec<&str> {
+        let mut hosts = match &self.host {
+            Some(host) => host_list(host),
+            None => {
+                let mut hosts = self
+                    .config
+                    .host
+                    .as_deref()
+                    .map(host_list)
+                    .unwrap_or_default();
+                if hosts.is_empty() {
+                    hosts.extend(self.config.hosts.primary.as_deref());
                 }
-
-                Err(e) => eprintln!("❌ {:?}", e),
+                hosts.extend(self.config.hosts.replicas.iter().map(String::as_str));
+                hosts
             }
+        };
+        if hosts.is_empty() {
+            hosts.push(DEFAULT_HOST);
         }
-        Ok(())
+        hosts
     }
 
-    fn legacy_import(&self, path: &str) -> Result<(), Report> {
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/documents");
-        // Read the markdown files and post them to local Meilisearch
-        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
-            match entry {
-                Ok(path) => {
-                    if let Ok(mdfm_doc) = markdown_fm_doc::parse_file(&path) {
-                        let doc: Vec<document::Document> = vec![mdfm_doc.into()];
-                        let res = client
-                            .post(url.as_ref())
-                            .body(serde_json::to_string(&doc).unwrap())
-                            .send()?;
-                        if self.verbosity > 0 {
-                            println!("✅ {} {:?}", doc[0], res);
-                        }
-                    } else {
-                        eprintln!("❌ Failed to load file {}", path.display());
-                    }
-                }
+    fn pager(&self) -> &str {
+        self.pager
+            .as_deref()
+            .or(self.config.pager.as_deref())
+            .unwrap_or("less")
+    }
 
-                Err(e) => eprintln!("❌ {:?}", e),
-            }
+    fn editor(&self) -> &str {
+        self.editor
+            .as_deref()
+            .or(self.config.editor.as_deref())
+            .unwrap_or("vim")
+    }
+
+    /// Where notes are dumped to, `path` if given
+    fn notes_dir(&self, path: Option<&Path>) -> PathBuf {
+        let dir = path
+            .or(self.config.notes_dir.as_deref())
+            .unwrap_or(Path::new("."));
+        PathBuf::from(shellexpand::tilde(&dir.to_string_lossy()).as_ref())
+    }
+
+    /// Client for the notes index that authenticates with the API key, if one is set
+    fn client(&self) -> Result<Client, Report> {
+        let key = auth::resolve_key(&self.profile, &self.key);
+        let hosts = self.hosts();
+        let mut client = self.connect(hosts[0], &key)?.with_replicas(&hosts[1..])?;
+        if let Some(index) = &self.config.index {
+            client = client.with_index(index);
         }
-        Ok(())
+        if self.encrypt || self.decrypt {
+            client = client.with_crypt(crypt::Crypt::for_profile(
+                &self.profile,
+                self.encrypt,
+                self.decrypt,
+            )?);
+        }
+        if self.offline {
+            return client.offline();
+        }
+        if let Some(recording) = &self.recording {
+            client = client.with_recording(recording.clone());
+        }
+        Ok(client)
     }
 
-    fn interactive_query(&self) -> Result<(), Report> {
-        interactive::setup_panic();
+    /// A client for `host` with the connection flags applied
+    fn connect(&self, host: &str, key: &str) -> Result<Client, Report> {
+        let retry = Retry {
+            attempts: self.retries,
+            delay: Duration::from_millis(self.retry_delay),
+        };
+        let options = Options {
+            ca_cert: self.ca_cert.clone(),
+            insecure: self.insecure,
+            client_cert: self.client_cert.clone(),
+            client_cert_password: self.client_cert_password.clone(),
+            timeout: self.timeout.map(Duration::from_secs),
+            connect_timeout: self.connect_timeout.map(Duration::from_secs),
+            proxy: self.proxy.clone(),
+            no_proxy: self.no_proxy,
+        };
+        let client = Client::with_options(host, key, &options)?.with_retry(retry);
+        let read_only = self.read_only
+            || self
+                .config
+                .profiles
+                .get(&self.profile)
+                .is_some_and(|p| p.read_only);
+        Ok(if read_only {
+            client.read_only()
+        } else {
+            client
+        })
+    }
 
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/search");
-        match interactive::query(
-            client,
-            url,
-            self.verbosity,
-            self.pager.clone(),
-            self.editor.clone(),
-        ) {
-            Ok(res) => {
-                println!("Document IDs: {:?}", res);
+    /// Run any subcommand but the interactive query
+    async fn run(&self) -> Result<(), Report> {
+        match self.subcmd {
+            Subcommands::Init {} => self.init().await,
+            Subcommands::Setup {} => self.setup().await,
+            Subcommands::Health {} => self.health().await,
+            Subcommands::Doctor {} => doctor::print(&self.client()?, self.host()).await,
+            Subcommands::Stats {} => self.stats().await,
+            Subcommands::Tasks(ref cmd) => self.tasks(cmd).await,
+            Subcommands::Completions { shell } => {
+                Opt::clap().gen_completions_to("mz", shell, &mut io::stdout());
+                Ok(())
             }
-            Err(e) => {
-                eprintln!("❌ {:?}", e);
-                //std::panic::panic_any(e);
+            Subcommands::Manpage {} => {
+                print!("{}", manpage::render(Opt::clap(), "mz")?);
+                Ok(())
             }
-        };
-        Ok(())
+            Subcommands::Auth(ref cmd) => self.auth(cmd),
+            Subcommands::Keys(ref cmd) => self.keys(cmd).await,
+            Subcommands::ServerDump(ref cmd) => self.server_dump(cmd).await,
+            Subcommands::Settings(ref cmd) => self.settings(cmd).await,
+            Subcommands::Stopwords(ref cmd) => self.stopwords(cmd).await,
+            Subcommands::RankingRules(ref cmd) => self.ranking_rules(cmd).await,
+            Subcommands::Import {
+                ref globpath,
+                on_conflict,
+                path_tags,
+                date_from,
+            } => {
+                self.import(globpath, on_conflict, path_tags, date_from)
+                    .await
+            }
+            Subcommands::Reimport { ref globpath } => self.reimport(globpath).await,
+            Subcommands::ImportGit {
+                ref repo,
+                ref globpath,
+            } => self.import_git(repo, globpath).await,
+            Subcommands::ImportBookmarks {
+                ref file,
+                format,
+                ref index,
+                on_conflict,
+            } => {
+                self.import_bookmarks(file, format, index.as_deref(), on_conflict)
+                    .await
+            }
+            Subcommands::ImportJoplin {
+                ref path,
+                on_conflict,
+            } => {
+                let dir = attachment::dir(&self.config.attachments)?;
+                self.import_converted(joplin::read(path, &dir)?, on_conflict)
+                    .await
+            }
+            Subcommands::ImportNotion {
+                ref zip,
+                on_conflict,
+            } => {
+                let dir = attachment::dir(&self.config.attachments)?;
+                self.import_converted(notion::read(zip, &dir)?, on_conflict)
+                    .await
+            }
+            Subcommands::ImportMail {
+                ref maildir,
+                ref filter_from,
+                on_conflict,
+            } => {
+                self.import_mail(maildir, filter_from.as_deref(), on_conflict)
+                    .await
+            }
+            Subcommands::Bench(ref cmd) => self.bench(cmd).await,
+            Subcommands::ImportLegacyMd { ref globpath } => self.legacy_import(globpath).await,
+            Subcommands::Query { .. } => bail!("❌ The interactive query runs outside the runtime"),
+            Subcommands::Dump {
+                ref path,
+                frontmatter,
+            } => {
+                self.dump(&self.notes_dir(path.as_deref()), frontmatter)
+                    .await
+            }
+            Subcommands::Verify {
+                ref path,
+                frontmatter,
+            } => {
+                self.verify(&self.notes_dir(path.as_deref()), frontmatter)
+                    .await
+            }
+            Subcommands::Migrate { dry_run } => self.migrate(dry_run).await,
+            Subcommands::ExportSearchIndex { format, ref out } => {
+                self.export_search_index(format, out).await
+            }
+            Subcommands::ExportAnki {
+                ref filter,
+                ref deck,
+                ref out,
+            } => self.export_anki(filter, deck, out.as_deref()).await,
+            Subcommands::Backup { ref path } => self.backup(path).await,
+            Subcommands::Restore { ref path, replace } => self.restore(path, replace).await,
+            Subcommands::Sync {} => self.sync().await.map(|_| ()),
+            Subcommands::Get { ref id, body } => self.get(id, body).await,
+            Subcommands::Diff {
+                ref id,
+                ref other,
+                ref file,
+            } => self.diff(id, other.as_deref(), file.as_deref()).await,
+            Subcommands::Pick {
+                ref query,
+                ref filter,
+                include_archived,
+            } => self.pick(query, filter.as_deref(), include_archived).await,
+            Subcommands::Tags(ref cmd) => self.tags(cmd).await,
+            Subcommands::Graph(ref cmd) => self.graph(cmd).await,
+            Subcommands::Backlinks(ref cmd) => self.backlinks(cmd).await,
+            Subcommands::Links(ref cmd) => self.links(cmd).await,
+            Subcommands::Slug(ref cmd) => self.slug(cmd).await,
+            Subcommands::Attach { ref id, ref file } => self.attach(id, file).await,
+            Subcommands::Attachments(ref cmd) => self.attachments(cmd).await,
+            Subcommands::Validate {
+                ref globpath,
+                date_from,
+            } => self.validate(globpath, date_from),
+            Subcommands::StaticQuery {
+                ref query,
+                ref filter,
+                ref indexes,
+                include_archived,
+                no_pager,
+                output,
+                all,
+            } => {
+                let search = query::Search {
+                    query: query.to_owned(),
+                    filter: filter.to_owned(),
+                    include_archived,
+                    all,
+                };
+                self.static_query(&search, indexes, no_pager, output).await
+            }
+            Subcommands::Archive {
+                ref ids,
+                ref filter,
+            } => self.archive(ids, filter.as_deref()).await,
+            Subcommands::New {
+                ref template,
+                ref title,
+                ref tags,
+                ref body,
+            } => {
+                self.new_note(template.as_deref(), title, tags, body.as_deref())
+                    .await
+            }
+            Subcommands::Append {
+                ref id,
+                ref text,
+                ref file,
+                ref stdin,
+                heading,
+            } => {
+                let text = match (text, file, stdin.as_deref()) {
+                    (Some(text), None, None) => text.to_owned(),
+                    (None, Some(file), None) => fs::read_to_string(file)?,
+                    (None, None, Some("-")) => {
+                        let mut text = String::new();
+                        io::stdin().read_to_string(&mut text)?;
+                        text
+                    }
+                    _ => bail!("❌ Give the text with one of --text, --file or -"),
+                };
+                self.append(id, &text, heading).await
+            }
+            Subcommands::Prune {
+                keep,
+                older_than,
+                dry_run,
+            } => self.prune(keep, older_than, dry_run).await,
+            Subcommands::Retitle {
+                ref id,
+                ref title,
+                rewrite_links,
+            } => self.retitle(id, title, rewrite_links).await,
+            Subcommands::Merge {
+                ref a,
+                ref b,
+                ref into,
+            } => self.merge(a, b, into.as_deref()).await,
+            Subcommands::Trash { ref ids, ref cmd } => self.trash(ids, cmd.as_ref()).await,
+            Subcommands::Untrash { ref ids } => {
+                let docs = self.client()?.restore_notes(ids).await?;
+                status!("✅ Restored {} documents from the trash", docs.len());
+                Ok(())
+            }
+            Subcommands::Purge {
+                ref filter,
+                ref backup_dir,
+                yes,
+            } => self.purge(filter, backup_dir.as_deref(), yes).await,
+            Subcommands::BulkEdit {
+                ref filter,
+                ref add_tags,
+                ref remove_tags,
+                ref sets,
+                dry_run,
+            } => {
+                self.bulk_edit(filter, add_tags, remove_tags, sets, dry_run)
+                    .await
+            }
+            Subcommands::Weight(ref cmd) => self.weight(cmd).await,
+            Subcommands::Dedupe {
+                threshold,
+                interactive,
+            } => self.dedupe(threshold, interactive).await,
+            Subcommands::Review { limit, interval } => self.review(limit, interval).await,
+            Subcommands::Daily {
+                ref text,
+                ref stdin,
+            } => {
+                let text = match (text, stdin.as_deref()) {
+                    (Some(text), None) => Some(text.to_owned()),
+                    (None, Some("-")) => {
+                        let mut text = String::new();
+                        io::stdin().read_to_string(&mut text)?;
+                        Some(text)
+                    }
+                    (None, None) => None,
+                    _ => bail!("❌ Give the text to append with either --text or -"),
+                };
+                self.daily(text.as_deref()).await
+            }
+            Subcommands::Add {} => unimplemented!("not yet"),
+        }
+    }
+
+    async fn init(&self) -> Result<(), Report> {
+        index::init(&self.client()?, self.verbosity).await
     }
 
-    fn static_query(&self, query: &str, filter: &str) -> Result<(), Report> {
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/search");
-        match query::query(client, url, query.to_string(), filter.to_string()) {
-            Ok(res) => {
-                println!("Document IDs: {:?}", res);
+    async fn setup(&self) -> Result<(), Report> {
+        let path = match self
+            .config_path
+            .clone()
+            .or_else(config::Config::default_path)
+        {
+            Some(path) => path,
+            None => bail!("❌ No config directory on this system, pass --config"),
+        };
+        if path.exists() {
+            println!(
+                "{} exists, answers replace the settings asked about",
+                path.display()
+            );
+        } else {
+            println!("Writing a new config to {}", path.display());
+        }
+        let stored = auth::stored_key(&self.profile).ok().flatten().is_some();
+        let answers = setup::Answers {
+            host: setup::ask("Meilisearch server", self.host())?,
+            key: setup::ask_secret(&format!(
+                "API key, empty for {}",
+                if stored { "the stored one" } else { "none" }
+            ))?,
+            index: setup::ask(
+                "Index for notes",
+                self.config.index.as_deref().unwrap_or(index::INDEX),
+            )?,
+            editor: setup::ask("Editor", self.editor())?,
+            pager: setup::ask("Pager", self.pager())?,
+            notes_dir: setup::ask(
+                "Notes directory, for dump and query --emit paths",
+                &self
+                    .config
+                    .notes_dir
+                    .as_deref()
+                    .unwrap_or(Path::new("~/notes"))
+                    .to_string_lossy(),
+            )?,
+        };
+
+        let key = match &answers.key {
+            Some(key) => key.clone(),
+            None => auth::resolve_key(&self.profile, &self.key),
+        };
+        let client = self
+            .connect(&answers.host, &key)?
+            .with_index(&answers.index);
+        let init = match client.health().await {
+            Ok(version) => {
+                status!("✅ Meilisearch {} is available", version.pkg_version);
+                setup::confirm(
+                    &format!("Create and configure index {} now", answers.index),
+                    true,
+                )?
             }
             Err(e) => {
-                eprintln!("❌ {:?}", e);
-                //std::panic::panic_any(e);
+                println!(
+                    "{}",
+                    style::mark(&format!("❌ Can't reach {}: {}", answers.host, e))
+                );
+                if !setup::confirm("Save the config anyway", false)? {
+                    bail!("❌ Nothing saved");
+                }
+                false
             }
         };
+
+        setup::write_config(&path, &answers)?;
+        status!("✅ Saved {}", path.display());
+        if let Some(key) = &answers.key {
+            match auth::store_key(&self.profile, key) {
+                Ok(()) => status!("✅ Stored the key for profile {}", self.profile),
+                Err(e) => println!("{}, pass it with --key or MEILI_KEY instead", e),
+            }
+        }
+        if init {
+            index::init(&client, self.verbosity).await?;
+        }
         Ok(())
     }
 
-    fn dump(&self, path: &str) -> Result<(), Report> {
-        fs::create_dir_all(path)?;
+    async fn health(&self) -> Result<(), Report> {
+        let version = self.client()?.health().await?;
+        status!("✅ Meilisearch {} is available", version.pkg_version);
+        if self.verbosity > 0 {
+            status!("Commit {} from {}", version.commit_sha, version.commit_date);
+        }
+        Ok(())
+    }
 
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/search");
-        let q = api::ApiQuery::new();
-
-        // Split up the JSON decoding into two steps.
-        // 1.) Get the text of the body.
-        let response_body = match client
-            .post(url.as_ref())
-            .body::<String>(serde_json::to_string(&q).unwrap())
-            .header(CONTENT_TYPE, "application/json")
-            .send()
-        {
-            Ok(resp) => {
-                if !resp.status().is_success() {
-                    eprintln!("Request failed: {:?}", resp);
+    async fn stats(&self) -> Result<(), Report> {
+        let client = self.client()?;
+        server::print_stats(&client.stats().await?, client.index())
+    }
+
+    async fn tasks(&self, cmd: &TasksCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            TasksCommand::List { status, watch } => loop {
+                let tasks = client.tasks(status.as_deref()).await?;
+                if *watch {
+                    // Clear the screen and go to its top left
+                    print!("\x1b[2J\x1b[1;1H");
                 }
-                match resp.text() {
-                    Ok(text) => text,
-                    Err(e) => {
-                        eprintln!("resp.text() failed: {:?}", e);
-                        String::from("")
-                    }
+                server::print_tasks(&tasks);
+                if !watch {
+                    return Ok(());
                 }
-            }
-            Err(e) => {
-                eprintln!("Send failed: {:?}", e);
-                String::from("")
-            }
-        };
-
-        // 2.) Parse the results as JSON.
-        match serde_json::from_str::<api::ApiResponse>(&response_body) {
-            Ok(mut resp) => {
-                for entry in resp
-                    .hits
-                    .iter_mut()
-                    .map(|mut m| {
-                        m.serialization_type = document::SerializationType::Disk;
-                        m.to_owned()
-                    })
-                    .collect::<Vec<_>>()
-                {
-                    let f = Path::new(&path).join(&entry.filename);
-                    fs::write(f, entry.to_string())?;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            },
+        }
+    }
+
+    async fn keys(&self, cmd: &KeysCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            KeysCommand::List {} => {
+                for key in client.keys().await? {
+                    server::print_key(&key);
                 }
             }
-            Err(e) => {
-                eprintln!("Response not OK: {:?}", e);
+            KeysCommand::Create {
+                description,
+                actions,
+                indexes,
+                expires_at,
+            } => {
+                let key = server::Key {
+                    description: description.to_owned(),
+                    actions: actions.to_owned(),
+                    indexes: if indexes.is_empty() {
+                        vec![client.index().to_owned()]
+                    } else {
+                        indexes.to_owned()
+                    },
+                    expires_at: expires_at.to_owned(),
+                    ..Default::default()
+                };
+                server::print_key(&client.create_key(&key).await?);
             }
-        };
+            KeysCommand::Delete { key } => {
+                client.delete_key(key).await?;
+                status!("✅ Deleted key {}", key);
+            }
+        }
         Ok(())
     }
-}
-
-pub fn glob_files(source: &str, verbosity: u8) -> Result<Paths, Box<dyn std::error::Error>> {
-    let glob_path = Path::new(&source);
-    let glob_str = shellexpand::tilde(glob_path.to_str().unwrap());
 
-    if verbosity > 0 {
-        println!("Sourcing Markdown documents matching : {}", glob_str);
+    async fn tags(&self, cmd: &TagsCommand) -> Result<(), Report> {
+        let (from, into, dry_run) = match cmd {
+            TagsCommand::List { sort, min_count } => {
+                let mut counts = tags::counts(&self.client()?).await?;
+                if sort == "name" {
+                    counts.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+                for (tag, count) in counts.iter().filter(|(_, c)| c >= min_count) {
+                    println!("{:>7} {}", count, tag);
+                }
+                return Ok(());
+            }
+            TagsCommand::Rename { old, new, dry_run } => (std::slice::from_ref(old), new, *dry_run),
+            TagsCommand::Merge {
+                tags,
+                into,
+                dry_run,
+            } => (tags.as_slice(), into, *dry_run),
+        };
+        let client = self.client()?;
+        let docs = tags::tagged(&client, from).await?;
+        let updates = tags::rewrites(&docs, from, into);
+        if dry_run {
+            println!("{} documents would change", updates.len());
+            if self.verbosity > 0 {
+                for update in &updates {
+                    println!("{} {}", update["id"], update["tags"]);
+                }
+            }
+            return Ok(());
+        }
+        let changed = updates.len();
+        if changed > 0 {
+            let tasks = client
+                .update_documents(&serde_json::Value::from(updates))
+                .await?;
+            client.wait_for_tasks(&tasks).await?;
+        }
+        status!("✅ Changed {} documents", changed);
+        Ok(())
     }
 
-    Ok(glob(&glob_str).expect("Failed to read glob pattern"))
-}
-
-fn setup() -> Result<(), Report> {
-    if std::env::var("RUST_LIB_BACKTRACE").is_err() {
-        std::env::set_var("RUST_LIB_BACKTRACE", "1")
+    async fn graph(&self, cmd: &GraphCommand) -> Result<(), Report> {
+        let docs = graph::latest(&self.client()?).await?;
+        match cmd {
+            GraphCommand::Export { format } => {
+                print!("{}", graph::Graph::build(&docs).render(*format))
+            }
+            GraphCommand::Orphans { exclude_tags } => {
+                let graph = graph::Graph::build(&docs);
+                let orphans = graph
+                    .orphans()
+                    .into_iter()
+                    .filter(|n| !n.tags.iter().any(|t| exclude_tags.contains(t)));
+                for node in orphans {
+                    println!("{} {}", node.id, node.title);
+                }
+            }
+        }
+        Ok(())
     }
-    color_eyre::install()?;
 
-    Ok(())
-}
+    async fn backlinks(&self, cmd: &BacklinksCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            BacklinksCommand::Rebuild {} => {
+                let docs = graph::latest(&client).await?;
+                let updates: Vec<_> = docs
+                    .iter()
+                    .zip(graph::backlinks(&docs))
+                    .filter(|(doc, backlinks)| doc.backlinks != *backlinks)
+                    .map(|(doc, backlinks)| serde_json::json!({ "id": doc.id, "backlinks": backlinks }))
+                    .collect();
+                let changed = updates.len();
+                if changed > 0 {
+                    let tasks = client
+                        .update_documents(&serde_json::Value::from(updates))
+                        .await?;
+                    client.wait_for_tasks(&tasks).await?;
+                }
+                status!("✅ Updated the backlinks of {} documents", changed);
+            }
+        }
+        Ok(())
+    }
 
-fn main() -> Result<(), Report> {
-    setup()?;
+    async fn links(&self, cmd: &LinksCommand) -> Result<(), Report> {
+        match cmd {
+            LinksCommand::Check { external } => {
+                let http = if *external {
+                    let timeout = Duration::from_secs(self.timeout.unwrap_or(10));
+                    Some(reqwest::Client::builder().timeout(timeout).build()?)
+                } else {
+                    None
+                };
+                let report = links::check(&self.client()?, http.as_ref()).await?;
+                for note in &report {
+                    println!("{} ({})", note.title, note.id);
+                    for (link, reason) in &note.links {
+                        println!("  {}: {}", link, reason);
+                    }
+                }
+                let broken: usize = report.iter().map(|n| n.links.len()).sum();
+                if broken > 0 {
+                    bail!("❌ {} broken links in {} notes", broken, report.len());
+                }
+                status!("✅ Every link resolves");
+            }
+        }
+        Ok(())
+    }
+
+    async fn attach(&self, id: &str, file: &Path) -> Result<(), Report> {
+        let client = self.client()?;
+        let mut doc = match client.latest_revision(id).await? {
+            Some(doc) => doc,
+            None => bail!("❌ No document {}", id),
+        };
+        let dir = attachment::dir(&self.config.attachments)?;
+        let attached = attachment::store(&dir, file)?;
+        if doc.attachments.contains(&attached) {
+            status!("✅ {} is already attached to {}", attached.name(), doc.id);
+            return Ok(());
+        }
+        doc.attachments.push(attached);
+        let update = serde_json::json!([{ "id": doc.id, "attachments": doc.attachments }]);
+        let tasks = client.update_documents(&update).await?;
+        client.wait_for_tasks(&tasks).await?;
+        status!(
+            "✅ Attached {} to {}",
+            attachment::path(&dir, doc.attachments.last().unwrap()).display(),
+            doc.id
+        );
+        Ok(())
+    }
+
+    async fn attachments(&self, cmd: &AttachmentsCommand) -> Result<(), Report> {
+        match cmd {
+            AttachmentsCommand::Get { id, out } => {
+                let doc = match self.client()?.latest_revision(id).await? {
+                    Some(doc) => doc,
+                    None => bail!("❌ No document {}", id),
+                };
+                let dir = attachment::dir(&self.config.attachments)?;
+                fs::create_dir_all(out)?;
+                for attached in &doc.attachments {
+                    let from = attachment::verified(&dir, attached)?;
+                    let to = out.join(attached.name());
+                    fs::copy(&from, &to)?;
+                    println!("{}", to.display());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn slug(&self, cmd: &SlugCommand) -> Result<(), Report> {
+        match cmd {
+            SlugCommand::Check {} => {
+                let docs = graph::latest(&self.client()?).await?;
+                let collisions = slug::collisions(&docs);
+                for (slug, docs) in &collisions {
+                    println!("{}", slug);
+                    for doc in docs {
+                        println!("  {} {}", doc.id, doc.title);
+                    }
+                }
+                if !collisions.is_empty() {
+                    bail!(
+                        "❌ {} slugs are shared by more than one note",
+                        collisions.len()
+                    );
+                }
+                status!("✅ Every slug is unique");
+            }
+        }
+        Ok(())
+    }
+
+    fn auth(&self, cmd: &AuthCommand) -> Result<(), Report> {
+        match cmd {
+            AuthCommand::Login {} => {
+                let key = match setup::ask_secret(&format!("API key for profile {}", self.profile))?
+                {
+                    Some(key) => key,
+                    None => bail!("❌ No key given"),
+                };
+                auth::store_key(&self.profile, &key)?;
+                status!("✅ Stored the key for profile {}", self.profile);
+            }
+            AuthCommand::Logout {} => {
+                if auth::forget_key(&self.profile)? {
+                    status!("✅ Removed the key for profile {}", self.profile);
+                } else {
+                    println!("No key stored for profile {}", self.profile);
+                }
+            }
+            AuthCommand::BodyKey { show, set } => {
+                if *set {
+                    let mut key = String::new();
+                    io::stdin().read_to_string(&mut key)?;
+                    crypt::store_key(&self.profile, key.trim())?;
+                    status!("✅ Stored the body key for profile {}", self.profile);
+                    return Ok(());
+                }
+                let key = match crypt::stored_key(&self.profile)? {
+                    Some(key) => key,
+                    None => {
+                        let key = crypt::generate_key()?;
+                        crypt::store_key(&self.profile, &key)?;
+                        status!("✅ Created a body key for profile {}", self.profile);
+                        key
+                    }
+                };
+                if *show {
+                    println!("{}", key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn server_dump(&self, cmd: &ServerDumpCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        let task = match cmd {
+            ServerDumpCommand::Create { wait } => {
+                let uid = client.create_dump().await?;
+                if *wait {
+                    client.wait_for_dump(uid).await?
+                } else {
+                    client.task(uid).await?
+                }
+            }
+            ServerDumpCommand::Status { task } => client.task(*task).await?,
+        };
+        println!(
+            "{} {} {}",
+            task.uid,
+            task.status,
+            task.dump_uid().unwrap_or("-")
+        );
+        Ok(())
+    }
+
+    async fn stopwords(&self, cmd: &StopwordsCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            StopwordsCommand::Get {} => {
+                for word in client.setting_list("stop-words").await? {
+                    println!("{}", word);
+                }
+            }
+            StopwordsCommand::Set { words } => {
+                client.set_setting_list("stop-words", words).await?;
+                status!("✅ Set {} stop words", words.len());
+            }
+            StopwordsCommand::Reset {} => {
+                client.reset_setting("stop-words").await?;
+                status!("✅ Reset the stop words");
+            }
+        }
+        Ok(())
+    }
+
+    async fn ranking_rules(&self, cmd: &RankingRulesCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            RankingRulesCommand::Get {} => {
+                for rule in client.setting_list("ranking-rules").await? {
+                    println!("{}", rule);
+                }
+            }
+            RankingRulesCommand::Set { rules, weight } => {
+                let mut rules = rules.to_owned();
+                if rules.is_empty() {
+                    if !weight {
+                        bail!("❌ Give the ranking rules to set, or --weight");
+                    }
+                    rules = client.setting_list("ranking-rules").await?;
+                }
+                if *weight && !rules.iter().any(|r| r == index::WEIGHT_RULE) {
+                    rules.push(index::WEIGHT_RULE.to_owned());
+                }
+                client.set_setting_list("ranking-rules", &rules).await?;
+                status!("✅ Ranking rules: {}", rules.join(", "));
+            }
+            RankingRulesCommand::Reset {} => {
+                client.reset_setting("ranking-rules").await?;
+                status!("✅ Reset the ranking rules");
+            }
+        }
+        Ok(())
+    }
+
+    async fn settings(&self, cmd: &SettingsCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            SettingsCommand::Get { diff: true, .. } => {
+                let diff = client.settings().await?.diff(&index::Settings::expected());
+                if diff.is_empty() {
+                    status!("✅ Settings match those expected");
+                } else {
+                    print!("{}", diff);
+                }
+            }
+            SettingsCommand::Get { format, .. } => {
+                let settings = client.settings().await?;
+                match format.as_str() {
+                    "json" => println!("{}", serde_json::to_string_pretty(&settings)?),
+                    "toml" => print!("{}", toml::to_string(&settings)?),
+                    _ => bail!("❌ Unknown format {}, expected json or toml", format),
+                }
+            }
+            SettingsCommand::Set { path } => {
+                let settings = index::Settings::from_file(path)?;
+                client.set_settings(&settings).await?;
+                status!("✅ Applied settings from {}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    // TODO can I use a trait to define this function once for both Document and markdown_fm_doc?
+    async fn import(
+        &self,
+        path: &str,
+        on_conflict: import::OnConflict,
+        path_tags: bool,
+        date_from: Option<date::DateFrom>,
+    ) -> Result<(), Report> {
+        let mut docs = Vec::new();
+        let mut failed = Vec::new();
+        let base = import::glob_base(&shellexpand::tilde(path));
+        let options = document::ParseOptions {
+            date_from,
+            field_map: self.config.field_map.clone(),
+        };
+        // Read the markdown files and post them to local Meilisearch
+        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
+            match entry {
+                Ok(path) => match document::Document::parse_file_with(&path, &options) {
+                    Ok(mut doc) => {
+                        slug::fill(&mut doc);
+                        if path_tags {
+                            for tag in import::path_tags(&base, &path) {
+                                if !doc.tags.contains(&tag) {
+                                    doc.tags.push(tag);
+                                }
+                            }
+                        }
+                        docs.push(doc)
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to load file {}: {}", path.display(), e);
+                        failed.push(path.display().to_string());
+                    }
+                },
+
+                Err(e) => {
+                    error!("❌ {:?}", e);
+                    failed.push(e.path().display().to_string());
+                }
+            }
+        }
+        self.add_each(&self.client()?, docs, failed, on_conflict)
+            .await
+    }
+
+    fn validate(&self, path: &str, date_from: Option<date::DateFrom>) -> Result<(), Report> {
+        let options = document::ParseOptions {
+            date_from,
+            field_map: self.config.field_map.clone(),
+        };
+        let mut checked = 0;
+        let mut bad = 0;
+        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
+            let path = entry?;
+            checked += 1;
+            let s = match fs::read_to_string(&path) {
+                Ok(s) => s,
+                Err(e) => {
+                    println!("{}: {}", path.display(), e);
+                    bad += 1;
+                    continue;
+                }
+            };
+            let mut problems = document::problems(&s, &options.field_map);
+            if problems.is_empty() {
+                // Parses, so only a missing date can still stop it being imported
+                if let Err(e) = document::Document::parse_file_with(&path, &options) {
+                    problems.push(document::Problem {
+                        line: 2,
+                        column: 1,
+                        key: Some("date".to_owned()),
+                        message: e.to_string(),
+                    });
+                }
+            }
+            for problem in &problems {
+                println!("{}:{}", path.display(), problem);
+            }
+            if !problems.is_empty() {
+                bad += 1;
+            }
+        }
+        if bad > 0 {
+            bail!("❌ {} of {} files have problems", bad, checked);
+        }
+        status!("✅ {} files are ready to import", checked);
+        Ok(())
+    }
+
+    /// Every note the files matching the unexpanded glob pattern `path` hold, and how many
+    /// files failed to load
+    fn load_notes(&self, path: &str) -> Result<(Vec<document::Document>, usize), Report> {
+        let mut docs = Vec::new();
+        let mut failed = 0;
+        let options = document::ParseOptions {
+            field_map: self.config.field_map.clone(),
+            ..Default::default()
+        };
+        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
+            match entry.map(|p| (document::Document::parse_file_with(&p, &options), p)) {
+                Ok((Ok(mut doc), _)) => {
+                    slug::fill(&mut doc);
+                    docs.push(doc)
+                }
+                Ok((Err(_), path)) => {
+                    error!("❌ Failed to load file {}", path.display());
+                    failed += 1;
+                }
+                Err(e) => {
+                    error!("❌ {:?}", e);
+                    failed += 1;
+                }
+            }
+        }
+        Ok((docs, failed))
+    }
+
+    /// Import the history of each file in `repo` matching `pattern`, leaving out notes already
+    /// in the index
+    async fn import_git(&self, repo: &str, pattern: &str) -> Result<(), Report> {
+        let repo = PathBuf::from(shellexpand::tilde(repo).as_ref());
+        let options = document::ParseOptions {
+            date_from: None,
+            field_map: self.config.field_map.clone(),
+        };
+        let client = self.client()?;
+        let mut summary = ImportSummary {
+            imported: 0,
+            skipped: 0,
+            failed: Vec::new(),
+        };
+        let mut docs = Vec::new();
+        for file in git::files(&repo, pattern)? {
+            let chain = match git::history(&repo, &file) {
+                Ok(versions) => git::chain(&versions, &options),
+                Err(e) => {
+                    error!("❌ Failed to read the history of {}: {}", file, e);
+                    summary.failed.push(file);
+                    continue;
+                }
+            };
+            let first = match chain.first() {
+                Some(first) => first,
+                None => {
+                    error!("❌ No version of {} could be read", file);
+                    summary.failed.push(file);
+                    continue;
+                }
+            };
+            if client.get_document(&first.id).await?.is_some() {
+                if self.verbosity > 0 {
+                    status!("Skipped {}, {} is already in the index", file, first.id);
+                }
+                summary.skipped += 1;
+                continue;
+            }
+            if self.verbosity > 0 {
+                status!("{}: {} revisions", file, chain.len());
+            }
+            summary.imported += 1;
+            docs.extend(chain);
+        }
+        let upload = &self.config.import;
+        let task_uids = client
+            .add_documents_batched(&docs, upload.batch_size(), upload.concurrency())
+            .await?;
+        client.wait_for_tasks(&task_uids).await?;
+        for doc in docs.iter().filter(|d| d.latest) {
+            hooks::run(&self.config.hooks, hooks::Event::Import, doc);
+        }
+        status!(
+            "✅ Imported {} notes as {} revisions",
+            summary.imported,
+            docs.len()
+        );
+        self.finish_import(summary)
+    }
+
+    async fn reimport(&self, path: &str) -> Result<(), Report> {
+        let (docs, failed) = self.load_notes(path)?;
+        // A rebuild replaces everything, so don't drop the notes that failed to load
+        if failed > 0 {
+            return Err(ParseError(format!(
+                "❌ {} files failed to load, not reimporting",
+                failed
+            ))
+            .into());
+        }
+        index::reimport(&self.client()?, &docs, &self.config.import, self.verbosity).await
+    }
+
+    async fn bench(&self, cmd: &BenchCommand) -> Result<(), Report> {
+        match cmd {
+            BenchCommand::Import {
+                globpath,
+                batch_sizes,
+                concurrency,
+                save,
+            } => {
+                let (docs, failed) = self.load_notes(globpath)?;
+                if failed > 0 {
+                    warn!("{} files failed to load, benchmarking without them", failed);
+                }
+                if docs.is_empty() {
+                    bail!("❌ No notes match {}", globpath);
+                }
+                let trials =
+                    bench::import(&self.client()?, &docs, batch_sizes, concurrency).await?;
+                let best = match bench::best(&trials) {
+                    Some(best) => best,
+                    None => bail!("❌ No batch sizes or concurrency to try"),
+                };
+                let advice = format!(
+                    "[import]\nbatch_size = {}\nconcurrency = {}\n",
+                    best.batch_size, best.concurrency
+                );
+                if !save {
+                    println!("Fastest, for the config file:\n\n{}", advice);
+                    return Ok(());
+                }
+                let path = match self
+                    .config_path
+                    .clone()
+                    .or_else(config::Config::default_path)
+                {
+                    Some(path) => path,
+                    None => bail!("❌ No config directory on this system, pass --config"),
+                };
+                setup::update_config(&path, |table| {
+                    let mut import = toml::value::Table::new();
+                    import.insert("batch_size".into(), (best.batch_size as i64).into());
+                    import.insert("concurrency".into(), (best.concurrency as i64).into());
+                    table.insert("import".into(), toml::Value::Table(import));
+                })?;
+                status!("✅ Saved to {}:\n{}", path.display(), advice);
+                Ok(())
+            }
+        }
+    }
+
+    async fn legacy_import(&self, path: &str) -> Result<(), Report> {
+        let mut docs = Vec::new();
+        let mut failed = Vec::new();
+        // Read the markdown files and post them to local Meilisearch
+        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
+            match entry {
+                Ok(path) => {
+                    if let Ok(mdfm_doc) = markdown_fm_doc::parse_file(&path) {
+                        docs.push(mdfm_doc.into());
+                    } else {
+                        error!("❌ Failed to load file {}", path.display());
+                        failed.push(path.display().to_string());
+                    }
+                }
+
+                Err(e) => {
+                    error!("❌ {:?}", e);
+                    failed.push(e.path().display().to_string());
+                }
+            }
+        }
+        // Legacy documents get fresh ids, so can't clash
+        self.add_each(&self.client()?, docs, failed, import::OnConflict::Overwrite)
+            .await
+    }
+
+    /// Import the bookmarks in the export `file`, into `index` if given
+    async fn import_bookmarks(
+        &self,
+        file: &Path,
+        format: bookmarks::Format,
+        index: Option<&str>,
+        on_conflict: import::OnConflict,
+    ) -> Result<(), Report> {
+        let mut client = self.client()?;
+        if let Some(index) = index {
+            client = client.with_index(index);
+        }
+        let docs = bookmarks::parse(&fs::read_to_string(file)?, format)
+            .iter()
+            .map(bookmarks::to_document)
+            .collect();
+        self.add_each(&client, docs, Vec::new(), on_conflict).await
+    }
+
+    /// Import the emails in `maildir` sent from an address containing `filter_from`, or all of
+    /// them
+    async fn import_mail(
+        &self,
+        maildir: &Path,
+        filter_from: Option<&str>,
+        on_conflict: import::OnConflict,
+    ) -> Result<(), Report> {
+        let filter_from = filter_from.map(str::to_lowercase);
+        let mut docs = Vec::new();
+        let mut failed = Vec::new();
+        for path in mail::messages(maildir)? {
+            let mail = match fs::read(&path) {
+                Ok(raw) => mail::parse(&raw),
+                Err(e) => {
+                    error!("❌ Failed to read {}: {}", path.display(), e);
+                    failed.push(path.display().to_string());
+                    continue;
+                }
+            };
+            if let Some(filter) = &filter_from {
+                let sender = mail.from.as_ref().map(|f| f.address.to_lowercase());
+                if !sender.is_some_and(|s| s.contains(filter.as_str())) {
+                    continue;
+                }
+            }
+            match mail::to_document(&mail, &path) {
+                Ok(doc) => docs.push(doc),
+                Err(e) => {
+                    error!("{}", e);
+                    failed.push(path.display().to_string());
+                }
+            }
+        }
+        if self.verbosity > 0 {
+            status!("{} emails to import", docs.len());
+        }
+        self.add_each(&self.client()?, docs, failed, on_conflict)
+            .await
+    }
+
+    /// Import the notes converted from another app's export, then list what couldn't be
+    async fn import_converted(
+        &self,
+        converted: import::Converted,
+        on_conflict: import::OnConflict,
+    ) -> Result<(), Report> {
+        let result = self
+            .add_each(&self.client()?, converted.docs, Vec::new(), on_conflict)
+            .await;
+        for item in &converted.unconverted {
+            status!("Not converted: {}", item);
+        }
+        result
+    }
+
+    /// Post each document in its own request, or in batches when they overwrite whatever is
+    /// indexed, as many at a time as the config's [import] section says. Files that `failed` to
+    /// load make the import partial, which is reported as an error once the rest are in.
+    async fn add_each(
+        &self,
+        client: &Client,
+        docs: Vec<document::Document>,
+        failed: Vec<String>,
+        on_conflict: import::OnConflict,
+    ) -> Result<(), Report> {
+        if on_conflict == import::OnConflict::Fail {
+            let conflicts = import::conflicts(client, &docs).await?;
+            if !conflicts.is_empty() {
+                bail!(
+                    "❌ Already in the index, nothing imported: {}",
+                    conflicts.join(", ")
+                );
+            }
+        }
+        let mut summary = ImportSummary {
+            imported: 0,
+            skipped: 0,
+            failed,
+        };
+        let upload = &self.config.import;
+        if on_conflict == import::OnConflict::Overwrite {
+            // Nothing to check per note, so they go up in batches. Each batch is waited for,
+            // so one the server rejects counts as failed rather than imported.
+            let mut uploads = stream::iter(docs.chunks(upload.batch_size()))
+                .map(|batch| async move {
+                    let task_uids = client.add_documents(batch).await?;
+                    let done = client.wait_for_tasks(&task_uids).await;
+                    Ok::<_, Report>((batch, task_uids, done))
+                })
+                .buffer_unordered(upload.concurrency());
+            while let Some(uploaded) = uploads.next().await {
+                let (batch, task_uids, done) = uploaded?;
+                if let Err(e) = done {
+                    error!("{}", e);
+                    summary
+                        .failed
+                        .extend(batch.iter().map(|doc| doc.id.clone()));
+                    continue;
+                }
+                summary.imported += batch.len();
+                if self.config.hooks.on_import.is_some() {
+                    for doc in batch {
+                        hooks::run(&self.config.hooks, hooks::Event::Import, doc);
+                    }
+                }
+                if self.verbosity > 0 {
+                    let tasks: Vec<String> = task_uids.iter().map(|t| t.to_string()).collect();
+                    status!("✅ {} notes, tasks {}", batch.len(), tasks.join(", "));
+                }
+            }
+            return self.finish_import(summary);
+        }
+        let mut added = stream::iter(docs)
+            .map(|doc| async move {
+                let result = import::add(client, &doc, on_conflict).await;
+                (doc, result)
+            })
+            .buffer_unordered(upload.concurrency());
+        while let Some((doc, result)) = added.next().await {
+            let (outcome, task_uids) = result?;
+            let tasks: Vec<String> = task_uids.iter().map(|t| t.to_string()).collect();
+            match outcome {
+                import::Outcome::Skipped => summary.skipped += 1,
+                _ => {
+                    summary.imported += 1;
+                    if self.config.hooks.on_import.is_some() {
+                        // The hook may read the note back from the server
+                        client.wait_for_tasks(&task_uids).await?;
+                        hooks::run(&self.config.hooks, hooks::Event::Import, &doc);
+                    }
+                }
+            }
+            if self.verbosity > 0 {
+                match outcome {
+                    import::Outcome::Skipped => status!("Skipped {}", doc.id),
+                    import::Outcome::Revised => {
+                        status!("✅ {} new revision, task {}", doc.id, tasks.join(", "))
+                    }
+                    import::Outcome::Added => status!("✅ {} task {}", doc, tasks.join(", ")),
+                }
+            }
+        }
+        self.finish_import(summary)
+    }
+
+    /// Report the outcome of an import, failing if any file failed to load
+    fn finish_import(&self, summary: ImportSummary) -> Result<(), Report> {
+        if !summary.failed.is_empty() {
+            return Err(summary.into());
+        }
+        if self.json {
+            eprintln!("{}", summary.to_json());
+        }
+        Ok(())
+    }
+
+    fn interactive_query(
+        &self,
+        fresh: bool,
+        emit: emit::Emit,
+        dir: &Path,
+        include_archived: bool,
+        script: Option<&Path>,
+    ) -> Result<(), Report> {
+        let client = blocking::Client::new(self.client()?)?;
+        // A script stands in for the keyboard, stdin doesn't matter then
+        let fallback = match script {
+            Some(_) if !io::stdout().is_terminal() => {
+                bail!("❌ --script needs a terminal on stdout to draw on")
+            }
+            Some(_) => menu::Fallback::None,
+            None => menu::Fallback::detect(),
+        };
+        let mut res = match fallback {
+            menu::Fallback::Menu => menu::query(&client, include_archived)?,
+            menu::Fallback::Static => menu::static_query(&client, include_archived)?,
+            menu::Fallback::None => self.tui_query(client, fresh, include_archived, script)?,
+        };
+        print!("{}", emit.render(&mut res, dir)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "interactive")]
+    fn tui_query(
+        &self,
+        client: blocking::Client,
+        fresh: bool,
+        include_archived: bool,
+        script: Option<&Path>,
+    ) -> Result<Vec<document::Document>, Report> {
+        let options = interactive::Options {
+            verbosity: self.verbosity,
+            pager: self.pager().to_owned(),
+            editor: self.editor().to_owned(),
+            fresh,
+            include_archived,
+            keys: match script {
+                Some(path) => interactive::event::Source::script(path)?,
+                None => interactive::event::Source::Stdin,
+            },
+        };
+        interactive::setup_panic();
+        interactive::query(client, &options, &self.config)
+    }
+
+    /// Built without the full-screen query, the numbered menu stands in for it
+    #[cfg(not(feature = "interactive"))]
+    fn tui_query(
+        &self,
+        client: blocking::Client,
+        _fresh: bool,
+        include_archived: bool,
+        script: Option<&Path>,
+    ) -> Result<Vec<document::Document>, Report> {
+        if script.is_some() {
+            bail!("❌ --script drives the full-screen query, which this mz was built without");
+        }
+        menu::query(&client, include_archived)
+    }
+
+    async fn static_query(
+        &self,
+        search: &query::Search,
+        indexes: &[String],
+        no_pager: bool,
+        output: query::Output,
+    ) -> Result<(), Report> {
+        let client = self.client()?;
+        // Anything but the notes index alone goes through multi-search
+        let out = if !indexes.is_empty() && indexes != [client.index()] {
+            if output == query::Output::Ndjson || search.all {
+                bail!("❌ --output ndjson and --all search the notes index alone");
+            }
+            query::multi_query(
+                &client,
+                indexes,
+                search.query.clone(),
+                search.filter.clone(),
+                search.include_archived,
+            )
+            .await?
+        } else if output == query::Output::Ndjson {
+            // Data for another program, written as it arrives rather than paged
+            let stdout = io::stdout();
+            let mut out = io::BufWriter::new(stdout.lock());
+            let written = match query::stream(&client, search, &mut out).await {
+                Ok(_) => out.flush().map_err(Report::from),
+                Err(e) => Err(e),
+            };
+            // Whatever reads the lines, like head, may stop early
+            return match written {
+                Err(e)
+                    if e.downcast_ref::<io::Error>().map(io::Error::kind)
+                        == Some(io::ErrorKind::BrokenPipe) =>
+                {
+                    Ok(())
+                }
+                r => r,
+            };
+        } else {
+            let width = style::terminal_size().map(|(w, _)| w as usize);
+            query::query(&client, search, output, width, style::color()).await?
+        };
+        if no_pager {
+            print!("{}", out);
+            return Ok(());
+        }
+        pager::print(self.pager(), &out)
+    }
+
+    async fn archive(&self, ids: &[String], filter: Option<&str>) -> Result<(), Report> {
+        let client = self.client()?;
+        let mut docs = Vec::new();
+        match filter {
+            Some(filter) => {
+                let mut q = api::ApiQuery::new();
+                q.process_filter(filter.to_owned());
+                let filter = match q.filter.take() {
+                    Some(f) => f,
+                    None => bail!("❌ Invalid filter {}", filter),
+                };
+                q.filter = Some(format!("({}) AND latest = true", filter));
+                q.exclude_archived();
+                docs.extend(api::Pages::search(&client, q).all().await?);
+            }
+            None if ids.is_empty() => bail!("❌ Give the ids to archive, or --filter"),
+            None => {
+                for id in ids {
+                    match client.latest_revision(id).await? {
+                        Some(doc) => docs.push(doc),
+                        None => bail!("❌ No document {}", id),
+                    }
+                }
+            }
+        }
+
+        let updates: Vec<_> = docs
+            .iter_mut()
+            .map(|doc| {
+                if !doc.tags.iter().any(|t| t == api::ARCHIVED) {
+                    doc.tags.push(api::ARCHIVED.to_owned());
+                }
+                serde_json::json!({ "id": doc.id, "latest": false, "tags": doc.tags })
+            })
+            .collect();
+        if !updates.is_empty() {
+            let tasks = client
+                .update_documents(&serde_json::Value::from(updates))
+                .await?;
+            client.wait_for_tasks(&tasks).await?;
+        }
+        status!("✅ Archived {} notes", docs.len());
+        Ok(())
+    }
 
-    let opt = Opt::from_args();
+    async fn new_note(
+        &self,
+        template: Option<&str>,
+        title: &str,
+        tags: &[String],
+        body: Option<&str>,
+    ) -> Result<(), Report> {
+        let skeleton = match template {
+            Some(name) => template::load(name, &self.config.new)?,
+            None => template::DEFAULT.to_owned(),
+        };
+        let placeholders = template::Placeholders::new(title, &self.config.new);
+        let contents = template::expand(&skeleton, &placeholders);
+
+        let mut tf = tempfile::Builder::new()
+            .prefix("meilizet-")
+            .suffix(".md")
+            .tempfile()?;
+        tf.write_all(contents.as_bytes())?;
+        // A body given up front is a quick capture, skip the editor
+        let body = match body {
+            Some("-") => {
+                let mut body = String::new();
+                io::stdin().read_to_string(&mut body)?;
+                Some(body)
+            }
+            Some(body) => Some(body.to_owned()),
+            None => {
+                self.edit(tf.path())?;
+                // Like git commit, leaving the skeleton untouched abandons the note
+                if fs::read_to_string(tf.path())? == contents {
+                    bail!("❌ Note left unchanged, not adding it");
+                }
+                None
+            }
+        };
+
+        let mut doc = document::Document::parse_file(tf.path())
+            .map_err(|e| ParseError(format!("❌ Failed to parse the new note: {}", e)))?;
+        // An unknown {{author}} expands to nothing
+        doc.authors.retain(|a| !a.is_empty());
+        for tag in tags {
+            if !doc.tags.contains(tag) {
+                doc.tags.push(tag.to_owned());
+            }
+        }
+        if let Some(body) = body {
+            doc.body.push_str(&body);
+        }
+        slug::fill(&mut doc);
+        // Not the temporary file's name, which `dump` would otherwise write it to
+        doc.filename = format!("{}.md", doc.slug.replace('/', "-"));
+        let client = self.client()?;
+        let tasks = match client.add_documents(std::slice::from_ref(&doc)).await {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                // Don't lose what was written
+                let mut on_disk = doc.clone();
+                on_disk.serialization_type = document::SerializationType::Disk;
+                fs::write(tf.path(), on_disk.to_string())?;
+                let (_, path) = tf.keep()?;
+                error!("❌ Kept the note in {}", path.display());
+                return Err(e);
+            }
+        };
+        client.wait_for_tasks(&tasks).await?;
+        status!("✅ Added {}", doc.id);
+        hooks::run(&self.config.hooks, hooks::Event::New, &doc);
+        Ok(())
+    }
+
+    async fn append(&self, id: &str, text: &str, heading: bool) -> Result<(), Report> {
+        let client = self.client()?;
+        let prev = match client.latest_revision(id).await? {
+            Some(doc) => doc,
+            None => bail!("❌ No document with id {}", id),
+        };
+        let mut next = prev.next_revision();
+        let heading = if heading {
+            Some(Local::now().format("%Y-%m-%d %H:%M").to_string())
+        } else {
+            None
+        };
+        next.append(text, heading.as_deref());
+        client
+            .wait_for_tasks(&client.add_revision(&prev, &next).await?)
+            .await?;
+        status!(
+            "✅ Appended to {}, now revision {}",
+            next.chain_id(),
+            next.revision
+        );
+        Ok(())
+    }
+
+    async fn daily(&self, text: Option<&str>) -> Result<(), Report> {
+        let today = Local::now();
+        let daily = &self.config.daily;
+        let slug = today.format(&daily.slug).to_string();
+        let client = self.client()?;
+
+        let mut q = api::ApiQuery::new();
+        q.filter = Some(format!("slug = \"{}\" AND latest = true", slug));
+        q.sort = Some(vec!["revision:desc".to_owned()]);
+        q.limit = 1;
+        let prev = client.search(&q).await?.hits.pop();
+        let mut doc = match &prev {
+            Some(prev) => prev.next_revision(),
+            None => document::Document {
+                id: document::DocumentId::generate().into(),
+                title: today.format(&daily.title).to_string(),
+                filename: format!("{}.md", slug.replace('/', "-")),
+                slug,
+                tags: daily.tags.clone(),
+                date: Date::new(today.timestamp()),
+                authors: template::author(&self.config.new).into_iter().collect(),
+                ..document::Document::new()
+            },
+        };
+
+        match text {
+            Some(text) => doc.append(text, Some(&today.format("%H:%M").to_string())),
+            None => match self.edit_document(&doc)? {
+                Some(edited) => doc = edited,
+                None => bail!("❌ Note left unchanged, not saving it"),
+            },
+        }
+        let tasks = match &prev {
+            Some(prev) => client.add_revision(prev, &doc).await?,
+            None => client.add_documents(std::slice::from_ref(&doc)).await?,
+        };
+        client.wait_for_tasks(&tasks).await?;
+        status!("✅ Saved {} revision {}", doc.slug, doc.revision);
+        Ok(())
+    }
+
+    async fn prune(
+        &self,
+        keep: usize,
+        older_than: Option<chrono::Duration>,
+        dry_run: bool,
+    ) -> Result<(), Report> {
+        let client = self.client()?;
+        let mut docs = api::Pages::documents(&client).all().await?;
+        docs.sort_by_key(|d| std::cmp::Reverse(d.revision));
+        let mut chains: HashMap<String, Vec<document::Document>> = HashMap::new();
+        for doc in docs {
+            chains
+                .entry(doc.chain_id().to_owned())
+                .or_default()
+                .push(doc);
+        }
+        let cutoff = older_than.map(|age| (Local::now() - age).timestamp());
+
+        let (mut ids, mut notes, mut bytes) = (Vec::new(), 0, 0);
+        for revisions in chains.values() {
+            // Sorted newest first, and the newest is never deleted, even if archived
+            let pruned: Vec<_> = revisions
+                .iter()
+                .skip(keep.max(1))
+                .filter(|d| !d.latest)
+                .filter(|d| match cutoff {
+                    Some(cutoff) => d.date.timestamp() < cutoff,
+                    None => true,
+                })
+                .collect();
+            if pruned.is_empty() {
+                continue;
+            }
+            notes += 1;
+            for doc in pruned {
+                bytes += serde_json::to_vec(doc)?.len();
+                ids.push(doc.id.clone());
+            }
+        }
+        if ids.is_empty() {
+            status!("✅ Nothing to prune");
+            return Ok(());
+        }
+
+        if dry_run {
+            println!(
+                "Would delete {} revisions of {} notes, about {} KiB",
+                ids.len(),
+                notes,
+                bytes / 1024
+            );
+            return Ok(());
+        }
+        let task = client.delete(&ids).await?;
+        client.wait_for_task(task).await?;
+        status!(
+            "✅ Deleted {} revisions of {} notes, about {} KiB",
+            ids.len(),
+            notes,
+            bytes / 1024
+        );
+        Ok(())
+    }
+
+    async fn purge(
+        &self,
+        filter: &str,
+        backup_dir: Option<&Path>,
+        yes: bool,
+    ) -> Result<(), Report> {
+        let client = self.client()?;
+        let mut q = api::ApiQuery::new();
+        q.process_filter(filter.to_owned());
+        if q.filter.is_none() {
+            bail!("❌ Invalid filter {}", filter);
+        }
+        let docs = api::Pages::search(&client, q).all().await?;
+        if docs.is_empty() {
+            status!("✅ Nothing matches {}", filter);
+            return Ok(());
+        }
+
+        let dir = match backup_dir {
+            Some(dir) => dir.to_owned(),
+            None => state::state_dir()?
+                .join("backups")
+                .join(format!("purge-{}", Local::now().format("%Y%m%dT%H%M%S"))),
+        };
+        // A directory per document, as revisions share a file name
+        for doc in &docs {
+            let mut doc = doc.clone();
+            doc.serialization_type = document::SerializationType::Disk;
+            let name = match doc.filename.as_str() {
+                "" => format!("{}.md", doc.id),
+                name => name.to_owned(),
+            };
+            let doc_dir = dir.join(&doc.id);
+            fs::create_dir_all(&doc_dir)?;
+            fs::write(doc_dir.join(name), doc.to_string())?;
+        }
+        println!("Backed up {} documents to {}", docs.len(), dir.display());
+
+        if !yes {
+            print!("Delete them? [y/N] ");
+            io::stdout().flush()?;
+            let answer = setup::read_line()?.unwrap_or_default();
+            if !matches!(answer.trim(), "y" | "yes") {
+                println!("Nothing deleted");
+                return Ok(());
+            }
+        }
+        let ids: Vec<_> = docs.iter().map(|d| d.id.clone()).collect();
+        let task = client.delete(&ids).await?;
+        client.wait_for_task(task).await?;
+        status!("✅ Deleted {} documents", ids.len());
+        for doc in &docs {
+            hooks::run(&self.config.hooks, hooks::Event::Delete, doc);
+        }
+        Ok(())
+    }
+
+    async fn retitle(&self, id: &str, title: &str, rewrite_links: bool) -> Result<(), Report> {
+        let client = self.client()?;
+        let prev = match client.latest_revision(id).await? {
+            Some(doc) => doc,
+            None => bail!("❌ No document {}", id),
+        };
+        let mut next = prev.next_revision();
+        slug::retitle(&mut next, title);
+        client
+            .wait_for_tasks(&client.add_revision(&prev, &next).await?)
+            .await?;
+        status!(
+            "✅ Retitled {}, now revision {} with slug {}",
+            next.chain_id(),
+            next.revision,
+            next.slug
+        );
+        if !rewrite_links || prev.slug.is_empty() || prev.slug == next.slug {
+            return Ok(());
+        }
+
+        let mut updates = Vec::new();
+        for mut doc in graph::latest(&client).await? {
+            if doc.chain_id() == next.chain_id() {
+                continue;
+            }
+            if links::relink(&mut doc, &prev.slug, &next.slug) {
+                updates.push(serde_json::json!({ "id": doc.id, "links": doc.links }));
+            }
+        }
+        let relinked = updates.len();
+        if relinked > 0 {
+            let tasks = client
+                .update_documents(&serde_json::Value::from(updates))
+                .await?;
+            client.wait_for_tasks(&tasks).await?;
+        }
+        status!(
+            "✅ Pointed the links of {} notes at {}",
+            relinked,
+            next.slug
+        );
+        Ok(())
+    }
+
+    async fn merge(&self, a: &str, b: &str, into: Option<&str>) -> Result<(), Report> {
+        let client = self.client()?;
+        let mut sources = Vec::new();
+        for id in [a, b] {
+            match client.latest_revision(id).await? {
+                Some(doc) if crypt::is_encrypted(&doc.body) => {
+                    bail!("❌ {} is encrypted, pass --decrypt to merge it", id)
+                }
+                Some(doc) => sources.push(doc),
+                None => bail!("❌ No document {}", id),
+            }
+        }
+        if sources[0].chain_id() == sources[1].chain_id() {
+            bail!("❌ {} and {} are revisions of the same note", a, b);
+        }
+        let merged = merge::merge(&sources[0], &sources[1], into);
+        client
+            .wait_for_tasks(&client.add_documents(std::slice::from_ref(&merged)).await?)
+            .await?;
+        let updates: Vec<_> = sources
+            .iter()
+            .map(|doc| {
+                let mut update = serde_json::json!({ "id": doc.id, "latest": false });
+                update[merge::MERGED_INTO] = merged.id.clone().into();
+                update
+            })
+            .collect();
+        let tasks = client
+            .update_documents(&serde_json::Value::from(updates))
+            .await?;
+        client.wait_for_tasks(&tasks).await?;
+        status!("✅ Merged {} and {} into {}", a, b, merged.id);
+        hooks::run(&self.config.hooks, hooks::Event::New, &merged);
+        Ok(())
+    }
+
+    async fn trash(&self, ids: &[String], cmd: Option<&TrashCommand>) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            None if ids.is_empty() => bail!("❌ Give the ids of the notes to trash"),
+            None => {
+                let docs = client.trash_notes(ids).await?;
+                status!("✅ Moved {} documents to the trash", docs.len());
+                for doc in docs.iter().filter(|d| d.latest) {
+                    hooks::run(&self.config.hooks, hooks::Event::Delete, doc);
+                }
+            }
+            Some(TrashCommand::List {}) => {
+                for doc in client.trashed().await?.iter().filter(|d| d.latest) {
+                    let when = trash::trashed_at(doc).unwrap_or_default();
+                    println!("{}\t{}\t{}", doc.id, doc.title, when);
+                }
+            }
+            Some(TrashCommand::Empty { older_than }) => {
+                let before =
+                    older_than.map(|age| date::Date::new((Local::now() - age).timestamp()));
+                let deleted = client.empty_trash(before).await?;
+                status!("✅ Deleted {} documents from the trash", deleted);
+            }
+        }
+        Ok(())
+    }
+
+    async fn bulk_edit(
+        &self,
+        filter: &str,
+        add_tags: &[String],
+        remove_tags: &[String],
+        sets: &[String],
+        dry_run: bool,
+    ) -> Result<(), Report> {
+        let mut fields = serde_json::Map::new();
+        for set in sets {
+            let (field, value) = match set.split_once('=') {
+                Some((field, _)) if field == "id" || field == "tags" => {
+                    bail!(
+                        "❌ Can't --set {}, use --add-tag and --remove-tag for tags",
+                        field
+                    )
+                }
+                Some((field, value)) => (field, value),
+                None => bail!("❌ Invalid --set {}, expected field=value", set),
+            };
+            let value =
+                serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::from(value));
+            fields.insert(field.to_owned(), value);
+        }
+        let add: Vec<_> = add_tags.iter().map(String::as_str).collect();
+        let remove: Vec<_> = remove_tags.iter().map(String::as_str).collect();
+
+        let client = self.client()?;
+        let mut q = api::ApiQuery::new();
+        q.process_filter(filter.to_owned());
+        let matched = match q.filter.take() {
+            Some(f) => f,
+            None => bail!("❌ Invalid filter {}", filter),
+        };
+        q.filter = Some(format!("({}) AND latest = true", matched));
+        let mut updates = Vec::new();
+        for doc in api::Pages::search(&client, q).all().await? {
+            let mut update = serde_json::Map::new();
+            let tags = tags::edit(&doc.tags, &add, &remove);
+            if tags != doc.tags {
+                update.insert("tags".to_owned(), serde_json::Value::from(tags));
+            }
+            let current = serde_json::to_value(&doc)?;
+            for (field, value) in &fields {
+                if current.get(field) != Some(value) {
+                    update.insert(field.to_owned(), value.clone());
+                }
+            }
+            if !update.is_empty() {
+                update.insert("id".to_owned(), serde_json::Value::from(doc.id));
+                updates.push(serde_json::Value::from(update));
+            }
+        }
+
+        if dry_run {
+            println!("{} notes would change", updates.len());
+            if self.verbosity > 0 {
+                for update in &updates {
+                    println!("{}", update);
+                }
+            }
+            return Ok(());
+        }
+        let mut done = 0;
+        for batch in updates.chunks(BULK_BATCH) {
+            let tasks = client
+                .update_documents(&serde_json::Value::from(batch.to_vec()))
+                .await?;
+            client.wait_for_tasks(&tasks).await?;
+            done += batch.len();
+            if !style::quiet() {
+                eprintln!("Updated {}/{}", done, updates.len());
+            }
+        }
+        status!("✅ Changed {} notes", updates.len());
+        Ok(())
+    }
+
+    async fn weight(&self, cmd: &WeightCommand) -> Result<(), Report> {
+        let client = self.client()?;
+        match cmd {
+            WeightCommand::Set { id, weight } => set_weight(&client, id, |_| *weight).await,
+            WeightCommand::Bump { id, by } => {
+                set_weight(&client, id, |w| w.saturating_add(*by)).await
+            }
+            WeightCommand::Top { limit } => {
+                let mut q = api::ApiQuery::new();
+                q.filter = Some("latest = true".to_owned());
+                q.exclude_archived();
+                q.sort = Some(vec!["weight:desc".to_owned()]);
+                q.limit = *limit;
+                for doc in client.search(&q).await?.hits {
+                    println!("{:>5} {} {}", doc.weight, doc.id, doc.title);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn dedupe(&self, threshold: f64, interactive: bool) -> Result<(), Report> {
+        let client = self.client()?;
+        let docs = graph::latest(&client).await?;
+        let found = dedupe::find(&docs, threshold);
+        if found.is_empty() {
+            status!("✅ No duplicates found");
+            return Ok(());
+        }
+
+        let mut retired = Vec::new();
+        for dup in &found {
+            let (a, b) = (&docs[dup.a], &docs[dup.b]);
+            if retired.contains(&a.id) || retired.contains(&b.id) {
+                continue;
+            }
+            println!(
+                "{:.2}{} 1) {} {} ({})  2) {} {} ({})",
+                dup.similarity,
+                if dup.same_title { " same title" } else { "" },
+                a.id,
+                a.title,
+                a.date,
+                b.id,
+                b.title,
+                b.date
+            );
+            if !interactive {
+                continue;
+            }
+            print!("Keep (1), (2), (b)oth or (q)uit? [b] ");
+            io::stdout().flush()?;
+            let answer = setup::read_line()?.unwrap_or_default();
+            match answer.trim() {
+                "1" => retired.push(b.id.clone()),
+                "2" => retired.push(a.id.clone()),
+                "" | "b" => {}
+                _ => break,
+            }
+        }
+
+        if !retired.is_empty() {
+            let updates: Vec<_> = retired
+                .iter()
+                .map(|id| serde_json::json!({ "id": id, "latest": false }))
+                .collect();
+            let tasks = client
+                .update_documents(&serde_json::Value::from(updates))
+                .await?;
+            client.wait_for_tasks(&tasks).await?;
+            status!("✅ Marked {} notes no longer latest", retired.len());
+        }
+        Ok(())
+    }
+
+    async fn review(&self, limit: usize, interval: i64) -> Result<(), Report> {
+        let client = self.client()?;
+        let now = Local::now().timestamp();
+        let cutoff = now - interval * 24 * 60 * 60;
+        let mut q = api::ApiQuery::new();
+        // Notes never reviewed have no last_reviewed at all
+        q.filter = Some(format!(
+            "latest = true AND last_reviewed < {0} OR latest = true AND last_reviewed NOT EXISTS",
+            cutoff
+        ));
+        q.sort = Some(vec!["views:asc".to_owned(), "date:asc".to_owned()]);
+        q.limit = limit as u32;
+        let due = client.search(&q).await?.hits;
+        if due.is_empty() {
+            status!("✅ Nothing due for review");
+            return Ok(());
+        }
+
+        let total = due.len();
+        let mut updates = Vec::new();
+        for (i, mut doc) in due.into_iter().enumerate() {
+            doc.serialization_type = document::SerializationType::Disk;
+            let mut tf = tempfile::Builder::new()
+                .prefix("meilizet-")
+                .suffix(".md")
+                .tempfile()?;
+            tf.write_all(doc.to_string().as_bytes())?;
+            self.open_with(self.pager(), tf.path())?;
+
+            print!(
+                "[{}/{}] {}: (r)eviewed, (s)kip or (q)uit? [r] ",
+                i + 1,
+                total,
+                doc.title
+            );
+            io::stdout().flush()?;
+            let answer = setup::read_line()?.unwrap_or_default();
+            match answer.trim() {
+                "" | "r" => updates.push(serde_json::json!({
+                    "id": doc.id,
+                    "views": doc.views + 1,
+                    "last_reviewed": now,
+                })),
+                "s" => {}
+                _ => break,
+            }
+        }
+
+        let reviewed = updates.len();
+        if reviewed > 0 {
+            let tasks = client
+                .update_documents(&serde_json::Value::from(updates))
+                .await?;
+            client.wait_for_tasks(&tasks).await?;
+        }
+        status!("✅ Reviewed {} notes", reviewed);
+        Ok(())
+    }
+
+    /// Open `doc` in the editor, returning it as edited or None if left unchanged. Its id,
+    /// revision and file name are kept whatever the edit did to them.
+    fn edit_document(
+        &self,
+        doc: &document::Document,
+    ) -> Result<Option<document::Document>, Report> {
+        let mut on_disk = doc.clone();
+        on_disk.serialization_type = document::SerializationType::Disk;
+        let contents = on_disk.to_string();
+        let mut tf = tempfile::Builder::new()
+            .prefix("meilizet-")
+            .suffix(".md")
+            .tempfile()?;
+        tf.write_all(contents.as_bytes())?;
+        self.edit(tf.path())?;
+        if fs::read_to_string(tf.path())? == contents {
+            return Ok(None);
+        }
+
+        let edited = document::Document::parse_file(tf.path())
+            .map_err(|e| ParseError(format!("❌ Failed to parse the edited note: {}", e)))?;
+        Ok(Some(document::Document {
+            id: doc.id.clone(),
+            origid: doc.origid.clone(),
+            revision: doc.revision,
+            latest: doc.latest,
+            filename: doc.filename.clone(),
+            ..edited
+        }))
+    }
+
+    /// Open `path` in the editor, failing if it exits unsuccessfully
+    fn edit(&self, path: &Path) -> Result<(), Report> {
+        self.open_with(self.editor(), path)
+    }
+
+    /// Run `program`, which may include arguments, e.g. `bat --paging always`, on `path`,
+    /// failing if it exits unsuccessfully
+    fn open_with(&self, program: &str, path: &Path) -> Result<(), Report> {
+        let mut args = program.split_whitespace();
+        let mut cmd = Command::new(args.next().unwrap_or("vi"));
+        let status = cmd.args(args).arg(path).status()?;
+        if !status.success() {
+            bail!("❌ {} exited with {}", program, status);
+        }
+        Ok(())
+    }
+
+    /// Save every document of the index for --offline, returning them
+    async fn sync(&self) -> Result<Vec<document::Document>, Report> {
+        if self.offline {
+            bail!("❌ Can't refresh the offline copy while offline");
+        }
+        let client = self.client()?;
+        let docs = cache::refresh(&client).await?;
+        status!("✅ Saved {} documents for offline use", docs.len());
+        Ok(docs)
+    }
+
+    /// Print the diff from the document `id` to `other`, or to what `file` holds
+    async fn diff(&self, id: &str, other: Option<&str>, file: Option<&Path>) -> Result<(), Report> {
+        let client = self.client()?;
+        let mut old = existing(&client, id).await?;
+        let (new, new_name) = match (other, file) {
+            (_, Some(path)) => {
+                let options = document::ParseOptions {
+                    field_map: self.config.field_map.clone(),
+                    ..Default::default()
+                };
+                let new = document::Document::parse_file_with(path, &options)?;
+                // Compare what the notes say, not how their frontmatter is written
+                old.frontmatter = new.frontmatter;
+                (new, path.display().to_string())
+            }
+            (Some(other), None) => (existing(&client, other).await?, other.to_owned()),
+            (None, None) => bail!("❌ Give a second document or --file to compare against"),
+        };
+        let diff = diff::render(&old, &new, id, &new_name, style::color());
+        if diff.is_empty() {
+            status!("✅ No differences");
+        } else {
+            print!("{}", diff);
+        }
+        Ok(())
+    }
+
+    async fn get(&self, id: &str, body: bool) -> Result<(), Report> {
+        match self.client()?.get_document(id).await? {
+            Some(doc) if body => {
+                print!("{}", doc.body);
+                Ok(())
+            }
+            Some(mut doc) => {
+                doc.serialization_type = document::SerializationType::Disk;
+                print!("{}", doc);
+                Ok(())
+            }
+            None => bail!("❌ No document {}", id),
+        }
+    }
+
+    async fn pick(
+        &self,
+        query: &str,
+        filter: Option<&str>,
+        include_archived: bool,
+    ) -> Result<(), Report> {
+        let mut q = api::ApiQuery::new();
+        if !query.is_empty() {
+            q.query = Some(query.to_owned());
+        }
+        q.filter = Some(match filter {
+            Some(filter) => {
+                q.process_filter(filter.to_owned());
+                match q.filter.take() {
+                    Some(f) => format!("({}) AND latest = true", f),
+                    None => bail!("❌ Invalid filter {}", filter),
+                }
+            }
+            None => "latest = true".to_owned(),
+        });
+        if !include_archived {
+            q.exclude_archived();
+        }
+        let stdout = io::stdout();
+        let mut out = io::BufWriter::new(stdout.lock());
+        for doc in self.client()?.search(&q).await?.hits {
+            let title: String = doc
+                .title
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect();
+            writeln!(
+                out,
+                "{}\t{}\t{}",
+                doc.id,
+                title,
+                doc.date.in_zone().format("%Y-%m-%d")
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn dump(
+        &self,
+        path: &Path,
+        frontmatter: Option<document::FrontmatterFormat>,
+    ) -> Result<(), Report> {
+        fs::create_dir_all(path)?;
+
+        let hits = self.sync().await?;
+        for (filename, doc) in verify::files(&hits, frontmatter) {
+            fs::write(path.join(filename), doc.to_string())?;
+        }
+        Ok(())
+    }
+
+    async fn migrate(&self, dry_run: bool) -> Result<(), Report> {
+        if self.offline {
+            bail!("❌ Can't migrate the offline copy, run it while online");
+        }
+        let client = self.client()?;
+        let raw = client.raw_documents().await?;
+        let (migrations, failed) = migrate::plan(&raw);
+        for (id, e) in &failed {
+            println!("{}", style::mark(&format!("❌ {}: {}", id, e)));
+        }
+        if dry_run {
+            for migration in &migrations {
+                print!("{}", migration.diff()?);
+            }
+            println!(
+                "{} of {} documents would be migrated",
+                migrations.len(),
+                raw.len()
+            );
+        } else if !migrations.is_empty() {
+            let docs: Vec<_> = migrations.iter().map(|m| m.after.clone()).collect();
+            client
+                .wait_for_tasks(&client.add_documents(&docs).await?)
+                .await?;
+            let old: Vec<_> = migrations
+                .iter()
+                .filter(|m| m.renamed())
+                .map(|m| m.old_id.clone())
+                .collect();
+            if !old.is_empty() {
+                client.wait_for_task(client.delete(&old).await?).await?;
+            }
+            status!(
+                "✅ Migrated {} of {} documents, {} to new ids",
+                migrations.len(),
+                raw.len(),
+                old.len()
+            );
+        } else {
+            status!("✅ All {} documents match the current schema", raw.len());
+        }
+        if !failed.is_empty() {
+            bail!("❌ {} documents could not be read", failed.len());
+        }
+        Ok(())
+    }
+
+    async fn export_search_index(
+        &self,
+        format: search_index::Format,
+        out: &Path,
+    ) -> Result<(), Report> {
+        let mut q = api::ApiQuery::new();
+        q.filter = Some("latest = true".to_owned());
+        q.exclude_archived();
+        let docs = api::Pages::search(&self.client()?, q).all().await?;
+        let count = search_index::export(format, &docs, out)?;
+        status!("✅ Exported {} notes to {}", count, out.display());
+        if format == search_index::Format::Pagefind {
+            status!(
+                "Build the bundle with `npx pagefind --site {}`",
+                out.display()
+            );
+        }
+        Ok(())
+    }
+
+    async fn export_anki(
+        &self,
+        filter: &str,
+        deck: &str,
+        out: Option<&Path>,
+    ) -> Result<(), Report> {
+        let mut q = api::ApiQuery::new();
+        q.process_filter(filter.to_owned());
+        let filter = match q.filter.take() {
+            Some(f) => f,
+            None => bail!("❌ Invalid filter {}", filter),
+        };
+        q.filter = Some(format!("({}) AND latest = true", filter));
+        q.exclude_archived();
+        let docs = api::Pages::search(&self.client()?, q).all().await?;
+        let (tsv, count) = anki::tsv(&docs, deck);
+        match out {
+            Some(path) => {
+                fs::write(path, tsv)?;
+                status!(
+                    "✅ Wrote {} cards from {} notes to {}",
+                    count,
+                    docs.len(),
+                    path.display()
+                );
+            }
+            None => print!("{}", tsv),
+        }
+        Ok(())
+    }
+
+    async fn backup(&self, path: &Path) -> Result<(), Report> {
+        let client = self.client()?;
+        let settings = client.all_settings().await?;
+        let docs = api::Pages::documents(&client).all().await?;
+        let backup = backup::Backup::new(client.index(), settings, docs);
+        backup.write(path)?;
+        status!(
+            "✅ Saved {} documents and the settings of {} to {}",
+            backup.manifest.documents,
+            client.index(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    async fn restore(&self, path: &Path, replace: bool) -> Result<(), Report> {
+        let backup = backup::Backup::read(path)?;
+        let client = self.client()?;
+        if !client.create_index().await? {
+            let existing = client
+                .stats()
+                .await?
+                .indexes
+                .get(client.index())
+                .map_or(0, |i| i.number_of_documents);
+            if existing > 0 {
+                if !replace {
+                    bail!(
+                        "❌ Index {} already has {} documents, pass --replace to delete them first",
+                        client.index(),
+                        existing
+                    );
+                }
+                client.delete_index().await?;
+                client.create_index().await?;
+            }
+        }
+        client.set_all_settings(&backup.settings).await?;
+        client
+            .wait_for_tasks(&client.add_documents(&backup.documents).await?)
+            .await?;
+        status!(
+            "✅ Restored {} documents from {} of {} taken {}",
+            backup.documents.len(),
+            path.display(),
+            backup.manifest.index,
+            backup.manifest.created
+        );
+        Ok(())
+    }
+
+    async fn verify(
+        &self,
+        dir: &Path,
+        frontmatter: Option<document::FrontmatterFormat>,
+    ) -> Result<(), Report> {
+        let docs = if self.offline {
+            api::Pages::documents(&self.client()?).all().await?
+        } else {
+            self.sync().await?
+        };
+        let files = verify::files(&docs, frontmatter);
+        let mismatches = verify::compare(dir, &files)?;
+        for mismatch in &mismatches {
+            println!("{}", mismatch);
+        }
+        if self.json {
+            eprintln!(
+                "{}",
+                serde_json::json!({ "files": files.len(), "mismatches": mismatches })
+            );
+        }
+        if !mismatches.is_empty() {
+            bail!(
+                "❌ {} differs from the index in {} places",
+                dir.display(),
+                mismatches.len()
+            );
+        }
+        status!("✅ {} files match the index", files.len());
+        Ok(())
+    }
+}
+
+/// The document `id`, failing if there is none
+async fn existing(client: &Client, id: &str) -> Result<document::Document, Report> {
+    match client.get_document(id).await? {
+        Some(doc) => Ok(doc),
+        None => bail!("❌ No document {}", id),
+    }
+}
+
+/// Give the latest revision of the note `id` the weight `weigh` computes from its current one
+async fn set_weight(client: &Client, id: &str, weigh: impl Fn(i32) -> i32) -> Result<(), Report> {
+    let doc = match client.latest_revision(id).await? {
+        Some(doc) => doc,
+        None => bail!("❌ No document {}", id),
+    };
+    let weight = weigh(doc.weight);
+    let update = serde_json::json!([{ "id": doc.id, "weight": weight }]);
+    let tasks = client.update_documents(&update).await?;
+    client.wait_for_tasks(&tasks).await?;
+    status!("✅ {} weighs {}", doc.id, weight);
+    Ok(())
+}
+
+pub fn glob_files(source: &str, verbosity: u8) -> Result<Paths, Box<dyn std::error::Error>> {
+    let glob_path = Path::new(&source);
+    let glob_str = shellexpand::tilde(glob_path.to_str().unwrap());
+
+    if verbosity > 0 {
+        println!("Sourcing Markdown documents matching : {}", glob_str);
+    }
+
+    Ok(glob(&glob_str).expect("Failed to read glob pattern"))
+}
+
+/// The servers in a comma-separated `list`
+fn host_list(list: &str) -> Vec<&str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|h| !h.is_empty())
+        .collect()
+}
+
+fn setup(opt: &Opt) -> Result<(), Report> {
+    if std::env::var("RUST_LIB_BACKTRACE").is_err() {
+        std::env::set_var("RUST_LIB_BACKTRACE", "1")
+    }
+    style::set(opt.color, opt.quiet);
+    let mut hook = color_eyre::config::HookBuilder::default();
+    if !style::color() {
+        hook = hook.theme(color_eyre::config::Theme::new());
+    }
+    hook.install()?;
+
+    Ok(())
+}
+
+fn run(opt: &mut Opt) -> Result<(), Report> {
+    logging::init(opt.verbosity, opt.quiet, opt.log_file.as_deref())?;
+    opt.config = config::Config::load(opt.config_path.as_deref())?;
+    if let Some(zone) = &opt.config.timezone {
+        date::Zone::set(zone.parse()?);
+    }
+    if let Some(scheme) = &opt.config.id_scheme {
+        document::IdScheme::set(scheme.parse()?);
+    }
+    if let Some(dir) = &opt.record {
+        opt.recording = Some(Arc::new(Recording::record(dir.clone())?));
+    } else if let Some(dir) = &opt.replay {
+        opt.recording = Some(Arc::new(Recording::replay(dir.clone())?));
+    }
 
     match opt.subcmd {
-        Subcommands::Import { ref globpath } => opt.import(globpath),
-        Subcommands::ImportLegacyMd { ref globpath } => opt.legacy_import(globpath),
-        Subcommands::Query {} => opt.interactive_query(),
-        Subcommands::Dump { ref path } => opt.dump(path),
-        Subcommands::StaticQuery {
-            ref query,
-            ref filter,
-        } => opt.static_query(query, filter),
-        Subcommands::New {} => unimplemented!("not yet"),
-        Subcommands::Add {} => unimplemented!("not yet"),
+        // The TUI drives its own runtime through the blocking client
+        Subcommands::Query {
+            fresh,
+            emit,
+            ref dir,
+            include_archived,
+            ref script,
+        } => opt.interactive_query(
+            fresh,
+            emit,
+            &opt.notes_dir(dir.as_deref()),
+            include_archived,
+            script.as_deref(),
+        ),
+        _ => Runtime::new()?.block_on(opt.run()),
     }
 }
+
+fn main() -> Result<(), Report> {
+    let mut opt = Opt::from_args();
+    setup(&opt)?;
+
+    if let Err(e) = run(&mut opt) {
+        if opt.json {
+            eprintln!("{}", error::to_json(&e));
+        } else if style::color() {
+            eprintln!("Error: {:?}", e);
+        } else {
+            eprintln!("Error: {}", format!("{:?}", e).replace("❌ ", ""));
+        }
+        std::process::exit(ExitCode::of(&e) as i32);
+    }
+    Ok(())
+}
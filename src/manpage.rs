@@ -0,0 +1,63 @@
+use clap::App;
+use color_eyre::Report;
+
+/// Width help text is wrapped to, so the page doesn't depend on the terminal it was built in
+const WIDTH: usize = 80;
+
+/// Render a man page, in roff, for `app` run as `name` with a section for every subcommand
+pub fn render(app: App, name: &str) -> Result<String, Report> {
+    let mut app = app.set_term_width(WIDTH).bin_name(name);
+    let mut out = format!(
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"\n",
+        name.to_uppercase(),
+        name,
+        app.p.meta.version.unwrap_or_default()
+    );
+    out.push_str(&format!(
+        ".SH NAME\n{} \\- {}\n",
+        name,
+        escape(app.p.meta.about.unwrap_or_default())
+    ));
+    out.push_str(".SH DESCRIPTION\n");
+    help(&mut app, &mut out)?;
+    out.push_str(".SH COMMANDS\n");
+    commands(&mut app, name, &mut out)?;
+    Ok(out)
+}
+
+/// Append the help of each subcommand of `app`, recursively, headed by how it is invoked
+fn commands(app: &mut App, path: &str, out: &mut String) -> Result<(), Report> {
+    // clap 2 offers no public way to walk the subcommands
+    for sub in app.p.subcommands.iter_mut() {
+        let path = format!("{} {}", path, sub.get_name());
+        sub.p.meta.bin_name = Some(path.clone());
+        sub.p.meta.term_w = Some(WIDTH);
+        out.push_str(&format!(".SS {}\n", escape(&path)));
+        help(sub, out)?;
+        commands(sub, &path, out)?;
+    }
+    Ok(())
+}
+
+/// Append the long help of `app` as preformatted text
+fn help(app: &mut App, out: &mut String) -> Result<(), Report> {
+    let mut buf = Vec::new();
+    app.write_long_help(&mut buf)?;
+    out.push_str(".nf\n");
+    for line in String::from_utf8_lossy(&buf).lines() {
+        out.push_str(&escape(line));
+        out.push('\n');
+    }
+    out.push_str(".fi\n");
+    Ok(())
+}
+
+/// Keep roff from interpreting backslashes, hyphens and lines starting with a control character
+fn escape(line: &str) -> String {
+    let line = line.replace('\\', "\\e").replace('-', "\\-");
+    if line.starts_with('.') || line.starts_with('\'') {
+        format!("\\&{}", line)
+    } else {
+        line
+    }
+}
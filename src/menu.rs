@@ -0,0 +1,137 @@
+use crate::blocking::Client;
+use crate::setup::read_line;
+use crate::{api, document::Document, style};
+use color_eyre::Report;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+
+/// Most matches listed at once, refine the query to reach the rest
+const MENU_SIZE: u32 = 20;
+
+/// Most matches emitted when reading the query from stdin
+const STATIC_SIZE: u32 = 1000;
+
+/// How `query` talks to the user when it can't take over the terminal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fallback {
+    /// Both ends are a capable terminal, run the TUI
+    None,
+    /// Stdin is a terminal but stdout is piped or the terminal is dumb: prompt on stderr and
+    /// pick from a numbered list
+    Menu,
+    /// Stdin is piped: read the query and filter from it and emit every match
+    Static,
+}
+
+impl Fallback {
+    pub fn detect() -> Fallback {
+        let dumb = env::var("TERM").map_or(true, |t| t.is_empty() || t == "dumb");
+        if !io::stdin().is_terminal() {
+            Fallback::Static
+        } else if dumb || !io::stdout().is_terminal() {
+            Fallback::Menu
+        } else {
+            Fallback::None
+        }
+    }
+}
+
+/// Search for `query` and `filter`, the first `limit` matches
+fn search(
+    client: &Client,
+    query: &str,
+    filter: &str,
+    include_archived: bool,
+    limit: u32,
+) -> Result<Vec<Document>, Report> {
+    let mut q = api::ApiQuery::new();
+    q.query = Some(query.to_owned());
+    q.limit = limit;
+    q.process_filter(filter.to_owned());
+    if !include_archived {
+        q.exclude_archived();
+    }
+    Ok(client.search(&q)?.hits)
+}
+
+/// Print `prompt` on stderr, keeping stdout for the picked documents, and read the reply
+fn prompt(prompt: &str) -> Result<Option<String>, Report> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    read_line()
+}
+
+/// Positions picked by `reply`, numbers and ranges like `1 3-5`, counted from 1. None if any
+/// part isn't one of the `len` matches.
+fn picks(reply: &str, len: usize) -> Option<Vec<usize>> {
+    let mut picked = Vec::new();
+    for part in reply.split(&[' ', ','][..]).filter(|p| !p.is_empty()) {
+        let (from, to) = match part.split_once('-') {
+            Some((from, to)) => (from.parse().ok()?, to.parse().ok()?),
+            None => {
+                let n = part.parse().ok()?;
+                (n, n)
+            }
+        };
+        if from == 0 || from > to || to > len {
+            return None;
+        }
+        picked.extend((from..=to).map(|n: usize| n - 1));
+    }
+    Some(picked)
+}
+
+/// Ask for a query and filter, list the matches numbered and read which to pick, until some
+/// are picked or input ends
+pub fn query(client: &Client, include_archived: bool) -> Result<Vec<Document>, Report> {
+    loop {
+        let query = match prompt("Query: ")? {
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+        let filter = match prompt("Filter: ")? {
+            Some(filter) => filter,
+            None => return Ok(Vec::new()),
+        };
+        let matches = search(client, &query, &filter, include_archived, MENU_SIZE)?;
+        if matches.is_empty() {
+            eprintln!("No matches");
+            continue;
+        }
+        for (n, doc) in matches.iter().enumerate() {
+            eprintln!(
+                "{:>3}) {} ({}) {}",
+                n + 1,
+                doc.title,
+                doc.date.in_zone().format("%Y-%m-%d"),
+                doc.id
+            );
+        }
+        loop {
+            let reply = match prompt("Pick (e.g. 1 3-5, empty to search again): ")? {
+                Some(reply) => reply,
+                None => return Ok(Vec::new()),
+            };
+            if reply.trim().is_empty() {
+                break;
+            }
+            match picks(&reply, matches.len()) {
+                Some(picked) => {
+                    return Ok(picked.into_iter().map(|n| matches[n].clone()).collect())
+                }
+                None => eprintln!(
+                    "{}",
+                    style::mark(&format!("❌ Pick numbers from 1 to {}", matches.len()))
+                ),
+            }
+        }
+    }
+}
+
+/// Read the query from the first line of stdin and the filter from the second, either may be
+/// missing, and return every match
+pub fn static_query(client: &Client, include_archived: bool) -> Result<Vec<Document>, Report> {
+    let query = read_line()?.unwrap_or_default();
+    let filter = read_line()?.unwrap_or_default();
+    search(client, &query, &filter, include_archived, STATIC_SIZE)
+}
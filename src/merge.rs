@@ -0,0 +1,80 @@
+use crate::date::Date;
+use crate::document::{Document, DocumentId};
+use crate::slug;
+
+/// Field on each note merged away naming the note it went into
+pub const MERGED_INTO: &str = "merged_into";
+
+/// `a` followed by whatever of `b` it doesn't have yet
+fn union<T: Clone + PartialEq>(a: &[T], b: &[T]) -> Vec<T> {
+    let mut all = a.to_vec();
+    for item in b {
+        if !all.contains(item) {
+            all.push(item.clone());
+        }
+    }
+    all
+}
+
+/// `body` under a `## title` heading, ending in a newline
+fn section(title: &str, body: &str) -> String {
+    let body = body.trim_end();
+    format!("## {}\n\n{}\n", title, body)
+}
+
+/// A new note, the first revision of its own chain, holding the bodies of `a` and then `b`,
+/// each under a heading with its title, and the tags, links, authors and attachments of both.
+/// It is titled `title`, or as `a` is, and encrypted if either of them was.
+pub fn merge(a: &Document, b: &Document, title: Option<&str>) -> Document {
+    let mut doc = Document {
+        id: DocumentId::generate().into(),
+        title: title.unwrap_or(&a.title).to_owned(),
+        date: Date::now(),
+        body: format!(
+            "{}\n{}",
+            section(&a.title, &a.body),
+            section(&b.title, &b.body)
+        ),
+        tags: union(&a.tags, &b.tags),
+        links: union(&a.links, &b.links),
+        authors: union(&a.authors, &b.authors),
+        attachments: union(&a.attachments, &b.attachments),
+        weight: a.weight.max(b.weight),
+        frontmatter: a.frontmatter,
+        // Sealed on upload, or refused without the key to seal it
+        encrypted: a.encrypted || b.encrypted,
+        ..Document::new()
+    };
+    slug::fill(&mut doc);
+    doc.filename = format!("{}.md", doc.slug.replace('/', "-"));
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(title: &str, body: &str, encrypted: bool) -> Document {
+        Document {
+            title: title.to_owned(),
+            body: body.to_owned(),
+            encrypted,
+            ..Document::new()
+        }
+    }
+
+    #[test]
+    fn merged_note_is_encrypted_if_either_was() {
+        for (a, b) in [(false, false), (true, false), (false, true), (true, true)] {
+            let merged = merge(&note("A", "a", a), &note("B", "b", b), None);
+            assert_eq!(merged.encrypted, a || b, "merging {} and {}", a, b);
+        }
+    }
+
+    #[test]
+    fn merged_body_has_each_under_its_title() {
+        let merged = merge(&note("A", "a\n", false), &note("B", "b", false), Some("C"));
+        assert_eq!(merged.title, "C");
+        assert_eq!(merged.body, "## A\n\na\n\n## B\n\nb\n");
+    }
+}
@@ -0,0 +1,92 @@
+use crate::document::{Document, DocumentId, IdScheme, SerializationType};
+use color_eyre::Report;
+use serde_json::Value;
+use similar::TextDiff;
+use std::collections::HashMap;
+
+/// A stored document that doesn't match the current schema, and what it becomes
+#[derive(Debug)]
+pub struct Migration {
+    /// Id it is stored under, deleted once `after` is written if that has a new one
+    pub old_id: String,
+    pub before: Value,
+    pub after: Document,
+}
+
+impl Migration {
+    /// Whether the document gets a new id, so the old one must be deleted
+    pub fn renamed(&self) -> bool {
+        self.after.id != self.old_id
+    }
+
+    /// Unified diff of the stored JSON against what will be written
+    pub fn diff(&self) -> Result<String, Report> {
+        let before = serde_json::to_string_pretty(&self.before)? + "\n";
+        let after = serde_json::to_string_pretty(&serde_json::to_value(&self.after)?)? + "\n";
+        Ok(TextDiff::from_lines(&before, &after)
+            .unified_diff()
+            .header(&self.old_id, &self.after.id)
+            .to_string())
+    }
+}
+
+/// Whether `id` is a hyphenated UUID, as versions before url-safe base64 ids generated
+fn is_hyphenated_uuid(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Whether `id` is in a scheme no longer generated, and should get a new one
+pub fn is_legacy_id(id: &str) -> bool {
+    is_hyphenated_uuid(id) && !matches!(IdScheme::current(), IdScheme::Uuid4 | IdScheme::Uuid7)
+}
+
+/// Work out how each of the stored documents `raw` moves to the current schema: legacy ids are
+/// replaced, along with every reference to them, and string dates and renamed or missing fields
+/// are normalized by reading the document as a Document and writing it back. Documents that
+/// already match are left out; documents that can't be read are returned with why.
+pub fn plan(raw: &[Value]) -> (Vec<Migration>, Vec<(String, Report)>) {
+    let ids: HashMap<String, String> = raw
+        .iter()
+        .filter_map(|v| v["id"].as_str())
+        .filter(|id| is_legacy_id(id))
+        .map(|id| (id.to_owned(), DocumentId::generate().into()))
+        .collect();
+    let renamed = |id: &mut String| {
+        if let Some(new) = ids.get(id.as_str()) {
+            *id = new.clone();
+        }
+    };
+
+    let mut migrations = Vec::new();
+    let mut failed = Vec::new();
+    for before in raw {
+        let old_id = before["id"].as_str().unwrap_or_default().to_owned();
+        let mut after: Document = match serde_json::from_value(before.clone()) {
+            Ok(doc) => doc,
+            Err(e) => {
+                failed.push((old_id, e.into()));
+                continue;
+            }
+        };
+        after.serialization_type = SerializationType::Storage;
+        renamed(&mut after.id);
+        renamed(&mut after.parentid);
+        renamed(&mut after.origid);
+        after.links.iter_mut().for_each(&renamed);
+        after.backlinks.iter_mut().for_each(&renamed);
+        match serde_json::to_value(&after) {
+            Ok(value) if &value == before => {}
+            Ok(_) => migrations.push(Migration {
+                old_id,
+                before: before.clone(),
+                after,
+            }),
+            Err(e) => failed.push((old_id, e.into())),
+        }
+    }
+    (migrations, failed)
+}
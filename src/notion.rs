@@ -0,0 +1,348 @@
+use crate::attachment::{self, Attachment};
+use crate::date::Date;
+use crate::document::{Document, DocumentId};
+use crate::import::{self, Converted};
+use crate::slug;
+use chrono::{NaiveDate, NaiveDateTime};
+use color_eyre::Report;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// How Notion writes dates in page properties, e.g. `September 13, 2020 12:26 PM`
+const PROPERTY_DATE: &str = "%B %e, %Y %l:%M %p";
+
+/// A file in the export
+struct Entry {
+    /// Its path in the archive
+    name: String,
+    bytes: Vec<u8>,
+    modified: Option<Date>,
+}
+
+/// The 32 hex digit id Notion ends a page's file name with, e.g. `Ideas 0123...cdef.md`
+fn page_id(name: &str) -> Option<&str> {
+    let stem = name.rsplit('/').next()?;
+    let stem = stem.strip_suffix(".md").unwrap_or(stem);
+    let id = stem.get(stem.len().checked_sub(32)?..)?;
+    if id.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// `s` with its %XX escapes decoded
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The archive path `target` refers to from a page in the directory `dir`
+fn resolve(dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// The directory of the archive path `name`, empty at the top
+fn parent(name: &str) -> &str {
+    name.rfind('/').map_or("", |i| &name[..i])
+}
+
+/// The files in the zip archive at `path`
+fn entries(path: &Path) -> Result<Vec<Entry>, Report> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let m = file.last_modified();
+        let modified = NaiveDate::from_ymd_opt(m.year().into(), m.month().into(), m.day().into())
+            .and_then(|d| d.and_hms_opt(m.hour().into(), m.minute().into(), m.second().into()))
+            .and_then(|d| Date::from_naive(&d));
+        entries.push(Entry {
+            name: file.name().to_owned(),
+            bytes,
+            modified,
+        });
+    }
+    Ok(entries)
+}
+
+/// The property lines `Key: Value` after the title of a database page, up to the first blank
+/// line, and the lines after them
+fn properties<'a>(lines: &[&'a str]) -> (Vec<(&'a str, &'a str)>, usize) {
+    let mut props = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        match line.split_once(": ") {
+            Some((key, value)) if !key.is_empty() => props.push((key.trim(), value.trim())),
+            _ if line.trim().is_empty() => return (props, i + 1),
+            _ => return (Vec::new(), 0),
+        }
+    }
+    (props, lines.len())
+}
+
+/// The pages of the Notion "Markdown & CSV" export zip at `path`, with the files they link to
+/// stored in the attachments directory `attachments`. Each page keeps its Notion id, is
+/// titled by its heading and links to the pages and attachments it links to. Pages of a
+/// database get its Tags as tags, its Created time as their date and every other property as
+/// a field named after it, e.g. notion_status for Status.
+pub fn read(path: &Path, attachments: &Path) -> Result<Converted, Report> {
+    let entries = entries(path)?;
+    let names: HashSet<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+    let pages: HashSet<&str> = entries
+        .iter()
+        .filter(|e| e.name.ends_with(".md"))
+        .filter_map(|e| page_id(&e.name))
+        .collect();
+    let mut converted = Converted::default();
+    let mut stored: HashMap<String, Attachment> = HashMap::new();
+    let scratch = TempDir::new()?;
+
+    for entry in entries.iter().filter(|e| e.name.ends_with(".md")) {
+        let text = String::from_utf8_lossy(&entry.bytes);
+        let mut lines: Vec<&str> = text.lines().collect();
+        let file_title = entry.name.rsplit('/').next().unwrap_or_default();
+        let file_title = file_title.trim_end_matches(".md");
+        let title = match lines.first().and_then(|l| l.strip_prefix("# ")) {
+            Some(title) => {
+                lines.remove(0);
+                title.trim().to_owned()
+            }
+            None => match page_id(file_title) {
+                Some(id) => file_title[..file_title.len() - id.len()].trim().to_owned(),
+                None => file_title.to_owned(),
+            },
+        };
+        while lines.first().is_some_and(|l| l.trim().is_empty()) {
+            lines.remove(0);
+        }
+        let mut doc = Document {
+            id: match page_id(&entry.name) {
+                Some(id) => id.to_owned(),
+                None => DocumentId::generate().into(),
+            },
+            title,
+            date: entry.modified.clone().unwrap_or_else(Date::now),
+            ..Document::new()
+        };
+
+        // A database's pages are in the directory named as its table, and start with its
+        // properties
+        let dir = parent(&entry.name);
+        if names.contains(format!("{}.csv", dir).as_str()) {
+            let (props, body_start) = properties(&lines);
+            for (key, value) in props {
+                match key.to_lowercase().as_str() {
+                    "tags" | "tag" => doc.tags.extend(
+                        value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|t| !t.is_empty())
+                            .map(str::to_owned),
+                    ),
+                    "created" | "created time" => {
+                        match NaiveDateTime::parse_from_str(value, PROPERTY_DATE) {
+                            Ok(created) => {
+                                if let Some(date) = Date::from_naive(&created) {
+                                    doc.date = date;
+                                }
+                            }
+                            Err(_) => converted
+                                .unconverted
+                                .push(format!("{}: can't read the date {}", entry.name, value)),
+                        }
+                    }
+                    // Prefixed so a property such as Date can't clash with a note's own field
+                    key => {
+                        let field = format!("notion_{}", key.replace(' ', "_"));
+                        doc.extra.insert(field, value.to_owned().into());
+                    }
+                }
+            }
+            lines.drain(..body_start);
+        }
+
+        let body = lines.join("\n");
+        let body = import::rewrite_links(&body, |target| {
+            if target.starts_with("http://") || target.starts_with("https://") {
+                // Notion links its own pages by their web address, ending in the id
+                let id = page_id(target.trim_end_matches('/'))?;
+                if !target.contains("notion.so/") || !pages.contains(id) {
+                    return None;
+                }
+                doc.links.push(id.to_owned());
+                return Some(id.to_owned());
+            }
+            let decoded = percent_decode(target);
+            if decoded.ends_with(".md") {
+                match page_id(&decoded).filter(|id| pages.contains(id)) {
+                    Some(id) => {
+                        doc.links.push(id.to_owned());
+                        return Some(id.to_owned());
+                    }
+                    None => {
+                        converted.unconverted.push(format!(
+                            "{}: link to {} isn't in the export",
+                            entry.name, decoded
+                        ));
+                        return None;
+                    }
+                }
+            }
+            let file = resolve(dir, &decoded);
+            let found = match stored.get(&file) {
+                Some(attachment) => Some(attachment.clone()),
+                None => entries.iter().find(|e| e.name == file).and_then(|e| {
+                    let name = file.rsplit('/').next()?.to_owned();
+                    let copy = scratch.path().join(stored.len().to_string());
+                    fs::write(&copy, &e.bytes).ok()?;
+                    let attachment = attachment::store_as(attachments, &copy, &name).ok()?;
+                    stored.insert(file.clone(), attachment.clone());
+                    Some(attachment)
+                }),
+            };
+            match found {
+                Some(attachment) => {
+                    let name = attachment.name().to_owned();
+                    if !doc.attachments.contains(&attachment) {
+                        doc.attachments.push(attachment);
+                    }
+                    Some(name)
+                }
+                None => {
+                    converted.unconverted.push(format!(
+                        "{}: link to {} isn't in the export",
+                        entry.name, decoded
+                    ));
+                    None
+                }
+            }
+        });
+        doc.body = format!("{}\n", body.trim_end());
+        slug::fill(&mut doc);
+        doc.filename = format!("{}.md", doc.slug.replace('/', "-"));
+        converted.docs.push(doc);
+    }
+
+    for entry in &entries {
+        if entry.name.ends_with(".csv") {
+            converted.unconverted.push(format!(
+                "{}: a database table, only its pages are imported",
+                entry.name
+            ));
+        } else if !entry.name.ends_with(".md") && !stored.contains_key(&entry.name) {
+            converted
+                .unconverted
+                .push(format!("{}: no page links to it", entry.name));
+        }
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn page_ids_end_file_names_and_addresses() {
+        let table = [
+            (format!("Ideas {}.md", ID), Some(ID)),
+            (format!("Notes/Ideas {}.md", ID), Some(ID)),
+            (format!("Ideas {}", ID), Some(ID)),
+            (format!("https://www.notion.so/Ideas-{}", ID), Some(ID)),
+            ("Ideas.md".to_owned(), None),
+            (format!("Ideas {}.md", ID.replace('a', "x")), None),
+        ];
+        for (name, id) in table.iter() {
+            assert_eq!(page_id(name), *id, "id of {}", name);
+        }
+    }
+
+    #[test]
+    fn percent_escapes_decode() {
+        assert_eq!(percent_decode("My%20Page%20%C3%A9.md"), "My Page é.md");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("50%zz"), "50%zz");
+    }
+
+    #[test]
+    fn targets_resolve_against_the_page_directory() {
+        assert_eq!(resolve("Notes/Ideas", "image.png"), "Notes/Ideas/image.png");
+        assert_eq!(
+            resolve("Notes/Ideas", "../Other/a.png"),
+            "Notes/Other/a.png"
+        );
+        assert_eq!(resolve("", "./a/b.png"), "a/b.png");
+        assert_eq!(parent("Notes/Ideas/page.md"), "Notes/Ideas");
+        assert_eq!(parent("page.md"), "");
+    }
+
+    #[test]
+    fn properties_run_until_the_first_blank_line() {
+        let lines = [
+            "Tags: rust, cli",
+            "Created: September 13, 2020 12:26 PM",
+            "",
+            "Body: not a property",
+        ];
+        assert_eq!(
+            properties(&lines),
+            (
+                vec![
+                    ("Tags", "rust, cli"),
+                    ("Created", "September 13, 2020 12:26 PM")
+                ],
+                3
+            )
+        );
+        assert_eq!(properties(&["Status: Done"]), (vec![("Status", "Done")], 1));
+        assert_eq!(properties(&["Plain text", "", "More"]), (vec![], 0));
+    }
+
+    #[test]
+    fn property_dates_parse() {
+        let created =
+            NaiveDateTime::parse_from_str("September 13, 2020 12:26 PM", PROPERTY_DATE).unwrap();
+        assert_eq!(created, NaiveDate::from_ymd(2020, 9, 13).and_hms(12, 26, 0));
+        let created =
+            NaiveDateTime::parse_from_str("March 3, 2021 9:05 AM", PROPERTY_DATE).unwrap();
+        assert_eq!(created, NaiveDate::from_ymd(2021, 3, 3).and_hms(9, 5, 0));
+    }
+}
@@ -0,0 +1,45 @@
+use crate::style;
+use color_eyre::Report;
+use eyre::bail;
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Rows `text` takes up on a terminal `width` columns wide, long lines wrapping
+fn rows(text: &str, width: usize) -> usize {
+    text.lines()
+        .map(|line| line.chars().count().div_ceil(width).max(1))
+        .sum()
+}
+
+/// Print `text`, through `pager` when stdout is a terminal too short to show it all, as git
+/// does. Like git, less is told to keep colours and not clear the screen on exit unless $LESS
+/// says otherwise.
+pub fn print(pager: &str, text: &str) -> Result<(), Report> {
+    let (width, height) = match style::terminal_size() {
+        Some((w, h)) => (w.max(1) as usize, h as usize),
+        None => {
+            print!("{}", text);
+            return Ok(());
+        }
+    };
+    if rows(text, width) < height {
+        print!("{}", text);
+        return Ok(());
+    }
+
+    let mut args = pager.split_whitespace();
+    let mut cmd = Command::new(args.next().unwrap_or("less"));
+    cmd.args(args).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        cmd.env("LESS", "FRX");
+    }
+    let mut child = cmd.spawn()?;
+    // Quitting the pager early closes its stdin, which is fine
+    let _ = child.stdin.take().unwrap().write_all(text.as_bytes());
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("❌ {} exited with {}", pager, status);
+    }
+    Ok(())
+}
@@ -1,65 +1,199 @@
-use crate::{api, document};
+use crate::client::Client;
+use crate::{api, document, table};
 use color_eyre::Report;
-use eyre::bail;
-use reqwest::header::CONTENT_TYPE;
-use url::Url;
+use eyre::eyre;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::Write;
+use std::str::FromStr;
 
-pub fn query(
-    client: reqwest::blocking::Client,
-    uri: Url,
+/// How `static-query` prints the matches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Aligned columns fitted to the terminal
+    Table,
+    /// The documents' debug representation, as older versions printed
+    Debug,
+    /// One JSON document per line
+    Ndjson,
+}
+
+impl FromStr for Output {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Output, Self::Err> {
+        match s {
+            "table" => Ok(Output::Table),
+            "debug" => Ok(Output::Debug),
+            "ndjson" => Ok(Output::Ndjson),
+            _ => Err(eyre!(
+                "❌ Unknown output {}, expected table, debug or ndjson",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MultiSearchResponse {
+    results: Vec<IndexResults>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexResults {
+    index_uid: String,
+    // Other indexes needn't follow the Document schema
+    hits: Vec<Value>,
+}
+
+/// What `static-query` searches the notes index for
+#[derive(Debug)]
+pub struct Search {
+    pub query: String,
+    pub filter: String,
+    pub include_archived: bool,
+    /// Page through every match rather than taking the first response's
+    pub all: bool,
+}
+
+impl Search {
+    fn api_query(&self) -> api::ApiQuery {
+        let mut q = api::ApiQuery::new();
+        q.query = Some(self.query.clone());
+
+        q.process_filter(self.filter.clone());
+        if !self.include_archived {
+            q.exclude_archived();
+        }
+        q
+    }
+
+    /// Hand the matches to `each` a page at a time, stopping after the first unless `all`
+    async fn pages<F>(&self, client: &Client, mut each: F) -> Result<(), Report>
+    where
+        F: FnMut(Vec<document::Document>) -> Result<(), Report>,
+    {
+        let mut pages = api::Pages::search(client, self.api_query());
+        while let Some(page) = pages.next().await? {
+            each(page)?;
+            if !self.all {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A document as a line of JSON, with every stored field
+fn json_line(mut doc: document::Document) -> Result<String, Report> {
+    doc.serialization_type = document::SerializationType::Storage;
+    Ok(format!("{}\n", serde_json::to_string(&doc)?))
+}
+
+/// Run `search`, returning the matches formatted as `output` for printing. A table is fitted
+/// to `width` columns, if given, and coloured when `color`.
+pub async fn query(
+    client: &Client,
+    search: &Search,
+    output: Output,
+    width: Option<usize>,
+    color: bool,
+) -> Result<String, Report> {
+    let mut hits = Vec::new();
+    search
+        .pages(client, |page| {
+            hits.extend(page);
+            Ok(())
+        })
+        .await?;
+    match output {
+        Output::Table => return Ok(table::render(&hits, width, color)),
+        Output::Ndjson => return hits.into_iter().map(json_line).collect(),
+        Output::Debug => {}
+    }
+    Ok(format!(
+        "Matches: {:?}\n",
+        hits.iter_mut()
+            .map(|mut m| {
+                m.serialization_type = document::SerializationType::Human;
+                m.to_owned()
+            })
+            .collect::<Vec<_>>()
+    ))
+}
+
+/// Run `search`, writing each match to `out` as a line of JSON as its page arrives instead of
+/// holding every match, so exports of any size run in little memory. Returns how many were
+/// written.
+pub async fn stream(
+    client: &Client,
+    search: &Search,
+    out: &mut dyn Write,
+) -> Result<usize, Report> {
+    let mut written = 0;
+    search
+        .pages(client, |page| {
+            for doc in page {
+                out.write_all(json_line(doc)?.as_bytes())?;
+                written += 1;
+            }
+            Ok(())
+        })
+        .await?;
+    Ok(written)
+}
+
+/// Search several indexes in one request, returning each hit labelled with its index. The
+/// filter grammar and date sort assume the notes schema, so only the notes index gets the sort,
+/// and archived notes left out.
+pub async fn multi_query(
+    client: &Client,
+    indexes: &[String],
     query_input: String,
     filter_input: String,
-) -> Result<(), Report> {
+    include_archived: bool,
+) -> Result<String, Report> {
     let mut q = api::ApiQuery::new();
     q.query = Some(query_input);
-
     q.process_filter(filter_input);
 
-    // Split up the JSON decoding into two steps.
-    // 1.) Get the text of the body.
-    let response_body = match client
-        .post(uri.as_ref())
-        .body::<String>(serde_json::to_string(&q).unwrap())
-        .header(CONTENT_TYPE, "application/json")
-        .send()
-    {
-        Ok(resp) => {
-            if !resp.status().is_success() {
-                bail!("Request failed: {:?}", resp);
-            }
-            match resp.text() {
-                Ok(text) => text,
-                Err(e) => {
-                    bail!("resp.text() failed: {:?}", e);
-                }
-            }
+    let mut queries = Vec::new();
+    for index in indexes {
+        let mut query = serde_json::to_value(&q)?;
+        query["indexUid"] = Value::from(index.as_str());
+        if index != client.index() {
+            query.as_object_mut().unwrap().remove("sort");
+        } else if !include_archived {
+            query["filter"] = Value::from(api::without_archived(q.filter.as_deref()));
         }
-        Err(e) => {
-            bail!("Send failed: {:?}", e);
-        }
-    };
-
-    // 2.) Parse the results as JSON.
-    match serde_json::from_str::<api::ApiResponse>(&response_body) {
-        Ok(mut resp) => {
-            println!(
-                "Matches: {:?}",
-                resp.hits
-                    .iter_mut()
-                    .map(|mut m| {
-                        m.serialization_type = document::SerializationType::Human;
-                        m.to_owned()
-                    })
-                    .collect::<Vec<_>>()
-            );
+        queries.push(query);
+    }
+    let resp: MultiSearchResponse = client
+        .send(
+            client
+                .post(client.url("multi-search")?)
+                .json(&serde_json::json!({ "queries": queries })),
+        )
+        .await?;
+
+    let mut out = String::new();
+    for results in resp.results {
+        for hit in results.hits {
+            out.push_str(&format!("[{}] {}\n", results.index_uid, label(&hit)));
         }
-        Err(e) => {
-            bail!(
-                "Could not deserialize body from: {}; error: {:?}",
-                response_body,
-                e
-            )
+    }
+    Ok(out)
+}
+
+/// Something recognisable to show for a hit of unknown schema
+fn label(hit: &Value) -> String {
+    for field in &["title", "name", "url", "id"] {
+        match &hit[field] {
+            Value::String(s) => return s.to_owned(),
+            Value::Number(n) => return n.to_string(),
+            _ => {}
         }
-    };
-    Ok(())
+    }
+    hit.to_string()
 }
@@ -0,0 +1,244 @@
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use url::Url;
+
+/// What secrets in recorded bodies are replaced with
+const REDACTED: &str = "[redacted]";
+
+/// Fields of request and response bodies holding secrets, e.g. the keys `auth keys` lists
+const SECRET_FIELDS: &[&str] = &["key", "apiKey", "masterKey", "password", "token"];
+
+/// A request or response body, kept as JSON when it parses so the files read well
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+struct Body {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+impl Body {
+    fn new(bytes: &[u8]) -> Body {
+        if bytes.is_empty() {
+            return Body::default();
+        }
+        match serde_json::from_slice::<Value>(bytes) {
+            Ok(mut json) => {
+                redact(&mut json);
+                Body {
+                    json: Some(json),
+                    text: None,
+                }
+            }
+            Err(_) => Body {
+                json: None,
+                text: Some(String::from_utf8_lossy(bytes).into_owned()),
+            },
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match (&self.json, &self.text) {
+            (Some(json), _) => json.to_string().into_bytes(),
+            (None, Some(text)) => text.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+/// Replace the value of every secret field in `json`, however deeply nested
+fn redact(json: &mut Value) {
+    match json {
+        Value::Object(map) => {
+            for (field, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&field.as_str()) && value.is_string() {
+                    *value = Value::from(REDACTED);
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => (),
+    }
+}
+
+/// A request as sent, without its headers so the API key is never written out
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Sent {
+    method: String,
+    /// Relative to the host, so a recording replays whatever host it was made against
+    path: String,
+    #[serde(flatten)]
+    body: Body,
+}
+
+impl Sent {
+    pub fn new(host: &Url, request: &Request) -> Sent {
+        let url = request.url().as_str();
+        Sent {
+            method: request.method().to_string(),
+            path: url.strip_prefix(host.as_str()).unwrap_or(url).to_owned(),
+            body: Body::new(
+                request
+                    .body()
+                    .and_then(|b| b.as_bytes())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Received {
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(flatten)]
+    body: Body,
+}
+
+/// One request and the response to it, saved as a numbered file
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Exchange {
+    request: Sent,
+    response: Received,
+}
+
+impl Exchange {
+    fn response(&self) -> Result<Response, Report> {
+        let mut builder = http::Response::builder().status(self.response.status);
+        if let Some(content_type) = &self.response.content_type {
+            builder = builder.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        Ok(Response::from(builder.body(self.response.body.bytes())?))
+    }
+}
+
+/// Every request and response saved to a directory with `--record`, or served back from one
+/// with `--replay` in place of the server
+#[derive(Debug)]
+pub enum Recording {
+    Record {
+        dir: PathBuf,
+        /// Number of the next file written
+        next: AtomicUsize,
+    },
+    Replay {
+        dir: PathBuf,
+        /// The recorded exchanges in order, each taken once it has been served
+        exchanges: Mutex<Vec<Option<Exchange>>>,
+    },
+}
+
+/// The file exchange number `n` is saved to, e.g. 0001.json
+fn file(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("{:04}.json", n))
+}
+
+impl Recording {
+    /// Record into `dir`, created if need be. It must not hold a recording already, which the
+    /// new one would be mixed into.
+    pub fn record(dir: PathBuf) -> Result<Recording, Report> {
+        fs::create_dir_all(&dir)?;
+        if file(&dir, 1).exists() {
+            bail!("❌ {} already holds a recording", dir.display());
+        }
+        Ok(Recording::Record {
+            dir,
+            next: AtomicUsize::new(1),
+        })
+    }
+
+    /// Replay the recording in `dir`
+    pub fn replay(dir: PathBuf) -> Result<Recording, Report> {
+        let mut exchanges = Vec::new();
+        for n in 1.. {
+            let path = file(&dir, n);
+            if !path.exists() {
+                break;
+            }
+            let exchange: Exchange = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| eyre!("❌ Failed to read {}: {}", path.display(), e))?;
+            exchanges.push(Some(exchange));
+        }
+        if exchanges.is_empty() {
+            bail!("❌ No recording in {}", dir.display());
+        }
+        Ok(Recording::Replay {
+            dir,
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Recording::Replay { .. })
+    }
+
+    /// The recorded response to the first exchange not yet served that matches `sent`: by
+    /// method, path and body if one does, else by method and path alone, since what was sent
+    /// can change between runs, e.g. with the date.
+    pub fn replayed(&self, sent: &Sent) -> Result<Response, Report> {
+        let (dir, exchanges) = match self {
+            Recording::Replay { dir, exchanges } => (dir, exchanges),
+            Recording::Record { .. } => bail!("❌ Not replaying a recording"),
+        };
+        let mut exchanges = exchanges.lock().unwrap();
+        let same = |e: &Option<Exchange>| {
+            e.as_ref()
+                .is_some_and(|e| e.request.method == sent.method && e.request.path == sent.path)
+        };
+        let found = exchanges
+            .iter()
+            .position(|e| same(e) && e.as_ref().unwrap().request.body == sent.body)
+            .or_else(|| exchanges.iter().position(same));
+        match found.and_then(|n| exchanges[n].take()) {
+            Some(exchange) => exchange.response(),
+            None => bail!(
+                "❌ {} has no recorded response left for {} {}",
+                dir.display(),
+                sent.method,
+                sent.path
+            ),
+        }
+    }
+
+    /// Save `sent` and the response to it, returning the response to be read as usual
+    pub async fn recorded(&self, sent: Sent, resp: Response) -> Result<Response, Report> {
+        let (dir, next) = match self {
+            Recording::Record { dir, next } => (dir, next),
+            Recording::Replay { .. } => return Ok(resp),
+        };
+        let status = resp.status();
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+        let bytes = resp.bytes().await?;
+        let exchange = Exchange {
+            request: sent,
+            response: Received {
+                status: status.as_u16(),
+                content_type,
+                body: Body::new(&bytes),
+            },
+        };
+        let n = next.fetch_add(1, Ordering::SeqCst);
+        fs::write(file(dir, n), serde_json::to_vec_pretty(&exchange)?)?;
+
+        // Hand back the body as received rather than the redacted copy
+        let mut builder = http::Response::builder().status(status);
+        if let Some(content_type) = &exchange.response.content_type {
+            builder = builder.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        Ok(Response::from(builder.body(bytes)?))
+    }
+}
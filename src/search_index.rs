@@ -0,0 +1,153 @@
+use crate::crypt;
+use crate::document::Document;
+use crate::graph::xml_escape;
+use color_eyre::Report;
+use eyre::eyre;
+use serde::Serialize;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Most characters of a note's body kept as its excerpt
+const EXCERPT_CHARS: usize = 200;
+
+/// Client-side search library an exported index is for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// A directory of one HTML page per note, for the pagefind CLI to index
+    Pagefind,
+    /// A JSON file of the records and the fields to index, for building a lunr index on load
+    Lunr,
+    /// A JSON array of the records, as Fuse.js takes them
+    Fuse,
+}
+
+impl FromStr for Format {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Format, Self::Err> {
+        match s {
+            "pagefind" => Ok(Format::Pagefind),
+            "lunr" => Ok(Format::Lunr),
+            "fuse" => Ok(Format::Fuse),
+            _ => Err(eyre!(
+                "❌ Unknown search index format {}, expected pagefind, lunr or fuse",
+                s
+            )),
+        }
+    }
+}
+
+/// What a published note exposes to search, nothing more of it leaves the server
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub excerpt: String,
+    pub slug: String,
+}
+
+impl From<&Document> for Record {
+    fn from(doc: &Document) -> Record {
+        Record {
+            title: doc.title.clone(),
+            tags: doc.tags.clone(),
+            // Encrypted bodies would only leak ciphertext
+            excerpt: if crypt::is_encrypted(&doc.body) {
+                String::new()
+            } else {
+                excerpt(&doc.body)
+            },
+            slug: doc.slug.clone(),
+        }
+    }
+}
+
+/// The start of `body` as plain words, without markdown headings, emphasis, code fences or
+/// link targets, cut at a word boundary
+pub fn excerpt(body: &str) -> String {
+    let mut words = Vec::new();
+    let mut len = 0;
+    let mut fenced = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+            continue;
+        }
+        if fenced {
+            continue;
+        }
+        for word in line.split_whitespace() {
+            let word = match word.find("](") {
+                Some(i) => &word[..i],
+                None => word,
+            };
+            let word: String = word.chars().filter(|c| !matches!(c, '*' | '`')).collect();
+            let word = word.trim_matches(&['#', '_', '>', '[', ']'][..]).to_owned();
+            if word.is_empty() {
+                continue;
+            }
+            let chars = word.chars().count();
+            if len + chars > EXCERPT_CHARS {
+                return format!("{}…", words.join(" "));
+            }
+            len += chars + 1;
+            words.push(word);
+        }
+    }
+    words.join(" ")
+}
+
+/// Write a search bundle of `docs` in `format` to `out`, a directory for pagefind and a file
+/// otherwise. Notes without a usable slug have no URL to link to and are left out. Returns how
+/// many records it holds.
+pub fn export(format: Format, docs: &[Document], out: &Path) -> Result<usize, Report> {
+    let records: Vec<Record> = docs
+        .iter()
+        .filter(|d| !d.slug.is_empty() && !d.slug.split('/').any(|part| part == ".."))
+        .map(Record::from)
+        .collect();
+    match format {
+        Format::Fuse => fs::write(out, serde_json::to_string(&records)?)?,
+        Format::Lunr => fs::write(
+            out,
+            serde_json::to_string(&json!({
+                "ref": "slug",
+                "fields": ["title", "tags", "excerpt"],
+                "documents": records,
+            }))?,
+        )?,
+        Format::Pagefind => {
+            for record in &records {
+                let dir = out.join(&record.slug);
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join("index.html"), page(record))?;
+            }
+        }
+    }
+    Ok(records.len())
+}
+
+/// A page pagefind indexes for `record`, with its tags as a filter. Its path gives the URL.
+fn page(record: &Record) -> String {
+    let tags: String = record
+        .tags
+        .iter()
+        .map(|t| {
+            format!(
+                "<span data-pagefind-filter=\"tag\">{}</span>\n",
+                xml_escape(t)
+            )
+        })
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+         <body data-pagefind-body>\n<h1 data-pagefind-meta=\"title\">{title}</h1>\n\
+         {tags}<p>{excerpt}</p>\n\
+         </body>\n</html>\n",
+        title = xml_escape(&record.title),
+        tags = tags,
+        excerpt = xml_escape(&record.excerpt),
+    )
+}
@@ -0,0 +1,264 @@
+use crate::client::Client;
+use crate::error::RequestFailed;
+use color_eyre::Report;
+use eyre::bail;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+struct Health {
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Version {
+    pub pkg_version: String,
+    #[serde(default)]
+    pub commit_sha: String,
+    #[serde(default)]
+    pub commit_date: String,
+}
+
+/// Server wide stats, `indexes` maps each index uid to its own stats
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    /// Bytes on disk taken by every index
+    pub database_size: u64,
+    #[serde(default)]
+    pub last_update: Option<String>,
+    pub indexes: BTreeMap<String, IndexStats>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    pub number_of_documents: u64,
+    pub is_indexing: bool,
+    /// Number of documents each field appears in
+    pub field_distribution: BTreeMap<String, u64>,
+}
+
+/// Print the stats of the notes index `uid` along with the database size
+pub fn print_stats(stats: &Stats, uid: &str) -> Result<(), Report> {
+    let index = match stats.indexes.get(uid) {
+        Some(i) => i,
+        None => bail!("❌ No {} index, create it with `mz init`", uid),
+    };
+    println!("Documents:     {}", index.number_of_documents);
+    println!("Database size: {}", human_bytes(stats.database_size));
+    println!("Indexing:      {}", index.is_indexing);
+    if let Some(last_update) = &stats.last_update {
+        println!("Last update:   {}", last_update);
+    }
+    println!("Fields:");
+    let width = index
+        .field_distribution
+        .keys()
+        .map(|f| f.len())
+        .max()
+        .unwrap_or(0);
+    for (field, count) in &index.field_distribution {
+        println!("  {:width$}  {}", field, count, width = width);
+    }
+    Ok(())
+}
+
+/// Format a byte count with a binary unit, e.g. 1.5 MiB
+fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// An asynchronous write, e.g. a document addition or settings update
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub uid: u64,
+    #[serde(default)]
+    pub index_uid: Option<String>,
+    /// One of enqueued, processing, succeeded or failed
+    pub status: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub details: Option<Value>,
+    #[serde(default)]
+    pub error: Option<Value>,
+    #[serde(default)]
+    pub enqueued_at: Option<String>,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tasks {
+    results: Vec<Task>,
+}
+
+impl Task {
+    /// Machine readable reason the task failed, e.g. index_already_exists
+    pub fn error_code(&self) -> Option<&str> {
+        self.error.as_ref().and_then(|e| e["code"].as_str())
+    }
+
+    /// Why the task failed, empty unless it did
+    pub fn error_message(&self) -> String {
+        match &self.error {
+            Some(e) => match e["message"].as_str() {
+                Some(m) => m.to_owned(),
+                None => e.to_string(),
+            },
+            None => String::from(""),
+        }
+    }
+
+    /// Name of the dump a dumpCreation task wrote, once it has succeeded
+    pub fn dump_uid(&self) -> Option<&str> {
+        self.details.as_ref().and_then(|d| d["dumpUid"].as_str())
+    }
+}
+
+/// One line per task, failed ones followed by the reason
+pub fn print_tasks(tasks: &[Task]) {
+    for task in tasks {
+        println!(
+            "{:>6}  {:<10}  {:<24}  {:<8}  {}",
+            task.uid,
+            task.status,
+            task.kind,
+            task.index_uid.as_deref().unwrap_or("-"),
+            task.finished_at
+                .as_deref()
+                .or(task.enqueued_at.as_deref())
+                .unwrap_or("")
+        );
+        if task.status == "failed" {
+            println!("        ❌ {}", task.error_message());
+        }
+    }
+}
+
+/// An API key and what it grants access to
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Key {
+    #[serde(default, skip_serializing)]
+    pub key: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Permitted actions, e.g. search or documents.add, `*` for all
+    pub actions: Vec<String>,
+    /// Index uids the key works on, `*` for all
+    pub indexes: Vec<String>,
+    /// RFC 3339 expiry, None for never
+    pub expires_at: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Keys {
+    results: Vec<Key>,
+}
+
+/// The secret followed by its description and grants
+pub fn print_key(key: &Key) {
+    println!("{}", key.key);
+    if let Some(description) = &key.description {
+        println!("  {}", description);
+    }
+    println!("  actions: {}", key.actions.join(", "));
+    println!("  indexes: {}", key.indexes.join(", "));
+    if let Some(expires_at) = &key.expires_at {
+        println!("  expires: {}", expires_at);
+    }
+}
+
+impl Client {
+    /// Check the server is available, returning its version
+    pub async fn health(&self) -> Result<Version, Report> {
+        let health: Health = self.send(self.get(self.url("health")?)).await?;
+        if health.status != "available" {
+            return Err(RequestFailed(format!("❌ Meilisearch is {}", health.status)).into());
+        }
+        self.send(self.get(self.url("version")?)).await
+    }
+
+    pub async fn stats(&self) -> Result<Stats, Report> {
+        self.send(self.get(self.url("stats")?)).await
+    }
+
+    pub async fn task(&self, uid: u64) -> Result<Task, Report> {
+        self.send(self.get(self.url(&format!("tasks/{}", uid))?))
+            .await
+    }
+
+    /// Every task the server remembers, newest first, optionally only those with `status`
+    pub async fn tasks(&self, status: Option<&str>) -> Result<Vec<Task>, Report> {
+        let tasks: Tasks = self.send(self.get(self.url("tasks")?)).await?;
+        // Filter here rather than with a query parameter, which older servers ignore
+        Ok(tasks
+            .results
+            .into_iter()
+            .filter(|t| status.is_none() || status == Some(t.status.as_str()))
+            .collect())
+    }
+
+    pub async fn keys(&self) -> Result<Vec<Key>, Report> {
+        let keys: Keys = self.send(self.get(self.url("keys")?)).await?;
+        Ok(keys.results)
+    }
+
+    /// Create a key, returning it with its generated secret
+    pub async fn create_key(&self, key: &Key) -> Result<Key, Report> {
+        self.send(self.post(self.url("keys")?).json(key)).await
+    }
+
+    pub async fn delete_key(&self, key: &str) -> Result<(), Report> {
+        let resp = self
+            .execute(self.delete_request(self.url(&format!("keys/{}", key))?))
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            return Err(RequestFailed(format!(
+                "❌ Failed to delete key with {}: {}",
+                status,
+                resp.text().await?
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Start a dump, which the server writes to its dumps directory, returning the uid of the
+    /// task writing it
+    pub async fn create_dump(&self) -> Result<u64, Report> {
+        self.enqueue(self.post(self.url("dumps")?)).await
+    }
+
+    /// Poll the task writing a dump until the server finishes it, however long that takes
+    pub async fn wait_for_dump(&self, uid: u64) -> Result<Task, Report> {
+        loop {
+            let task = self.task(uid).await?;
+            match task.status.as_str() {
+                "enqueued" | "processing" => tokio::time::sleep(Duration::from_secs(1)).await,
+                "failed" => bail!("❌ Dump task {} failed: {}", uid, task.error_message()),
+                _ => return Ok(task),
+            }
+        }
+    }
+}
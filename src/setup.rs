@@ -0,0 +1,119 @@
+use color_eyre::Report;
+use eyre::eyre;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use toml::Value;
+
+/// What `setup` asks for
+#[derive(Debug)]
+pub struct Answers {
+    pub host: String,
+    /// None to leave the stored key, if any, alone
+    pub key: Option<String>,
+    pub index: String,
+    pub editor: String,
+    pub pager: String,
+    pub notes_dir: String,
+}
+
+/// Ask `question`, answering `default` when the reply is empty
+pub fn ask(question: &str, default: &str) -> Result<String, Report> {
+    print!("{} [{}]: ", question, default);
+    io::stdout().flush()?;
+    let mut reply = String::new();
+    if io::stdin().read_line(&mut reply)? == 0 {
+        return Err(eyre!("❌ No answer to {}", question));
+    }
+    let reply = reply.trim();
+    Ok(if reply.is_empty() { default } else { reply }.to_owned())
+}
+
+/// Ask a yes or no `question`, answering `default` when the reply is empty
+pub fn confirm(question: &str, default: bool) -> Result<bool, Report> {
+    let reply = ask(question, if default { "Y/n" } else { "y/N" })?;
+    Ok(match reply.to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    })
+}
+
+/// A line from stdin without its newline, None at end of input
+pub fn read_line() -> Result<Option<String>, Report> {
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_owned()))
+}
+
+/// Ask for a secret, not echoing it unless it is piped in, or mz is built without the
+/// interactive feature and so can't turn echo off. None if the reply is empty.
+pub fn ask_secret(question: &str) -> Result<Option<String>, Report> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    print!("{}: ", question);
+    stdout.flush()?;
+    let secret = if stdin.is_terminal() && cfg!(feature = "interactive") {
+        let secret = read_passwd()?;
+        println!();
+        secret
+    } else {
+        read_line()?
+    };
+    Ok(secret
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty()))
+}
+
+#[cfg(feature = "interactive")]
+fn read_passwd() -> Result<Option<String>, Report> {
+    use termion::input::TermRead;
+    Ok(io::stdin().lock().read_passwd(&mut io::stdout())?)
+}
+
+#[cfg(not(feature = "interactive"))]
+fn read_passwd() -> Result<Option<String>, Report> {
+    read_line()
+}
+
+/// Write `answers` to the config file at `path`, keeping any other settings in it. Comments
+/// don't survive the rewrite.
+pub fn write_config(path: &Path, answers: &Answers) -> Result<(), Report> {
+    update_config(path, |table| {
+        for (key, value) in &[
+            ("host", &answers.host),
+            ("index", &answers.index),
+            ("editor", &answers.editor),
+            ("pager", &answers.pager),
+            ("notes_dir", &answers.notes_dir),
+        ] {
+            table.insert((*key).to_owned(), Value::String((*value).clone()));
+        }
+    })
+}
+
+/// Let `update` change the top-level table of the config file at `path`, creating the file if
+/// needed, and write it back. Comments don't survive the rewrite.
+pub fn update_config<F>(path: &Path, update: F) -> Result<(), Report>
+where
+    F: FnOnce(&mut toml::value::Table),
+{
+    let mut config = match fs::read_to_string(path) {
+        Ok(s) => s
+            .parse::<Value>()
+            .map_err(|e| eyre!("❌ Failed to parse config {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Value::Table(Default::default()),
+        Err(e) => return Err(eyre!("❌ Failed to read config {}: {}", path.display(), e)),
+    };
+    let table = config
+        .as_table_mut()
+        .ok_or_else(|| eyre!("❌ Config {} is not a table", path.display()))?;
+    update(table);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string(&config)?)?;
+    Ok(())
+}
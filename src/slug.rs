@@ -0,0 +1,151 @@
+use crate::document::Document;
+use std::collections::BTreeMap;
+use unicode_normalization::UnicodeNormalization;
+
+/// Letters that don't decompose into an ASCII letter and a mark
+const SPELLED: &[(char, &str)] = &[
+    ('ß', "ss"),
+    ('æ', "ae"),
+    ('œ', "oe"),
+    ('ø', "o"),
+    ('ł', "l"),
+    ('đ', "d"),
+    ('ð', "d"),
+    ('þ', "th"),
+];
+
+/// `title` as lowercase ASCII words joined by dashes, accents dropped, e.g. `Crème brûlée, 2nd
+/// try` as `creme-brulee-2nd-try`. Empty if nothing in it transliterates.
+pub fn slugify(title: &str) -> String {
+    let mut ascii = String::new();
+    for c in title.to_lowercase().nfkd() {
+        if c.is_ascii_alphanumeric() {
+            ascii.push(c);
+        } else if let Some((_, spelled)) = SPELLED.iter().find(|(s, _)| *s == c) {
+            ascii.push_str(spelled);
+        } else if !c.is_alphanumeric() && !is_mark(c) {
+            ascii.push(' ');
+        }
+    }
+    ascii.split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+/// Combining marks left by decomposing accented letters
+fn is_mark(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}')
+}
+
+/// Give `doc` a slug from its title if it has none, or its id if the title has nothing to make
+/// one from
+pub fn fill(doc: &mut Document) {
+    if doc.slug.is_empty() {
+        doc.slug = slugify(&doc.title);
+    }
+    if doc.slug.is_empty() {
+        doc.slug = doc.id.clone();
+    }
+}
+
+/// Title `doc` `title` and give it the slug made from that, renaming its file to match if it
+/// was named after the old slug
+pub fn retitle(doc: &mut Document, title: &str) {
+    let named_after_slug = doc.filename == format!("{}.md", doc.slug.replace('/', "-"));
+    doc.title = title.to_owned();
+    doc.slug.clear();
+    fill(doc);
+    if named_after_slug {
+        doc.filename = format!("{}.md", doc.slug.replace('/', "-"));
+    }
+}
+
+/// Slugs shared by more than one of `docs`, with the documents sharing each
+pub fn collisions(docs: &[Document]) -> BTreeMap<&str, Vec<&Document>> {
+    let mut by_slug: BTreeMap<&str, Vec<&Document>> = BTreeMap::new();
+    for doc in docs.iter().filter(|d| !d.slug.is_empty()) {
+        by_slug.entry(&doc.slug).or_default().push(doc);
+    }
+    by_slug.retain(|_, docs| docs.len() > 1);
+    by_slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_transliterate_to_kebab_case() {
+        let table = [
+            ("Crème brûlée, 2nd try", "creme-brulee-2nd-try"),
+            (
+                "  Rust: Ownership & Borrowing! ",
+                "rust-ownership-borrowing",
+            ),
+            ("Straße in Ærøskøbing", "strasse-in-aeroskobing"),
+            ("ﬁle under ½", "file-under-1-2"),
+            ("already-a-slug", "already-a-slug"),
+            ("日本語", ""),
+            ("", ""),
+        ];
+        for (title, slug) in table.iter() {
+            assert_eq!(slugify(title), *slug, "slugifying {}", title);
+        }
+    }
+
+    fn doc(id: &str, title: &str, slug: &str) -> Document {
+        Document {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            slug: slug.to_owned(),
+            ..Document::new()
+        }
+    }
+
+    #[test]
+    fn fill_keeps_a_slug_and_falls_back_to_the_id() {
+        let mut kept = doc("1", "Title", "custom/slug");
+        fill(&mut kept);
+        assert_eq!(kept.slug, "custom/slug");
+
+        let mut filled = doc("2", "New title", "");
+        fill(&mut filled);
+        assert_eq!(filled.slug, "new-title");
+
+        let mut untitled = doc("3", "日本語", "");
+        fill(&mut untitled);
+        assert_eq!(untitled.slug, "3");
+    }
+
+    #[test]
+    fn retitle_renames_files_named_after_the_slug_only() {
+        let mut named = doc("1", "Old", "old");
+        named.filename = "old.md".into();
+        retitle(&mut named, "New");
+        assert_eq!(
+            (named.slug.as_str(), named.filename.as_str()),
+            ("new", "new.md")
+        );
+
+        let mut custom = doc("2", "Old", "old");
+        custom.filename = "mine.md".into();
+        retitle(&mut custom, "New");
+        assert_eq!(
+            (custom.slug.as_str(), custom.filename.as_str()),
+            ("new", "mine.md")
+        );
+    }
+
+    #[test]
+    fn collisions_group_shared_slugs() {
+        let docs = [
+            doc("1", "A", "same"),
+            doc("2", "B", "other"),
+            doc("3", "C", "same"),
+            doc("4", "D", ""),
+            doc("5", "E", ""),
+        ];
+        let found = collisions(&docs);
+        assert_eq!(found.len(), 1);
+        let ids: Vec<_> = found["same"].iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["1", "3"]);
+    }
+}
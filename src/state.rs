@@ -0,0 +1,96 @@
+use color_eyre::Report;
+use eyre::eyre;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Directory holding state that persists between invocations, e.g. ~/.local/share/meilizet
+pub fn state_dir() -> Result<PathBuf, Report> {
+    match dirs::data_local_dir() {
+        Some(dir) => Ok(dir.join("meilizet")),
+        None => Err(eyre!("❌ Could not determine the local data directory")),
+    }
+}
+
+/// Interactive query session, saved on exit and restored on the next `query`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub filter: String,
+    #[serde(default)]
+    pub sort: Vec<String>,
+    /// Id of the document that was selected
+    #[serde(default)]
+    pub selected: Option<String>,
+}
+
+impl Session {
+    fn path() -> Result<PathBuf, Report> {
+        Ok(state_dir()?.join("session.json"))
+    }
+
+    /// Load the last saved session, falling back to an empty one if none can be read
+    pub fn load() -> Session {
+        Session::path()
+            .ok()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Report> {
+        let path = Session::path()?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Most entries kept per input box
+const HISTORY_LIMIT: usize = 1000;
+
+/// Previously submitted query and filter inputs, oldest first
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    #[serde(default)]
+    pub query: Vec<String>,
+    #[serde(default)]
+    pub filter: Vec<String>,
+}
+
+impl History {
+    fn path() -> Result<PathBuf, Report> {
+        Ok(state_dir()?.join("history.json"))
+    }
+
+    /// Load the saved history, falling back to an empty one if none can be read
+    pub fn load() -> History {
+        History::path()
+            .ok()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Report> {
+        let path = History::path()?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Record a query and filter, skipping empty inputs and repeats of the latest entry
+    pub fn push(&mut self, query: &str, filter: &str) {
+        for (entries, value) in [(&mut self.query, query), (&mut self.filter, filter)] {
+            if value.is_empty() || entries.last().map(String::as_str) == Some(value) {
+                continue;
+            }
+            entries.push(value.to_owned());
+            if entries.len() > HISTORY_LIMIT {
+                entries.remove(0);
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use color_eyre::Report;
+use eyre::eyre;
+use std::borrow::Cow;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether output may use colour and emoji, settled once the flags are parsed
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Whether status messages are left out
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// When to use colour and emoji
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    /// When stdout is a terminal and NO_COLOR isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<ColorChoice, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(eyre!(
+                "❌ Unknown color choice {}, expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Use colour as `choice` says, and leave out status messages if `quiet`, from now on
+pub fn set(choice: ColorChoice, quiet: bool) {
+    COLOR.store(choice.enabled(), Ordering::Relaxed);
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Columns and rows of the terminal on stdout, None when stdout isn't one
+#[cfg(feature = "interactive")]
+pub fn terminal_size() -> Option<(u16, u16)> {
+    termion::terminal_size()
+        .ok()
+        .filter(|_| io::stdout().is_terminal())
+}
+
+/// Columns and rows of the terminal on stdout, None when stdout isn't one. Without termion to
+/// ask the terminal, $COLUMNS and $LINES are taken at their word.
+#[cfg(not(feature = "interactive"))]
+pub fn terminal_size() -> Option<(u16, u16)> {
+    let var = |name| env::var(name).ok()?.parse::<u16>().ok();
+    match (var("COLUMNS"), var("LINES")) {
+        (Some(w), Some(h)) if io::stdout().is_terminal() => Some((w, h)),
+        _ => None,
+    }
+}
+
+/// `s` with its ✅ and ❌ marks spelled out when colour is off, for logs and dumb terminals
+pub fn mark(s: &str) -> Cow<'_, str> {
+    if color() || !s.contains(&['✅', '❌'][..]) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(s.replace("✅", "ok:").replace("❌", "error:"))
+}
+
+/// Print a status message to stdout unless `--quiet`, with its marks spelled out when colour
+/// is off. Takes the same arguments as `println!`.
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::style::quiet() {
+            println!("{}", $crate::style::mark(&format!($($arg)*)));
+        }
+    };
+}
@@ -0,0 +1,147 @@
+use crate::document::Document;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Spaces between columns
+const GAP: &str = "  ";
+
+/// Narrowest the title gets before the table is let run past the terminal's edge
+const MIN_TITLE: usize = 12;
+
+/// Colours tags cycle through, each tag always getting the same one: cyan, green, yellow,
+/// magenta, blue and red
+const TAG_COLORS: &[&str] = &[
+    "\x1b[36m", "\x1b[32m", "\x1b[33m", "\x1b[35m", "\x1b[34m", "\x1b[31m",
+];
+
+/// Back to the terminal's own foreground colour
+const DEFAULT_COLOR: &str = "\x1b[39m";
+
+const BOLD: &str = "\x1b[1m";
+
+/// Every attribute off
+const RESET: &str = "\x1b[m";
+
+/// `s` cut to `width` columns, ending in … when anything was cut
+fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_owned();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out.push('…');
+    out
+}
+
+/// `s` padded with spaces to `width` columns, on the right unless `right` aligns it
+fn pad(s: &str, width: usize, right: bool) -> String {
+    let fill = " ".repeat(width.saturating_sub(s.width()));
+    if right {
+        format!("{}{}", fill, s)
+    } else {
+        format!("{}{}", s, fill)
+    }
+}
+
+/// The tags cut to `width` columns, coloured when `color`. Coloured text is padded here
+/// because the escape codes would throw off `pad`.
+fn tags(tags: &[String], width: usize, color: bool) -> String {
+    let plain = truncate(&tags.join(" "), width);
+    if !color {
+        return pad(&plain, width, false);
+    }
+    let mut out = String::new();
+    let mut rest = plain.as_str();
+    for tag in tags {
+        if rest.is_empty() {
+            break;
+        }
+        let shown = if rest.len() >= tag.len() && rest.starts_with(tag.as_str()) {
+            tag.as_str()
+        } else {
+            // The truncated last tag
+            rest
+        };
+        let hue = tag
+            .bytes()
+            .fold(0usize, |h, b| h.wrapping_mul(31) + b as usize);
+        out.push_str(&format!(
+            "{}{}{}",
+            TAG_COLORS[hue % TAG_COLORS.len()],
+            shown,
+            DEFAULT_COLOR
+        ));
+        rest = rest[shown.len()..].trim_start();
+        if !rest.is_empty() {
+            out.push(' ');
+        }
+    }
+    out.push_str(&" ".repeat(width.saturating_sub(plain.width())));
+    out
+}
+
+/// A table of `docs` with their id, title, tags, date and weight. Given the terminal `width`,
+/// the title and then the tags shrink to fit it; without one every column gets its full width.
+pub fn render(docs: &[Document], width: Option<usize>, color: bool) -> String {
+    let dates: Vec<String> = docs
+        .iter()
+        .map(|d| d.date.in_zone().format("%Y-%m-%d").to_string())
+        .collect();
+    let widest = |header: &str, cells: &mut dyn Iterator<Item = usize>| {
+        cells.fold(header.width(), usize::max)
+    };
+    let id_w = widest("ID", &mut docs.iter().map(|d| d.id.width()));
+    let date_w = widest("DATE", &mut dates.iter().map(|d| d.width()));
+    let weight_w = widest(
+        "WEIGHT",
+        &mut docs.iter().map(|d| d.weight.to_string().len()),
+    );
+    let mut title_w = widest("TITLE", &mut docs.iter().map(|d| d.title.width()));
+    let mut tags_w = widest("TAGS", &mut docs.iter().map(|d| d.tags.join(" ").width()));
+
+    if let Some(width) = width {
+        let fixed = id_w + date_w + weight_w + GAP.len() * 4;
+        let room = width.saturating_sub(fixed);
+        if title_w + tags_w > room {
+            // Tags get at most a third of the room, the title the rest
+            tags_w = tags_w.min((room / 3).max("TAGS".len()));
+            title_w = title_w.min(room.saturating_sub(tags_w)).max(MIN_TITLE);
+        }
+    }
+
+    let mut out = String::new();
+    let header = [
+        pad("ID", id_w, false),
+        pad("TITLE", title_w, false),
+        pad("TAGS", tags_w, false),
+        pad("DATE", date_w, true),
+        pad("WEIGHT", weight_w, true),
+    ]
+    .join(GAP);
+    if color {
+        out.push_str(&format!("{}{}{}\n", BOLD, header, RESET));
+    } else {
+        out.push_str(&header);
+        out.push('\n');
+    }
+    for (doc, date) in docs.iter().zip(&dates) {
+        let row = [
+            pad(&doc.id, id_w, false),
+            pad(&truncate(&doc.title, title_w), title_w, false),
+            tags(&doc.tags, tags_w, color),
+            pad(date, date_w, true),
+            pad(&doc.weight.to_string(), weight_w, true),
+        ]
+        .join(GAP);
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+    out
+}
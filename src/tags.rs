@@ -0,0 +1,90 @@
+use crate::client::Client;
+use crate::{api, document};
+use color_eyre::Report;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Every tag with the number of documents carrying it, most used first. Taken from the facet
+/// distribution, or if the server can't give one, e.g. as `tags` isn't filterable, by counting
+/// the tags of every document.
+pub async fn counts(client: &Client) -> Result<Vec<(String, u32)>, Report> {
+    let mut q = api::ApiQuery::new();
+    q.facets = Some(vec!["tags".to_owned()]);
+    q.sort = None;
+    q.limit = 0;
+    let facets = match client.search(&q).await {
+        Ok(resp) => resp.facet_distribution.and_then(|mut f| f.remove("tags")),
+        Err(e) => {
+            debug!("No tags facet distribution, counting instead: {}", e);
+            None
+        }
+    };
+    let counts = match facets {
+        Some(counts) => counts,
+        None => {
+            let mut counts = HashMap::new();
+            for doc in api::Pages::documents(client).all().await? {
+                for tag in doc.tags {
+                    *counts.entry(tag).or_insert(0) += 1;
+                }
+            }
+            counts
+        }
+    };
+    let mut counts: Vec<(String, u32)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(counts)
+}
+
+/// Every document, revisions included, tagged with any of `tags`
+pub async fn tagged(client: &Client, tags: &[String]) -> Result<Vec<document::Document>, Report> {
+    let mut q = api::ApiQuery::new();
+    q.filter = Some(
+        tags.iter()
+            .map(|t| format!("tags = \"{}\"", t))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    );
+    api::Pages::search(client, q).all().await
+}
+
+/// `tags` with each of `from` replaced by `into`, which is kept only once and where the first
+/// replaced tag was. None if none of `from` is present.
+pub fn replace(tags: &[String], from: &[String], into: &str) -> Option<Vec<String>> {
+    if !tags.iter().any(|t| from.contains(t)) {
+        return None;
+    }
+    let mut out: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = if from.contains(tag) { into } else { tag };
+        if !out.iter().any(|t| t == tag) {
+            out.push(tag.to_owned());
+        }
+    }
+    Some(out)
+}
+
+/// `tags` without those in `remove`, then with those in `add` it lacks appended
+pub fn edit(tags: &[String], add: &[&str], remove: &[&str]) -> Vec<String> {
+    let mut out: Vec<String> = tags
+        .iter()
+        .filter(|t| !remove.contains(&t.as_str()))
+        .cloned()
+        .collect();
+    for t in add {
+        if !t.is_empty() && !out.iter().any(|o| o == t) {
+            out.push(t.to_string());
+        }
+    }
+    out
+}
+
+/// Partial updates rewriting the tags of those `docs` that carry any of `from`
+pub fn rewrites(docs: &[document::Document], from: &[String], into: &str) -> Vec<Value> {
+    docs.iter()
+        .filter_map(|d| {
+            replace(&d.tags, from, into).map(|tags| json!({ "id": d.id, "tags": tags }))
+        })
+        .collect()
+}
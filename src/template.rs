@@ -0,0 +1,74 @@
+use crate::config::NewConfig;
+use crate::date::Date;
+use crate::document::DocumentId;
+use color_eyre::Report;
+use eyre::eyre;
+use std::fs;
+use std::path::PathBuf;
+
+/// Skeleton for `new` without `--template`
+pub const DEFAULT: &str = "---
+title: \"{{title}}\"
+date: {{date}}
+authors: [\"{{author}}\"]
+tags: []
+id: {{uuid}}
+---
+";
+
+/// Values substituted for `{{date}}`, `{{title}}`, `{{author}}` and `{{uuid}}`
+#[derive(Debug)]
+pub struct Placeholders {
+    pub date: String,
+    pub title: String,
+    pub author: String,
+    pub uuid: String,
+}
+
+impl Placeholders {
+    /// The current time, a fresh id and the configured author, or $USER if there is none
+    pub fn new(title: &str, config: &NewConfig) -> Placeholders {
+        Placeholders {
+            date: Date::now().to_string(),
+            title: title.to_owned(),
+            author: author(config).unwrap_or_default(),
+            uuid: DocumentId::generate().into(),
+        }
+    }
+}
+
+/// Author of new notes, as configured or else $USER
+pub fn author(config: &NewConfig) -> Option<String> {
+    config
+        .author
+        .clone()
+        .or_else(|| std::env::var("USER").ok())
+        .filter(|a| !a.is_empty())
+}
+
+/// Default directory templates are looked up in, e.g. ~/.config/meilizet/templates
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("meilizet").join("templates"))
+}
+
+/// Read the template `name`, i.e. `<dir>/<name>.md`, from the configured templates directory
+pub fn load(name: &str, config: &NewConfig) -> Result<String, Report> {
+    let dir = match &config.templates {
+        Some(d) => PathBuf::from(shellexpand::tilde(&d.to_string_lossy()).as_ref()),
+        None => {
+            default_dir().ok_or_else(|| eyre!("❌ No templates directory, set [new] templates"))?
+        }
+    };
+    let path = dir.join(format!("{}.md", name));
+    fs::read_to_string(&path)
+        .map_err(|e| eyre!("❌ Failed to read template {}: {}", path.display(), e))
+}
+
+/// Substitute the placeholders in `template`, leaving unknown ones as they are
+pub fn expand(template: &str, values: &Placeholders) -> String {
+    template
+        .replace("{{date}}", &values.date)
+        .replace("{{title}}", &values.title)
+        .replace("{{author}}", &values.author)
+        .replace("{{uuid}}", &values.uuid)
+}
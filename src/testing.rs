@@ -0,0 +1,630 @@
+use crate::client::Client;
+use crate::document::{self, Document, SerializationType};
+use crate::index::INDEX;
+use crate::{api, cache, date::Date};
+use chrono::NaiveDate;
+use color_eyre::Report;
+use serde_json::{json, Map, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Version the mock server claims to be
+pub const VERSION: &str = "1.5.0";
+
+/// When every task the mock server runs was enqueued and finished
+const TASK_TIME: &str = "2023-11-20T09:00:00.000000Z";
+
+/// Hits a search returns when the request doesn't give a limit, as Meilisearch does
+const DEFAULT_LIMIT: u32 = 20;
+
+/// Matches a search pages through unless pagination.maxTotalHits says otherwise
+const DEFAULT_MAX_TOTAL_HITS: u64 = 1000;
+
+/// A request the mock server received, its body parsed as JSON or null
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    /// With the query string, e.g. `indexes/notes/documents?offset=0&limit=1000`
+    pub path: String,
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    /// As uploaded, in the order first added
+    documents: Vec<Value>,
+    settings: Map<String, Value>,
+}
+
+impl Index {
+    fn position(&self, id: &str) -> Option<usize> {
+        self.documents.iter().position(|d| d["id"] == id)
+    }
+
+    /// Add `doc`, replacing any document with its id, or with `merge` only change the fields
+    /// it has
+    fn put(&mut self, doc: Value, merge: bool) {
+        let id = doc["id"].as_str().unwrap_or_default().to_owned();
+        match (self.position(&id), doc) {
+            (Some(n), Value::Object(fields)) if merge => {
+                if let Value::Object(existing) = &mut self.documents[n] {
+                    existing.extend(fields);
+                }
+            }
+            (Some(n), doc) => self.documents[n] = doc,
+            (None, doc) => self.documents.push(doc),
+        }
+    }
+
+    fn search(&self, body: Value) -> (u16, Value) {
+        let mut q: api::ApiQuery = match serde_json::from_value(body.clone()) {
+            Ok(q) => q,
+            Err(e) => return error(400, "bad_request", &e.to_string()),
+        };
+        if body.get("limit").is_none() {
+            q.limit = DEFAULT_LIMIT;
+        }
+        let docs: Vec<Document> = self
+            .documents
+            .iter()
+            .filter_map(|d| serde_json::from_value(d.clone()).ok())
+            .collect();
+        let resp = cache::search(&docs, &q);
+        // Nothing past maxTotalHits is returned or counted
+        let max = self
+            .settings
+            .get("pagination")
+            .and_then(|p| p["maxTotalHits"].as_u64())
+            .unwrap_or(DEFAULT_MAX_TOTAL_HITS) as u32;
+        let reachable = max.saturating_sub(resp.offset) as usize;
+        // Hits as uploaded, rather than as Document would write them
+        let hits: Vec<Value> = resp
+            .hits
+            .iter()
+            .take(reachable)
+            .filter_map(|hit| self.position(&hit.id).map(|n| self.documents[n].clone()))
+            .collect();
+        // As VERSION answers, without the nbHits and exhaustiveNbHits of servers before v0.28
+        let mut reply = json!({
+            "hits": hits,
+            "query": resp.query,
+            "processingTimeMs": 0,
+            "limit": resp.limit,
+            "offset": resp.offset,
+            "estimatedTotalHits": resp.num_hits.min(max),
+        });
+        if let Some(fields) = &q.facets {
+            q.offset = None;
+            q.limit = u32::MAX;
+            let matches = cache::search(&docs, &q).hits;
+            let mut facets = Map::new();
+            for field in fields {
+                let mut counts = BTreeMap::new();
+                for doc in &matches {
+                    let values = match field.as_str() {
+                        "tags" => &doc.tags,
+                        "authors" => &doc.authors,
+                        _ => continue,
+                    };
+                    for value in values {
+                        *counts.entry(value.clone()).or_insert(0u32) += 1;
+                    }
+                }
+                facets.insert(field.clone(), json!(counts));
+            }
+            reply["facetDistribution"] = Value::Object(facets);
+            // Only numeric facets have stats, and tags and authors aren't
+            reply["facetStats"] = json!({});
+        }
+        (200, reply)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    indexes: BTreeMap<String, Index>,
+    /// Numbered by their position, each finished as soon as it is enqueued
+    tasks: Vec<Value>,
+    requests: Vec<Request>,
+}
+
+/// A Meilisearch error response
+fn error(status: u16, code: &str, message: &str) -> (u16, Value) {
+    (
+        status,
+        json!({ "message": message, "code": code, "type": "invalid_request", "link": "" }),
+    )
+}
+
+impl State {
+    /// Record a task for `index`, failed with `failure`'s code and message if given, and
+    /// answer as the server does on enqueueing it
+    fn enqueue(&mut self, index: &str, kind: &str, failure: Option<(&str, &str)>) -> (u16, Value) {
+        let uid = self.tasks.len();
+        let mut task = json!({
+            "uid": uid,
+            "indexUid": index,
+            "status": "succeeded",
+            "type": kind,
+            "enqueuedAt": TASK_TIME,
+            "finishedAt": TASK_TIME,
+        });
+        if let Some((code, message)) = failure {
+            task["status"] = json!("failed");
+            task["error"] = json!({ "code": code, "message": message });
+        }
+        self.tasks.push(task);
+        (
+            202,
+            json!({ "taskUid": uid, "indexUid": index, "status": "enqueued", "type": kind }),
+        )
+    }
+
+    fn handle(&mut self, method: &str, target: &str, body: Value) -> (u16, Value) {
+        self.requests.push(Request {
+            method: method.to_owned(),
+            path: target.trim_start_matches('/').to_owned(),
+            body: body.clone(),
+        });
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            ("GET", ["health"]) => (200, json!({ "status": "available" })),
+            ("GET", ["version"]) => (
+                200,
+                json!({ "pkgVersion": VERSION, "commitSha": "mock", "commitDate": TASK_TIME }),
+            ),
+            ("GET", ["stats"]) => {
+                let indexes: Map<String, Value> = self
+                    .indexes
+                    .iter()
+                    .map(|(uid, index)| {
+                        let stats = json!({
+                            "numberOfDocuments": index.documents.len(),
+                            "isIndexing": false,
+                            "fieldDistribution": {},
+                        });
+                        (uid.clone(), stats)
+                    })
+                    .collect();
+                (200, json!({ "databaseSize": 0, "indexes": indexes }))
+            }
+            ("GET", ["tasks"]) => {
+                let tasks: Vec<&Value> = self.tasks.iter().rev().collect();
+                (200, json!({ "results": tasks }))
+            }
+            ("GET", ["tasks", uid]) => {
+                match uid.parse().ok().and_then(|n: usize| self.tasks.get(n)) {
+                    Some(task) => (200, task.clone()),
+                    None => error(404, "task_not_found", &format!("Task `{}` not found.", uid)),
+                }
+            }
+            ("GET", ["indexes"]) => {
+                let indexes: Vec<Value> = self
+                    .indexes
+                    .keys()
+                    .map(|uid| json!({ "uid": uid, "primaryKey": "id" }))
+                    .collect();
+                (200, json!({ "results": indexes }))
+            }
+            ("POST", ["indexes"]) => {
+                let uid = body["uid"].as_str().unwrap_or_default().to_owned();
+                if self.indexes.contains_key(&uid) {
+                    let message = format!("Index `{}` already exists.", uid);
+                    return self.enqueue(
+                        &uid,
+                        "indexCreation",
+                        Some(("index_already_exists", &message)),
+                    );
+                }
+                self.indexes.insert(uid.clone(), Index::default());
+                self.enqueue(&uid, "indexCreation", None)
+            }
+            ("GET", ["indexes", uid]) if self.indexes.contains_key(*uid) => {
+                (200, json!({ "uid": uid, "primaryKey": "id" }))
+            }
+            ("DELETE", ["indexes", uid]) => {
+                if self.indexes.remove(*uid).is_none() {
+                    let message = format!("Index `{}` not found.", uid);
+                    return self.enqueue(uid, "indexDeletion", Some(("index_not_found", &message)));
+                }
+                self.enqueue(uid, "indexDeletion", None)
+            }
+            ("POST", ["swap-indexes"]) => {
+                let uids = &body[0]["indexes"];
+                let (a, b) = match (uids[0].as_str(), uids[1].as_str()) {
+                    (Some(a), Some(b)) => (a.to_owned(), b.to_owned()),
+                    _ => return error(400, "invalid_swap_indexes", "Expected two indexes"),
+                };
+                let first = self.indexes.remove(&a).unwrap_or_default();
+                let second = self.indexes.remove(&b).unwrap_or_default();
+                self.indexes.insert(a, second);
+                self.indexes.insert(b, first);
+                self.enqueue("", "indexSwap", None)
+            }
+            (_, ["indexes", uid, rest @ ..]) => {
+                let uid = uid.to_string();
+                self.index(method, &uid, rest, query, body)
+            }
+            _ => error(404, "not_found", &format!("{} {} not found", method, path)),
+        }
+    }
+
+    /// Handle a request under `indexes/<uid>/`, `rest` being the path after that
+    fn index(
+        &mut self,
+        method: &str,
+        uid: &str,
+        rest: &[&str],
+        query: &str,
+        body: Value,
+    ) -> (u16, Value) {
+        // Writes create the index, as they do on the server
+        let writes = !matches!((method, rest), ("GET", _) | ("POST", ["search"]));
+        if writes {
+            self.indexes.entry(uid.to_owned()).or_default();
+        }
+        let index = match self.indexes.get_mut(uid) {
+            Some(index) => index,
+            None => {
+                return error(
+                    404,
+                    "index_not_found",
+                    &format!("Index `{}` not found.", uid),
+                )
+            }
+        };
+        match (method, rest) {
+            ("POST", ["search"]) => index.search(body),
+            ("GET", ["documents"]) => {
+                let mut offset = 0;
+                let mut limit = DEFAULT_LIMIT as usize;
+                for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+                    match key.as_ref() {
+                        "offset" => offset = value.parse().unwrap_or(0),
+                        "limit" => limit = value.parse().unwrap_or(limit),
+                        _ => (),
+                    }
+                }
+                let page: Vec<&Value> = index.documents.iter().skip(offset).take(limit).collect();
+                let reply = json!({
+                    "results": page,
+                    "offset": offset,
+                    "limit": limit,
+                    "total": index.documents.len(),
+                });
+                (200, reply)
+            }
+            ("GET", ["documents", id]) => match index.position(id) {
+                Some(n) => (200, index.documents[n].clone()),
+                None => error(
+                    404,
+                    "document_not_found",
+                    &format!("Document `{}` not found.", id),
+                ),
+            },
+            ("POST", ["documents"]) | ("PUT", ["documents"]) => {
+                let docs = match body {
+                    Value::Array(docs) => docs,
+                    doc => vec![doc],
+                };
+                if docs.iter().any(|d| !d["id"].is_string()) {
+                    let message = "Document doesn't have an `id` attribute.";
+                    return self.enqueue(
+                        uid,
+                        "documentAdditionOrUpdate",
+                        Some(("missing_document_id", message)),
+                    );
+                }
+                for doc in docs {
+                    index.put(doc, method == "PUT");
+                }
+                self.enqueue(uid, "documentAdditionOrUpdate", None)
+            }
+            ("POST", ["documents", "delete-batch"]) => {
+                let ids: Vec<&str> = body
+                    .as_array()
+                    .map(|ids| ids.iter().filter_map(Value::as_str).collect())
+                    .unwrap_or_default();
+                index
+                    .documents
+                    .retain(|d| !ids.iter().any(|id| d["id"] == *id));
+                self.enqueue(uid, "documentDeletion", None)
+            }
+            ("DELETE", ["documents", id]) => {
+                index.documents.retain(|d| d["id"] != *id);
+                self.enqueue(uid, "documentDeletion", None)
+            }
+            ("DELETE", ["documents"]) => {
+                index.documents.clear();
+                self.enqueue(uid, "documentDeletion", None)
+            }
+            ("GET", ["settings"]) => (200, Value::Object(index.settings.clone())),
+            ("PATCH", ["settings"]) => {
+                if let Value::Object(settings) = body {
+                    index.settings.extend(settings);
+                }
+                self.enqueue(uid, "settingsUpdate", None)
+            }
+            ("DELETE", ["settings"]) => {
+                index.settings.clear();
+                self.enqueue(uid, "settingsUpdate", None)
+            }
+            ("GET", ["settings", name]) => {
+                let setting = match index.settings.get(*name) {
+                    Some(setting) => setting.clone(),
+                    None if *name == "pagination" => {
+                        json!({ "maxTotalHits": DEFAULT_MAX_TOTAL_HITS })
+                    }
+                    None => Value::Null,
+                };
+                (200, setting)
+            }
+            ("PUT", ["settings", name]) => {
+                index.settings.insert(name.to_string(), body);
+                self.enqueue(uid, "settingsUpdate", None)
+            }
+            ("PATCH", ["settings", name]) => {
+                let setting = index
+                    .settings
+                    .entry(name.to_string())
+                    .or_insert_with(|| json!({}));
+                if let (Value::Object(setting), Value::Object(fields)) = (setting, body) {
+                    setting.extend(fields);
+                }
+                self.enqueue(uid, "settingsUpdate", None)
+            }
+            ("DELETE", ["settings", name]) => {
+                index.settings.remove(*name);
+                self.enqueue(uid, "settingsUpdate", None)
+            }
+            _ => error(
+                404,
+                "not_found",
+                &format!("{} indexes/{}/{} not found", method, uid, rest.join("/")),
+            ),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Unknown",
+    }
+}
+
+/// Answer the one request read from `stream`, then close it
+fn serve(mut stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+        _ => return Ok(()),
+    };
+    let mut length = 0;
+    let mut if_none_match = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("if-none-match") {
+                if_none_match = Some(value.trim().to_owned());
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let (status, reply) = state.lock().unwrap().handle(&method, &target, body);
+    let reply = reply.to_string();
+    // GETs carry an ETag, as from a caching proxy in front of the server, and are answered 304
+    // when the client already holds the same reply
+    let mut etag = String::new();
+    if method == "GET" && status == 200 {
+        let mut hasher = DefaultHasher::new();
+        reply.hash(&mut hasher);
+        let tag = format!("\"{:x}\"", hasher.finish());
+        if if_none_match.as_ref() == Some(&tag) {
+            write!(
+                stream,
+                "HTTP/1.1 304 Not Modified\r\nETag: {}\r\nConnection: close\r\n\r\n",
+                tag
+            )?;
+            return stream.flush();
+        }
+        etag = format!("ETag: {}\r\n", tag);
+    }
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        reply.len(),
+        etag,
+        reply
+    )?;
+    stream.flush()
+}
+
+/// A minimal stand-in for Meilisearch on a free local port, answering health, version, stats,
+/// task, index, settings, document and search requests from memory. Searches are answered as
+/// `--offline` answers them, and GETs honor If-None-Match. Every task succeeds, or fails as the server's would, as soon as it
+/// is enqueued. The server stops when dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    url: String,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Start a server without any index
+    pub fn start() -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (shared, stop) = (state.clone(), stopped.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let state = shared.clone();
+                    thread::spawn(move || serve(stream, &state));
+                }
+            }
+        });
+        Ok(MockServer {
+            addr,
+            url: format!("http://{}", addr),
+            state,
+            stopped,
+        })
+    }
+
+    /// Start a server whose notes index holds `notes()`
+    pub fn with_notes() -> io::Result<MockServer> {
+        let server = MockServer::start()?;
+        server.seed(INDEX, &notes());
+        Ok(server)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// A client for the notes index of this server
+    pub fn client(&self) -> Result<Client, Report> {
+        Client::new(&self.url, "")
+    }
+
+    /// Add `docs` to the index `uid`, creating it if need be, as if they had been uploaded
+    pub fn seed(&self, uid: &str, docs: &[Document]) {
+        let mut state = self.state.lock().unwrap();
+        let index = state.indexes.entry(uid.to_owned()).or_default();
+        for doc in docs {
+            let mut doc = doc.clone();
+            doc.serialization_type = SerializationType::Storage;
+            index.put(serde_json::to_value(&doc).unwrap(), false);
+        }
+    }
+
+    /// The documents of the index `uid` in the order first added, empty if there is no such
+    /// index
+    pub fn documents(&self, uid: &str) -> Vec<Document> {
+        let state = self.state.lock().unwrap();
+        state.indexes.get(uid).map_or_else(Vec::new, |index| {
+            index
+                .documents
+                .iter()
+                .filter_map(|d| serde_json::from_value(d.clone()).ok())
+                .collect()
+        })
+    }
+
+    /// Every request received so far, in order
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The body of every search of the index `uid` received so far, in order
+    pub fn searches(&self, uid: &str) -> Vec<api::ApiQuery> {
+        let path = format!("indexes/{}/search", uid);
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == "POST" && r.path == path)
+            .filter_map(|r| serde_json::from_value(r.body).ok())
+            .collect()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the listener so it sees it has been stopped
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+/// A note dated noon UTC on `date`, a YYYY-MM-DD string
+fn note(id: &str, title: &str, date: &str, tags: &[&str], body: &str) -> Document {
+    let noon = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .unwrap()
+        .and_hms(12, 0, 0);
+    let (word_count, reading_minutes) = document::reading_time(body);
+    Document {
+        id: id.to_owned(),
+        parentid: id.to_owned(),
+        title: title.to_owned(),
+        date: Date::new(noon.timestamp()),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+        body: body.to_owned(),
+        filename: format!("{}.md", id),
+        word_count,
+        reading_minutes,
+        ..Document::new()
+    }
+}
+
+/// Canned notes for tests: two about Rust, tagged rust and one of them async, one about
+/// cooking, an archived one about finance and a long journal entry, dated from 2019 to 2022
+pub fn notes() -> Vec<Document> {
+    vec![
+        note(
+            "rust-ownership",
+            "Rust ownership",
+            "2021-03-14",
+            &["rust", "programming"],
+            "Every value has a single owner, and is dropped when the owner goes out of scope.\n",
+        ),
+        note(
+            "async-rust",
+            "Async Rust with tokio",
+            "2022-06-01",
+            &["rust", "async"],
+            "Futures do nothing until polled, tokio's runtime does the polling.\n",
+        ),
+        note(
+            "sourdough",
+            "Sourdough starter",
+            "2020-11-02",
+            &["cooking"],
+            "Feed the starter equal weights of flour and water every day.\n",
+        ),
+        note(
+            "taxes-2018",
+            "Old tax notes",
+            "2019-04-10",
+            &["finance", "archived"],
+            "Receipts are in the blue folder.\n",
+        ),
+        note(
+            "weekly-review",
+            "Weekly review",
+            "2022-12-30",
+            &["journal"],
+            &"Looked back over the week, what went well and what to change. ".repeat(30),
+        ),
+    ]
+}
@@ -0,0 +1,239 @@
+use crate::config::{StyleConfig, ThemeConfig};
+use color_eyre::Report;
+use eyre::{bail, eyre};
+use tui::style::{Color, Modifier, Style};
+
+/// Styles applied to the interactive query widgets
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Query and filter input boxes
+    pub input: Style,
+    /// Selected match in the results list
+    pub highlight: Style,
+    /// Status line below the inputs
+    pub status: Style,
+    /// Debug message pane
+    pub debug: Style,
+    /// Error message pane
+    pub error: Style,
+    /// Syntect theme used to highlight the preview, None leaves it uncolored
+    pub syntax: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Theme {
+        Theme {
+            input: Style::default().fg(Color::Yellow),
+            highlight: Style::default().add_modifier(Modifier::REVERSED),
+            status: Style::default().fg(Color::Gray),
+            debug: Style::default().fg(Color::Green).bg(Color::Black),
+            error: Style::default().fg(Color::Red).bg(Color::Black),
+            syntax: Some(String::from("Solarized (dark)")),
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            input: Style::default().fg(Color::Blue),
+            highlight: Style::default().add_modifier(Modifier::REVERSED),
+            status: Style::default().fg(Color::DarkGray),
+            debug: Style::default().fg(Color::Green).bg(Color::White),
+            error: Style::default().fg(Color::Red).bg(Color::White),
+            syntax: Some(String::from("Solarized (light)")),
+        }
+    }
+
+    pub fn monochrome() -> Theme {
+        Theme {
+            input: Style::default().add_modifier(Modifier::BOLD),
+            highlight: Style::default().add_modifier(Modifier::REVERSED),
+            status: Style::default(),
+            debug: Style::default(),
+            error: Style::default().add_modifier(Modifier::BOLD),
+            syntax: None,
+        }
+    }
+
+    /// Start from the configured built-in theme and apply any per-widget overrides
+    pub fn from_config(config: &ThemeConfig) -> Result<Theme, Report> {
+        let mut theme = match config.base.as_deref() {
+            None | Some("dark") => Theme::dark(),
+            Some("light") => Theme::light(),
+            Some("monochrome") => Theme::monochrome(),
+            Some(other) => bail!(
+                "❌ Unknown theme {}, expected dark, light or monochrome",
+                other
+            ),
+        };
+        if let Some(syntax) = &config.syntax {
+            theme.syntax = match syntax.as_str() {
+                "none" => None,
+                s => Some(s.to_owned()),
+            };
+        }
+        for (style, overrides) in [
+            (&mut theme.input, &config.input),
+            (&mut theme.highlight, &config.highlight),
+            (&mut theme.status, &config.status),
+            (&mut theme.debug, &config.debug),
+            (&mut theme.error, &config.error),
+        ] {
+            if let Some(overrides) = overrides {
+                *style = apply(*style, overrides)?;
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// Layer the colors and modifiers from `config` over `style`
+fn apply(mut style: Style, config: &StyleConfig) -> Result<Style, Report> {
+    if let Some(fg) = &config.fg {
+        style = style.fg(parse_color(fg)?);
+    }
+    if let Some(bg) = &config.bg {
+        style = style.bg(parse_color(bg)?);
+    }
+    for m in &config.modifiers {
+        style = style.add_modifier(parse_modifier(m)?);
+    }
+    Ok(style)
+}
+
+/// Parse a color name, `#rrggbb` hex value, or 0-255 terminal palette index
+pub fn parse_color(s: &str) -> Result<Color, Report> {
+    let color = match s.to_lowercase().replace(&['-', '_', ' '][..], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        hex if hex.starts_with('#') && hex.len() == 7 && hex.is_ascii() => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            match (channel(1), channel(3), channel(5)) {
+                (Ok(r), Ok(g), Ok(b)) => Color::Rgb(r, g, b),
+                _ => bail!("❌ Invalid hex color {}", s),
+            }
+        }
+        idx => Color::Indexed(
+            idx.parse::<u8>()
+                .map_err(|_| eyre!("❌ Unknown color {}", s))?,
+        ),
+    };
+    Ok(color)
+}
+
+fn parse_modifier(s: &str) -> Result<Modifier, Report> {
+    let modifier = match s.to_lowercase().as_str() {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" | "underline" => Modifier::UNDERLINED,
+        "reversed" | "reverse" => Modifier::REVERSED,
+        "crossedout" | "strikethrough" => Modifier::CROSSED_OUT,
+        _ => bail!("❌ Unknown style modifier {}", s),
+    };
+    Ok(modifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepted_colors() {
+        let table = [
+            ("red", Color::Red),
+            ("Default", Color::Reset),
+            ("grey", Color::Gray),
+            ("dark-gray", Color::DarkGray),
+            ("Light Blue", Color::LightBlue),
+            ("light_magenta", Color::LightMagenta),
+            ("#ff8000", Color::Rgb(255, 128, 0)),
+            ("#FF8000", Color::Rgb(255, 128, 0)),
+            ("0", Color::Indexed(0)),
+            ("255", Color::Indexed(255)),
+        ];
+        for (s, color) in table.iter() {
+            assert_eq!(parse_color(s).unwrap(), *color, "parsing {}", s);
+        }
+    }
+
+    #[test]
+    fn rejected_colors() {
+        for s in ["", "purple", "256", "#fff", "#ff80000", "#ff800g", "#ééé"].iter() {
+            assert!(parse_color(s).is_err(), "parsing {}", s);
+        }
+    }
+
+    #[test]
+    fn accepted_modifiers() {
+        let table = [
+            ("bold", Modifier::BOLD),
+            ("DIM", Modifier::DIM),
+            ("italic", Modifier::ITALIC),
+            ("underline", Modifier::UNDERLINED),
+            ("underlined", Modifier::UNDERLINED),
+            ("reverse", Modifier::REVERSED),
+            ("strikethrough", Modifier::CROSSED_OUT),
+            ("crossedout", Modifier::CROSSED_OUT),
+        ];
+        for (s, modifier) in table.iter() {
+            assert_eq!(parse_modifier(s).unwrap(), *modifier, "parsing {}", s);
+        }
+    }
+
+    #[test]
+    fn rejected_modifiers() {
+        for s in ["", "blink", "bold italic", "crossed-out"].iter() {
+            assert!(parse_modifier(s).is_err(), "parsing {}", s);
+        }
+    }
+
+    #[test]
+    fn overrides_layer_over_the_base_theme() {
+        let config = ThemeConfig {
+            base: Some("light".into()),
+            syntax: Some("none".into()),
+            input: Some(StyleConfig {
+                fg: Some("#102030".into()),
+                bg: None,
+                modifiers: vec!["bold".into()],
+            }),
+            ..ThemeConfig::default()
+        };
+        let theme = Theme::from_config(&config).unwrap();
+        assert_eq!(
+            theme.input,
+            Style::default()
+                .fg(Color::Rgb(16, 32, 48))
+                .add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(theme.status, Theme::light().status);
+        assert_eq!(theme.syntax, None);
+
+        let config = ThemeConfig {
+            base: Some("solarized".into()),
+            ..ThemeConfig::default()
+        };
+        assert!(Theme::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::api::{self, ApiQuery};
+use crate::client::Client;
+use crate::date::Date;
+use crate::document::Document;
+use color_eyre::Report;
+use eyre::bail;
+use reqwest::StatusCode;
+use std::collections::HashSet;
+
+/// Field holding when a document was trashed, epoch seconds
+pub const TRASHED_AT: &str = "trashed_at";
+
+/// When `doc` was trashed, None if it wasn't
+pub fn trashed_at(doc: &Document) -> Option<Date> {
+    doc.extra.get(TRASHED_AT)?.as_i64().map(Date::new)
+}
+
+impl Client {
+    /// The trash of this connection's index, e.g. notes-trash for notes. Documents in it are
+    /// out of every search until restored.
+    pub fn trash(&self) -> Client {
+        self.with_index(&format!("{}-trash", self.index()))
+    }
+
+    /// Every revision of the note `id` is a revision of
+    async fn chain(&self, id: &str) -> Result<Vec<Document>, Report> {
+        let doc = match self.get_document(id).await? {
+            Some(doc) => doc,
+            None => bail!("❌ No document {}", id),
+        };
+        let mut q = ApiQuery::new();
+        q.filter = Some(format!("origid = \"{0}\" OR id = \"{0}\"", doc.chain_id()));
+        api::Pages::search(self, q).all().await
+    }
+
+    /// Move every revision of the notes `ids` to the trash, stamped with the time, returning
+    /// them
+    pub async fn trash_notes(&self, ids: &[String]) -> Result<Vec<Document>, Report> {
+        let mut docs = Vec::new();
+        for id in ids {
+            docs.extend(self.chain(id).await?);
+        }
+        let now = Date::now().timestamp();
+        for doc in &mut docs {
+            doc.extra.insert(TRASHED_AT.to_owned(), now.into());
+        }
+        let trash = self.trash();
+        trash.create_index().await?;
+        trash
+            .wait_for_tasks(&trash.add_documents(&docs).await?)
+            .await?;
+        let ids: Vec<_> = docs.iter().map(|d| d.id.clone()).collect();
+        self.wait_for_task(self.delete(&ids).await?).await?;
+        Ok(docs)
+    }
+
+    /// Every document in the trash, empty if nothing was ever trashed
+    pub async fn trashed(&self) -> Result<Vec<Document>, Report> {
+        let trash = self.trash();
+        let url = self.url(&format!("indexes/{}", trash.index()))?;
+        if self.execute(self.get(url)).await?.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        api::Pages::documents(&trash).all().await
+    }
+
+    /// Move every revision of the trashed notes `ids` back out of the trash, returning them
+    pub async fn restore_notes(&self, ids: &[String]) -> Result<Vec<Document>, Report> {
+        let trashed = self.trashed().await?;
+        let mut chains = HashSet::new();
+        for id in ids {
+            match trashed.iter().find(|d| d.id == *id) {
+                Some(doc) => chains.insert(doc.chain_id().to_owned()),
+                None => bail!("❌ No document {} in the trash", id),
+            };
+        }
+        let mut docs: Vec<_> = trashed
+            .into_iter()
+            .filter(|d| chains.contains(d.chain_id()))
+            .collect();
+        for doc in &mut docs {
+            doc.extra.remove(TRASHED_AT);
+        }
+        self.wait_for_tasks(&self.add_documents(&docs).await?)
+            .await?;
+        let trash = self.trash();
+        let ids: Vec<_> = docs.iter().map(|d| d.id.clone()).collect();
+        trash.wait_for_task(trash.delete(&ids).await?).await?;
+        Ok(docs)
+    }
+
+    /// Delete for good the documents trashed before `before`, or all of them, returning how
+    /// many went
+    pub async fn empty_trash(&self, before: Option<Date>) -> Result<usize, Report> {
+        let ids: Vec<_> = self
+            .trashed()
+            .await?
+            .into_iter()
+            .filter(|d| match (&before, trashed_at(d)) {
+                (Some(before), Some(at)) => at.timestamp() < before.timestamp(),
+                _ => true,
+            })
+            .map(|d| d.id)
+            .collect();
+        if !ids.is_empty() {
+            let trash = self.trash();
+            trash.wait_for_task(trash.delete(&ids).await?).await?;
+        }
+        Ok(ids.len())
+    }
+}
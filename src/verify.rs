@@ -0,0 +1,110 @@
+use crate::document::{Document, FrontmatterFormat, SerializationType};
+use color_eyre::Report;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// How a dumped directory differs from the index
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Mismatch {
+    /// A document with no file
+    Missing { filename: String, id: String },
+    /// A file whose contents aren't what dump would write for its document
+    Changed {
+        filename: String,
+        id: String,
+        expected: String,
+        actual: String,
+    },
+    /// A file no document is dumped to
+    Extra { filename: String },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Missing { filename, id } => write!(f, "missing {} ({})", filename, id),
+            Mismatch::Changed {
+                filename,
+                id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "changed {} ({}): sha1 {}, expected {}",
+                filename, id, actual, expected
+            ),
+            Mismatch::Extra { filename } => write!(f, "extra {}", filename),
+        }
+    }
+}
+
+/// Hex SHA-1 of `bytes`
+fn sha1(bytes: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input(bytes);
+    format!("{:x}", hasher.result())
+}
+
+/// The files `dump` writes for `docs`, by file name. Documents sharing a file name overwrite
+/// each other in order, so the last one is kept.
+pub fn files(
+    docs: &[Document],
+    frontmatter: Option<FrontmatterFormat>,
+) -> BTreeMap<String, Document> {
+    docs.iter()
+        .map(|doc| {
+            let mut doc = doc.clone();
+            doc.serialization_type = SerializationType::Disk;
+            if let Some(format) = frontmatter {
+                doc.frontmatter = format;
+            }
+            (doc.filename.clone(), doc)
+        })
+        .collect()
+}
+
+/// Every way the files in `dir` differ from `files`: missing and changed files, then extra ones,
+/// each in file name order
+pub fn compare(dir: &Path, files: &BTreeMap<String, Document>) -> Result<Vec<Mismatch>, Report> {
+    let mut mismatches = Vec::new();
+    for (filename, doc) in files {
+        let path = dir.join(filename);
+        if !path.is_file() {
+            mismatches.push(Mismatch::Missing {
+                filename: filename.clone(),
+                id: doc.id.clone(),
+            });
+            continue;
+        }
+        let expected = sha1(doc.to_string().as_bytes());
+        let actual = sha1(&fs::read(&path)?);
+        if actual != expected {
+            mismatches.push(Mismatch::Changed {
+                filename: filename.clone(),
+                id: doc.id.clone(),
+                expected,
+                actual,
+            });
+        }
+    }
+    let mut extra = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && !files.contains_key(&filename) {
+            extra.push(filename);
+        }
+    }
+    extra.sort();
+    mismatches.extend(
+        extra
+            .into_iter()
+            .map(|filename| Mismatch::Extra { filename }),
+    );
+    Ok(mismatches)
+}
@@ -0,0 +1,743 @@
+use meilizet::api::{ApiQuery, ApiResponse, Pages};
+use meilizet::client::{Client, Retry};
+use meilizet::index;
+use meilizet::testing::{self, MockServer};
+use std::fs;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+/// Run `mz` against `server` with `args`, in a home of its own so none of the user's config,
+/// offline copies or keys get in the way
+fn mz(server: &MockServer, home: &Path, args: &[&str]) -> Output {
+    let out = Command::new(env!("CARGO_BIN_EXE_mz"))
+        .args(["--host", server.url(), "--color", "never"])
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_DATA_HOME", home.join(".local/share"))
+        .env_remove("MEILI_KEY")
+        .env_remove("MEILIZET_CONFIG")
+        .env_remove("MEILIZET_PROFILE")
+        .env_remove("MEILIZET_LOG_FILE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "mz {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+    out
+}
+
+/// Titles of the matches `mz static-query` prints for `query` and `filter`, in order
+fn titles(server: &MockServer, home: &Path, query: &str, filter: &str) -> Vec<String> {
+    let out = mz(
+        server,
+        home,
+        &["static-query", "--output", "ndjson", query, filter],
+    );
+    String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let doc: serde_json::Value = serde_json::from_str(line).unwrap();
+            doc["title"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn import_uploads_each_note() {
+    let server = MockServer::start().unwrap();
+    let home = TempDir::new().unwrap();
+    let notes = home.path().join("notes");
+    fs::create_dir(&notes).unwrap();
+    fs::write(
+        notes.join("first.md"),
+        "---\ntitle: First\ndate: 2021-05-01T10:00:00Z\ntags: [rust]\n---\nOne two three\n",
+    )
+    .unwrap();
+    fs::write(
+        notes.join("second.md"),
+        "---\ntitle: Second\ndate: 2021-05-02T10:00:00Z\ntags: [cooking, bread]\n---\nFour\n",
+    )
+    .unwrap();
+
+    let pattern = format!("{}/*.md", notes.display());
+    mz(&server, home.path(), &["import", &pattern]);
+
+    let mut docs = server.documents("notes");
+    docs.sort_by(|a, b| a.title.cmp(&b.title));
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0].title, "First");
+    assert_eq!(docs[0].tags, vec!["rust"]);
+    assert_eq!(docs[0].body.trim(), "One two three");
+    assert_eq!(docs[0].word_count, 3);
+    assert_eq!(docs[1].title, "Second");
+    assert_eq!(docs[1].tags, vec!["cooking", "bread"]);
+    assert!(docs.iter().all(|d| d.latest && d.revision == 1));
+}
+
+#[test]
+fn static_query_leaves_out_archived_notes() {
+    let server = MockServer::with_notes().unwrap();
+    let home = TempDir::new().unwrap();
+
+    // Newest first
+    assert_eq!(
+        titles(&server, home.path(), "", ""),
+        vec![
+            "Weekly review",
+            "Async Rust with tokio",
+            "Rust ownership",
+            "Sourdough starter"
+        ]
+    );
+    assert_eq!(
+        titles(&server, home.path(), "tokio", ""),
+        vec!["Async Rust with tokio"]
+    );
+
+    let out = mz(
+        &server,
+        home.path(),
+        &[
+            "static-query",
+            "--output",
+            "ndjson",
+            "--include-archived",
+            "receipts",
+        ],
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Old tax notes"));
+}
+
+#[test]
+fn filter_grammar() {
+    let server = MockServer::with_notes().unwrap();
+    let home = TempDir::new().unwrap();
+    let cases: &[(&str, &[&str])] = &[
+        ("rust", &["Async Rust with tokio", "Rust ownership"]),
+        ("rust+async", &["Async Rust with tokio"]),
+        ("cooking|journal", &["Weekly review", "Sourdough starter"]),
+        ("!rust", &["Weekly review", "Sourdough starter"]),
+        ("2021", &["Rust ownership"]),
+        ("2022-06", &["Async Rust with tokio"]),
+        (">2022-01-01", &["Weekly review", "Async Rust with tokio"]),
+        ("<2021-01", &["Sourdough starter"]),
+        ("words>100", &["Weekly review"]),
+        (
+            "words<100+rust",
+            &["Async Rust with tokio", "Rust ownership"],
+        ),
+    ];
+    for (filter, expected) in cases {
+        assert_eq!(
+            titles(&server, home.path(), "", filter),
+            *expected,
+            "filter {}",
+            filter
+        );
+    }
+
+    // What the grammar turns a filter into, as sent to the server
+    let sent: Vec<_> = server
+        .searches("notes")
+        .into_iter()
+        .filter_map(|q| q.filter)
+        .collect();
+    assert!(sent.contains(&"(tags = rust AND tags = async) AND tags != \"archived\"".to_owned()));
+    assert!(sent.contains(&"(tags != rust) AND tags != \"archived\"".to_owned()));
+    assert!(sent.contains(&"(word_count > 100) AND tags != \"archived\"".to_owned()));
+}
+
+#[test]
+fn filter_grammar_ignores_what_it_cannot_parse() {
+    let mut q = ApiQuery::new();
+    q.process_filter("rust + (async".to_owned());
+    assert_eq!(q.filter, None);
+}
+
+#[test]
+fn dump_writes_a_file_per_note() {
+    let server = MockServer::with_notes().unwrap();
+    let home = TempDir::new().unwrap();
+    let dir = home.path().join("dump");
+
+    mz(&server, home.path(), &["dump", dir.to_str().unwrap()]);
+
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    let mut expected: Vec<_> = testing::notes().into_iter().map(|d| d.filename).collect();
+    expected.sort();
+    assert_eq!(files, expected);
+
+    let sourdough = fs::read_to_string(dir.join("sourdough.md")).unwrap();
+    assert!(sourdough.contains("title: Sourdough starter"));
+    assert!(sourdough.contains("Feed the starter equal weights of flour and water every day."));
+}
+
+#[test]
+fn sync_keeps_pages_the_server_says_are_unchanged() {
+    let server = MockServer::with_notes().unwrap();
+    let home = TempDir::new().unwrap();
+    let copy = home.path().join(".local/share/meilizet/cache/notes.json");
+
+    mz(&server, home.path(), &["sync"]);
+    let synced = fs::read_to_string(&copy).unwrap();
+    assert!(synced.contains("Sourdough starter"));
+    // Only a page that was downloaded again would undo this
+    fs::write(&copy, synced.replace("Sourdough starter", "Stale starter")).unwrap();
+
+    mz(&server, home.path(), &["sync"]);
+    assert!(fs::read_to_string(&copy).unwrap().contains("Stale starter"));
+
+    let mut added = testing::notes().remove(0);
+    added.id = "added".to_owned();
+    server.seed("notes", &[added]);
+    mz(&server, home.path(), &["sync"]);
+    let synced = fs::read_to_string(&copy).unwrap();
+    assert!(synced.contains("Sourdough starter"));
+    assert!(synced.contains("\"added\""));
+}
+
+#[test]
+fn client_pages_through_documents() {
+    let server = MockServer::start().unwrap();
+    let many: Vec<_> = (0..2500)
+        .map(|n| {
+            let mut doc = testing::notes().remove(0);
+            doc.id = format!("note-{}", n);
+            doc
+        })
+        .collect();
+    server.seed("notes", &many);
+
+    let client = server.client().unwrap();
+    let docs = Runtime::new()
+        .unwrap()
+        .block_on(Pages::documents(&client).all())
+        .unwrap();
+    assert_eq!(docs.len(), 2500);
+    assert_eq!(docs[2499].id, "note-2499");
+}
+
+#[test]
+fn searches_past_max_total_hits_fail_until_init_raises_it() {
+    let server = MockServer::start().unwrap();
+    let many: Vec<_> = (0..1500)
+        .map(|n| {
+            let mut doc = testing::notes().remove(0);
+            doc.id = format!("note-{}", n);
+            doc
+        })
+        .collect();
+    server.seed("notes", &many);
+    let client = server.client().unwrap();
+    let rust = || {
+        let mut q = ApiQuery::new();
+        q.filter = Some("tags = rust".to_owned());
+        q
+    };
+
+    let rt = Runtime::new().unwrap();
+    // The server stops at its default of 1000
+    assert!(rt.block_on(Pages::search(&client, rust()).all()).is_err());
+
+    rt.block_on(index::init(&client, 0)).unwrap();
+    let docs = rt.block_on(Pages::search(&client, rust()).all()).unwrap();
+    assert_eq!(docs.len(), 1500);
+}
+
+#[test]
+fn search_replies_without_nb_hits_parse() {
+    // As servers since v0.28 answer, with facets asked for
+    let reply = serde_json::json!({
+        "hits": [],
+        "query": "rust",
+        "processingTimeMs": 1,
+        "limit": 0,
+        "offset": 0,
+        "estimatedTotalHits": 2,
+        "facetDistribution": { "tags": { "rust": 2, "async": 1 } },
+        "facetStats": {},
+    });
+    let resp: ApiResponse = serde_json::from_value(reply).unwrap();
+    assert_eq!(resp.num_hits, 2);
+    assert_eq!(resp.facet_distribution.unwrap()["tags"]["async"], 1);
+
+    let server = MockServer::with_notes().unwrap();
+    let mut q = ApiQuery::new();
+    q.query = Some("rust".to_owned());
+    q.facets = Some(vec!["tags".to_owned()]);
+    let resp = Runtime::new()
+        .unwrap()
+        .block_on(server.client().unwrap().search(&q))
+        .unwrap();
+    assert_eq!(resp.num_hits, 2);
+    assert_eq!(resp.facet_distribution.unwrap()["tags"]["rust"], 2);
+    assert!(server
+        .requests()
+        .iter()
+        .all(|r| r.body.get("facetsDistribution").is_none()));
+}
+
+#[test]
+fn reads_fall_back_to_a_replica_when_the_primary_is_down() {
+    let replica = MockServer::with_notes().unwrap();
+    // Nothing listens on the port once the listener is dropped
+    let down = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let retry = Retry {
+        attempts: 0,
+        delay: Duration::from_millis(0),
+    };
+    let client = Client::new(&format!("http://{}", down), "")
+        .unwrap()
+        .with_retry(retry)
+        .with_replicas(&[replica.url()])
+        .unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let hits = rt.block_on(client.search(&ApiQuery::new())).unwrap().hits;
+    assert_eq!(hits.len(), replica.documents("notes").len());
+    // Writes only ever go to the primary
+    assert!(rt
+        .block_on(client.add_documents(&testing::notes()))
+        .is_err());
+}
+
+#[test]
+fn read_only_client_searches_but_changes_nothing() {
+    let server = MockServer::with_notes().unwrap();
+    let client = server.client().unwrap().read_only();
+
+    let rt = Runtime::new().unwrap();
+    let hits = rt.block_on(client.search(&ApiQuery::new())).unwrap().hits;
+    assert_eq!(hits.len(), server.documents("notes").len());
+    let ids: Vec<_> = hits.iter().map(|d| d.id.clone()).collect();
+    assert!(rt.block_on(client.delete(&ids)).is_err());
+    assert_eq!(server.documents("notes").len(), ids.len());
+    assert!(server
+        .requests()
+        .iter()
+        .all(|r| r.method == "GET" || r.path.ends_with("/search")));
+}
+
+#[test]
+fn diff_shows_local_edits_against_the_index() {
+    let server = MockServer::with_notes().unwrap();
+    let home = TempDir::new().unwrap();
+    let dir = home.path().join("dump");
+    mz(&server, home.path(), &["dump", dir.to_str().unwrap()]);
+    let path = dir.join("sourdough.md");
+
+    let unchanged = mz(
+        &server,
+        home.path(),
+        &["diff", "sourdough", "--file", path.to_str().unwrap()],
+    );
+    assert!(!String::from_utf8(unchanged.stdout).unwrap().contains("@@"));
+
+    let dumped = fs::read_to_string(&path).unwrap();
+    fs::write(&path, dumped.replace("every day", "twice a day")).unwrap();
+    let out = mz(
+        &server,
+        home.path(),
+        &["diff", "sourdough", "--file", path.to_str().unwrap()],
+    );
+    let diff = String::from_utf8(out.stdout).unwrap();
+    assert!(diff.contains("-Feed the starter equal weights of flour and water every day.\n"));
+    assert!(diff.contains("+Feed the starter equal weights of flour and water twice a day.\n"));
+}
+
+#[test]
+fn trash_and_untrash_move_notes_out_of_searches_and_back() {
+    let server = MockServer::with_notes().unwrap();
+    let home = TempDir::new().unwrap();
+    let sourdough = || titles(&server, home.path(), "starter", "");
+
+    mz(&server, home.path(), &["trash", "sourdough"]);
+    assert!(sourdough().is_empty());
+    let listed = mz(&server, home.path(), &["trash", "list"]);
+    assert!(String::from_utf8(listed.stdout)
+        .unwrap()
+        .starts_with("sourdough\tSourdough starter\t"));
+
+    mz(&server, home.path(), &["untrash", "sourdough"]);
+    assert_eq!(sourdough(), vec!["Sourdough starter"]);
+    assert!(server.documents("notes-trash").is_empty());
+
+    mz(&server, home.path(), &["trash", "sourdough"]);
+    mz(
+        &server,
+        home.path(),
+        &["trash", "empty", "--older-than", "30d"],
+    );
+    assert_eq!(server.documents("notes-trash").len(), 1);
+    mz(&server, home.path(), &["trash", "empty"]);
+    assert!(server.documents("notes-trash").is_empty());
+    assert!(sourdough().is_empty());
+}
+
+#[test]
+fn merge_combines_two_notes_into_a_new_one() {
+    let server = MockServer::with_notes().unwrap();
+    let home = TempDir::new().unwrap();
+
+    mz(
+        &server,
+        home.path(),
+        &[
+            "merge",
+            "rust-ownership",
+            "async-rust",
+            "--into",
+            "Rust notes",
+        ],
+    );
+
+    let docs = server.documents("notes");
+    let merged = docs.iter().find(|d| d.title == "Rust notes").unwrap();
+    assert!(merged.latest);
+    assert_eq!(merged.tags, vec!["rust", "programming", "async"]);
+    assert!(merged.body.starts_with("## Rust ownership\n\nEvery value"));
+    assert!(merged
+        .body
+        .contains("\n## Async Rust with tokio\n\nFutures do nothing"));
+    for id in &["rust-ownership", "async-rust"] {
+        let source = docs.iter().find(|d| d.id == *id).unwrap();
+        assert!(!source.latest);
+        assert_eq!(
+            source.extra["merged_into"].as_str(),
+            Some(merged.id.as_str())
+        );
+    }
+}
+
+#[test]
+fn retitle_renames_the_slug_and_rewrites_links_to_it() {
+    let server = MockServer::start().unwrap();
+    let mut notes = testing::notes();
+    notes[0].slug = "rust-ownership".to_owned();
+    notes[1].links = vec!["rust-ownership".to_owned()];
+    server.seed("notes", &notes[..2]);
+    let home = TempDir::new().unwrap();
+
+    mz(
+        &server,
+        home.path(),
+        &[
+            "retitle",
+            "rust-ownership",
+            "Ownership and borrowing",
+            "--rewrite-links",
+        ],
+    );
+
+    let docs = server.documents("notes");
+    let retitled = docs.iter().find(|d| d.latest && d.revision == 2).unwrap();
+    assert_eq!(retitled.origid, "rust-ownership");
+    assert_eq!(retitled.title, "Ownership and borrowing");
+    assert_eq!(retitled.slug, "ownership-and-borrowing");
+    assert_eq!(retitled.filename, "ownership-and-borrowing.md");
+    let linking = docs.iter().find(|d| d.id == "async-rust").unwrap();
+    assert_eq!(linking.links, vec!["ownership-and-borrowing"]);
+}
+
+/// Commit `contents` to `file` in the repository at `repo`, as of `time` epoch seconds
+fn commit(repo: &Path, file: &str, contents: &str, time: i64) {
+    fs::write(repo.join(file), contents).unwrap();
+    let date = format!("{} +0000", time);
+    for args in &[vec!["add", "-A"], vec!["commit", "-q", "-m", file]] {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "mz")
+            .env("GIT_AUTHOR_EMAIL", "mz@example.com")
+            .env("GIT_COMMITTER_NAME", "mz")
+            .env("GIT_COMMITTER_EMAIL", "mz@example.com")
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
+
+#[test]
+fn import_git_makes_a_revision_per_commit() {
+    let server = MockServer::start().unwrap();
+    let home = TempDir::new().unwrap();
+    let repo = TempDir::new().unwrap();
+    let status = Command::new("git")
+        .args(["init", "-q"])
+        .arg(repo.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let note = |body: &str| format!("---\nid: diary\ntitle: Diary\n---\n{}\n", body);
+    commit(repo.path(), "diary.md", &note("Monday"), 1_600_000_000);
+    commit(
+        repo.path(),
+        "diary.md",
+        &note("Monday\nTuesday"),
+        1_600_086_400,
+    );
+    commit(repo.path(), "other.txt", "not a note", 1_600_100_000);
+
+    mz(
+        &server,
+        home.path(),
+        &["import-git", repo.path().to_str().unwrap(), "*.md"],
+    );
+
+    let mut docs = server.documents("notes");
+    docs.sort_by_key(|d| d.revision);
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0].id, "diary");
+    assert_eq!(docs[0].body, "Monday\n");
+    assert_eq!(docs[0].date.timestamp(), 1_600_000_000);
+    assert!(!docs[0].latest);
+    assert_eq!(docs[1].origid, "diary");
+    assert_eq!(docs[1].revision, docs[0].revision + 1);
+    assert_eq!(docs[1].body, "Monday\nTuesday\n");
+    assert_eq!(docs[1].date.timestamp(), 1_600_086_400);
+    assert!(docs[1].latest);
+}
+
+#[test]
+fn import_bookmarks_reads_a_browser_export_into_its_own_index() {
+    let server = MockServer::start().unwrap();
+    let home = TempDir::new().unwrap();
+    let export = home.path().join("bookmarks.html");
+    fs::write(
+        &export,
+        r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><H3>Rust</H3>
+    <DL><p>
+        <DT><A HREF="https://doc.rust-lang.org/book/" ADD_DATE="1600000000" TAGS="rust,docs">The Rust Programming Language</A>
+        <DD>The book &amp; more
+        <DT><A HREF="place:sort=8&amp;maxResults=10">Most Visited</A>
+    </DL><p>
+</DL><p>
+"#,
+    )
+    .unwrap();
+
+    for _ in 0..2 {
+        mz(
+            &server,
+            home.path(),
+            &[
+                "import-bookmarks",
+                export.to_str().unwrap(),
+                "--format",
+                "netscape-html",
+                "--index",
+                "bookmarks",
+            ],
+        );
+    }
+
+    let docs = server.documents("bookmarks");
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].title, "The Rust Programming Language");
+    assert_eq!(docs[0].links, vec!["https://doc.rust-lang.org/book/"]);
+    assert_eq!(docs[0].tags, vec!["rust", "docs"]);
+    assert_eq!(docs[0].body, "The book & more\n");
+    assert_eq!(docs[0].date.timestamp(), 1_600_000_000);
+    assert!(server.documents("notes").is_empty());
+}
+
+#[test]
+fn import_mail_turns_emails_from_me_into_notes() {
+    let server = MockServer::start().unwrap();
+    let home = TempDir::new().unwrap();
+    let maildir = home.path().join("Archive");
+    fs::create_dir_all(maildir.join("cur")).unwrap();
+    fs::create_dir_all(maildir.join("new")).unwrap();
+    fs::write(
+        maildir.join("cur/1600000000.1.host:2,S"),
+        "From: Me <me@example.com>\r\n\
+         To: me@example.com, Ann <ann@example.org>\r\n\
+         Subject: =?UTF-8?Q?Caf=C3=A9_ideas?=\r\n\
+         Date: Sun, 13 Sep 2020 12:26:40 +0000\r\n\
+         Message-ID: <note-1@example.com>\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: quoted-printable\r\n\
+         \r\n\
+         Open a caf=C3=A9.\r\n",
+    )
+    .unwrap();
+    fs::write(
+        maildir.join("new/1600000001.2.host"),
+        "From: Shop <offers@shop.example>\r\n\
+         To: me@example.com\r\n\
+         Subject: Sale\r\n\
+         \r\n\
+         Buy now\r\n",
+    )
+    .unwrap();
+
+    mz(
+        &server,
+        home.path(),
+        &[
+            "import-mail",
+            maildir.to_str().unwrap(),
+            "--filter-from",
+            "me@",
+        ],
+    );
+
+    let docs = server.documents("notes");
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].title, "Café ideas");
+    assert_eq!(docs[0].body, "Open a café.\n");
+    assert_eq!(docs[0].date.timestamp(), 1_600_000_000);
+    assert_eq!(docs[0].authors, vec!["Me"]);
+    assert_eq!(docs[0].tags, vec!["me@example.com", "ann@example.org"]);
+}
+
+#[test]
+fn import_joplin_keeps_notebooks_tags_links_and_resources() {
+    let server = MockServer::start().unwrap();
+    let home = TempDir::new().unwrap();
+    let export = home.path().join("joplin");
+    fs::create_dir_all(export.join("resources")).unwrap();
+    let id = |c: char| c.to_string().repeat(32);
+    let (diary, monday, tuesday, cat, tag) = (id('a'), id('c'), id('b'), id('d'), id('e'));
+    let items = vec![
+        (diary.clone(), "Diary\n\ntype_: 2".to_owned()),
+        (
+            monday.clone(),
+            format!(
+                "Monday\n\nSee [Tuesday](:/{}) and ![cat](:/{})\n\nparent_id: {}\n\
+                 created_time: 2020-09-13T12:26:40.000Z\ntype_: 1",
+                tuesday, cat, diary
+            ),
+        ),
+        (
+            tuesday.clone(),
+            format!(
+                "Tuesday\n\nBack to [nowhere](:/{})\n\nparent_id: {}\ntype_: 1",
+                id('f'),
+                diary
+            ),
+        ),
+        (
+            cat.clone(),
+            "cat.png\n\nfile_extension: png\ntype_: 4".to_owned(),
+        ),
+        (tag.clone(), "cats\n\ntype_: 5".to_owned()),
+        (
+            id('1'),
+            format!("note_id: {}\ntag_id: {}\ntype_: 6", monday, tag),
+        ),
+    ];
+    for (item_id, text) in &items {
+        fs::write(
+            export.join(format!("{}.md", item_id)),
+            format!("{}\nid: {}\n", text, item_id),
+        )
+        .unwrap();
+    }
+    fs::write(export.join(format!("resources/{}.png", cat)), b"PNG").unwrap();
+
+    let out = mz(
+        &server,
+        home.path(),
+        &["import-joplin", export.to_str().unwrap()],
+    );
+
+    let docs = server.documents("notes");
+    assert_eq!(docs.len(), 2);
+    let note = docs.iter().find(|d| d.id == monday).unwrap();
+    assert_eq!(note.title, "Monday");
+    assert_eq!(note.tags, vec!["Diary", "cats"]);
+    assert_eq!(note.links, vec![tuesday.clone()]);
+    assert_eq!(note.attachments[0].name(), "cat.png");
+    assert_eq!(
+        note.body,
+        format!("See [Tuesday]({}) and ![cat](cat.png)\n", tuesday)
+    );
+    assert_eq!(note.date.timestamp(), 1_600_000_000);
+    let report = String::from_utf8_lossy(&out.stdout);
+    assert!(report.contains(&format!(
+        "Not converted: Tuesday: link to :/{} isn't in the export",
+        id('f')
+    )));
+}
+
+#[test]
+fn import_notion_reads_pages_properties_and_files() {
+    let server = MockServer::start().unwrap();
+    let home = TempDir::new().unwrap();
+    let export = home.path().join("notion.zip");
+    let page = "0123456789abcdef0123456789abcdef";
+    let table = "Reading List 1111222233334444aaaabbbbccccdddd";
+    let row = "fedcba9876543210fedcba9876543210";
+    let files = vec![
+        (
+            format!("Ideas {}.md", page),
+            format!(
+                "# Ideas\n\nSee [Cafe]({}/Cafe%20{}.md) and ![map](Ideas%20{}/map.png)\n",
+                table.replace(' ', "%20"),
+                row,
+                page
+            ),
+        ),
+        (format!("Ideas {}/map.png", page), "PNG".to_owned()),
+        (format!("{}.csv", table), "Name,Tags\n".to_owned()),
+        (
+            format!("{}/Cafe {}.md", table, row),
+            "# Cafe\n\nTags: food, travel\nCreated: September 13, 2020 12:26 PM\n\
+             Status: Done\n\nOpen one.\n"
+                .to_owned(),
+        ),
+    ];
+    let mut zip = zip::ZipWriter::new(fs::File::create(&export).unwrap());
+    for (name, contents) in &files {
+        zip.start_file(name.as_str(), zip::write::FileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+
+    let out = mz(
+        &server,
+        home.path(),
+        &["import-notion", export.to_str().unwrap()],
+    );
+
+    let docs = server.documents("notes");
+    assert_eq!(docs.len(), 2);
+    let ideas = docs.iter().find(|d| d.id == page).unwrap();
+    assert_eq!(ideas.title, "Ideas");
+    assert_eq!(ideas.links, vec![row]);
+    assert_eq!(ideas.attachments[0].name(), "map.png");
+    assert_eq!(
+        ideas.body,
+        format!("See [Cafe]({}) and ![map](map.png)\n", row)
+    );
+    let cafe = docs.iter().find(|d| d.id == row).unwrap();
+    assert_eq!(cafe.title, "Cafe");
+    assert_eq!(cafe.tags, vec!["food", "travel"]);
+    assert_eq!(cafe.body, "Open one.\n");
+    assert_eq!(cafe.extra["notion_status"].as_str(), Some("Done"));
+    let report = String::from_utf8_lossy(&out.stdout);
+    assert!(report.contains(&format!("{}.csv: a database table", table)));
+}
@@ -0,0 +1,132 @@
+use meilizet::blocking::Client;
+use meilizet::config::Config;
+use meilizet::document::Document;
+use meilizet::interactive::{self, event::Source};
+use meilizet::testing::MockServer;
+use std::fs;
+use tempfile::TempDir;
+use termion::event::Key;
+use tui::buffer::Buffer;
+
+fn options(keys: Source) -> interactive::Options {
+    interactive::Options {
+        verbosity: 0,
+        pager: "less".to_owned(),
+        editor: "vi".to_owned(),
+        fresh: true,
+        include_archived: false,
+        keys,
+    }
+}
+
+/// Run the interactive query against `server` with `keys` on a 100 by 30 screen
+fn run(server: &MockServer, keys: Source) -> (Vec<Document>, Buffer) {
+    let client = Client::new(server.client().unwrap()).unwrap();
+    interactive::query_headless(client, &options(keys), &Config::default(), 100, 30).unwrap()
+}
+
+/// Press `keys` in order
+fn press(server: &MockServer, keys: Vec<Key>) -> (Vec<Document>, Buffer) {
+    let (tx, source) = Source::channel();
+    for key in keys {
+        tx.send(key).unwrap();
+    }
+    drop(tx);
+    run(server, source)
+}
+
+/// The keys typing `s`
+fn typed(s: &str) -> Vec<Key> {
+    s.chars().map(Key::Char).collect()
+}
+
+/// The screen as text, a line per row
+fn screen(buffer: &Buffer) -> String {
+    let width = buffer.area.width as usize;
+    buffer
+        .content
+        .chunks(width)
+        .map(|row| row.iter().map(|c| c.symbol.as_str()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn typing_a_query_narrows_the_matches() {
+    let server = MockServer::with_notes().unwrap();
+    let (picked, buffer) = press(&server, [typed("tokio"), vec![Key::Ctrl('c')]].concat());
+
+    let screen = screen(&buffer);
+    assert!(screen.contains("Async Rust with tokio"), "{}", screen);
+    assert!(!screen.contains("Sourdough starter"), "{}", screen);
+    // Quitting picks nothing
+    assert!(picked.is_empty());
+}
+
+#[test]
+fn enter_picks_the_highlighted_match() {
+    let server = MockServer::with_notes().unwrap();
+    let keys = vec![Key::Down, Key::Down, Key::Char('\n')];
+    let (picked, _) = press(&server, [typed("rust"), keys].concat());
+
+    let titles: Vec<_> = picked.iter().map(|d| d.title.as_str()).collect();
+    assert_eq!(titles, vec!["Rust ownership"]);
+    // Picking counts as a view
+    let viewed = server
+        .documents("notes")
+        .into_iter()
+        .find(|d| d.id == "rust-ownership")
+        .unwrap();
+    assert_eq!(viewed.views, 1);
+}
+
+#[test]
+fn tab_switches_to_the_filter() {
+    let server = MockServer::with_notes().unwrap();
+    let keys = [
+        vec![Key::Char('\t')],
+        typed("cooking"),
+        vec![Key::Ctrl('c')],
+    ];
+    let (_, buffer) = press(&server, keys.concat());
+
+    let screen = screen(&buffer);
+    assert!(screen.contains("Sourdough starter"), "{}", screen);
+    assert!(!screen.contains("Rust ownership"), "{}", screen);
+    let filters: Vec<_> = server
+        .searches("notes")
+        .into_iter()
+        .filter_map(|q| q.filter)
+        .collect();
+    assert!(filters.contains(&"(tags = cooking) AND tags != \"archived\"".to_owned()));
+}
+
+#[test]
+fn script_file_drives_the_query() {
+    let server = MockServer::with_notes().unwrap();
+    let dir = TempDir::new().unwrap();
+    let script = dir.path().join("demo.keys");
+    fs::write(
+        &script,
+        "# Find the bread notes\ntype sour\nwait 20\n\ndown enter\n",
+    )
+    .unwrap();
+
+    let (picked, _) = run(&server, Source::script(&script).unwrap());
+    assert_eq!(picked.len(), 1);
+    assert_eq!(picked[0].title, "Sourdough starter");
+}
+
+#[test]
+fn script_errors_name_the_line() {
+    let dir = TempDir::new().unwrap();
+    let script = dir.path().join("bad.keys");
+    fs::write(&script, "type rust\nwait soon\n").unwrap();
+
+    let e = Source::script(&script).err().unwrap();
+    assert!(e.to_string().contains("line 2"), "{}", e);
+
+    fs::write(&script, "down ctrl-shift-x\n").unwrap();
+    let e = Source::script(&script).err().unwrap();
+    assert!(e.to_string().contains("line 1"), "{}", e);
+}